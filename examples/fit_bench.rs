@@ -0,0 +1,76 @@
+//! Cubic fitting benchmark for the advanced LOD segmenter
+//!
+//! For each capture, fits the windows `build_segments` tries (sizes growing
+//! from `initial_size` by `growth_factor`, at positions spread over the capture)
+//! with `analyze_segment` (SVD) and with `PrefixFitter` (prefix sums), checks
+//! that their R² agree, and times a full `build_segments`. The SVD cost grows
+//! with the window size; the prefix-sum fit costs the same for every window.
+//!
+//! Run with: cargo run --release --example fit_bench [capture.csv ...]
+
+use MouseTesterRust::formats;
+use MouseTesterRust::lod_advanced::{PrefixFitter, SegmentFit, SegmentParams, analyze_segment, build_segments};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CAPTURES: &str = "examples/test";
+/// Window start positions per capture
+const POSITIONS: usize = 50;
+/// Largest window tried
+const MAX_WINDOW: usize = 5_000;
+
+/// Time of one run of `f`, repeated until about 100 ms have passed
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let started = Instant::now();
+    let mut result = f();
+    let mut runs = 1u32;
+    while started.elapsed() < Duration::from_millis(100) {
+        result = f();
+        runs += 1;
+    }
+    (started.elapsed() / runs, result)
+}
+
+fn max_r_squared_diff(a: &[SegmentFit], b: &[SegmentFit]) -> f64 {
+    a.iter().zip(b).flat_map(|(a, b)| [a.dx_r_squared - b.dx_r_squared, a.dy_r_squared - b.dy_r_squared, a.time_r_squared - b.time_r_squared]).fold(0.0, |max, d| max.max(d.abs()))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        paths = std::fs::read_dir(DEFAULT_CAPTURES)?.map(|entry| entry.map(|e| e.path())).collect::<Result<_, _>>()?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext == "csv"));
+        paths.sort();
+    }
+
+    let p = SegmentParams::default();
+    println!("{:<36} {:>8} {:>8} {:>11} {:>11} {:>8} {:>10} {:>10} {:>11}", "capture", "events", "windows", "svd/window", "sums/window", "speedup", "max dR²", "sums build", "segmenting");
+    for path in paths {
+        let events = formats::load_events(&path, None, None)?;
+        if events.len() < p.initial_size {
+            println!("{:<36} {:>8} too few events", path.display(), events.len());
+            continue;
+        }
+
+        let mut windows = Vec::new();
+        for k in 0..POSITIONS {
+            let start = k * (events.len() - p.initial_size) / POSITIONS;
+            let mut size = p.initial_size;
+            while start + size <= events.len() && size <= MAX_WINDOW {
+                windows.push((start, start + size));
+                size = (size as f64 * p.growth_factor).ceil() as usize;
+            }
+        }
+
+        let (build_time, fitter) = time(|| PrefixFitter::new(&events, p.degree));
+        let (svd_time, svd) = time(|| windows.iter().filter_map(|&(start, end)| analyze_segment(&events, start, end, p.degree)).collect::<Vec<_>>());
+        let (sums_time, sums) = time(|| windows.iter().filter_map(|&(start, end)| fitter.fit(&events, start, end)).collect::<Vec<_>>());
+        assert_eq!(svd.len(), sums.len(), "fitters disagree on which windows fit");
+        let (segment_time, _) = time(|| build_segments(&events, p.initial_size, p.growth_factor, p.min_r_squared, p.balance_weight, p.degree));
+
+        let name = path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+        let per_window = |d: Duration| d / windows.len() as u32;
+        println!("{:<36} {:>8} {:>8} {:>11.2?} {:>11.2?} {:>7.0}x {:>10.1e} {:>10.2?} {:>11.2?}", name, events.len(), windows.len(), per_window(svd_time), per_window(sums_time), svd_time.as_secs_f64() / sums_time.as_secs_f64().max(1e-12), max_r_squared_diff(&svd, &sums), build_time, segment_time);
+    }
+    Ok(())
+}
@@ -5,10 +5,11 @@
 //!
 //! Run with: cargo run --example lod_analysis
 
+use MouseTesterRust::fit::Degree;
+use MouseTesterRust::formats;
 use MouseTesterRust::lod::{build_segment_tree, collect_for_view};
 use MouseTesterRust::mouse_event::MouseMoveEvent;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::path::Path;
 
 struct DatasetInfo {
     name: String,
@@ -17,41 +18,14 @@ struct DatasetInfo {
     avg_report_rate: f64,
 }
 
-fn load_csv(path: &str) -> Result<Vec<MouseMoveEvent>, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut events = Vec::new();
-
-    for (i, line) in reader.lines().enumerate() {
-        if i == 0 {
-            continue; // Skip header
-        }
-
-        let line = line?;
-        if line.trim().is_empty() || line.starts_with('#') {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() >= 3 {
-            let dx: i16 = parts[0].trim().parse()?;
-            let dy: i16 = parts[1].trim().parse()?;
-            let time: f64 = parts[2].trim().parse()?;
-
-            // Convert time to pcap format (seconds + microseconds)
-            let ts_sec = time.floor() as u32;
-            let ts_usec = ((time.fract() * 1_000_000.0) as u32);
-
-            events.push(MouseMoveEvent::new(dx, dy, ts_sec, ts_usec));
-        }
-    }
-
-    Ok(events)
+/// Load a capture (CSV or session file) with exact microsecond timestamps
+fn load_events(path: &str) -> Result<Vec<MouseMoveEvent>, Box<dyn std::error::Error>> {
+    Ok(formats::load_events(Path::new(path), None, None)?)
 }
 
 fn analyze_dataset(name: &str, path: &str) -> Result<DatasetInfo, Box<dyn std::error::Error>> {
     println!("\n=== Analyzing: {} ===", name);
-    let events = load_csv(path)?;
+    let events = load_events(path)?;
 
     if events.is_empty() {
         println!("  No events found!");
@@ -87,7 +61,7 @@ fn test_lod_parameters(dataset: &DatasetInfo) {
     let test_configs = vec![(5, 1.0, "Conservative"), (10, 1.0, "Moderate"), (5, 0.5, "Aggressive"), (10, 0.5, "Very Aggressive"), (3, 1.5, "Preserve Detail")];
 
     for (min_pts, tol_px, name) in test_configs {
-        let tree = build_segment_tree(&dataset.events, 0, dataset.events.len(), min_pts, 1000, 1.0, tol_px);
+        let tree = build_segment_tree(&dataset.events, 0, dataset.events.len(), min_pts, 1000, 1.0, tol_px, Degree::Cubic);
 
         // Test at different view tolerances
         let view_tolerances = [0.5, 1.0, 2.0, 5.0];
@@ -179,6 +153,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=================================================");
 
     println!("\nBased on the analysis of {} datasets:", dataset_infos.len());
+    for info in &dataset_infos {
+        println!("  - {}: {} events over {:.3}s", info.name, info.events.len(), info.time_span);
+    }
 
     // Calculate average report rates
     let avg_rate: f64 = dataset_infos.iter().map(|d| d.avg_report_rate).sum::<f64>() / dataset_infos.len() as f64;
@@ -7,6 +7,7 @@
 //!
 //! Run with: cargo run --example lod_demo
 
+use MouseTesterRust::fit::Degree;
 use MouseTesterRust::lod::{build_segment_tree, collect_for_view};
 use MouseTesterRust::mouse_event::MouseMoveEvent;
 
@@ -45,7 +46,7 @@ fn main() {
     println!("  px_scale: {}", px_scale);
     println!("  tol_px: {}\n", tol_px);
 
-    let tree = build_segment_tree(&events, 0, events.len(), min_pts, max_pts, px_scale, tol_px);
+    let tree = build_segment_tree(&events, 0, events.len(), min_pts, max_pts, px_scale, tol_px, Degree::Cubic);
 
     println!("Segment tree built successfully!");
     println!("  Root segment: [{}, {})", tree.start, tree.end);
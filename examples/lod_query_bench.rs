@@ -0,0 +1,75 @@
+//! Viewport query benchmark for the advanced LOD
+//!
+//! Builds segments for a capture once, then times `collect_visible_indices`
+//! (full scan) and `collect_visible_indices_indexed` at several zoom levels,
+//! each view centred on the middle of the capture. Like the GUI, every frame
+//! queries the dx and the -dy line. The indexed query should
+//! stay inside `QUERY_BUDGET` at every zoom level and get cheaper as the
+//! view narrows; the full scan costs about the same at every zoom level.
+//!
+//! Run with: cargo run --release --example lod_query_bench [capture.csv]
+
+use MouseTesterRust::formats;
+use MouseTesterRust::lod_advanced::{SegmentIndex, SegmentParams, Series, build_segments, collect_visible_indices, collect_visible_indices_indexed};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CAPTURE: &str = "examples/test/output-20kSensor_8kReport.csv";
+/// Plot area of the GUI at its default window size
+const RENDER_SIZE: (f64, f64) = (1000.0, 500.0);
+/// Frame budget at 60 fps
+const FRAME_BUDGET: Duration = Duration::from_micros(16_667);
+/// Share of the frame a query may take, leaving the rest for drawing
+const QUERY_BUDGET: Duration = Duration::from_micros(4_000);
+
+/// Mean time of `f` over enough runs to fill about 200 ms
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let started = Instant::now();
+    let mut result = f();
+    let mut runs = 1u32;
+    while started.elapsed() < Duration::from_millis(200) {
+        result = f();
+        runs += 1;
+    }
+    (started.elapsed() / runs, result)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_CAPTURE.to_string());
+    let events = formats::load_events(Path::new(&path), None, None)?;
+    if events.is_empty() {
+        return Err(format!("{} has no events", path).into());
+    }
+    let (t0, t1) = (events[0].time_secs(), events.last().unwrap().time_secs());
+    println!("{}: {} events over {:.3} s", path, events.len(), t1 - t0);
+
+    let p = SegmentParams::default();
+    let started = Instant::now();
+    let segments = build_segments(&events, p.initial_size, p.growth_factor, p.min_r_squared, p.balance_weight, p.degree);
+    println!("build_segments: {} segments in {:.2?}", segments.len(), started.elapsed());
+    let started = Instant::now();
+    let index = SegmentIndex::new(&segments, &events);
+    println!("SegmentIndex::new: {:.2?}", started.elapsed());
+
+    let y_range = events.iter().flat_map(|e| [e.dx as f64, -(e.dy as f64)]).fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let (width, height) = RENDER_SIZE;
+    println!("\nQuery budget {:.2?} of a {:.2?} frame (60 fps), {}x{} px plot", QUERY_BUDGET, FRAME_BUDGET, width, height);
+    println!("{:>12} {:>10} {:>12} {:>12} {:>9} {:>6}", "view", "points", "full scan", "indexed", "speedup", "target");
+    let mid = (t0 + t1) / 2.0;
+    let mut all_ok = true;
+    for span in [t1 - t0, 1.0, 0.1, 0.01, 0.001] {
+        let x_range = (mid - span / 2.0, mid + span / 2.0);
+        let (full_time, full) = time(|| [Series::Dx, Series::NegDy].map(|series| collect_visible_indices(&segments, &events, series, width, height, x_range, y_range, 3.0, 1.2)));
+        let (indexed_time, indexed) = time(|| [Series::Dx, Series::NegDy].map(|series| collect_visible_indices_indexed(&index, &segments, &events, series, width, height, x_range, y_range, 3.0, 1.2)));
+        assert_eq!(full, indexed, "indexed query differs from the full scan");
+        let points = indexed[0].len() + indexed[1].len();
+        let ok = indexed_time <= QUERY_BUDGET;
+        all_ok &= ok;
+        let view = if span >= 1.0 { format!("{:.1} s", span) } else { format!("{:.0} ms", span * 1000.0) };
+        println!("{:>12} {:>10} {:>12.2?} {:>12.2?} {:>8.1}x {:>6}", view, points, full_time, indexed_time, full_time.as_secs_f64() / indexed_time.as_secs_f64().max(1e-12), if ok { "ok" } else { "MISS" });
+    }
+    if !all_ok {
+        println!("\nSome indexed queries exceeded the {:.2?} budget", QUERY_BUDGET);
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+//! Pcap parser throughput benchmark
+//!
+//! Synthesizes a USBPcap stream with several mice reporting at 8 kHz and
+//! measures how fast it is parsed and decoded, compared with real time.
+//! A copy of the previous `Vec::drain` based parser is timed as a baseline.
+//!
+//! Run with: cargo run --release --example pcap_throughput [devices] [seconds]
+
+use MouseTesterRust::capture::{PcapStreamParser, TargetDevice, USBPCAP_HEADER_LEN, capture_from_reader};
+use MouseTesterRust::mouse_event::PcapRecordHeader;
+use std::io::Read;
+use std::sync::{Arc, atomic::AtomicBool};
+use std::time::Instant;
+
+const REPORT_RATE_HZ: u32 = 8000;
+/// Read size of a pipe read, similar to USBPcapCMD's `-b 262144` buffer
+const READ_CHUNK: usize = 65536;
+
+fn global_header() -> Vec<u8> {
+    let mut v = Vec::new();
+    v.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+    v.extend_from_slice(&2u16.to_le_bytes());
+    v.extend_from_slice(&4u16.to_le_bytes());
+    v.extend_from_slice(&0i32.to_le_bytes());
+    v.extend_from_slice(&0u32.to_le_bytes());
+    v.extend_from_slice(&65535u32.to_le_bytes());
+    v.extend_from_slice(&249u32.to_le_bytes());
+    v
+}
+
+fn push_record(out: &mut Vec<u8>, ts_sec: u32, ts_usec: u32, device: u16, dx: i16, dy: i16) {
+    let incl_len = (USBPCAP_HEADER_LEN + 8) as u32;
+    out.extend_from_slice(&ts_sec.to_le_bytes());
+    out.extend_from_slice(&ts_usec.to_le_bytes());
+    out.extend_from_slice(&incl_len.to_le_bytes());
+    out.extend_from_slice(&incl_len.to_le_bytes());
+    out.extend_from_slice(&(USBPCAP_HEADER_LEN as u16).to_le_bytes());
+    out.extend_from_slice(&0u64.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0x0009u16.to_le_bytes());
+    out.push(1); // IN
+    out.extend_from_slice(&1u16.to_le_bytes()); // bus
+    out.extend_from_slice(&device.to_le_bytes());
+    out.push(0x81);
+    out.push(1);
+    out.extend_from_slice(&8u32.to_le_bytes());
+    let (dx, dy) = (dx.to_le_bytes(), dy.to_le_bytes());
+    out.extend_from_slice(&[0x01, 0x00, dx[0], dx[1], dy[0], dy[1], 0x00, 0x00]);
+}
+
+fn synthesize(devices: u16, seconds: u32) -> Vec<u8> {
+    let reports = (REPORT_RATE_HZ * seconds) as usize;
+    let mut stream = global_header();
+    stream.reserve(reports * devices as usize * (16 + USBPCAP_HEADER_LEN + 8));
+    for i in 0..reports {
+        let micros = i as u64 * 1_000_000 / REPORT_RATE_HZ as u64;
+        for device in 1..=devices {
+            push_record(&mut stream, (micros / 1_000_000) as u32, (micros % 1_000_000) as u32, device, (i % 37) as i16 - 18, (i % 23) as i16 - 11);
+        }
+    }
+    stream
+}
+
+/// The parser as it was before: append, parse, then `drain` consumed bytes
+fn legacy_drain_parse(stream: &[u8]) -> usize {
+    let mut buffer: Vec<u8> = Vec::with_capacity(262144);
+    let mut skipped_global = false;
+    let mut records = 0;
+    for chunk in stream.chunks(READ_CHUNK) {
+        buffer.extend_from_slice(chunk);
+        let mut offset = 0;
+        if !skipped_global {
+            buffer.drain(0..24);
+            skipped_global = true;
+        }
+        while offset + 16 <= buffer.len() {
+            let Some((hdr, size)) = PcapRecordHeader::parse(&buffer[offset..]) else { break };
+            let total = offset + size + hdr.incl_len as usize;
+            if buffer.len() < total {
+                break;
+            }
+            records += 1;
+            offset = total;
+        }
+        buffer.drain(0..offset);
+    }
+    records
+}
+
+/// `Read` adapter returning at most `READ_CHUNK` bytes per call, like a pipe
+struct PipeLike<'a>(&'a [u8]);
+
+impl Read for PipeLike<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(READ_CHUNK).min(self.0.len());
+        buf[..n].copy_from_slice(&self.0[..n]);
+        self.0 = &self.0[n..];
+        Ok(n)
+    }
+}
+
+fn report(name: &str, records: usize, bytes: usize, secs: f64, captured_secs: u32) {
+    println!(
+        "{:<28} {:>10} records  {:>8.3} s  {:>7.2} M records/s  {:>8.1} MB/s  {:>7.0}x real time",
+        name,
+        records,
+        secs,
+        records as f64 / secs / 1e6,
+        bytes as f64 / secs / 1e6,
+        captured_secs as f64 / secs
+    );
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let devices: u16 = args.next().and_then(|a| a.parse().ok()).unwrap_or(4);
+    let seconds: u32 = args.next().and_then(|a| a.parse().ok()).unwrap_or(60);
+
+    println!("Synthesizing {} devices x {} Hz x {} s...", devices, REPORT_RATE_HZ, seconds);
+    let stream = synthesize(devices, seconds);
+    let expected = (REPORT_RATE_HZ * seconds) as usize * devices as usize;
+    println!("Stream: {:.1} MB, {} records\n", stream.len() as f64 / 1e6, expected);
+
+    let t = Instant::now();
+    let records = legacy_drain_parse(&stream);
+    report("legacy drain parser", records, stream.len(), t.elapsed().as_secs_f64(), seconds);
+    assert_eq!(records, expected);
+
+    let t = Instant::now();
+    let mut parser = PcapStreamParser::new();
+    let mut reader = PipeLike(&stream);
+    let mut records = 0;
+    while parser.read_from(&mut reader).unwrap() > 0 {
+        while parser.next_record().is_some() {
+            records += 1;
+        }
+    }
+    report("PcapStreamParser", records, stream.len(), t.elapsed().as_secs_f64(), seconds);
+    assert_eq!(records, expected);
+
+    let t = Instant::now();
+    let mut events = Vec::new();
+    let target = TargetDevice { bus_id: 1, device_address: 1, endpoint: 1 };
+    capture_from_reader(PipeLike(&stream), &mut events, Arc::new(AtomicBool::new(false)), Some(target)).unwrap();
+    report("parse + decode + filter", expected, stream.len(), t.elapsed().as_secs_f64(), seconds);
+    assert_eq!(events.len(), (REPORT_RATE_HZ * seconds) as usize);
+}
@@ -0,0 +1,466 @@
+//! Summary statistics for captured mouse movement events
+//!
+//! This module computes the numbers shown by the CLI summary, the GUI statistics
+//! panel and the CSV summary footer: totals, rates, the movement magnitude
+//! histogram and report interval statistics. It also splits captures into
+//! strokes and finds timing and movement anomalies for the analysis report.
+
+use crate::mouse_event::MouseMoveEvent;
+use serde::{Deserialize, Serialize};
+
+/// Number of buckets in the movement magnitude histogram
+pub const HISTOGRAM_BUCKETS: usize = 12;
+
+/// Summary statistics for a list of events
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub count: usize,
+    /// Time span between first and last event in seconds
+    pub duration: f64,
+    pub total_dx: i64,
+    pub total_dy: i64,
+    /// Sum of step lengths sqrt(dx^2 + dy^2)
+    pub total_distance: f64,
+    pub avg_distance_per_event: f64,
+    /// Average speed in units per second
+    pub avg_speed: f64,
+    pub events_per_sec: f64,
+    /// Movement magnitude histogram with `HISTOGRAM_BUCKETS` buckets
+    pub histogram: Vec<usize>,
+    /// Width of each histogram bucket
+    pub bucket_size: f64,
+    /// Report interval statistics in microseconds
+    pub interval: IntervalStats,
+}
+
+/// Statistics of the time between consecutive reports, in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntervalStats {
+    pub min_us: u64,
+    pub max_us: u64,
+    pub mean_us: f64,
+    pub std_dev_us: f64,
+}
+
+impl IntervalStats {
+    /// Compute interval statistics from consecutive event timestamps
+    pub fn from_events(events: &[MouseMoveEvent]) -> Self {
+        if events.len() < 2 {
+            return Self::default();
+        }
+
+        let intervals: Vec<u64> = events.windows(2).map(|w| w[1].time_micros().saturating_sub(w[0].time_micros())).collect();
+        let n = intervals.len() as f64;
+        let mean_us = intervals.iter().sum::<u64>() as f64 / n;
+        let variance = intervals.iter().map(|&d| (d as f64 - mean_us).powi(2)).sum::<f64>() / n;
+
+        Self {
+            min_us: intervals.iter().copied().min().unwrap_or(0),
+            max_us: intervals.iter().copied().max().unwrap_or(0),
+            mean_us,
+            std_dev_us: variance.sqrt(),
+        }
+    }
+}
+
+/// Movement magnitude sqrt(dx^2 + dy^2) of a single event
+pub fn magnitude(e: &MouseMoveEvent) -> f64 {
+    ((e.dx as f64).powi(2) + (e.dy as f64).powi(2)).sqrt()
+}
+
+/// Compute summary statistics for the given events
+pub fn compute_stats(events: &[MouseMoveEvent]) -> Stats {
+    compute_stats_with(|| events.iter().copied())
+}
+
+/// Compute summary statistics from a re-iterable event source
+///
+/// `events` is called twice: once for totals, time range and the largest
+/// magnitude, then for the histogram and interval variance. This lets sources
+/// such as on-disk sessions be analyzed without loading them into memory, with
+/// the same results as `compute_stats`.
+pub fn compute_stats_with<F, I>(mut events: F) -> Stats
+where
+    F: FnMut() -> I,
+    I: Iterator<Item = MouseMoveEvent>,
+{
+    let mut count = 0usize;
+    let mut time_start = f64::INFINITY;
+    let mut time_end = f64::NEG_INFINITY;
+    let (mut total_dx, mut total_dy) = (0i64, 0i64);
+    let mut total_distance = 0.0;
+    let mut max_magnitude = 0.0f64;
+    let (mut interval_sum, mut interval_min, mut interval_max) = (0u64, u64::MAX, 0u64);
+    let mut prev_micros: Option<u64> = None;
+
+    for e in events() {
+        count += 1;
+        time_start = time_start.min(e.time_secs());
+        time_end = time_end.max(e.time_secs());
+        total_dx += e.dx as i64;
+        total_dy += e.dy as i64;
+        let m = magnitude(&e);
+        total_distance += m;
+        max_magnitude = max_magnitude.max(m);
+        if let Some(prev) = prev_micros {
+            let d = e.time_micros().saturating_sub(prev);
+            interval_sum += d;
+            interval_min = interval_min.min(d);
+            interval_max = interval_max.max(d);
+        }
+        prev_micros = Some(e.time_micros());
+    }
+
+    if count == 0 {
+        return Stats::default();
+    }
+
+    let duration = (time_end - time_start).max(0.0);
+    let avg_distance_per_event = total_distance / (count as f64);
+    let avg_speed = if duration > 0.0 { total_distance / duration } else { 0.0 };
+    let events_per_sec = if duration > 0.0 { count as f64 / duration } else { 0.0 };
+
+    // Second pass: histogram buckets and interval variance need the first pass results
+    let mut histogram = vec![0usize; HISTOGRAM_BUCKETS];
+    let bucket_size = bucket_size_for(max_magnitude, HISTOGRAM_BUCKETS);
+    let interval_count = count - 1;
+    let mean_us = if interval_count > 0 { interval_sum as f64 / interval_count as f64 } else { 0.0 };
+    let mut variance_sum = 0.0;
+    let mut prev_micros: Option<u64> = None;
+    for e in events() {
+        histogram[bucket_index(magnitude(&e), bucket_size, HISTOGRAM_BUCKETS)] += 1;
+        if let Some(prev) = prev_micros {
+            variance_sum += (e.time_micros().saturating_sub(prev) as f64 - mean_us).powi(2);
+        }
+        prev_micros = Some(e.time_micros());
+    }
+
+    let interval = if interval_count > 0 {
+        IntervalStats {
+            min_us: interval_min,
+            max_us: interval_max,
+            mean_us,
+            std_dev_us: (variance_sum / interval_count as f64).sqrt(),
+        }
+    } else {
+        IntervalStats::default()
+    };
+
+    Stats {
+        count,
+        duration,
+        total_dx,
+        total_dy,
+        total_distance,
+        avg_distance_per_event,
+        avg_speed,
+        events_per_sec,
+        histogram,
+        bucket_size,
+        interval,
+    }
+}
+
+/// Idle time that ends a stroke, in microseconds
+pub const STROKE_IDLE_GAP_US: u64 = 100_000;
+
+/// A report interval within a stroke this many times the polling interval is a gap
+pub const GAP_FACTOR: f64 = 4.0;
+
+/// An event this many times larger than both neighbours is a spike
+pub const SPIKE_FACTOR: f64 = 4.0;
+
+/// Smallest magnitude reported as a spike, in counts
+pub const SPIKE_MIN_MAGNITUDE: f64 = 20.0;
+
+/// A run of events without an idle gap of `STROKE_IDLE_GAP_US` or more
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    /// Index of the first event of the stroke
+    pub first_event: usize,
+    /// Index of the last event of the stroke (inclusive)
+    pub last_event: usize,
+    /// Time of the first and last event in seconds, like the CSV time column
+    pub start: f64,
+    pub end: f64,
+    pub events: usize,
+    pub total_dx: i64,
+    pub total_dy: i64,
+    /// Sum of step lengths
+    pub distance: f64,
+    /// Average speed in units per second, 0 for single-event strokes
+    pub avg_speed: f64,
+    /// Largest single-event magnitude
+    pub max_step: f64,
+}
+
+impl Stroke {
+    fn start_at(index: usize, e: &MouseMoveEvent) -> Self {
+        Self { first_event: index, last_event: index, start: e.time_secs(), end: e.time_secs(), ..Default::default() }.extended(index, e)
+    }
+
+    fn extended(mut self, index: usize, e: &MouseMoveEvent) -> Self {
+        let m = magnitude(e);
+        self.last_event = index;
+        self.end = e.time_secs();
+        self.events += 1;
+        self.total_dx += e.dx as i64;
+        self.total_dy += e.dy as i64;
+        self.distance += m;
+        self.max_step = self.max_step.max(m);
+        self
+    }
+
+    fn finished(mut self) -> Self {
+        let duration = self.end - self.start;
+        self.avg_speed = if duration > 0.0 { self.distance / duration } else { 0.0 };
+        self
+    }
+}
+
+/// Split events into strokes separated by idle gaps of at least `STROKE_IDLE_GAP_US`
+pub fn find_strokes<I: IntoIterator<Item = MouseMoveEvent>>(events: I) -> Vec<Stroke> {
+    let mut strokes = Vec::new();
+    let mut current: Option<Stroke> = None;
+    let mut prev_micros = 0u64;
+
+    for (i, e) in events.into_iter().enumerate() {
+        current = Some(match current.take() {
+            Some(stroke) if e.time_micros().saturating_sub(prev_micros) < STROKE_IDLE_GAP_US => stroke.extended(i, &e),
+            Some(stroke) => {
+                strokes.push(stroke.finished());
+                Stroke::start_at(i, &e)
+            }
+            None => Stroke::start_at(i, &e),
+        });
+        prev_micros = e.time_micros();
+    }
+    strokes.extend(current.map(Stroke::finished));
+    strokes
+}
+
+/// Kind of problem found by `find_anomalies_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// Timestamp earlier than the previous event; `value` is how much, in microseconds
+    TimeReversal,
+    /// Same timestamp as the previous event
+    DuplicateTimestamp,
+    /// Missing reports during a stroke; `value` is the interval in microseconds
+    ReportGap,
+    /// Single event much larger than its neighbours; `value` is its magnitude
+    Spike,
+}
+
+/// A suspicious event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    /// Index of the event
+    pub event: usize,
+    /// Time of the event in seconds
+    pub time: f64,
+    pub value: f64,
+}
+
+/// Find timing and movement anomalies from a re-iterable event source
+///
+/// The first pass estimates the polling interval as the mean interval within
+/// strokes; the second flags gaps longer than `GAP_FACTOR` times that, duplicate
+/// or backwards timestamps, and spikes of `SPIKE_FACTOR` times both neighbours
+/// within a stroke.
+pub fn find_anomalies_with<F, I>(mut events: F) -> Vec<Anomaly>
+where
+    F: FnMut() -> I,
+    I: Iterator<Item = MouseMoveEvent>,
+{
+    let (mut sum, mut n) = (0u64, 0u64);
+    let mut prev: Option<u64> = None;
+    for e in events() {
+        if let Some(p) = prev {
+            let d = e.time_micros().saturating_sub(p);
+            if d > 0 && d < STROKE_IDLE_GAP_US {
+                sum += d;
+                n += 1;
+            }
+        }
+        prev = Some(e.time_micros());
+    }
+    let polling_us = if n > 0 { sum as f64 / n as f64 } else { 0.0 };
+
+    let mut anomalies = Vec::new();
+    // Previous two events as (index, event, interval to the one before, in microseconds)
+    let mut window: [Option<(usize, MouseMoveEvent, u64)>; 2] = [None, None];
+    for (i, e) in events().enumerate() {
+        let interval = match &window[1] {
+            Some((_, p, _)) => {
+                let (t, pt) = (e.time_micros(), p.time_micros());
+                if t < pt {
+                    anomalies.push(Anomaly { kind: AnomalyKind::TimeReversal, event: i, time: e.time_secs(), value: (pt - t) as f64 });
+                } else if t == pt {
+                    anomalies.push(Anomaly { kind: AnomalyKind::DuplicateTimestamp, event: i, time: e.time_secs(), value: 0.0 });
+                } else if polling_us > 0.0 && t - pt < STROKE_IDLE_GAP_US && (t - pt) as f64 > GAP_FACTOR * polling_us {
+                    anomalies.push(Anomaly { kind: AnomalyKind::ReportGap, event: i, time: e.time_secs(), value: (t - pt) as f64 });
+                }
+                t.saturating_sub(pt)
+            }
+            None => u64::MAX,
+        };
+
+        // The middle event is a spike when it dwarfs both neighbours within the same stroke
+        if let (Some((_, before, _)), Some((mid_idx, mid, mid_interval))) = (&window[0], &window[1])
+            && *mid_interval < STROKE_IDLE_GAP_US
+            && interval < STROKE_IDLE_GAP_US
+        {
+            let m = magnitude(mid);
+            if m >= SPIKE_MIN_MAGNITUDE && m > SPIKE_FACTOR * magnitude(before).max(magnitude(&e)) {
+                anomalies.push(Anomaly { kind: AnomalyKind::Spike, event: *mid_idx, time: mid.time_secs(), value: m });
+            }
+        }
+
+        window = [window[1].take(), Some((i, e, interval))];
+    }
+    anomalies.sort_by_key(|a| a.event);
+    anomalies
+}
+
+/// Histogram of report intervals within strokes, in microseconds
+///
+/// Idle gaps of `STROKE_IDLE_GAP_US` or more are not report intervals and are
+/// left out. Returns the bucket counts and the bucket width.
+pub fn interval_histogram(events: &[MouseMoveEvent], bucket_count: usize) -> (Vec<usize>, f64) {
+    let intervals: Vec<f64> = events.windows(2).map(|w| w[1].time_micros().saturating_sub(w[0].time_micros())).filter(|&d| d < STROKE_IDLE_GAP_US).map(|d| d as f64).collect();
+    histogram(&intervals, bucket_count)
+}
+
+/// Convert a distance in mouse counts to millimetres at the given CPI
+pub fn counts_to_mm(counts: f64, cpi: u32) -> f64 {
+    counts / cpi as f64 * 25.4
+}
+
+/// Bucket non-negative values into `bucket_count` equal-width buckets starting at 0
+///
+/// Returns the bucket counts and the bucket width. Values at the maximum land
+/// in the last bucket.
+pub fn histogram(values: &[f64], bucket_count: usize) -> (Vec<usize>, f64) {
+    let max_val = values.iter().copied().fold(0.0f64, |a, b| a.max(b));
+    let mut buckets = vec![0usize; bucket_count];
+    let bucket_size = bucket_size_for(max_val, bucket_count);
+
+    for &v in values {
+        buckets[bucket_index(v, bucket_size, bucket_count)] += 1;
+    }
+
+    (buckets, bucket_size)
+}
+
+fn bucket_size_for(max_val: f64, bucket_count: usize) -> f64 {
+    if max_val <= 0.0 { 1.0 } else { max_val / (bucket_count as f64) }
+}
+
+fn bucket_index(v: f64, bucket_size: f64, bucket_count: usize) -> usize {
+    let idx = (v / bucket_size).floor() as isize;
+    idx.max(0).min((bucket_count - 1) as isize) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compute_stats_empty() {
+        let stats = compute_stats(&[]);
+        assert_eq!(stats.count, 0);
+        assert!(stats.histogram.is_empty());
+    }
+
+    #[test]
+    fn test_compute_stats_basic() {
+        let events = vec![MouseMoveEvent::new(3, 4, 0, 0), MouseMoveEvent::new(0, 0, 0, 500_000), MouseMoveEvent::new(-6, 8, 1, 0)];
+        let stats = compute_stats(&events);
+
+        assert_eq!(stats.count, 3);
+        assert!((stats.duration - 1.0).abs() < 1e-9);
+        assert_eq!(stats.total_dx, -3);
+        assert_eq!(stats.total_dy, 12);
+        assert!((stats.total_distance - 15.0).abs() < 1e-9);
+        assert!((stats.avg_speed - 15.0).abs() < 1e-9);
+        assert!((stats.events_per_sec - 3.0).abs() < 1e-9);
+        assert_eq!(stats.histogram.len(), HISTOGRAM_BUCKETS);
+        assert_eq!(stats.histogram.iter().sum::<usize>(), 3);
+        // max magnitude 10 goes into the last bucket
+        assert_eq!(stats.histogram[HISTOGRAM_BUCKETS - 1], 1);
+    }
+
+    #[test]
+    fn test_interval_stats() {
+        let events = vec![MouseMoveEvent::new(1, 0, 0, 0), MouseMoveEvent::new(1, 0, 0, 125), MouseMoveEvent::new(1, 0, 0, 375)];
+        let interval = IntervalStats::from_events(&events);
+
+        assert_eq!(interval.min_us, 125);
+        assert_eq!(interval.max_us, 250);
+        assert!((interval.mean_us - 187.5).abs() < 1e-9);
+        assert!((interval.std_dev_us - 62.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_streaming_stats_match_slice_stats() {
+        let events: Vec<MouseMoveEvent> = (0..500u32).map(|i| MouseMoveEvent::new((i % 17) as i16 - 8, (i % 5) as i16, i / 1000, (i % 1000) * 1000 + (i % 3) * 7)).collect();
+        let a = compute_stats(&events);
+        let b = compute_stats_with(|| events.clone().into_iter());
+        assert_eq!(a.histogram, b.histogram);
+        assert_eq!((a.count, a.total_dx, a.total_dy), (b.count, b.total_dx, b.total_dy));
+        assert_eq!(a.interval.min_us, b.interval.min_us);
+        assert!((a.interval.std_dev_us - b.interval.std_dev_us).abs() < 1e-9);
+        assert!((a.avg_speed - b.avg_speed).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_find_strokes_splits_on_idle_gaps() {
+        // Two strokes 1 ms apart internally, separated by half a second of idle
+        let events = [
+            MouseMoveEvent::new(3, 4, 0, 0),
+            MouseMoveEvent::new(3, 4, 0, 1_000),
+            MouseMoveEvent::new(0, 1, 0, 500_000),
+            MouseMoveEvent::new(0, 2, 0, 501_000),
+            MouseMoveEvent::new(0, 3, 0, 502_000),
+        ];
+        let strokes = find_strokes(events.iter().copied());
+        assert_eq!(strokes.len(), 2);
+        assert_eq!((strokes[0].first_event, strokes[0].last_event, strokes[0].events), (0, 1, 2));
+        assert!((strokes[0].distance - 10.0).abs() < 1e-9);
+        assert!((strokes[0].avg_speed - 10_000.0).abs() < 1e-6);
+        assert_eq!((strokes[1].first_event, strokes[1].last_event, strokes[1].total_dy), (2, 4, 6));
+        assert_eq!(strokes[1].max_step, 3.0);
+        assert!(find_strokes(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_find_anomalies() {
+        // 1 ms polling with a 10 ms gap, a duplicate, a reversed timestamp and a spike
+        let us = [0, 1_000, 2_000, 12_000, 13_000, 13_000, 12_500, 14_000, 15_000, 16_000, 17_000];
+        let dx = [2, 2, 2, 2, 2, 2, 2, 2, 90, 2, 2];
+        let events: Vec<MouseMoveEvent> = us.iter().zip(dx).map(|(&t, dx)| MouseMoveEvent::new(dx, 0, 0, t)).collect();
+        let anomalies = find_anomalies_with(|| events.iter().copied());
+        let kinds: Vec<(AnomalyKind, usize)> = anomalies.iter().map(|a| (a.kind, a.event)).collect();
+        assert_eq!(kinds, vec![(AnomalyKind::ReportGap, 3), (AnomalyKind::DuplicateTimestamp, 5), (AnomalyKind::TimeReversal, 6), (AnomalyKind::Spike, 8)]);
+        assert_eq!(anomalies[0].value, 10_000.0);
+        assert_eq!(anomalies[2].value, 500.0);
+        assert!((anomalies[3].time - 0.015).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interval_histogram_skips_idle_gaps() {
+        let events = [MouseMoveEvent::new(1, 0, 0, 0), MouseMoveEvent::new(1, 0, 0, 125), MouseMoveEvent::new(1, 0, 0, 250), MouseMoveEvent::new(1, 0, 0, 500), MouseMoveEvent::new(1, 0, 1, 0)];
+        let (buckets, width) = interval_histogram(&events, 4);
+        assert_eq!(width, 62.5);
+        assert_eq!(buckets, vec![0, 0, 2, 1]);
+    }
+
+    #[test]
+    fn test_counts_to_mm() {
+        assert!((counts_to_mm(1600.0, 1600) - 25.4).abs() < 1e-9);
+        assert!((counts_to_mm(400.0, 800) - 12.7).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,1420 @@
+//! USB capture module for USBPcap pcap streams
+//!
+//! This module turns the raw pcap byte stream produced by `USBPcapCMD` into
+//! `MouseMoveEvent`s. It is split into small pieces so other tools can reuse
+//! the parsing without the capture front end:
+//!
+//! - `UsbPcapHeader`: the USBPcap per-packet header that precedes each USB payload
+//! - `PcapStreamParser`: incremental parser that yields borrowed pcap records without
+//!   copying record data, and resynchronizes after corrupt record headers
+//! - `TargetDevice`: `bus.device.endpoint` filter for selecting a single mouse
+//! - `ReportDecoder`: filters records and converts HID reports into events with relative timestamps
+//! - `EventSink`: destination for decoded events (a `Vec`, shared storage, ...)
+//! - `StopConditions` / `StopMonitor`: automatic stop after a duration, event count
+//!   or idle period, optionally armed by the first motion
+//! - `RawTee`: saves the incoming pcap stream (or only the target device's records)
+//!   for inspection in Wireshark or a later replay
+//!
+//! `run_capture` wires these together around a `USBPcapCMD` child process, and
+//! `capture_from_reader` does the same for any `Read` source (e.g. a saved pcap file).
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use MouseTesterRust::capture::{capture_from_reader, parse_target_device};
+//! use std::sync::{Arc, atomic::AtomicBool};
+//!
+//! let file = std::fs::File::open("capture.pcap")?;
+//! let target = parse_target_device("1.2.1")?;
+//! let mut events = Vec::new();
+//! capture_from_reader(file, &mut events, Arc::new(AtomicBool::new(false)), Some(target))?;
+//! ```
+
+use crate::key_utils;
+use crate::mouse_event::{self, MouseMoveEvent, PcapRecordHeader};
+use anyhow::{Context, Result, anyhow, bail};
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Cursor, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Size of the pcap global header at the start of every stream
+pub const PCAP_GLOBAL_HEADER_LEN: usize = 24;
+/// Size of a pcap record header
+pub const PCAP_RECORD_HEADER_LEN: usize = 16;
+/// Size of the USBPcap packet header as parsed by `UsbPcapHeader::parse`
+pub const USBPCAP_HEADER_LEN: usize = 27;
+/// Default location of the USBPcap command line tool
+pub const DEFAULT_USBPCAP_CMD: &str = r"C:\Program Files\USBPcap\USBPcapCMD.exe";
+/// Default USBPcap root hub interface
+pub const DEFAULT_USBPCAP_INTERFACE: &str = r"\\.\USBPcap1";
+
+/// USBPcap packet header preceding each captured USB transfer
+#[derive(Debug, Clone, Copy)]
+pub struct UsbPcapHeader {
+    pub header_len: u16,
+    pub irp_id: u64,
+    pub status: u32,
+    pub function: u16,
+    pub info: u8,
+    pub bus_id: u16,
+    pub device_address: u16,
+    pub endpoint: u8,
+    pub direction_in: bool,
+    pub transfer_type: u8,
+    pub data_length: u32,
+}
+
+impl UsbPcapHeader {
+    /// Parse a UsbPcapHeader from raw bytes
+    ///
+    /// Returns the header and the number of bytes consumed, or None if the
+    /// data is too short.
+    pub fn parse(data: &[u8]) -> Option<(Self, usize)> {
+        if data.len() < USBPCAP_HEADER_LEN {
+            return None;
+        }
+        let mut cur = Cursor::new(data);
+
+        let header_len = cur.read_u16::<LittleEndian>().ok()?;
+        let irp_id = cur.read_u64::<LittleEndian>().ok()?;
+        let status = cur.read_u32::<LittleEndian>().ok()?;
+        let function = cur.read_u16::<LittleEndian>().ok()?;
+        let info = cur.read_u8().ok()?;
+        let bus_id = cur.read_u16::<LittleEndian>().ok()?;
+        let device_address = cur.read_u16::<LittleEndian>().ok()?;
+        let raw_endpoint = cur.read_u8().ok()?;
+        let transfer_type = cur.read_u8().ok()?;
+        let data_length = cur.read_u32::<LittleEndian>().ok()?;
+
+        let direction_in = (raw_endpoint & 0x80) != 0;
+        let endpoint_number = raw_endpoint & 0x7F;
+        Some((
+            UsbPcapHeader {
+                header_len,
+                irp_id,
+                status,
+                function,
+                info,
+                bus_id,
+                device_address,
+                endpoint: endpoint_number,
+                direction_in,
+                transfer_type,
+                data_length,
+            },
+            USBPCAP_HEADER_LEN,
+        ))
+    }
+
+    /// Whether the transfer travels from the device to the host (PDO -> FDO)
+    pub fn is_in_direction(&self) -> bool {
+        self.info == 1u8
+    }
+}
+
+/// USB device selector in `bus.device.endpoint` form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TargetDevice {
+    pub bus_id: u16,
+    pub device_address: u16,
+    pub endpoint: u8,
+}
+
+impl TargetDevice {
+    /// Check whether a USBPcap header belongs to this device
+    pub fn matches(&self, usb_hdr: &UsbPcapHeader) -> bool {
+        self.bus_id == usb_hdr.bus_id && self.device_address == usb_hdr.device_address && self.endpoint == usb_hdr.endpoint
+    }
+}
+
+impl FromStr for TargetDevice {
+    type Err = anyhow::Error;
+
+    fn from_str(arg: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid device format: {}, expected bus.device.endpoint (e.g. 1.2.1)", arg);
+        let parts: Vec<&str> = arg.split('.').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        Ok(TargetDevice {
+            bus_id: u16::from_str(parts[0]).map_err(|_| invalid())?,
+            device_address: u16::from_str(parts[1]).map_err(|_| invalid())?,
+            endpoint: u8::from_str(parts[2]).map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl TryFrom<String> for TargetDevice {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<TargetDevice> for String {
+    fn from(td: TargetDevice) -> Self {
+        td.to_string()
+    }
+}
+
+impl fmt::Display for TargetDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.bus_id, self.device_address, self.endpoint)
+    }
+}
+
+/// Settings for a live `USBPcapCMD` capture
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    /// Path to `USBPcapCMD.exe`
+    pub usbpcap_cmd: PathBuf,
+    /// USBPcap root hub interface, e.g. `\\.\USBPcap1`
+    pub interface: String,
+    /// Mouse to record; without it reports are only passed to `EventSink::push_untargeted`
+    pub target_device: Option<TargetDevice>,
+    /// Turn off F2 detection, e.g. when the GUI handles F2 itself
+    pub disable_f2_watcher: bool,
+    /// Conditions that end the capture without user input
+    pub stop: StopConditions,
+    /// Save the raw pcap stream to a file while capturing
+    pub raw_output: Option<RawOutput>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            usbpcap_cmd: PathBuf::from(DEFAULT_USBPCAP_CMD),
+            interface: DEFAULT_USBPCAP_INTERFACE.to_string(),
+            target_device: None,
+            disable_f2_watcher: false,
+            stop: StopConditions::default(),
+            raw_output: None,
+        }
+    }
+}
+
+/// Conditions that end a capture automatically
+///
+/// All limits are optional; with none set the capture runs until F2, `stop_flag`
+/// or end of stream. Times are wall clock seconds, so they also work while no
+/// reports arrive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StopConditions {
+    /// Stop this many seconds after the capture started (or was armed)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_duration_secs: Option<f64>,
+    /// Stop after recording this many events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_events: Option<usize>,
+    /// Stop when no motion was seen for this many seconds after the first motion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<f64>,
+    /// Drop events and hold the duration timer until the first non-zero motion
+    pub arm_on_motion: bool,
+}
+
+impl StopConditions {
+    /// Whether any automatic stop condition is configured
+    pub fn is_active(&self) -> bool {
+        self.max_duration_secs.is_some() || self.max_events.is_some() || self.idle_timeout_secs.is_some() || self.arm_on_motion
+    }
+
+    /// Check that limits are positive and finite
+    pub fn validate(&self) -> Result<()> {
+        for (name, secs) in [("max_duration_secs", self.max_duration_secs), ("idle_timeout_secs", self.idle_timeout_secs)] {
+            if let Some(secs) = secs
+                && !(secs.is_finite() && secs > 0.0)
+            {
+                return Err(anyhow!("{} must be a positive number of seconds", name));
+            }
+        }
+        if self.max_events == Some(0) {
+            return Err(anyhow!("max_events must be greater than 0"));
+        }
+        Ok(())
+    }
+}
+
+/// Why a capture was stopped automatically
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxDuration,
+    MaxEvents,
+    Idle,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StopReason::MaxDuration => "maximum duration reached",
+            StopReason::MaxEvents => "maximum event count reached",
+            StopReason::Idle => "no motion within idle timeout",
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct MonitorState {
+    events: usize,
+    first_motion: Option<Instant>,
+    last_motion: Option<Instant>,
+    reason: Option<StopReason>,
+}
+
+/// Tracks capture progress against `StopConditions`
+///
+/// `accept` is called for each event on the reading thread and `check` is
+/// polled by the watcher thread; both take the current time so the rules can
+/// be tested without sleeping.
+#[derive(Debug)]
+pub struct StopMonitor {
+    conditions: StopConditions,
+    started: Instant,
+    state: Mutex<MonitorState>,
+}
+
+impl StopMonitor {
+    pub fn new(conditions: StopConditions, started: Instant) -> Self {
+        Self { conditions, started, state: Mutex::new(MonitorState::default()) }
+    }
+
+    /// Register an event seen at `now`; returns false if it must not be recorded
+    pub fn accept(&self, event: &MouseMoveEvent, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.reason.is_some() {
+            return false;
+        }
+        let moving = event.dx != 0 || event.dy != 0;
+        if moving {
+            state.first_motion.get_or_insert(now);
+            state.last_motion = Some(now);
+        }
+        if self.conditions.arm_on_motion && state.first_motion.is_none() {
+            return false;
+        }
+        state.events += 1;
+        if self.conditions.max_events.is_some_and(|max| state.events >= max) {
+            state.reason = Some(StopReason::MaxEvents);
+        }
+        true
+    }
+
+    /// Evaluate the conditions at `now`, latching and returning the first one that is met
+    pub fn check(&self, now: Instant) -> Option<StopReason> {
+        let mut state = self.state.lock().unwrap();
+        if state.reason.is_some() {
+            return state.reason;
+        }
+        let elapsed = |since: Instant| now.saturating_duration_since(since).as_secs_f64();
+
+        let timer_start = if self.conditions.arm_on_motion { state.first_motion } else { Some(self.started) };
+        if let (Some(max), Some(start)) = (self.conditions.max_duration_secs, timer_start)
+            && elapsed(start) >= max
+        {
+            state.reason = Some(StopReason::MaxDuration);
+        } else if let (Some(timeout), Some(last)) = (self.conditions.idle_timeout_secs, state.last_motion)
+            && elapsed(last) >= timeout
+        {
+            state.reason = Some(StopReason::Idle);
+        }
+        state.reason
+    }
+
+    /// Number of events accepted so far
+    pub fn event_count(&self) -> usize {
+        self.state.lock().unwrap().events
+    }
+
+    /// Whether recording has started (always true unless waiting for the first motion)
+    pub fn is_armed(&self) -> bool {
+        !self.conditions.arm_on_motion || self.state.lock().unwrap().first_motion.is_some()
+    }
+}
+
+/// `EventSink` adapter that only forwards events accepted by a `StopMonitor`
+///
+/// The watcher thread in `run_capture` turns a met condition into a stop request.
+struct MonitoredSink<S> {
+    inner: S,
+    monitor: Arc<StopMonitor>,
+}
+
+impl<S> MonitoredSink<S> {
+    fn admit(&self, event: &MouseMoveEvent) -> bool {
+        let was_armed = self.monitor.is_armed();
+        let accepted = self.monitor.accept(event, Instant::now());
+        if accepted && !was_armed {
+            println!("Motion detected, recording started");
+        }
+        accepted
+    }
+}
+
+impl<S: EventSink> EventSink for MonitoredSink<S> {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        if self.admit(&event) {
+            self.inner.push_event(event);
+        }
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        if self.admit(&event) {
+            self.inner.push_report(event, report);
+        }
+    }
+
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        self.inner.push_untargeted(usb_hdr, event, payload);
+    }
+}
+
+/// Parse a target device from `bus.device.endpoint` notation (e.g. `1.2.1`)
+pub fn parse_target_device(arg: &str) -> Result<TargetDevice> {
+    arg.parse()
+}
+
+/// Largest record the parser accepts; USBPcapCMD is run with `-s 65535`
+pub const MAX_RECORD_LEN: usize = 65535;
+/// Parser buffer size; always holds at least one maximal record
+const PARSER_BUFFER_LEN: usize = 4 * (PCAP_RECORD_HEADER_LEN + MAX_RECORD_LEN);
+/// Maximum jump from the last valid record's timestamp accepted for candidate headers while resynchronizing
+///
+/// Outside a resync, records are never rejected for their timestamp, so real idle gaps of any length are kept.
+const RESYNC_MAX_TS_JUMP_SECS: u32 = 3600;
+
+/// A complete pcap record borrowed from the parser buffer
+#[derive(Debug, Clone, Copy)]
+pub struct PcapRecord<'a> {
+    pub header: PcapRecordHeader,
+    pub data: &'a [u8],
+    /// The whole record as received: record header followed by `data`
+    pub raw: &'a [u8],
+}
+
+/// Counters describing what the parser has consumed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParserStats {
+    pub records: u64,
+    /// Times a corrupt record header forced a search for the next valid one
+    pub resyncs: u64,
+    /// Bytes thrown away while resynchronizing
+    pub skipped_bytes: u64,
+}
+
+/// Incremental pcap stream parser
+///
+/// Bytes are read (`read_from`) or copied (`feed`) into a fixed buffer and
+/// complete records are returned as slices into it (`next_record`), so record
+/// data is never copied. Consumed bytes are dropped by advancing a cursor; only
+/// an incomplete trailing record is moved to the front, and only when the
+/// buffer end is reached.
+///
+/// Record headers are sanity checked (length against the snapshot length and
+/// USBPcap header, microseconds). A corrupt header does not stall the stream
+/// waiting for bytes that never come: the parser skips forward one byte at a
+/// time until a plausible record header follows, which while resynchronizing
+/// must also be within `RESYNC_MAX_TS_JUMP_SECS` of the last valid record.
+#[derive(Debug)]
+pub struct PcapStreamParser {
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    skipped_global: bool,
+    global_header: [u8; PCAP_GLOBAL_HEADER_LEN],
+    max_record_len: usize,
+    last_ts_sec: Option<u32>,
+    resyncing: bool,
+    stats: ParserStats,
+}
+
+impl Default for PcapStreamParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum RecordCheck {
+    Valid(PcapRecordHeader, usize),
+    Incomplete,
+    Corrupt,
+}
+
+impl PcapStreamParser {
+    /// Create a parser expecting a pcap global header first
+    pub fn new() -> Self {
+        Self {
+            buffer: vec![0u8; PARSER_BUFFER_LEN].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            skipped_global: false,
+            global_header: [0; PCAP_GLOBAL_HEADER_LEN],
+            max_record_len: MAX_RECORD_LEN,
+            last_ts_sec: None,
+            resyncing: false,
+            stats: ParserStats::default(),
+        }
+    }
+
+    /// Free space at the end of the buffer, compacting first if it is full
+    fn spare(&mut self) -> &mut [u8] {
+        if self.end == self.buffer.len() && self.start > 0 {
+            self.buffer.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        &mut self.buffer[self.end..]
+    }
+
+    /// Read once from `reader` straight into the buffer; returns the byte count (0 at EOF)
+    pub fn read_from<R: Read>(&mut self, reader: &mut R) -> std::io::Result<usize> {
+        let n = loop {
+            match reader.read(self.spare()) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                other => break other?,
+            }
+        };
+        self.end += n;
+        Ok(n)
+    }
+
+    /// The last `n` bytes added to the buffer, e.g. right after `read_from` returned `n`
+    pub fn recent_bytes(&self, n: usize) -> &[u8] {
+        &self.buffer[self.end - n..self.end]
+    }
+
+    /// The stream's pcap global header, parsed first if it is buffered
+    pub fn global_header(&mut self) -> Option<&[u8; PCAP_GLOBAL_HEADER_LEN]> {
+        if !self.skipped_global {
+            if self.end - self.start < PCAP_GLOBAL_HEADER_LEN {
+                return None;
+            }
+            let snaplen = u32::from_le_bytes(self.buffer[self.start + 16..self.start + 20].try_into().unwrap()) as usize;
+            if snaplen > 0 {
+                self.max_record_len = snaplen.min(MAX_RECORD_LEN);
+            }
+            self.global_header.copy_from_slice(&self.buffer[self.start..self.start + PCAP_GLOBAL_HEADER_LEN]);
+            self.start += PCAP_GLOBAL_HEADER_LEN;
+            self.skipped_global = true;
+        }
+        Some(&self.global_header)
+    }
+
+    /// Feed a chunk of bytes, calling `on_record` for each complete record
+    pub fn feed<F: FnMut(&PcapRecordHeader, &[u8])>(&mut self, mut data: &[u8], mut on_record: F) {
+        while !data.is_empty() {
+            let spare = self.spare();
+            let n = spare.len().min(data.len());
+            spare[..n].copy_from_slice(&data[..n]);
+            self.end += n;
+            data = &data[n..];
+            while let Some(rec) = self.next_record() {
+                on_record(&rec.header, rec.data);
+            }
+        }
+    }
+
+    /// Next complete record, or `None` until more bytes arrive
+    pub fn next_record(&mut self) -> Option<PcapRecord<'_>> {
+        self.global_header()?;
+
+        loop {
+            match self.check_record(self.start) {
+                RecordCheck::Incomplete => return None,
+                RecordCheck::Corrupt => {
+                    if !self.resyncing {
+                        self.resyncing = true;
+                        self.stats.resyncs += 1;
+                    }
+                    self.start += 1;
+                    self.stats.skipped_bytes += 1;
+                }
+                RecordCheck::Valid(header, total) => {
+                    let record_start = self.start;
+                    self.start += total;
+                    self.resyncing = false;
+                    self.last_ts_sec = Some(header.ts_sec);
+                    self.stats.records += 1;
+                    return Some(PcapRecord { header, data: &self.buffer[record_start + PCAP_RECORD_HEADER_LEN..self.start], raw: &self.buffer[record_start..self.start] });
+                }
+            }
+        }
+    }
+
+    /// Validate the record header at `offset`
+    fn check_record(&self, offset: usize) -> RecordCheck {
+        let available = &self.buffer[offset..self.end];
+        let Some((header, _)) = PcapRecordHeader::parse(available) else {
+            return RecordCheck::Incomplete;
+        };
+        let incl_len = header.incl_len as usize;
+        // Only candidates found while resynchronizing must continue the last valid timestamp
+        let ts_jump = self.last_ts_sec.filter(|_| self.resyncing).map(|last| header.ts_sec.abs_diff(last)).unwrap_or(0);
+        if incl_len > self.max_record_len || header.orig_len < header.incl_len || header.ts_usec >= 1_000_000 || ts_jump > RESYNC_MAX_TS_JUMP_SECS {
+            return RecordCheck::Corrupt;
+        }
+        // The USBPcap header must fit the record and its header and data lengths
+        // must add up to the original length; this rejects misaligned candidates
+        // before waiting for their bytes
+        if incl_len < USBPCAP_HEADER_LEN {
+            return RecordCheck::Corrupt;
+        }
+        let Some(usb) = available.get(PCAP_RECORD_HEADER_LEN..PCAP_RECORD_HEADER_LEN + USBPCAP_HEADER_LEN) else {
+            return RecordCheck::Incomplete;
+        };
+        let usb_header_len = u16::from_le_bytes([usb[0], usb[1]]) as u64;
+        let data_length = u32::from_le_bytes([usb[23], usb[24], usb[25], usb[26]]) as u64;
+        if usb_header_len < USBPCAP_HEADER_LEN as u64 || usb_header_len > incl_len as u64 || usb_header_len + data_length != header.orig_len as u64 {
+            return RecordCheck::Corrupt;
+        }
+        let total = PCAP_RECORD_HEADER_LEN + incl_len;
+        if available.len() < total { RecordCheck::Incomplete } else { RecordCheck::Valid(header, total) }
+    }
+
+    /// Number of buffered bytes not yet consumed as a complete record
+    pub fn pending_len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Counters for records parsed and corruption skipped so far
+    pub fn stats(&self) -> ParserStats {
+        self.stats
+    }
+}
+
+/// A mouse report decoded from a pcap record
+#[derive(Debug, Clone, Copy)]
+pub enum DecodedReport<'a> {
+    /// Report from the target device, timestamped relative to its first report
+    Target { event: MouseMoveEvent, payload: &'a [u8] },
+    /// Report seen while no target device is set; carries the absolute pcap timestamp
+    Untargeted { usb_hdr: UsbPcapHeader, event: MouseMoveEvent, payload: &'a [u8] },
+}
+
+/// Filters pcap records and decodes HID mouse reports
+///
+/// With a target device, only IN transfers from that device are decoded and
+/// their timestamps are made relative to the first matching report. Without a
+/// target every 7/8-byte IN report is returned as `DecodedReport::Untargeted`
+/// so the user can discover which device to select.
+#[derive(Debug, Clone, Copy)]
+pub struct ReportDecoder {
+    target_device: Option<TargetDevice>,
+    first_target_ts: Option<(u32, u32)>, // Store (ts_sec, ts_usec) as base
+}
+
+impl ReportDecoder {
+    /// Create a decoder for an optional target device
+    pub fn new(target_device: Option<TargetDevice>) -> Self {
+        Self { target_device, first_target_ts: None }
+    }
+
+    /// The configured target device
+    pub fn target_device(&self) -> Option<TargetDevice> {
+        self.target_device
+    }
+
+    /// Decode a single pcap record (record header plus record data)
+    pub fn decode<'a>(&mut self, rec_hdr: &PcapRecordHeader, record_data: &'a [u8]) -> Option<DecodedReport<'a>> {
+        let (usb_hdr, usb_size) = UsbPcapHeader::parse(record_data)?;
+        if !usb_hdr.is_in_direction() {
+            return None;
+        }
+        let payload = &record_data[usb_size..];
+        let event = match usb_hdr.data_length {
+            8 if payload.len() >= 8 => mouse_event::parser::parse_with_report_id(payload, rec_hdr)?,
+            7 if payload.len() >= 7 => mouse_event::parser::parse_without_report_id(payload, rec_hdr)?,
+            _ => return None,
+        };
+
+        let Some(td) = self.target_device else {
+            return Some(DecodedReport::Untargeted { usb_hdr, event, payload });
+        };
+        if !td.matches(&usb_hdr) {
+            return None;
+        }
+
+        let (rel_sec, rel_usec) = self.relative_timestamp(rec_hdr.ts_sec, rec_hdr.ts_usec);
+        Some(DecodedReport::Target { event: MouseMoveEvent::new(event.dx, event.dy, rel_sec, rel_usec), payload })
+    }
+
+    /// Convert an absolute pcap timestamp into one relative to the first target report
+    fn relative_timestamp(&mut self, ts_sec: u32, ts_usec: u32) -> (u32, u32) {
+        let Some((base_sec, base_usec)) = self.first_target_ts else {
+            self.first_target_ts = Some((ts_sec, ts_usec));
+            return (0, 0);
+        };
+        let mut sec_diff = ts_sec as i64 - base_sec as i64;
+        let mut usec_diff = ts_usec as i64 - base_usec as i64;
+
+        if usec_diff < 0 {
+            sec_diff -= 1;
+            usec_diff += 1_000_000;
+        }
+
+        (sec_diff as u32, usec_diff as u32)
+    }
+}
+
+/// Destination for decoded mouse events
+pub trait EventSink {
+    /// Store one event from the target device
+    fn push_event(&mut self, event: MouseMoveEvent);
+
+    /// Store one event together with the HID report it was decoded from
+    ///
+    /// The default drops the report; sinks that archive raw data override it.
+    fn push_report(&mut self, event: MouseMoveEvent, _report: &[u8]) {
+        self.push_event(event);
+    }
+
+    /// Handle a report seen while no target device is set
+    ///
+    /// The default prints the report so users can find their mouse.
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        println!("?Mouse Move {}.{}.{}: dx={:<4} dy={:<4} raw={:02X?}", usb_hdr.bus_id, usb_hdr.device_address, usb_hdr.endpoint, event.dx, event.dy, payload);
+    }
+}
+
+impl EventSink for Vec<MouseMoveEvent> {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.push(event);
+    }
+}
+
+impl EventSink for Arc<Mutex<Vec<MouseMoveEvent>>> {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.lock().unwrap().push(event);
+    }
+}
+
+/// A sink shared with another thread, e.g. a session file flushed on a timer
+impl<S: EventSink> EventSink for &Mutex<S> {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.lock().unwrap().push_event(event);
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        self.lock().unwrap().push_report(event, report);
+    }
+
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        self.lock().unwrap().push_untargeted(usb_hdr, event, payload);
+    }
+}
+
+impl<S: EventSink + ?Sized> EventSink for &mut S {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        (**self).push_event(event);
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        (**self).push_report(event, report);
+    }
+
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        (**self).push_untargeted(usb_hdr, event, payload);
+    }
+}
+
+/// An absent sink drops events, e.g. an optional session file
+impl<S: EventSink> EventSink for Option<S> {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        if let Some(sink) = self {
+            sink.push_event(event);
+        }
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        if let Some(sink) = self {
+            sink.push_report(event, report);
+        }
+    }
+
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        if let Some(sink) = self {
+            sink.push_untargeted(usb_hdr, event, payload);
+        }
+    }
+}
+
+/// Send events to both sinks; untargeted reports only go to the first
+impl<A: EventSink, B: EventSink> EventSink for (A, B) {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.0.push_event(event);
+        self.1.push_event(event);
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        self.0.push_report(event, report);
+        self.1.push_report(event, report);
+    }
+
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, event: MouseMoveEvent, payload: &[u8]) {
+        self.0.push_untargeted(usb_hdr, event, payload);
+    }
+}
+
+/// Which part of the incoming pcap stream a `RawTee` saves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RawTeeMode {
+    /// Every byte exactly as received, including other devices and corrupt data
+    #[default]
+    All,
+    /// The global header and the complete records of the target device
+    Target,
+}
+
+impl FromStr for RawTeeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "all" => Ok(RawTeeMode::All),
+            "target" => Ok(RawTeeMode::Target),
+            _ => bail!("Unknown raw pcap mode '{}', expected all or target", s),
+        }
+    }
+}
+
+impl fmt::Display for RawTeeMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RawTeeMode::All => "all",
+            RawTeeMode::Target => "target",
+        })
+    }
+}
+
+/// Where `run_capture` saves the raw pcap stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawOutput {
+    pub path: PathBuf,
+    pub mode: RawTeeMode,
+}
+
+/// Copies the incoming pcap stream to a writer during capture
+///
+/// Both modes produce a valid pcap file that Wireshark opens and that
+/// `capture_from_reader` replays into the same events: `All` writes the bytes
+/// as read, `Target` writes the original global header and the unmodified
+/// records of the target device. A write error disables the tee with a warning
+/// instead of ending the capture.
+pub struct RawTee {
+    writer: BufWriter<Box<dyn Write + Send>>,
+    mode: RawTeeMode,
+    target_device: Option<TargetDevice>,
+    header_written: bool,
+    bytes: u64,
+    error: Option<std::io::Error>,
+}
+
+impl RawTee {
+    /// Tee into `writer`; `Target` mode needs a target device
+    pub fn new(writer: impl Write + Send + 'static, mode: RawTeeMode, target_device: Option<TargetDevice>) -> Result<Self> {
+        if mode == RawTeeMode::Target && target_device.is_none() {
+            bail!("Saving only the target device's packets requires a target device");
+        }
+        Ok(Self { writer: BufWriter::with_capacity(1 << 16, Box::new(writer)), mode, target_device, header_written: false, bytes: 0, error: None })
+    }
+
+    /// Tee into a new file at `output.path`
+    pub fn create(output: &RawOutput, target_device: Option<TargetDevice>) -> Result<Self> {
+        let file = File::create(&output.path).with_context(|| format!("Failed to create {}", output.path.display()))?;
+        Self::new(file, output.mode, target_device)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        match self.writer.write_all(bytes) {
+            Ok(()) => self.bytes += bytes.len() as u64,
+            Err(e) => {
+                eprintln!("Raw pcap write failed, no longer saving packets: {}", e);
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Bytes just read from the stream
+    fn on_read(&mut self, bytes: &[u8]) {
+        if self.mode == RawTeeMode::All {
+            self.write(bytes);
+        }
+    }
+
+    /// The pcap global header, as soon as it is parsed
+    ///
+    /// Written right away in `Target` mode, so a capture without target
+    /// records still produces a valid, empty pcap file.
+    fn on_global_header(&mut self, header: &[u8; PCAP_GLOBAL_HEADER_LEN]) {
+        if self.mode == RawTeeMode::Target {
+            self.write(header);
+        }
+        self.header_written = true;
+    }
+
+    /// A complete record parsed from the stream
+    fn on_record(&mut self, record: &PcapRecord) {
+        if self.mode != RawTeeMode::Target {
+            return;
+        }
+        let is_target = UsbPcapHeader::parse(record.data).is_some_and(|(usb_hdr, _)| self.target_device.is_some_and(|td| td.matches(&usb_hdr)));
+        if is_target {
+            self.write(record.raw);
+        }
+    }
+
+    /// Flush and return the number of bytes written
+    pub fn finish(mut self) -> Result<u64> {
+        if let Some(e) = self.error.take() {
+            return Err(e).context("Failed to write raw pcap");
+        }
+        self.writer.flush().context("Failed to write raw pcap")?;
+        Ok(self.bytes)
+    }
+}
+
+/// Read a pcap stream until EOF or `stop_flag`, sending decoded reports to `sink`
+pub fn capture_from_reader<R: Read, S: EventSink>(reader: R, sink: S, stop_flag: Arc<AtomicBool>, target_device: Option<TargetDevice>) -> Result<()> {
+    capture_from_reader_tee(reader, sink, stop_flag, target_device, None)
+}
+
+/// `capture_from_reader` that also copies the raw stream into `tee`
+pub fn capture_from_reader_tee<R: Read, S: EventSink>(mut reader: R, mut sink: S, stop_flag: Arc<AtomicBool>, target_device: Option<TargetDevice>, mut tee: Option<&mut RawTee>) -> Result<()> {
+    let mut parser = PcapStreamParser::new();
+    let mut decoder = ReportDecoder::new(target_device);
+
+    loop {
+        if stop_flag.load(AtomicOrdering::SeqCst) {
+            println!("Stop requested, breaking read loop...");
+            break;
+        }
+
+        match parser.read_from(&mut reader) {
+            Ok(0) => {
+                println!("Capture stream closed, exiting read loop...");
+                break;
+            }
+            Ok(n) => {
+                if let Some(tee) = tee.as_deref_mut() {
+                    tee.on_read(parser.recent_bytes(n));
+                }
+            }
+            Err(e) => {
+                eprintln!("Error reading from pipe: {}", e);
+                break;
+            }
+        }
+
+        if let Some(tee) = tee.as_deref_mut()
+            && !tee.header_written
+            && let Some(header) = parser.global_header()
+        {
+            tee.on_global_header(header);
+        }
+        while let Some(rec) = parser.next_record() {
+            if let Some(tee) = tee.as_deref_mut() {
+                tee.on_record(&rec);
+            }
+            match decoder.decode(&rec.header, rec.data) {
+                Some(DecodedReport::Target { event, payload }) => sink.push_report(event, payload),
+                Some(DecodedReport::Untargeted { usb_hdr, event, payload }) => sink.push_untargeted(&usb_hdr, event, payload),
+                None => {}
+            }
+        }
+    }
+
+    let stats = parser.stats();
+    if stats.resyncs > 0 {
+        eprintln!("Skipped {} corrupt bytes in {} places while parsing the capture", stats.skipped_bytes, stats.resyncs);
+    }
+    Ok(())
+}
+
+/// Capture from `USBPcapCMD` until F2, `stop_flag`, a stop condition or end of stream
+///
+/// Setting `stop_flag` always terminates the child process. When one of
+/// `options.stop` is met, `stop_flag` is set so callers sharing it see the stop.
+pub fn run_capture<S: EventSink>(sink: S, stop_flag: Arc<AtomicBool>, options: &CaptureOptions) -> Result<()> {
+    let target_device = options.target_device;
+    let disable_f2_watcher = options.disable_f2_watcher;
+    println!("Filtering for target device: {:?}", target_device);
+    println!("Starting USBPcapCMD for device {}", options.interface);
+    options.stop.validate()?;
+    let mut tee = options.raw_output.as_ref().map(|raw| RawTee::create(raw, target_device)).transpose()?;
+    let monitor = options.stop.is_active().then(|| Arc::new(StopMonitor::new(options.stop, Instant::now())));
+
+    // Start usbpcap child
+    let mut child = Command::new(&options.usbpcap_cmd)
+        .args(["-d", options.interface.as_str(), "-o", "-", "-A", "-s", "65535", "-b", "262144"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start {}: {}", options.usbpcap_cmd.display(), e))?;
+
+    // Extract stdout before moving child into Arc
+    let stdout = child.stdout.take().ok_or_else(|| anyhow!("Failed to capture stdout"))?;
+
+    // Put child in Arc<Mutex<>> for shared access
+    let child_arc = Arc::new(Mutex::new(child));
+
+    // Stop watcher thread: kills the child as soon as a stop is requested so a
+    // blocked read returns. On Windows it also turns F2 into a stop request,
+    // unless disabled (e.g., when GUI is handling F2 separately).
+    {
+        let stop_flag = Arc::clone(&stop_flag);
+        let child_for_watcher = Arc::clone(&child_arc);
+        let monitor = monitor.clone();
+        thread::spawn(move || {
+            loop {
+                if !disable_f2_watcher && key_utils::is_f2_pressed() {
+                    println!("F2 pressed: requesting stop...");
+                    stop_flag.store(true, AtomicOrdering::SeqCst);
+                }
+                if let Some(monitor) = &monitor
+                    && let Some(reason) = monitor.check(Instant::now())
+                    && !stop_flag.swap(true, AtomicOrdering::SeqCst)
+                {
+                    println!("Stopping capture: {} ({} events)", reason, monitor.event_count());
+                }
+                if stop_flag.load(AtomicOrdering::SeqCst) {
+                    // Kill the child directly using the shared handle
+                    if let Ok(mut child) = child_for_watcher.lock() {
+                        let _ = child.kill();
+                    }
+                    break;
+                }
+                // Exit once the capture finished on its own
+                if let Ok(mut child) = child_for_watcher.lock()
+                    && matches!(child.try_wait(), Ok(Some(_)))
+                {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(80));
+            }
+        });
+    }
+
+    println!("Reading USB data from pipe... (press F2 to stop capture and analyze)");
+    if options.stop.arm_on_motion {
+        println!("Waiting for the first motion before recording...");
+    }
+    let result = match monitor {
+        Some(monitor) => capture_from_reader_tee(stdout, MonitoredSink { inner: sink, monitor }, stop_flag, target_device, tee.as_mut()),
+        None => capture_from_reader_tee(stdout, sink, stop_flag, target_device, tee.as_mut()),
+    };
+
+    // ensure child stopped
+    if let Ok(mut child) = child_arc.lock() {
+        child.kill().ok();
+        child.wait().ok();
+    }
+
+    let saved = match (tee, &options.raw_output) {
+        (Some(tee), Some(raw)) => tee.finish().map(|bytes| println!("Saved {} bytes of raw pcap ({}) to {}", bytes, raw.mode, raw.path.display())),
+        _ => Ok(()),
+    };
+    // A capture error says more than the raw pcap error it may have caused
+    match (result, saved) {
+        (Err(e), Err(raw_error)) => {
+            eprintln!("{:#}", raw_error);
+            Err(e)
+        }
+        (result, saved) => result.and(saved),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn global_header() -> Vec<u8> {
+        let mut v = Vec::new();
+        v.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes()); // magic
+        v.extend_from_slice(&2u16.to_le_bytes()); // version major
+        v.extend_from_slice(&4u16.to_le_bytes()); // version minor
+        v.extend_from_slice(&0i32.to_le_bytes()); // thiszone
+        v.extend_from_slice(&0u32.to_le_bytes()); // sigfigs
+        v.extend_from_slice(&65535u32.to_le_bytes()); // snaplen
+        v.extend_from_slice(&249u32.to_le_bytes()); // LINKTYPE_USBPCAP
+        v
+    }
+
+    /// Build a pcap record holding a USBPcap header and the given HID payload
+    fn usb_record(ts_sec: u32, ts_usec: u32, device: (u16, u16, u8), info: u8, payload: &[u8]) -> Vec<u8> {
+        let mut usb = Vec::new();
+        usb.extend_from_slice(&(USBPCAP_HEADER_LEN as u16).to_le_bytes()); // header_len
+        usb.extend_from_slice(&0x1122334455667788u64.to_le_bytes()); // irp_id
+        usb.extend_from_slice(&0u32.to_le_bytes()); // status
+        usb.extend_from_slice(&0x0009u16.to_le_bytes()); // function
+        usb.push(info);
+        usb.extend_from_slice(&device.0.to_le_bytes());
+        usb.extend_from_slice(&device.1.to_le_bytes());
+        usb.push(0x80 | device.2); // endpoint with IN bit
+        usb.push(1); // interrupt transfer
+        usb.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        usb.extend_from_slice(payload);
+
+        let mut rec = Vec::new();
+        rec.extend_from_slice(&ts_sec.to_le_bytes());
+        rec.extend_from_slice(&ts_usec.to_le_bytes());
+        rec.extend_from_slice(&(usb.len() as u32).to_le_bytes());
+        rec.extend_from_slice(&(usb.len() as u32).to_le_bytes());
+        rec.extend_from_slice(&usb);
+        rec
+    }
+
+    fn report8(dx: i16, dy: i16) -> [u8; 8] {
+        let dx = dx.to_le_bytes();
+        let dy = dy.to_le_bytes();
+        [0x01, 0x00, dx[0], dx[1], dy[0], dy[1], 0x00, 0x00]
+    }
+
+    fn report7(dx: i16, dy: i16) -> [u8; 7] {
+        let dx = dx.to_le_bytes();
+        let dy = dy.to_le_bytes();
+        [0x00, dx[0], dx[1], dy[0], dy[1], 0x00, 0x00]
+    }
+
+    #[test]
+    fn test_usbpcap_header_parse() {
+        let rec = usb_record(0, 0, (1, 2, 1), 1, &report8(3, -4));
+        let (hdr, size) = UsbPcapHeader::parse(&rec[PCAP_RECORD_HEADER_LEN..]).unwrap();
+        assert_eq!(size, USBPCAP_HEADER_LEN);
+        assert_eq!(hdr.irp_id, 0x1122334455667788);
+        assert_eq!(hdr.bus_id, 1);
+        assert_eq!(hdr.device_address, 2);
+        assert_eq!(hdr.endpoint, 1);
+        assert!(hdr.direction_in);
+        assert!(hdr.is_in_direction());
+        assert_eq!(hdr.data_length, 8);
+
+        assert!(UsbPcapHeader::parse(&rec[PCAP_RECORD_HEADER_LEN..PCAP_RECORD_HEADER_LEN + 10]).is_none());
+    }
+
+    #[test]
+    fn test_parse_target_device() {
+        let td = parse_target_device("1.12.3").unwrap();
+        assert_eq!(td, TargetDevice { bus_id: 1, device_address: 12, endpoint: 3 });
+        assert_eq!(td.to_string(), "1.12.3");
+
+        assert!(parse_target_device("1.2").is_err());
+        assert!(parse_target_device("1.2.x").is_err());
+        assert!(parse_target_device("1.2.300").is_err());
+    }
+
+    #[test]
+    fn test_stream_parser_handles_split_chunks() {
+        let mut stream = global_header();
+        stream.extend(usb_record(10, 100, (1, 2, 1), 1, &report8(1, 2)));
+        stream.extend(usb_record(10, 200, (1, 2, 1), 1, &report8(3, 4)));
+
+        // Feed one byte at a time to exercise every partial state
+        let mut parser = PcapStreamParser::new();
+        let mut records = Vec::new();
+        for b in &stream {
+            parser.feed(std::slice::from_ref(b), |hdr, data| records.push((hdr.ts_usec, data.len())));
+        }
+
+        assert_eq!(records, vec![(100, USBPCAP_HEADER_LEN + 8), (200, USBPCAP_HEADER_LEN + 8)]);
+        assert_eq!(parser.pending_len(), 0);
+    }
+
+    /// Write target shared with the test after the tee is consumed
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reader handing out a few bytes per call, like a pipe under load
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(13).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    fn tee_capture(stream: &[u8], mode: RawTeeMode, target: TargetDevice) -> (Vec<MouseMoveEvent>, Vec<u8>) {
+        let out = SharedBuf::default();
+        let mut tee = RawTee::new(out.clone(), mode, Some(target)).unwrap();
+        let mut events = Vec::new();
+        capture_from_reader_tee(Trickle(stream), &mut events, Arc::new(AtomicBool::new(false)), Some(target), Some(&mut tee)).unwrap();
+        assert_eq!(tee.finish().unwrap() as usize, out.0.lock().unwrap().len());
+        let bytes = out.0.lock().unwrap().clone();
+        (events, bytes)
+    }
+
+    fn mixed_stream() -> Vec<u8> {
+        let mut stream = global_header();
+        for i in 0..50u32 {
+            stream.extend(usb_record(20, i * 1000, (1, 2, 1), 1, &report8(i as i16 - 25, 3)));
+            stream.extend(usb_record(20, i * 1000 + 500, (1, 5, 1), 1, &report7(1, 1)));
+            if i % 10 == 0 {
+                stream.extend(usb_record(20, i * 1000 + 600, (1, 2, 1), 0, &[0; 8])); // OUT transfer
+            }
+        }
+        stream
+    }
+
+    #[test]
+    fn test_raw_tee_all_copies_stream_exactly() {
+        let target = TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 };
+        let mut stream = mixed_stream();
+        stream.extend_from_slice(&[0xde, 0xad]); // trailing garbage is kept too
+        let (events, raw) = tee_capture(&stream, RawTeeMode::All, target);
+        assert_eq!(events.len(), 50);
+        assert_eq!(raw, stream);
+    }
+
+    #[test]
+    fn test_raw_tee_target_replays_same_events() {
+        let target = TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 };
+        let stream = mixed_stream();
+        let (events, raw) = tee_capture(&stream, RawTeeMode::Target, target);
+        assert_eq!(&raw[..PCAP_GLOBAL_HEADER_LEN], &stream[..PCAP_GLOBAL_HEADER_LEN]);
+        // 50 IN reports and 5 OUT transfers of the target, nothing from device 5
+        assert_eq!(raw.len(), PCAP_GLOBAL_HEADER_LEN + 55 * (PCAP_RECORD_HEADER_LEN + USBPCAP_HEADER_LEN + 8));
+
+        let mut replayed = Vec::new();
+        capture_from_reader(raw.as_slice(), &mut replayed, Arc::new(AtomicBool::new(false)), Some(target)).unwrap();
+        assert_eq!(replayed.len(), events.len());
+        assert!(replayed.iter().zip(&events).all(|(a, b)| (a.dx, a.dy, a.ts_sec, a.ts_usec) == (b.dx, b.dy, b.ts_sec, b.ts_usec)));
+
+        assert!(RawTee::new(SharedBuf::default(), RawTeeMode::Target, None).is_err());
+        assert_eq!("TARGET".parse::<RawTeeMode>().unwrap(), RawTeeMode::Target);
+    }
+
+    #[test]
+    fn test_raw_tee_target_without_target_traffic_is_valid_pcap() {
+        let target = TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 };
+        let mut stream = global_header();
+        for i in 0..20u32 {
+            stream.extend(usb_record(20, i * 1000, (1, 5, 1), 1, &report7(1, 1)));
+        }
+        for stream in [&stream[..], &stream[..PCAP_GLOBAL_HEADER_LEN]] {
+            let (events, raw) = tee_capture(stream, RawTeeMode::Target, target);
+            assert!(events.is_empty());
+            assert_eq!(raw, global_header(), "the header is saved even if no target record follows");
+            assert!(capture_from_reader(raw.as_slice(), &mut Vec::new(), Arc::new(AtomicBool::new(false)), Some(target)).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_stream_parser_recovers_from_corrupt_incl_len() {
+        let mut stream = global_header();
+        stream.extend(usb_record(10, 100, (1, 2, 1), 1, &report8(1, 0)));
+        let mut corrupt = usb_record(10, 200, (1, 2, 1), 1, &report8(2, 0));
+        corrupt[8..12].copy_from_slice(&0x7fff_ff00u32.to_le_bytes()); // huge incl_len
+        stream.extend(corrupt);
+        let mut short = usb_record(10, 300, (1, 2, 1), 1, &report8(3, 0));
+        short[8..12].copy_from_slice(&45u32.to_le_bytes()); // claims 10 bytes more than it has
+        short[12..16].copy_from_slice(&45u32.to_le_bytes());
+        let mut truncated = usb_record(10, 350, (1, 2, 1), 1, &report8(3, 0));
+        truncated.truncate(30); // cut off mid USBPcap header
+        stream.extend(short);
+        stream.extend(truncated);
+        for i in 4..8u32 {
+            stream.extend(usb_record(10, i * 100, (1, 2, 1), 1, &report8(i as i16, 0)));
+        }
+
+        let mut parser = PcapStreamParser::new();
+        let mut seen = Vec::new();
+        parser.feed(&stream, |hdr, _| seen.push(hdr.ts_usec));
+
+        assert_eq!(seen.first(), Some(&100));
+        assert_eq!(seen, vec![100, 400, 500, 600, 700]);
+        assert_eq!(parser.pending_len(), 0, "parser must not wait for bytes of a corrupt record");
+        // The three damaged records are adjacent, so one resync skips all of them
+        let record_len = (PCAP_RECORD_HEADER_LEN + USBPCAP_HEADER_LEN + 8) as u64;
+        assert_eq!(parser.stats().resyncs, 1);
+        assert_eq!(parser.stats().skipped_bytes, 2 * record_len + 30);
+    }
+
+    #[test]
+    fn test_stream_parser_keeps_records_after_long_idle_gap() {
+        let mut stream = global_header();
+        stream.extend(usb_record(10, 100, (1, 2, 1), 1, &report8(1, 0)));
+        // Two hours idle, longer than RESYNC_MAX_TS_JUMP_SECS
+        for i in 0..3u32 {
+            stream.extend(usb_record(10 + 7200, i * 100, (1, 2, 1), 1, &report8(2, 0)));
+        }
+        // A corrupt header after the gap resyncs against the timestamps after the gap
+        let mut corrupt = usb_record(10 + 7200, 400, (1, 2, 1), 1, &report8(3, 0));
+        corrupt[8..12].copy_from_slice(&0x7fff_ff00u32.to_le_bytes());
+        stream.extend(corrupt);
+        stream.extend(usb_record(10 + 7201, 0, (1, 2, 1), 1, &report8(4, 0)));
+
+        let mut parser = PcapStreamParser::new();
+        let mut seen = Vec::new();
+        parser.feed(&stream, |hdr, _| seen.push((hdr.ts_sec, hdr.ts_usec)));
+        assert_eq!(seen, vec![(10, 100), (7210, 0), (7210, 100), (7210, 200), (7211, 0)]);
+        assert_eq!(parser.stats().resyncs, 1);
+    }
+
+    #[test]
+    fn test_stream_parser_reads_past_buffer_size() {
+        let mut stream = global_header();
+        let count = 2 * PARSER_BUFFER_LEN / (PCAP_RECORD_HEADER_LEN + USBPCAP_HEADER_LEN + 8) + 7;
+        for i in 0..count {
+            stream.extend(usb_record(1 + (i / 8000) as u32, ((i % 8000) * 125) as u32, (1, 2, 1), 1, &report8((i % 100) as i16, 0)));
+        }
+
+        // Odd read sizes force records to straddle reads and the buffer end
+        struct Chunked(Cursor<Vec<u8>>);
+        impl Read for Chunked {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(7001);
+                self.0.read(&mut buf[..n])
+            }
+        }
+
+        let mut reader = Chunked(Cursor::new(stream));
+        let mut parser = PcapStreamParser::new();
+        let mut parsed = 0usize;
+        while parser.read_from(&mut reader).unwrap() > 0 {
+            while let Some(rec) = parser.next_record() {
+                assert_eq!(rec.data.len(), USBPCAP_HEADER_LEN + 8);
+                assert_eq!(rec.data[USBPCAP_HEADER_LEN + 2] as usize, parsed % 100);
+                parsed += 1;
+            }
+        }
+        assert_eq!(parsed, count);
+        assert_eq!(parser.stats(), ParserStats { records: count as u64, resyncs: 0, skipped_bytes: 0 });
+    }
+
+    #[test]
+    fn test_decoder_filters_and_relativizes_timestamps() {
+        let target = TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 };
+        let mut decoder = ReportDecoder::new(Some(target));
+        let records = [
+            usb_record(100, 900_000, (1, 2, 1), 1, &report8(5, -5)),
+            usb_record(100, 950_000, (1, 3, 1), 1, &report8(9, 9)), // other device
+            usb_record(101, 100_000, (1, 2, 1), 0, &report8(9, 9)), // OUT/submit direction
+            usb_record(101, 100_000, (1, 2, 1), 1, &report7(-7, 8)),
+        ];
+
+        let events: Vec<MouseMoveEvent> = records
+            .iter()
+            .filter_map(|r| {
+                let (hdr, size) = PcapRecordHeader::parse(r).unwrap();
+                match decoder.decode(&hdr, &r[size..]) {
+                    Some(DecodedReport::Target { event, .. }) => Some(event),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].dx, events[0].dy, events[0].ts_sec, events[0].ts_usec), (5, -5, 0, 0));
+        // 101.100000 - 100.900000 = 0.200000 (usec borrow)
+        assert_eq!((events[1].dx, events[1].dy, events[1].ts_sec, events[1].ts_usec), (-7, 8, 0, 200_000));
+    }
+
+    #[test]
+    fn test_decoder_without_target_reports_untargeted() {
+        let mut decoder = ReportDecoder::new(None);
+        let rec = usb_record(7, 8, (2, 5, 3), 1, &report8(1, -1));
+        let (hdr, size) = PcapRecordHeader::parse(&rec).unwrap();
+
+        match decoder.decode(&hdr, &rec[size..]) {
+            Some(DecodedReport::Untargeted { usb_hdr, event, payload }) => {
+                assert_eq!((usb_hdr.bus_id, usb_hdr.device_address, usb_hdr.endpoint), (2, 5, 3));
+                assert_eq!((event.dx, event.dy, event.ts_sec, event.ts_usec), (1, -1, 7, 8));
+                assert_eq!(payload.len(), 8);
+            }
+            other => panic!("unexpected decode result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_capture_from_reader_collects_events() {
+        let mut stream = global_header();
+        for i in 0..5u32 {
+            stream.extend(usb_record(1, i * 125, (1, 2, 1), 1, &report8(i as i16, -(i as i16))));
+        }
+        stream.extend(usb_record(1, 999, (9, 9, 9), 1, &report8(50, 50)));
+
+        let mut events = Vec::new();
+        capture_from_reader(Cursor::new(stream), &mut events, Arc::new(AtomicBool::new(false)), Some(TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 })).unwrap();
+
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4].dx, 4);
+        assert_eq!(events[4].time_micros(), 500);
+    }
+
+    #[test]
+    fn test_stop_monitor_max_events_and_duration() {
+        let t0 = Instant::now();
+        let monitor = StopMonitor::new(StopConditions { max_events: Some(2), max_duration_secs: Some(1.0), ..Default::default() }, t0);
+        let moved = MouseMoveEvent::new(1, 0, 0, 0);
+
+        assert_eq!(monitor.check(t0 + Duration::from_millis(500)), None);
+        assert!(monitor.accept(&moved, t0));
+        assert!(monitor.accept(&moved, t0));
+        assert!(!monitor.accept(&moved, t0), "events past the limit are dropped");
+        assert_eq!(monitor.event_count(), 2);
+        assert_eq!(monitor.check(t0), Some(StopReason::MaxEvents));
+
+        let monitor = StopMonitor::new(StopConditions { max_duration_secs: Some(1.0), ..Default::default() }, t0);
+        assert_eq!(monitor.check(t0 + Duration::from_millis(999)), None);
+        assert_eq!(monitor.check(t0 + Duration::from_secs(1)), Some(StopReason::MaxDuration));
+    }
+
+    #[test]
+    fn test_stop_monitor_idle_and_arm_on_motion() {
+        let t0 = Instant::now();
+        let conditions = StopConditions { idle_timeout_secs: Some(0.5), max_duration_secs: Some(2.0), arm_on_motion: true, ..Default::default() };
+        let monitor = StopMonitor::new(conditions, t0);
+        let still = MouseMoveEvent::new(0, 0, 0, 0);
+        let moved = MouseMoveEvent::new(0, -3, 0, 0);
+
+        // Nothing counts before the first motion, so neither timer runs
+        assert!(!monitor.accept(&still, t0));
+        assert!(!monitor.is_armed());
+        assert_eq!(monitor.check(t0 + Duration::from_secs(10)), None);
+
+        let armed = t0 + Duration::from_secs(10);
+        assert!(monitor.accept(&moved, armed));
+        assert!(monitor.accept(&still, armed + Duration::from_millis(100)), "zero reports are kept once armed");
+        assert_eq!(monitor.check(armed + Duration::from_millis(400)), None);
+        assert_eq!(monitor.check(armed + Duration::from_millis(500)), Some(StopReason::Idle));
+    }
+
+    #[test]
+    fn test_stop_conditions_validate() {
+        assert!(StopConditions::default().validate().is_ok());
+        assert!(!StopConditions::default().is_active());
+        assert!(StopConditions { max_duration_secs: Some(0.0), ..Default::default() }.validate().is_err());
+        assert!(StopConditions { idle_timeout_secs: Some(f64::NAN), ..Default::default() }.validate().is_err());
+        assert!(StopConditions { max_events: Some(0), ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,431 @@
+//! Command line interface definitions
+//!
+//! Each subcommand maps to a handler in `main.rs`. Options that have a config
+//! file equivalent are kept as `Option`s so the front end can tell an explicit
+//! flag (which wins) from an omitted one (which falls back to `mousetester.toml`).
+
+use MouseTesterRust::capture::{RawOutput, RawTeeMode, TargetDevice};
+use MouseTesterRust::formats::EventFormat;
+use MouseTesterRust::plot::Panel;
+use MouseTesterRust::report::ReportFormat;
+use clap::{ArgAction, Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "MouseTesterRust", version, about = "Capture and analyze USB mouse movement reports", arg_required_else_help = true)]
+pub struct Cli {
+    /// Print more detail (repeat for even more)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Only print warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Use this config file instead of the nearest project mousetester.toml
+    #[arg(short, long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+impl Cli {
+    /// Verbosity level: -1 quiet, 0 normal, 1+ verbose
+    pub fn verbosity(&self) -> i8 {
+        if self.quiet { -1 } else { self.verbose.min(i8::MAX as u8) as i8 }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Capture mouse reports with USBPcapCMD, then analyze, save and plot them
+    Capture(CaptureArgs),
+    /// Analyze a saved capture file
+    Analyze(AnalyzeArgs),
+    /// Listen briefly and list USB devices that send mouse reports
+    Devices(DevicesArgs),
+    /// Convert a capture file to another format
+    Convert(ConvertArgs),
+    /// Compare statistics of two or more capture files
+    Compare(CompareArgs),
+    /// Open the interactive GUI and start capturing (or show a saved capture with --open)
+    Gui(GuiArgs),
+    /// Truncate a session file left by an interrupted capture to its last complete chunk
+    Recover(RecoverArgs),
+    /// Print the effective configuration and the files it was loaded from
+    Config,
+}
+
+#[derive(Debug, Args)]
+pub struct CaptureArgs {
+    /// Target device as bus.device.endpoint (e.g. 1.2.1); without it reports are only printed
+    #[arg(short = 'd', long = "device", value_name = "BUS.DEV.EP")]
+    pub device: Option<TargetDevice>,
+
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub stop: StopArgs,
+
+    /// Also write events to this session file while capturing, flushed every second
+    #[arg(long, value_name = "PATH")]
+    pub session: Option<PathBuf>,
+
+    /// Keep events only in the session file, for captures too big for memory
+    #[arg(long, requires = "session")]
+    pub disk_only: bool,
+
+    #[command(flatten)]
+    pub raw: RawPcapArgs,
+
+    #[command(flatten)]
+    pub notes: NotesArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+/// USBPcapCMD location and mouse properties
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// Path to USBPcapCMD.exe
+    #[arg(long, value_name = "PATH")]
+    pub usbpcap_cmd: Option<PathBuf>,
+
+    /// USBPcap root hub interface (e.g. \\.\USBPcap1)
+    #[arg(long, value_name = "NAME")]
+    pub interface: Option<String>,
+
+    /// Mouse resolution in counts per inch, for physical distance and speed
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub cpi: Option<u32>,
+}
+
+/// Automatic stop conditions for unattended captures
+#[derive(Debug, Args)]
+pub struct StopArgs {
+    /// Stop after this many seconds (counted from the first motion with --arm-on-motion)
+    #[arg(long, value_name = "SECS", value_parser = parse_positive_secs)]
+    pub max_duration: Option<f64>,
+
+    /// Stop after recording this many events
+    #[arg(long, value_name = "N", value_parser = parse_positive_count)]
+    pub max_events: Option<usize>,
+
+    /// Stop when the mouse has not moved for this many seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_positive_secs)]
+    pub idle_timeout: Option<f64>,
+
+    /// Start recording only once the mouse moves
+    #[arg(long)]
+    pub arm_on_motion: bool,
+}
+
+/// Test setup notes stored in session files
+#[derive(Debug, Args)]
+pub struct NotesArgs {
+    /// Mouse model [default: from config]
+    #[arg(long, value_name = "NAME")]
+    pub mouse_model: Option<String>,
+
+    /// Surface the mouse is used on, e.g. "cloth pad" [default: from config]
+    #[arg(long, value_name = "TEXT")]
+    pub surface: Option<String>,
+
+    /// Free-form comment
+    #[arg(long, value_name = "TEXT")]
+    pub note: Option<String>,
+}
+
+/// Saving the raw USBPcap stream for Wireshark or replay
+#[derive(Debug, Args)]
+pub struct RawPcapArgs {
+    /// Save the raw pcap stream from USBPcapCMD to this file while capturing
+    #[arg(long, value_name = "PATH")]
+    pub raw_pcap: Option<PathBuf>,
+
+    /// What to save: the exact stream (all) or only the target device's records (target)
+    #[arg(long, value_name = "MODE", default_value_t = RawTeeMode::All, requires = "raw_pcap")]
+    pub raw_pcap_mode: RawTeeMode,
+}
+
+impl RawPcapArgs {
+    pub fn output(&self) -> Option<RawOutput> {
+        self.raw_pcap.clone().map(|path| RawOutput { path, mode: self.raw_pcap_mode })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AnalyzeArgs {
+    /// Capture file to analyze
+    pub input: PathBuf,
+
+    /// Input format (detected from the extension if omitted)
+    #[arg(long = "input-format", value_name = "FORMAT")]
+    pub input_format: Option<EventFormat>,
+
+    /// Target device for pcap input, as bus.device.endpoint
+    #[arg(short = 'd', long = "device", value_name = "BUS.DEV.EP")]
+    pub device: Option<TargetDevice>,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct DevicesArgs {
+    /// How long to listen for reports, in seconds
+    #[arg(short, long, default_value_t = 5.0, value_parser = parse_positive_secs)]
+    pub seconds: f64,
+
+    #[command(flatten)]
+    pub source: SourceArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ConvertArgs {
+    /// Source capture file
+    pub input: PathBuf,
+
+    /// Destination file
+    pub output: PathBuf,
+
+    /// Input format (detected from the extension if omitted)
+    #[arg(long = "input-format", value_name = "FORMAT")]
+    pub input_format: Option<EventFormat>,
+
+    /// Output format (detected from the extension if omitted)
+    #[arg(short = 'f', long = "format", value_name = "FORMAT")]
+    pub format: Option<EventFormat>,
+
+    /// Target device for pcap input, as bus.device.endpoint
+    #[arg(short = 'd', long = "device", value_name = "BUS.DEV.EP")]
+    pub device: Option<TargetDevice>,
+}
+
+#[derive(Debug, Args)]
+pub struct CompareArgs {
+    /// Capture files to compare; the first is the baseline
+    #[arg(required = true, num_args = 2..)]
+    pub inputs: Vec<PathBuf>,
+
+    /// Target device for pcap inputs, as bus.device.endpoint
+    #[arg(short = 'd', long = "device", value_name = "BUS.DEV.EP")]
+    pub device: Option<TargetDevice>,
+}
+
+#[derive(Debug, Args)]
+pub struct GuiArgs {
+    /// Target device as bus.device.endpoint (e.g. 1.2.1)
+    #[arg(short = 'd', long = "device", value_name = "BUS.DEV.EP")]
+    pub device: Option<TargetDevice>,
+
+    #[command(flatten)]
+    pub source: SourceArgs,
+
+    #[command(flatten)]
+    pub stop: StopArgs,
+
+    /// Also write events to this session file while capturing (overwritten on restart)
+    #[arg(long, value_name = "PATH")]
+    pub session: Option<PathBuf>,
+
+    /// Keep events only in the session file and plot them from there, for captures too big for memory
+    #[arg(long, requires = "session")]
+    pub disk_only: bool,
+
+    /// Show a saved capture (CSV, pcap or session file) instead of starting a live one
+    #[arg(long, value_name = "PATH")]
+    pub open: Option<PathBuf>,
+
+    /// Format of the --open file (detected from the extension if omitted)
+    #[arg(long = "input-format", value_name = "FORMAT", requires = "open")]
+    pub input_format: Option<EventFormat>,
+
+    #[command(flatten)]
+    pub raw: RawPcapArgs,
+
+    #[command(flatten)]
+    pub notes: NotesArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RecoverArgs {
+    /// Session file to repair
+    pub input: PathBuf,
+}
+
+/// Where and how analysis results are written
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Events output file [default: from config, output.csv]
+    #[arg(short = 'o', long = "output", value_name = "PATH")]
+    pub output: Option<PathBuf>,
+
+    /// Directory for default-named output files [default: from config, .]
+    #[arg(long, value_name = "DIR")]
+    pub output_dir: Option<PathBuf>,
+
+    /// Events output format (detected from the extension if omitted)
+    #[arg(short = 'f', long = "format", value_name = "FORMAT")]
+    pub format: Option<EventFormat>,
+
+    /// Do not write the events file
+    #[arg(long, conflicts_with_all = ["output", "format"])]
+    pub no_output: bool,
+
+    /// Plot image file, .png or .svg [default: from config, mouse_plot.png]
+    #[arg(long = "plot", value_name = "PATH")]
+    pub plot: Option<PathBuf>,
+
+    /// Do not render the plot image
+    #[arg(long, conflicts_with = "plot")]
+    pub no_plot: bool,
+
+    /// Plot width in pixels [default: from config, 3840]
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(64..=32768))]
+    pub plot_width: Option<u32>,
+
+    /// Plot height in pixels [default: from config, 2160]
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(64..=32768))]
+    pub plot_height: Option<u32>,
+
+    /// Plot resolution; scales text and lines relative to 96 [default: from config, 96]
+    #[arg(long, value_name = "DPI", value_parser = clap::value_parser!(u32).range(24..=1200))]
+    pub plot_dpi: Option<u32>,
+
+    /// Plot panels, comma-separated: time, interval, interval_histogram, magnitude_histogram, path, stats [default: from config, all]
+    #[arg(long, value_name = "PANELS", value_delimiter = ',')]
+    pub panels: Vec<Panel>,
+
+    /// Draw every event in the plot instead of a reduction with min/max shading [default: from config, off]
+    #[arg(long)]
+    pub full_resolution: bool,
+
+    /// Do not open the plot in the default image viewer
+    #[arg(long)]
+    pub no_open: bool,
+
+    /// Also write an analysis report in these formats (comma-separated: json, html)
+    #[arg(long = "report", value_name = "FORMAT", value_delimiter = ',')]
+    pub report: Vec<ReportFormat>,
+
+    /// Report file; with several formats the extension is replaced [default: from config, report.<format>]
+    #[arg(long = "report-output", value_name = "PATH", requires = "report")]
+    pub report_output: Option<PathBuf>,
+}
+
+fn parse_positive_secs(s: &str) -> Result<f64, String> {
+    let v: f64 = s.parse().map_err(|_| format!("'{}' is not a number", s))?;
+    if v.is_finite() && v > 0.0 { Ok(v) } else { Err("must be a positive number of seconds".to_string()) }
+}
+
+fn parse_positive_count(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(v) if v > 0 => Ok(v),
+        _ => Err("must be a positive whole number".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_capture_options() {
+        let cli = Cli::try_parse_from(["mt", "-v", "capture", "-d", "1.2.1", "-o", "run.csv", "--plot-width", "1920", "--no-open", "--cpi", "800"]).unwrap();
+        assert_eq!(cli.verbosity(), 1);
+        let Command::Capture(args) = cli.command else { panic!("expected capture") };
+        assert_eq!(args.device.unwrap().to_string(), "1.2.1");
+        assert_eq!(args.output.output.unwrap(), PathBuf::from("run.csv"));
+        assert_eq!(args.output.plot_width, Some(1920));
+        assert!(args.output.panels.is_empty());
+        assert!(args.output.no_open);
+        assert_eq!(args.source.cpi, Some(800));
+        assert_eq!(args.stop.max_duration, None);
+        let cli = Cli::try_parse_from(["mt", "analyze", "a.csv", "--plot", "a.svg", "--plot-dpi", "192", "--panels", "time,path,stats", "--full-resolution"]).unwrap();
+        let Command::Analyze(args) = cli.command else { panic!("expected analyze") };
+        assert_eq!(args.output.plot_dpi, Some(192));
+        assert_eq!(args.output.panels, vec![Panel::Time, Panel::Path, Panel::Stats]);
+        assert!(args.output.full_resolution);
+    }
+
+    #[test]
+    fn test_parse_stop_options() {
+        let cli = Cli::try_parse_from(["mt", "gui", "--max-duration", "30", "--idle-timeout", "1.5", "--max-events", "10000", "--arm-on-motion"]).unwrap();
+        let Command::Gui(args) = cli.command else { panic!("expected gui") };
+        assert_eq!(args.stop.max_duration, Some(30.0));
+        assert_eq!(args.stop.idle_timeout, Some(1.5));
+        assert_eq!(args.stop.max_events, Some(10000));
+        assert!(args.stop.arm_on_motion);
+        assert!(Cli::try_parse_from(["mt", "capture", "--max-events", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_session_options() {
+        let cli = Cli::try_parse_from(["mt", "capture", "--session", "soak.mtsession", "--disk-only"]).unwrap();
+        let Command::Capture(args) = cli.command else { panic!("expected capture") };
+        assert_eq!(args.session, Some(PathBuf::from("soak.mtsession")));
+        assert!(args.disk_only);
+        let cli = Cli::try_parse_from(["mt", "gui", "--session", "s.mtsession", "--mouse-model", "G Pro", "--surface", "glass", "--note", "after cleaning"]).unwrap();
+        let Command::Gui(args) = cli.command else { panic!("expected gui") };
+        assert_eq!(args.notes.mouse_model.as_deref(), Some("G Pro"));
+        assert_eq!(args.notes.surface.as_deref(), Some("glass"));
+        assert_eq!(args.notes.note.as_deref(), Some("after cleaning"));
+        assert!(Cli::try_parse_from(["mt", "capture", "--disk-only"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_raw_pcap_options() {
+        let cli = Cli::try_parse_from(["mt", "capture", "-d", "1.2.1", "--raw-pcap", "raw.pcap", "--raw-pcap-mode", "target"]).unwrap();
+        let Command::Capture(args) = cli.command else { panic!("expected capture") };
+        assert_eq!(args.raw.output(), Some(RawOutput { path: PathBuf::from("raw.pcap"), mode: RawTeeMode::Target }));
+        let cli = Cli::try_parse_from(["mt", "gui", "--raw-pcap", "raw.pcap"]).unwrap();
+        let Command::Gui(args) = cli.command else { panic!("expected gui") };
+        assert_eq!(args.raw.output().unwrap().mode, RawTeeMode::All);
+        assert!(Cli::try_parse_from(["mt", "capture", "--raw-pcap-mode", "all"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "capture", "--raw-pcap", "r.pcap", "--raw-pcap-mode", "some"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_report_options() {
+        let cli = Cli::try_parse_from(["mt", "analyze", "run.csv", "--report", "json", "--report-output", "out/run.json"]).unwrap();
+        let Command::Analyze(args) = cli.command else { panic!("expected analyze") };
+        assert_eq!(args.output.report, vec![ReportFormat::Json]);
+        assert_eq!(args.output.report_output, Some(PathBuf::from("out/run.json")));
+        let cli = Cli::try_parse_from(["mt", "capture", "--report", "json,html"]).unwrap();
+        let Command::Capture(args) = cli.command else { panic!("expected capture") };
+        assert_eq!(args.output.report, vec![ReportFormat::Json, ReportFormat::Html]);
+        assert!(Cli::try_parse_from(["mt", "capture", "--report", "xml"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "capture", "--report-output", "r.json"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_gui_open() {
+        let cli = Cli::try_parse_from(["mt", "gui", "-d", "1.2.1", "--open", "old.pcap", "--input-format", "pcap"]).unwrap();
+        let Command::Gui(args) = cli.command else { panic!("expected gui") };
+        assert_eq!(args.open, Some(PathBuf::from("old.pcap")));
+        assert_eq!(args.input_format, Some(EventFormat::Pcap));
+        assert!(Cli::try_parse_from(["mt", "gui", "--input-format", "csv"]).is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_values() {
+        assert!(Cli::try_parse_from(["mt", "capture", "-d", "1.2"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "capture", "--plot-width", "0"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "capture", "--panels", "pie"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "analyze", "a.csv", "--format", "xml"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "compare", "only_one.csv"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "devices", "--seconds", "-1"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "-q", "-v", "gui"]).is_err());
+        assert!(Cli::try_parse_from(["mt", "gui", "--cpi", "0"]).is_err());
+    }
+}
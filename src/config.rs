@@ -0,0 +1,418 @@
+//! Persistent defaults loaded from `mousetester.toml`
+//!
+//! Settings are layered, later layers overriding earlier ones key by key:
+//!
+//! 1. Built-in defaults (`Config::default()`)
+//! 2. Per-user file: `%APPDATA%\MouseTesterRust\mousetester.toml` on Windows,
+//!    `$XDG_CONFIG_HOME/mousetester/mousetester.toml` (or `~/.config/...`) elsewhere
+//! 3. Per-project file: the nearest `mousetester.toml` in the current directory
+//!    or one of its parents (or an explicit `--config <path>`)
+//! 4. Command line flags, applied by the front end
+//!
+//! # Example
+//!
+//! ```toml
+//! [capture]
+//! device = "1.2.1"
+//! usbpcap_cmd = 'C:\Program Files\USBPcap\USBPcapCMD.exe'
+//! interface = '\\.\USBPcap1'
+//!
+//! [stop]
+//! max_duration_secs = 30.0
+//! idle_timeout_secs = 2.0
+//! arm_on_motion = true
+//!
+//! [mouse]
+//! cpi = 1600
+//! model = "G Pro"
+//! surface = "cloth pad"
+//!
+//! [output]
+//! directory = "captures"
+//! events_template = "{date}_{time}_{device}"
+//! plot_template = "{date}_{time}_{device}_plot"
+//! report_template = "{date}_{time}_{device}_report"
+//!
+//! [plot]
+//! width = 1920
+//! height = 1080
+//! dpi = 144
+//! panels = ["time", "interval", "stats"]
+//! full_resolution = false
+//!
+//! [lod]
+//! initial_size = 10
+//! growth_factor = 1.6
+//! min_r_squared = 0.98
+//! balance_weight = 0.091
+//! degree = "cubic"  # linear, quadratic, cubic or quintic
+//! ```
+
+use crate::capture::{CaptureOptions, DEFAULT_USBPCAP_CMD, DEFAULT_USBPCAP_INTERFACE, StopConditions, TargetDevice};
+use crate::lod_advanced::SegmentParams;
+use crate::plot::Panel;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File name looked up for per-user and per-project configuration
+pub const CONFIG_FILE_NAME: &str = "mousetester.toml";
+
+/// Complete application configuration
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub capture: CaptureConfig,
+    pub stop: StopConditions,
+    pub mouse: MouseConfig,
+    pub output: OutputConfig,
+    pub plot: PlotConfig,
+    pub lod: SegmentParams,
+}
+
+/// Where and what to capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Target device as `bus.device.endpoint`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<TargetDevice>,
+    /// Path to `USBPcapCMD.exe`
+    pub usbpcap_cmd: PathBuf,
+    /// USBPcap root hub interface
+    pub interface: String,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            device: None,
+            usbpcap_cmd: PathBuf::from(DEFAULT_USBPCAP_CMD),
+            interface: DEFAULT_USBPCAP_INTERFACE.to_string(),
+        }
+    }
+}
+
+/// Properties of the mouse under test
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MouseConfig {
+    /// Sensor resolution in counts per inch, used to convert counts to physical units
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpi: Option<u32>,
+    /// Mouse model, recorded in session files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Surface the mouse is tested on, recorded in session files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+}
+
+/// Output file locations
+///
+/// Templates are file names without extension and may contain `{date}`
+/// (YYYYMMDD, UTC), `{time}` (HHMMSS, UTC), `{device}` and `{cpi}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub directory: PathBuf,
+    pub events_template: String,
+    pub plot_template: String,
+    pub report_template: String,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("."),
+            events_template: "output".to_string(),
+            plot_template: "mouse_plot".to_string(),
+            report_template: "report".to_string(),
+        }
+    }
+}
+
+/// Static plot image settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlotConfig {
+    pub width: u32,
+    pub height: u32,
+    /// Scales text and lines relative to 96 DPI
+    pub dpi: u32,
+    /// Panels of the report image, the time panel on top and the rest in a grid
+    pub panels: Vec<Panel>,
+    /// Draw every event instead of a reduction with min/max shading
+    pub full_resolution: bool,
+}
+
+impl Default for PlotConfig {
+    fn default() -> Self {
+        Self { width: 3840, height: 2160, dpi: 96, panels: Panel::ALL.to_vec(), full_resolution: false }
+    }
+}
+
+/// Values substituted into output name templates
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TemplateVars {
+    pub device: Option<TargetDevice>,
+    pub cpi: Option<u32>,
+    /// Seconds since the Unix epoch used for `{date}` and `{time}`
+    pub unix_secs: u64,
+}
+
+impl TemplateVars {
+    /// Variables for the current time
+    pub fn now(device: Option<TargetDevice>, cpi: Option<u32>) -> Self {
+        let unix_secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        Self { device, cpi, unix_secs }
+    }
+}
+
+/// Convert days since 1970-01-01 into a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Expand `{date}`, `{time}`, `{device}` and `{cpi}` in an output name template
+pub fn expand_template(template: &str, vars: &TemplateVars) -> String {
+    let secs = vars.unix_secs;
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let tod = secs % 86_400;
+
+    template
+        .replace("{date}", &format!("{:04}{:02}{:02}", year, month, day))
+        .replace("{time}", &format!("{:02}{:02}{:02}", tod / 3600, (tod / 60) % 60, tod % 60))
+        .replace("{device}", &vars.device.map(|d| format!("{}-{}-{}", d.bus_id, d.device_address, d.endpoint)).unwrap_or_else(|| "any".to_string()))
+        .replace("{cpi}", &vars.cpi.map(|c| c.to_string()).unwrap_or_else(|| "unknown".to_string()))
+}
+
+impl Config {
+    /// Parse a config from TOML text
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let config: Config = toml::from_str(text)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Serialize the config as TOML
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Check value ranges that would make capture or segmentation misbehave
+    pub fn validate(&self) -> Result<()> {
+        if self.plot.width < 64 || self.plot.height < 64 {
+            bail!("plot.width and plot.height must be at least 64 pixels");
+        }
+        if !(24..=1200).contains(&self.plot.dpi) {
+            bail!("plot.dpi must be between 24 and 1200");
+        }
+        if self.plot.panels.is_empty() {
+            bail!("plot.panels must list at least one panel");
+        }
+        if self.mouse.cpi == Some(0) {
+            bail!("mouse.cpi must be greater than 0");
+        }
+        if self.output.events_template.is_empty() || self.output.plot_template.is_empty() || self.output.report_template.is_empty() {
+            bail!("output templates must not be empty");
+        }
+        self.stop.validate().context("invalid [stop] settings")?;
+        let lod = &self.lod;
+        if lod.initial_size < lod.degree.terms() {
+            bail!("lod.initial_size must be at least {} ({} fits need {} points)", lod.degree.terms(), lod.degree.name(), lod.degree.terms());
+        }
+        if lod.growth_factor.is_nan() || lod.growth_factor <= 1.0 {
+            bail!("lod.growth_factor must be greater than 1.0");
+        }
+        if !(0.0..=1.0).contains(&lod.min_r_squared) {
+            bail!("lod.min_r_squared must be between 0.0 and 1.0");
+        }
+        if !(0.0..=1.0).contains(&lod.balance_weight) {
+            bail!("lod.balance_weight must be between 0.0 and 1.0");
+        }
+        Ok(())
+    }
+
+    /// Load the layered configuration
+    ///
+    /// `project_override` replaces the per-project lookup with an explicit file.
+    /// Returns the config and the files that were applied, in order.
+    pub fn load(project_override: Option<&Path>) -> Result<(Self, Vec<PathBuf>)> {
+        let mut files = Vec::new();
+        if let Some(user) = user_config_path().filter(|p| p.is_file()) {
+            files.push(user);
+        }
+        match project_override {
+            Some(path) => {
+                if !path.is_file() {
+                    bail!("Config file not found: {}", path.display());
+                }
+                files.push(path.to_path_buf());
+            }
+            None => {
+                if let Some(project) = env::current_dir().ok().and_then(|dir| find_project_config(&dir)) {
+                    // Avoid applying the same file twice when running from the config directory
+                    if !files.contains(&project) {
+                        files.push(project);
+                    }
+                }
+            }
+        }
+
+        let config = Self::load_files(&files)?;
+        Ok((config, files))
+    }
+
+    /// Merge the given TOML files over the defaults, later files winning
+    pub fn load_files(files: &[PathBuf]) -> Result<Self> {
+        let mut merged = toml::Table::new();
+        for path in files {
+            let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let table: toml::Table = text.parse().with_context(|| format!("Invalid TOML in {}", path.display()))?;
+            merge_tables(&mut merged, table);
+        }
+        let config: Config = merged.try_into().with_context(|| format!("Invalid configuration in {}", files.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Capture settings for `run_capture`
+    pub fn capture_options(&self) -> CaptureOptions {
+        CaptureOptions {
+            usbpcap_cmd: self.capture.usbpcap_cmd.clone(),
+            interface: self.capture.interface.clone(),
+            target_device: self.capture.device,
+            stop: self.stop,
+            ..CaptureOptions::default()
+        }
+    }
+
+    /// Default events file path: `<directory>/<events_template>.<ext>`
+    pub fn events_path(&self, vars: &TemplateVars, extension: &str) -> PathBuf {
+        self.output.directory.join(format!("{}.{}", expand_template(&self.output.events_template, vars), extension))
+    }
+
+    /// Default plot file path: `<directory>/<plot_template>.<ext>`
+    pub fn plot_path(&self, vars: &TemplateVars, extension: &str) -> PathBuf {
+        self.output.directory.join(format!("{}.{}", expand_template(&self.output.plot_template, vars), extension))
+    }
+
+    /// Default report file path: `<directory>/<report_template>.<ext>`
+    pub fn report_path(&self, vars: &TemplateVars, extension: &str) -> PathBuf {
+        self.output.directory.join(format!("{}.{}", expand_template(&self.output.report_template, vars), extension))
+    }
+}
+
+/// Recursively merge `overlay` into `base`, replacing non-table values
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base_table)), toml::Value::Table(overlay_table)) => merge_tables(base_table, overlay_table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Location of the per-user config file
+pub fn user_config_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("MouseTesterRust").join(CONFIG_FILE_NAME))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("mousetester").join(CONFIG_FILE_NAME))
+    }
+}
+
+/// Find the nearest `mousetester.toml` in `start` or its ancestors
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start.ancestors().map(|dir| dir.join(CONFIG_FILE_NAME)).find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fit::Degree;
+
+    #[test]
+    fn test_default_round_trip() {
+        let config = Config::default();
+        let text = config.to_toml().unwrap();
+        assert_eq!(Config::from_toml(&text).unwrap(), config);
+        assert_eq!(config.lod, SegmentParams::default());
+    }
+
+    #[test]
+    fn test_partial_config_keeps_defaults() {
+        let config = Config::from_toml("[capture]\ndevice = \"1.2.1\"\n[lod]\ngrowth_factor = 2.0\n").unwrap();
+        assert_eq!(config.capture.device, Some(TargetDevice { bus_id: 1, device_address: 2, endpoint: 1 }));
+        assert_eq!(config.capture.interface, DEFAULT_USBPCAP_INTERFACE);
+        assert_eq!(config.lod.growth_factor, 2.0);
+        assert_eq!(config.lod.initial_size, 10);
+        assert_eq!(config.plot, PlotConfig::default());
+        assert!(!config.stop.is_active());
+    }
+
+    #[test]
+    fn test_rejects_invalid_values() {
+        assert!(Config::from_toml("[capture]\ndevice = \"1.2\"\n").is_err());
+        assert!(Config::from_toml("[lod]\ngrowth_factor = 1.0\n").is_err());
+        assert!(Config::from_toml("[lod]\ndegree = \"quintic\"\ninitial_size = 5\n").is_err());
+        assert!(Config::from_toml("[lod]\ndegree = \"septic\"\n").is_err());
+        assert_eq!(Config::from_toml("[lod]\ndegree = \"linear\"\ninitial_size = 2\n").unwrap().lod.degree, Degree::Linear);
+        assert!(Config::from_toml("[plot]\nwidth = 0\n").is_err());
+        assert!(Config::from_toml("[plot]\npanels = []\n").is_err());
+        assert!(Config::from_toml("[plot]\npanels = [\"pie\"]\n").is_err());
+        assert_eq!(Config::from_toml("[plot]\npanels = [\"path\", \"stats\"]\n").unwrap().plot.panels, vec![Panel::Path, Panel::Stats]);
+        assert!(Config::from_toml("[unknown]\nx = 1\n").is_err());
+        assert!(Config::from_toml("[stop]\nmax_events = 0\n").is_err());
+    }
+
+    #[test]
+    fn test_layered_files_merge_by_key() {
+        let dir = env::temp_dir().join(format!("mousetester_config_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let user = dir.join("user.toml");
+        let project = dir.join("project.toml");
+        fs::write(&user, "[mouse]\ncpi = 800\n[plot]\nwidth = 1000\nheight = 500\n").unwrap();
+        fs::write(&project, "[plot]\nwidth = 2000\n").unwrap();
+
+        let config = Config::load_files(&[user, project]).unwrap();
+        assert_eq!(config.mouse.cpi, Some(800));
+        assert_eq!(config.plot.width, 2000);
+        assert_eq!(config.plot.height, 500);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_expand_template() {
+        let vars = TemplateVars {
+            device: Some(TargetDevice { bus_id: 1, device_address: 2, endpoint: 3 }),
+            cpi: Some(1600),
+            unix_secs: 1_700_000_000, // 2023-11-14 22:13:20 UTC
+        };
+        assert_eq!(expand_template("{date}_{time}_{device}_{cpi}", &vars), "20231114_221320_1-2-3_1600");
+        assert_eq!(expand_template("output", &TemplateVars::default()), "output");
+        assert_eq!(expand_template("{device}", &TemplateVars::default()), "any");
+    }
+}
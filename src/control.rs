@@ -0,0 +1,228 @@
+//! Console control of a running CLI capture
+//!
+//! Works on every platform, unlike the F2 watcher:
+//!
+//! - Ctrl-C / SIGINT requests a stop; the capture still ends with the usual
+//!   analysis. A second Ctrl-C exits immediately.
+//! - Commands typed on stdin, one per line: `stop`, `mark [label]`, `restart`
+//!
+//! Marks are placed using a `CaptureProgress`, either the in-memory `EventStore`
+//! or a `ProgressCounter` when events only go to a session file.
+
+use MouseTesterRust::capture::EventSink;
+use MouseTesterRust::event_store::EventStore;
+use MouseTesterRust::mouse_event::MouseMoveEvent;
+use anyhow::{Context, Result, bail};
+use std::{
+    io::BufRead,
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+/// A command typed on stdin during capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Stop,
+    Mark(Option<String>),
+    Restart,
+}
+
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (word, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let rest = rest.trim();
+        match word.to_ascii_lowercase().as_str() {
+            "stop" | "s" | "q" | "quit" => Ok(ControlCommand::Stop),
+            "mark" | "m" => Ok(ControlCommand::Mark((!rest.is_empty()).then(|| rest.to_string()))),
+            "restart" | "r" => Ok(ControlCommand::Restart),
+            _ => bail!("Unknown command '{}', expected stop, mark [label] or restart", s),
+        }
+    }
+}
+
+/// A point of interest marked during capture
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mark {
+    /// Number of events recorded when the mark was set
+    pub event_index: usize,
+    /// Time of the last recorded event in seconds (0 if none yet)
+    pub time_secs: f64,
+    pub label: String,
+}
+
+/// Where a mark lands: events recorded so far and the time of the last one in seconds
+pub trait CaptureProgress: Send + Sync {
+    fn position(&self) -> (usize, f64);
+}
+
+impl CaptureProgress for EventStore {
+    fn position(&self) -> (usize, f64) {
+        (self.len(), self.last().map(|e| e.time_secs()).unwrap_or(0.0))
+    }
+}
+
+/// Counts events without keeping them, for captures that only spill to disk
+#[derive(Debug, Clone, Default)]
+pub struct ProgressCounter {
+    count: Arc<AtomicUsize>,
+    last_time_us: Arc<AtomicU64>,
+}
+
+impl ProgressCounter {
+    pub fn reset(&self) {
+        self.count.store(0, Ordering::Release);
+        self.last_time_us.store(0, Ordering::Release);
+    }
+}
+
+impl EventSink for ProgressCounter {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.last_time_us.store(event.ts_sec as u64 * 1_000_000 + event.ts_usec as u64, Ordering::Release);
+        self.count.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl CaptureProgress for ProgressCounter {
+    fn position(&self) -> (usize, f64) {
+        (self.count.load(Ordering::Acquire), self.last_time_us.load(Ordering::Acquire) as f64 / 1e6)
+    }
+}
+
+/// Shared state between the capture loop, Ctrl-C handler and stdin reader
+#[derive(Clone)]
+pub struct ConsoleControl {
+    progress: Arc<dyn CaptureProgress>,
+    stop_flag: Arc<AtomicBool>,
+    restart: Arc<AtomicBool>,
+    marks: Arc<Mutex<Vec<Mark>>>,
+}
+
+impl ConsoleControl {
+    pub fn new(progress: impl CaptureProgress + 'static, stop_flag: Arc<AtomicBool>) -> Self {
+        Self { progress: Arc::new(progress), stop_flag, restart: Arc::new(AtomicBool::new(false)), marks: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Route Ctrl-C to `stop_flag`; a second Ctrl-C while stopping exits the process
+    pub fn install_ctrlc(&self) -> Result<()> {
+        let stop_flag = Arc::clone(&self.stop_flag);
+        ctrlc::set_handler(move || {
+            if stop_flag.swap(true, Ordering::SeqCst) {
+                eprintln!("Ctrl-C pressed again: exiting without analysis");
+                std::process::exit(130);
+            }
+            println!("Ctrl-C: requesting stop...");
+        })
+        .context("Failed to install Ctrl-C handler")
+    }
+
+    /// Read commands from stdin on a background thread until EOF
+    pub fn spawn_stdin_reader(&self) {
+        let control = self.clone();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match line.parse() {
+                    Ok(command) => control.handle(command),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+        });
+    }
+
+    /// Apply one command
+    pub fn handle(&self, command: ControlCommand) {
+        match command {
+            ControlCommand::Stop => {
+                println!("Stop requested...");
+                self.stop_flag.store(true, Ordering::SeqCst);
+            }
+            ControlCommand::Restart => {
+                println!("Restart requested, discarding events so far...");
+                self.restart.store(true, Ordering::SeqCst);
+                self.stop_flag.store(true, Ordering::SeqCst);
+            }
+            ControlCommand::Mark(label) => {
+                let (event_index, time_secs) = self.progress.position();
+                let mut marks = self.marks.lock().unwrap();
+                let label = label.unwrap_or_else(|| format!("mark {}", marks.len() + 1));
+                println!("Mark '{}' at event {} ({:.6}s)", label, event_index, time_secs);
+                marks.push(Mark { event_index, time_secs, label });
+            }
+        }
+    }
+
+    /// Whether the last stop was a restart request; clears the request and the marks
+    pub fn take_restart(&self) -> bool {
+        let restart = self.restart.swap(false, Ordering::SeqCst);
+        if restart {
+            self.marks.lock().unwrap().clear();
+        }
+        restart
+    }
+
+    /// Marks set during the capture
+    pub fn marks(&self) -> Vec<Mark> {
+        self.marks.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!("stop".parse::<ControlCommand>().unwrap(), ControlCommand::Stop);
+        assert_eq!(" Q ".parse::<ControlCommand>().unwrap(), ControlCommand::Stop);
+        assert_eq!("restart".parse::<ControlCommand>().unwrap(), ControlCommand::Restart);
+        assert_eq!("mark".parse::<ControlCommand>().unwrap(), ControlCommand::Mark(None));
+        assert_eq!("m  fast flick ".parse::<ControlCommand>().unwrap(), ControlCommand::Mark(Some("fast flick".to_string())));
+        assert!("pause".parse::<ControlCommand>().is_err());
+    }
+
+    #[test]
+    fn test_handle_mark_and_restart() {
+        let events = EventStore::new();
+        events.push(MouseMoveEvent::new(1, 0, 0, 0));
+        events.push(MouseMoveEvent::new(1, 0, 1, 250_000));
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let control = ConsoleControl::new(events, Arc::clone(&stop_flag));
+
+        control.handle(ControlCommand::Mark(None));
+        control.handle(ControlCommand::Mark(Some("lift".to_string())));
+        let marks = control.marks();
+        assert_eq!(marks.len(), 2);
+        assert_eq!(marks[0].label, "mark 1");
+        assert_eq!(marks[1].event_index, 2);
+        assert!((marks[1].time_secs - 1.25).abs() < 1e-9);
+        assert!(!stop_flag.load(Ordering::SeqCst));
+
+        control.handle(ControlCommand::Restart);
+        assert!(stop_flag.load(Ordering::SeqCst));
+        assert!(control.take_restart());
+        assert!(control.marks().is_empty());
+        assert!(!control.take_restart());
+    }
+
+    #[test]
+    fn test_progress_counter_places_marks() {
+        let mut counter = ProgressCounter::default();
+        let control = ConsoleControl::new(counter.clone(), Arc::new(AtomicBool::new(false)));
+        counter.push_event(MouseMoveEvent::new(1, 0, 2, 500_000));
+        control.handle(ControlCommand::Mark(None));
+        assert_eq!(control.marks()[0].event_index, 1);
+        assert!((control.marks()[0].time_secs - 2.5).abs() < 1e-9);
+        counter.reset();
+        assert_eq!(counter.position(), (0, 0.0));
+    }
+}
@@ -0,0 +1,230 @@
+//! Append-only event storage shared between a capture thread and its readers
+//!
+//! `EventStore` replaces `Arc<Mutex<Vec<MouseMoveEvent>>>` for live captures:
+//!
+//! - Events are appended to a small tail chunk. Full chunks are sealed into
+//!   immutable `Arc<[MouseMoveEvent]>` blocks that are never copied again.
+//! - The event count is an atomic, so polling it (e.g. every GUI frame) takes no lock.
+//! - `snapshot` clones the chunk handles and copies at most one tail chunk,
+//!   regardless of how long the capture has been running.
+//!
+//! The tail lock is only taken by the producer and by `snapshot`/`last`, and
+//! is held for a single push or a copy of at most `CHUNK_LEN` events, so the
+//! capture thread never waits on the UI for longer than that.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use MouseTesterRust::event_store::EventStore;
+//!
+//! let store = EventStore::new();
+//! let writer = store.clone(); // hand this one to the capture thread
+//! writer.push(event);
+//! assert_eq!(store.len(), 1);
+//! let events: Vec<_> = store.snapshot().to_vec();
+//! ```
+
+use crate::capture::EventSink;
+use crate::mouse_event::MouseMoveEvent;
+use std::sync::{
+    Arc, Mutex, RwLock,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of events per sealed chunk
+pub const CHUNK_LEN: usize = 4096;
+
+#[derive(Debug, Default)]
+struct Shared {
+    sealed: RwLock<Vec<Arc<[MouseMoveEvent]>>>,
+    tail: Mutex<Vec<MouseMoveEvent>>,
+    len: AtomicUsize,
+}
+
+/// Chunked append-only event store; clones are handles to the same storage
+#[derive(Debug, Clone, Default)]
+pub struct EventStore {
+    shared: Arc<Shared>,
+}
+
+impl EventStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append one event
+    ///
+    /// Intended for a single producer; concurrent pushes are safe but their
+    /// relative order is unspecified.
+    pub fn push(&self, event: MouseMoveEvent) {
+        let mut tail = self.shared.tail.lock().unwrap();
+        if tail.capacity() == 0 {
+            tail.reserve_exact(CHUNK_LEN);
+        }
+        tail.push(event);
+        if tail.len() == CHUNK_LEN {
+            let chunk: Arc<[MouseMoveEvent]> = std::mem::replace(&mut *tail, Vec::with_capacity(CHUNK_LEN)).into();
+            self.shared.sealed.write().unwrap().push(chunk);
+        }
+        self.shared.len.fetch_add(1, Ordering::Release);
+    }
+
+    /// Number of events stored, without locking
+    pub fn len(&self) -> usize {
+        self.shared.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The most recently stored event
+    pub fn last(&self) -> Option<MouseMoveEvent> {
+        let tail = self.shared.tail.lock().unwrap();
+        if let Some(e) = tail.last() {
+            return Some(*e);
+        }
+        self.shared.sealed.read().unwrap().last().and_then(|chunk| chunk.last().copied())
+    }
+
+    /// Consistent view of all events stored so far
+    pub fn snapshot(&self) -> EventSnapshot {
+        // Holding the tail lock keeps the producer from sealing while we read
+        let tail = self.shared.tail.lock().unwrap();
+        let mut chunks = self.shared.sealed.read().unwrap().clone();
+        if !tail.is_empty() {
+            chunks.push(tail.as_slice().into());
+        }
+        let len = chunks.iter().map(|c| c.len()).sum();
+        EventSnapshot { chunks, len }
+    }
+
+    /// Remove all events
+    pub fn clear(&self) {
+        let mut tail = self.shared.tail.lock().unwrap();
+        self.shared.sealed.write().unwrap().clear();
+        tail.clear();
+        self.shared.len.store(0, Ordering::Release);
+    }
+}
+
+impl EventSink for EventStore {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        self.push(event);
+    }
+}
+
+/// Immutable view of an `EventStore` at one point in time
+#[derive(Debug, Clone, Default)]
+pub struct EventSnapshot {
+    chunks: Vec<Arc<[MouseMoveEvent]>>,
+    len: usize,
+}
+
+impl EventSnapshot {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Event at `index`
+    pub fn get(&self, index: usize) -> Option<&MouseMoveEvent> {
+        if index >= self.len {
+            return None;
+        }
+        // All chunks but the last are exactly CHUNK_LEN long
+        self.chunks.get(index / CHUNK_LEN).and_then(|c| c.get(index % CHUNK_LEN))
+    }
+
+    /// Iterate over all events in order
+    pub fn iter(&self) -> impl Iterator<Item = &MouseMoveEvent> {
+        self.chunks.iter().flat_map(|c| c.iter())
+    }
+
+    /// Iterate over events from `start` on, e.g. to process only new events
+    pub fn iter_from(&self, start: usize) -> impl Iterator<Item = &MouseMoveEvent> {
+        let (first_chunk, offset) = (start / CHUNK_LEN, start % CHUNK_LEN);
+        self.chunks.iter().enumerate().skip(first_chunk).flat_map(move |(i, c)| c.get(if i == first_chunk { offset } else { 0 }..).unwrap_or(&[]).iter())
+    }
+
+    /// Copy all events into a contiguous vector
+    pub fn to_vec(&self) -> Vec<MouseMoveEvent> {
+        let mut out = Vec::with_capacity(self.len);
+        for chunk in &self.chunks {
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn event(i: usize) -> MouseMoveEvent {
+        MouseMoveEvent::new((i % 100) as i16, -((i % 7) as i16), (i / 1000) as u32, ((i % 1000) * 1000) as u32)
+    }
+
+    #[test]
+    fn test_push_snapshot_across_chunks() {
+        let store = EventStore::new();
+        let n = CHUNK_LEN * 2 + 17;
+        for i in 0..n {
+            store.push(event(i));
+        }
+        assert_eq!(store.len(), n);
+        assert_eq!(store.last().unwrap().dx, event(n - 1).dx);
+
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.len(), n);
+        let all = snapshot.to_vec();
+        assert_eq!(all.len(), n);
+        for (i, e) in all.iter().enumerate() {
+            assert_eq!((e.dx, e.dy, e.ts_sec, e.ts_usec), (event(i).dx, event(i).dy, event(i).ts_sec, event(i).ts_usec));
+        }
+        assert_eq!(snapshot.get(CHUNK_LEN).unwrap().dx, event(CHUNK_LEN).dx);
+        assert!(snapshot.get(n).is_none());
+        assert_eq!(snapshot.iter_from(CHUNK_LEN + 3).count(), n - CHUNK_LEN - 3);
+        assert_eq!(snapshot.iter_from(n).count(), 0);
+        assert_eq!(snapshot.iter_from(n + 5).count(), 0);
+
+        // Later pushes do not change an existing snapshot
+        store.push(event(n));
+        assert_eq!(snapshot.len(), n);
+        assert_eq!(store.len(), n + 1);
+
+        store.clear();
+        assert!(store.is_empty());
+        assert!(store.snapshot().is_empty());
+        assert!(store.last().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_reader_sees_prefixes() {
+        let store = EventStore::new();
+        let writer = store.clone();
+        let n = CHUNK_LEN * 5 + 3;
+        let producer = thread::spawn(move || {
+            for i in 0..n {
+                writer.push(event(i));
+            }
+        });
+
+        let mut last_len = 0;
+        while last_len < n {
+            let snapshot = store.snapshot();
+            assert!(snapshot.len() >= last_len, "snapshots never shrink");
+            // Every snapshot is an exact prefix of the pushed sequence
+            if let Some(e) = snapshot.get(snapshot.len().saturating_sub(1)) {
+                assert_eq!(e.dx, event(snapshot.len() - 1).dx);
+            }
+            last_len = snapshot.len();
+        }
+        producer.join().unwrap();
+        assert_eq!(store.len(), n);
+    }
+}
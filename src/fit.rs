@@ -0,0 +1,251 @@
+//! Polynomial least-squares fitting shared by the LOD modules
+//!
+//! `fit_poly` fits a polynomial of a chosen `Degree` to samples, optionally
+//! weighted, using SVD of the design matrix for numerical stability.
+//! `normalize` maps the sample positions onto [0, 1] or [-1, 1] first to keep
+//! the design matrix well conditioned, and `FitMetrics` scores a fit by R²,
+//! RMSE and maximum error.
+
+use nalgebra::{DMatrix, DVector};
+use serde::{Deserialize, Serialize};
+
+/// Singular values below this, relative to the largest, are treated as zero
+const SVD_TOLERANCE: f64 = 1e-10;
+/// Smallest range `normalize` divides by
+const MIN_RANGE_VALUE: f64 = 1e-10;
+/// Total sum of squares below which data counts as constant and any fit as perfect
+pub const MIN_VARIANCE: f64 = 1e-10;
+/// Coefficients of the highest `Degree`
+pub const MAX_TERMS: usize = 6;
+
+/// Degree of a fitted polynomial
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Degree {
+    Linear,
+    Quadratic,
+    #[default]
+    Cubic,
+    Quintic,
+}
+
+impl Degree {
+    pub const ALL: [Degree; 4] = [Degree::Linear, Degree::Quadratic, Degree::Cubic, Degree::Quintic];
+
+    /// Highest power of x
+    pub fn value(self) -> usize {
+        match self {
+            Degree::Linear => 1,
+            Degree::Quadratic => 2,
+            Degree::Cubic => 3,
+            Degree::Quintic => 5,
+        }
+    }
+
+    /// Number of coefficients, and the fewest samples a fit needs
+    pub fn terms(self) -> usize {
+        self.value() + 1
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Degree::Linear => "linear",
+            Degree::Quadratic => "quadratic",
+            Degree::Cubic => "cubic",
+            Degree::Quintic => "quintic",
+        }
+    }
+}
+
+/// Polynomial f(x) = c0 + c1·x + ... + cd·x^d
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Poly {
+    coeffs: [f64; MAX_TERMS],
+    degree: Degree,
+}
+
+impl Poly {
+    /// Polynomial with `coeffs` from c0 up; missing coefficients are zero
+    pub fn new(degree: Degree, coeffs: &[f64]) -> Self {
+        assert!(coeffs.len() <= degree.terms(), "{} coefficients for a {} polynomial", coeffs.len(), degree.name());
+        let mut all = [0.0; MAX_TERMS];
+        all[..coeffs.len()].copy_from_slice(coeffs);
+        Self { coeffs: all, degree }
+    }
+
+    pub fn zero(degree: Degree) -> Self {
+        Self::new(degree, &[])
+    }
+
+    pub fn degree(&self) -> Degree {
+        self.degree
+    }
+
+    /// Coefficients from c0 up
+    pub fn coeffs(&self) -> &[f64] {
+        &self.coeffs[..self.degree.terms()]
+    }
+
+    /// Evaluate the polynomial at x
+    pub fn eval(&self, x: f64) -> f64 {
+        self.coeffs().iter().rev().fold(0.0, |acc, &c| acc * x + c)
+    }
+
+    /// The polynomial `scale · f(x) + offset`
+    pub fn scaled(&self, scale: f64, offset: f64) -> Self {
+        let mut poly = *self;
+        poly.coeffs.iter_mut().for_each(|c| *c *= scale);
+        poly.coeffs[0] += offset;
+        poly
+    }
+}
+
+/// Interval `normalize` maps values onto
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Domain {
+    /// [0, 1], minimum to maximum
+    Unit,
+    /// [-1, 1], centred on the midpoint
+    Symmetric,
+}
+
+/// Map `values` onto `domain` for better conditioning
+///
+/// Returns `(normalized, offset, scale)` with `normalized = (value - offset) * scale`.
+pub fn normalize(values: &[f64], domain: Domain) -> (Vec<f64>, f64, f64) {
+    if values.is_empty() {
+        return (Vec::new(), 0.0, 1.0);
+    }
+
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = (max - min).max(MIN_RANGE_VALUE);
+    let (offset, scale) = match domain {
+        Domain::Unit => (min, 1.0 / range),
+        Domain::Symmetric => ((min + max) / 2.0, 2.0 / range),
+    };
+
+    (values.iter().map(|&v| (v - offset) * scale).collect(), offset, scale)
+}
+
+/// Least-squares fit of a `degree` polynomial to the samples (x, y)
+///
+/// With `weights`, each sample's squared residual counts that many times;
+/// weights must not be negative. Returns `None` with fewer samples than
+/// coefficients or if SVD solving fails.
+pub fn fit_poly(x: &[f64], y: &[f64], weights: Option<&[f64]>, degree: Degree) -> Option<Poly> {
+    let (n, terms) = (x.len(), degree.terms());
+    if n < terms || y.len() != n || weights.is_some_and(|w| w.len() != n) {
+        return None;
+    }
+
+    // Design matrix [1, x, ..., x^d], rows scaled by the square root of their weight
+    let mut a_data = vec![0.0; n * terms];
+    let mut b_data = y.to_vec();
+    for i in 0..n {
+        let w = weights.map_or(1.0, |w| w[i].sqrt());
+        let mut power = w;
+        for value in &mut a_data[i * terms..(i + 1) * terms] {
+            *value = power;
+            power *= x[i];
+        }
+        b_data[i] *= w;
+    }
+
+    let a = DMatrix::from_row_slice(n, terms, &a_data);
+    let b = DVector::from_vec(b_data);
+    let coeffs = a.svd(true, true).solve(&b, SVD_TOLERANCE).ok()?;
+    Some(Poly::new(degree, coeffs.as_slice()))
+}
+
+/// How well a polynomial fits samples
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FitMetrics {
+    /// Coefficient of determination, 1.0 for constant data
+    pub r_squared: f64,
+    /// Root mean square residual
+    pub rmse: f64,
+    /// Largest absolute residual
+    pub max_error: f64,
+}
+
+impl FitMetrics {
+    /// Metrics of `poly` on the samples (x, y), weighted like `fit_poly`
+    ///
+    /// Samples with zero weight do not count towards the maximum error.
+    pub fn new(x: &[f64], y: &[f64], poly: &Poly, weights: Option<&[f64]>) -> Self {
+        let weight = |i: usize| weights.map_or(1.0, |w| w[i]);
+        let total_weight: f64 = (0..y.len()).map(weight).sum();
+        if y.is_empty() || x.len() != y.len() || total_weight <= 0.0 {
+            return Self::default();
+        }
+
+        let y_mean = (0..y.len()).map(|i| weight(i) * y[i]).sum::<f64>() / total_weight;
+        let (mut ss_tot, mut ss_res, mut max_error) = (0.0, 0.0, 0.0f64);
+        for i in 0..y.len() {
+            let residual = y[i] - poly.eval(x[i]);
+            ss_tot += weight(i) * (y[i] - y_mean).powi(2);
+            ss_res += weight(i) * residual.powi(2);
+            if weight(i) > 0.0 {
+                max_error = max_error.max(residual.abs());
+            }
+        }
+
+        let r_squared = if ss_tot < MIN_VARIANCE { 1.0 } else { 1.0 - ss_res / ss_tot };
+        Self { r_squared, rmse: (ss_res / total_weight).sqrt(), max_error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poly_eval_and_scaled() {
+        let poly = Poly::new(Degree::Cubic, &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!((poly.eval(0.0), poly.eval(1.0), poly.eval(2.0)), (1.0, 10.0, 49.0));
+        assert_eq!(Poly::new(Degree::Quintic, &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0]).eval(2.0), 32.0);
+        assert_eq!(poly.scaled(2.0, 5.0).coeffs(), [7.0, 4.0, 6.0, 8.0]);
+        assert_eq!(Poly::zero(Degree::Linear).coeffs(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let values = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let (unit, offset, scale) = normalize(&values, Domain::Unit);
+        assert_eq!((unit, offset, scale), (vec![0.0, 0.25, 0.5, 0.75, 1.0], 0.0, 0.25));
+        let (symmetric, offset, scale) = normalize(&values, Domain::Symmetric);
+        assert_eq!((symmetric, offset, scale), (vec![-1.0, -0.5, 0.0, 0.5, 1.0], 2.0, 0.5));
+        // Constant values do not divide by zero
+        assert!(normalize(&[3.0; 4], Domain::Unit).0.iter().all(|&v| v == 0.0));
+    }
+
+    #[test]
+    fn test_fit_poly_recovers_each_degree() {
+        let x: Vec<f64> = (0..20).map(|i| i as f64 / 19.0 * 2.0 - 1.0).collect();
+        for degree in Degree::ALL {
+            let truth = Poly::new(degree, &[0.5, -1.0, 2.0, 0.25, -3.0, 1.5][..degree.terms()]);
+            let y: Vec<f64> = x.iter().map(|&x| truth.eval(x)).collect();
+            let poly = fit_poly(&x, &y, None, degree).unwrap();
+            assert!(poly.coeffs().iter().zip(truth.coeffs()).all(|(a, b)| (a - b).abs() < 1e-9), "{:?}", poly);
+            let metrics = FitMetrics::new(&x, &y, &poly, None);
+            assert!((metrics.r_squared - 1.0).abs() < 1e-12 && metrics.rmse < 1e-9 && metrics.max_error < 1e-9);
+            assert!(fit_poly(&x[..degree.value()], &y[..degree.value()], None, degree).is_none());
+        }
+    }
+
+    #[test]
+    fn test_weighted_fit_ignores_zero_weights() {
+        let x = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let y = [1.0, 3.0, 100.0, 7.0, 9.0];
+        let weights = [1.0, 1.0, 0.0, 1.0, 1.0];
+        let poly = fit_poly(&x, &y, Some(&weights), Degree::Linear).unwrap();
+        assert!((poly.eval(0.0) - 1.0).abs() < 1e-9 && (poly.eval(1.0) - 3.0).abs() < 1e-9, "{:?}", poly);
+        let metrics = FitMetrics::new(&x, &y, &poly, Some(&weights));
+        assert!((metrics.r_squared - 1.0).abs() < 1e-12 && metrics.max_error < 1e-9);
+
+        // Unweighted, the outlier pulls the line and dominates the metrics
+        let unweighted = FitMetrics::new(&x, &y, &fit_poly(&x, &y, None, Degree::Linear).unwrap(), None);
+        assert!(unweighted.r_squared < 0.1 && unweighted.max_error > 50.0 && unweighted.rmse > 30.0, "{:?}", unweighted);
+    }
+}
@@ -0,0 +1,280 @@
+//! Reading and writing captured events in the supported file formats
+//!
+//! - `Csv`: `dx,dy,time` rows with time in seconds and an optional `# Summary` footer,
+//!   as written by the capture front end
+//! - `Pcap`: a raw USBPcap capture, replayed through `capture::capture_from_reader`
+//!   (read-only, needs a target device)
+//! - `Session`: the native binary format from `session` with exact timestamps,
+//!   written during capture and readable even after a crash
+//!
+//! CSV times are parsed digit by digit so the microsecond timestamps written
+//! with `{:.6}` round-trip exactly.
+
+use crate::analysis::Stats;
+use crate::capture::{TargetDevice, capture_from_reader};
+use crate::mouse_event::MouseMoveEvent;
+use crate::session::{SESSION_EXTENSION, SessionReader, SessionWriter};
+use anyhow::{Context, Result, anyhow, bail};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, atomic::AtomicBool},
+};
+
+/// Supported event file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFormat {
+    Csv,
+    Pcap,
+    Session,
+}
+
+impl EventFormat {
+    /// All formats, in the order shown to users
+    pub const ALL: [EventFormat; 3] = [EventFormat::Csv, EventFormat::Pcap, EventFormat::Session];
+
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "csv" => Some(EventFormat::Csv),
+            "pcap" => Some(EventFormat::Pcap),
+            SESSION_EXTENSION => Some(EventFormat::Session),
+            _ => None,
+        }
+    }
+
+    /// Whether events can be written in this format
+    pub fn is_writable(&self) -> bool {
+        matches!(self, EventFormat::Csv | EventFormat::Session)
+    }
+
+    /// Canonical lowercase name
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventFormat::Csv => "csv",
+            EventFormat::Pcap => "pcap",
+            EventFormat::Session => "session",
+        }
+    }
+
+    /// File extension for default file names
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EventFormat::Session => SESSION_EXTENSION,
+            other => other.name(),
+        }
+    }
+}
+
+impl FromStr for EventFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        EventFormat::ALL
+            .iter()
+            .copied()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("Unknown format '{}', expected one of: {}", s, EventFormat::ALL.map(|f| f.name()).join(", ")))
+    }
+}
+
+impl fmt::Display for EventFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Resolve the format of `path`, preferring an explicit choice over the extension
+pub fn resolve_format(path: &Path, explicit: Option<EventFormat>) -> Result<EventFormat> {
+    explicit
+        .or_else(|| EventFormat::from_path(path))
+        .ok_or_else(|| anyhow!("Cannot determine the format of {}; pass --format ({})", path.display(), EventFormat::ALL.map(|f| f.name()).join(", ")))
+}
+
+/// Parse a seconds value like `10.735498` into (ts_sec, ts_usec) without float rounding
+fn parse_time(s: &str) -> Result<(u32, u32)> {
+    let s = s.trim();
+    let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+    if !int_part.is_empty() && int_part.bytes().all(|b| b.is_ascii_digit()) && frac_part.bytes().all(|b| b.is_ascii_digit()) {
+        let ts_sec: u32 = int_part.parse()?;
+        let mut digits: String = frac_part.chars().take(6).collect();
+        while digits.len() < 6 {
+            digits.push('0');
+        }
+        return Ok((ts_sec, digits.parse()?));
+    }
+
+    // Fallback for other float notations (e.g. exponents)
+    let time: f64 = s.parse().with_context(|| format!("invalid time value '{}'", s))?;
+    if !time.is_finite() || time < 0.0 {
+        bail!("invalid time value '{}'", s);
+    }
+    let micros = (time * 1_000_000.0).round() as u64;
+    Ok(((micros / 1_000_000) as u32, (micros % 1_000_000) as u32))
+}
+
+/// Read events from CSV (`dx,dy,time` with optional header and `#` comment lines)
+pub fn read_csv<R: BufRead>(reader: R) -> Result<Vec<MouseMoveEvent>> {
+    let mut events = Vec::new();
+
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("dx,") {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split(',').collect();
+        if parts.len() < 3 {
+            bail!("line {}: expected dx,dy,time but found '{}'", line_num + 1, line);
+        }
+        let dx: i16 = parts[0].trim().parse().with_context(|| format!("line {}: invalid dx", line_num + 1))?;
+        let dy: i16 = parts[1].trim().parse().with_context(|| format!("line {}: invalid dy", line_num + 1))?;
+        let (ts_sec, ts_usec) = parse_time(parts[2]).with_context(|| format!("line {}", line_num + 1))?;
+
+        events.push(MouseMoveEvent::new(dx, dy, ts_sec, ts_usec));
+    }
+
+    Ok(events)
+}
+
+/// Write events as CSV, followed by a `# Summary` footer when stats are given
+pub fn write_csv<W: Write, I: IntoIterator<Item = MouseMoveEvent>>(mut w: W, events: I, stats: Option<&Stats>) -> Result<()> {
+    writeln!(w, "dx,dy,time")?;
+    for e in events {
+        writeln!(w, "{},{},{}.{:06}", e.dx, e.dy, e.ts_sec, e.ts_usec)?;
+    }
+    if let Some(stats) = stats {
+        writeln!(w, "\n# Summary")?;
+        writeln!(w, "# Count,{},TimeSpan(s),{:.6}", stats.count, stats.duration)?;
+        writeln!(w, "# TotalDistance,{:.6}", stats.total_distance)?;
+        writeln!(w, "# AvgDistancePerEvent,{:.6}", stats.avg_distance_per_event)?;
+        writeln!(w, "# AvgSpeed(units/s),{:.6}", stats.avg_speed)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Load events from a file in the given (or detected) format
+///
+/// `target_device` selects the mouse when reading pcap captures.
+pub fn load_events(path: &Path, format: Option<EventFormat>, target_device: Option<TargetDevice>) -> Result<Vec<MouseMoveEvent>> {
+    let format = resolve_format(path, format)?;
+    if format == EventFormat::Session {
+        let reader = open_session(path)?;
+        return reader.read_all();
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    match format {
+        EventFormat::Csv => read_csv(BufReader::new(file)).with_context(|| format!("Failed to read {}", path.display())),
+        EventFormat::Pcap => {
+            if target_device.is_none() {
+                bail!("Reading {} requires a target device (-d bus.device.endpoint)", path.display());
+            }
+            let mut events = Vec::new();
+            capture_from_reader(file, &mut events, Arc::new(AtomicBool::new(false)), target_device)?;
+            Ok(events)
+        }
+        EventFormat::Session => unreachable!("handled above"),
+    }
+}
+
+/// Open a session file for streaming, warning when a torn tail was skipped
+pub fn open_session(path: &Path) -> Result<SessionReader> {
+    let reader = SessionReader::open(path)?;
+    let info = reader.info();
+    if info.is_truncated() {
+        eprintln!(
+            "Warning: {} ends with {} bytes of an incomplete or corrupt chunk (interrupted capture?); using the {} events before it",
+            path.display(),
+            info.file_len - info.valid_len,
+            info.events
+        );
+    }
+    Ok(reader)
+}
+
+/// Save events to a file in the given (or detected) format
+pub fn save_events<I: IntoIterator<Item = MouseMoveEvent>>(path: &Path, format: Option<EventFormat>, events: I, stats: Option<&Stats>) -> Result<()> {
+    let format = resolve_format(path, format)?;
+    if !format.is_writable() {
+        bail!("Writing {} files is not supported", format);
+    }
+
+    match format {
+        EventFormat::Csv => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+            write_csv(BufWriter::new(file), events, stats)
+        }
+        EventFormat::Session => {
+            let mut writer = SessionWriter::create(path)?;
+            for e in events {
+                writer.push(e)?;
+            }
+            if let Some(stats) = stats {
+                writer.write_analysis(stats)?;
+            }
+            writer.finish().map(|_| ())
+        }
+        EventFormat::Pcap => unreachable!("pcap is read-only"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::compute_stats;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_time_exact() {
+        assert_eq!(parse_time("10.735498").unwrap(), (10, 735498));
+        assert_eq!(parse_time("0.000124").unwrap(), (0, 124));
+        assert_eq!(parse_time("3.5").unwrap(), (3, 500000));
+        assert_eq!(parse_time("7").unwrap(), (7, 0));
+        assert_eq!(parse_time("1.5e-3").unwrap(), (0, 1500));
+        assert!(parse_time("-1.0").is_err());
+        assert!(parse_time("abc").is_err());
+    }
+
+    #[test]
+    fn test_csv_round_trip() {
+        let events = vec![MouseMoveEvent::new(28, -6, 0, 0), MouseMoveEvent::new(26, -5, 0, 124), MouseMoveEvent::new(-1, 13, 10, 735498)];
+        let stats = compute_stats(&events);
+
+        let mut buf = Vec::new();
+        write_csv(&mut buf, events.iter().copied(), Some(&stats)).unwrap();
+        let text = String::from_utf8(buf.clone()).unwrap();
+        assert!(text.starts_with("dx,dy,time\n28,-6,0.000000\n"));
+        assert!(text.contains("# Summary"));
+
+        let loaded = read_csv(Cursor::new(buf)).unwrap();
+        assert_eq!(loaded.len(), events.len());
+        for (a, b) in loaded.iter().zip(&events) {
+            assert_eq!((a.dx, a.dy, a.ts_sec, a.ts_usec), (b.dx, b.dy, b.ts_sec, b.ts_usec));
+        }
+    }
+
+    #[test]
+    fn test_read_csv_reports_bad_lines() {
+        let err = read_csv(Cursor::new("dx,dy,time\n1,2,0.1\n1,x,0.2\n")).unwrap_err();
+        assert!(format!("{:#}", err).contains("line 3"));
+    }
+
+    #[test]
+    fn test_event_format_detection() {
+        assert_eq!(EventFormat::from_path(Path::new("a/b.CSV")), Some(EventFormat::Csv));
+        assert_eq!(EventFormat::from_path(Path::new("cap.pcap")), Some(EventFormat::Pcap));
+        assert_eq!(EventFormat::from_path(Path::new("noext")), None);
+        assert_eq!(EventFormat::from_path(Path::new("run.mtsession")), Some(EventFormat::Session));
+        assert_eq!(EventFormat::Session.extension(), "mtsession");
+        assert_eq!("PCAP".parse::<EventFormat>().unwrap(), EventFormat::Pcap);
+        assert!("xml".parse::<EventFormat>().is_err());
+        assert!(resolve_format(Path::new("noext"), None).is_err());
+    }
+}
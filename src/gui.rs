@@ -1,36 +1,253 @@
-use crate::mouse_event::MouseMoveEvent;
+use crate::control::{CaptureProgress, ProgressCounter};
+use MouseTesterRust::analysis::{Stats, compute_stats, compute_stats_with};
+use MouseTesterRust::capture::{CaptureOptions, run_capture};
+use MouseTesterRust::event_store::EventStore;
+use MouseTesterRust::formats::{EventFormat, load_events, open_session, resolve_format};
 // Import the new advanced LOD module
-use crate::lod_advanced::{Segment, build_segments, collect_visible_indices};
+use MouseTesterRust::lod_advanced::{Segment, SegmentBuilder, SegmentParams, Series};
+use MouseTesterRust::lod_file::{LodFile, LodFileKey, cache_path};
+use MouseTesterRust::lod_strategy::{AdvancedLod, LodStrategy, M4Lod, PlotView, SegmentTreeLod, m4_indices, m4_reduce};
+use MouseTesterRust::mouse_event::MouseMoveEvent;
+use MouseTesterRust::report::{Report, ReportFormat};
+use MouseTesterRust::session::{CaptureSettings, SESSION_EXTENSION, SessionMetadata, SessionWriter, with_timed_flush};
+use anyhow::Result;
 use eframe::egui;
+use std::path::{Path, PathBuf};
 use std::sync::{
-    Arc, Mutex,
-    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, PoisonError, mpsc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
 };
-use std::thread;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 #[cfg(windows)]
-use crate::key_utils;
+use MouseTesterRust::key_utils;
 
 pub struct MouseAnalyzerGui {
-    events: Arc<Mutex<Vec<MouseMoveEvent>>>,
+    events: EventStore,
+    live_count: ProgressCounter,                // Events of the running capture, also when only written to disk
+    capture_thread: Option<JoinHandle<()>>,     // Thread of the running capture, joined when a disk-only session is shown
     stop_flag: Arc<AtomicBool>,
     show_plot: bool,
     show_stats: bool,
     show_histogram: bool,
     show_events_table: bool,
     is_capturing: bool,
-    captured_events: Vec<MouseMoveEvent>,       // Events snapshot when capture stopped
+    captured_events: Arc<[MouseMoveEvent]>,     // Events snapshot when capture stopped, shared with the LOD worker
+    stats: Stats,                               // Stats of captured_events
     last_f2_state: bool,                        // For edge detection
-    target_device: Option<crate::TargetDevice>, // Store target device for restarts
-
-    // Advanced LOD state
-    advanced_lod_segments: Vec<Segment>,
+    pending_action: Option<CaptureAction>,      // Start/Stop/Restart button clicked last frame
+    capture_options: CaptureOptions,            // Store capture settings for restarts
+    segment_params: SegmentParams,              // build_segments parameters from the config
+    session: Option<SessionOutput>,             // Session file each capture run spills to
+    loaded_file: Option<PathBuf>,               // File the shown events were loaded from
+    reduced_from: Option<PathBuf>,              // Session the shown events are an M4 reduction of, if too large to hold
+    max_shown_events: usize,                    // Larger sessions are reduced with m4_reduce
+    load_error: Option<String>,                 // Last failed open, shown until the next one
+    metadata: Option<SessionMetadata>,          // Metadata of the shown events, for reports
+    export_status: Option<Result<PathBuf, String>>, // Outcome of the last report export
+
+    // LOD state
+    lod_choice: LodChoice,
+    m4_lod: M4Lod,
+    segment_tree_lod: Option<SegmentTreeLod>,
+    // Segments with their time index and a cache of the points of a widened view
+    advanced_lod: Option<AdvancedLod>,
     // Error points detected by regression analysis (indices of events with high residuals)
     // Filtered to only show points between min_x_visible and max_x_visible
     advanced_lod_error_points: Vec<usize>,
     advanced_lod_error_points_backup: Vec<usize>,
     advanced_lod_last_events_len: usize,
-    advanced_lod_last_bounds: Option<PlotBounds>,
+    // Plot view of the last frame, for comparing the strategies
+    lod_view: Option<PlotView>,
+    // Segment build running in the background; the plot uses M4 until it finishes
+    lod_job: Option<LodJob>,
+    lod_cancelled: bool,
+    // Finalized segments of the events in segmented, lent to lod_job while it runs
+    segment_builder: Option<SegmentBuilder>,
+    segmented: Segmented,
+    // Events of the running capture copied from its store, lent to live jobs and the live plot
+    live_events: Arc<Vec<MouseMoveEvent>>,
+    live_segmented_at: Option<Instant>,
+}
+
+/// Events the segment builder was last continued with
+enum Segmented {
+    /// Shown events; new ones continue them if they start with them
+    Shown(Arc<[MouseMoveEvent]>),
+    /// The first `n` events of the running capture, which only grow until it stops
+    Live(usize),
+}
+
+/// LOD strategy picked for the interactive plot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LodChoice {
+    M4,
+    SegmentTree,
+    Advanced,
+}
+
+impl LodChoice {
+    const ALL: [LodChoice; 3] = [LodChoice::M4, LodChoice::SegmentTree, LodChoice::Advanced];
+
+    /// Name of the strategy, as given by `LodStrategy::name`
+    fn name(self) -> &'static str {
+        match self {
+            LodChoice::M4 => "M4 min/max",
+            LodChoice::SegmentTree => "Segment tree",
+            LodChoice::Advanced => "Adaptive segments",
+        }
+    }
+}
+
+/// Share of `total` points left out when drawing `shown`, in percent
+fn reduction_percent(shown: usize, total: usize) -> f64 {
+    100.0 * (1.0 - shown as f64 / total.max(1) as f64)
+}
+
+/// Series of the movement plot lines, dx and -dy
+const PLOT_SERIES: [Series; 2] = [Series::Dx, Series::NegDy];
+
+/// Plot size the segment tree is built for, about the plot area at the default window size
+const SEGMENT_TREE_PLOT_SIZE: (usize, f64) = (1000, 500.0);
+
+/// How often a running capture is segmented, so stopping it leaves only the newest events
+const LIVE_SEGMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Seconds of a running capture the live plot shows
+const LIVE_PLOT_WINDOW: f64 = 10.0;
+
+/// Sessions with more events are shown as an M4 reduction streamed from disk (about 200 MB of events)
+const MAX_SHOWN_EVENTS: usize = 16 << 20;
+
+/// Segment tree, indexed segments and error points of the shown events, built by a `LodJob`
+type LodResult = (SegmentTreeLod, AdvancedLod, Vec<usize>);
+
+/// What a `LodJob` sends back
+struct LodOutput {
+    /// Continued with the job's events, also when it was cancelled
+    builder: SegmentBuilder,
+    /// `None` for live and cancelled jobs
+    result: Option<LodResult>,
+}
+
+/// Segment building, segment tree and error point detection on a worker thread
+///
+/// Dropping the job cancels it.
+struct LodJob {
+    total: usize,
+    /// Events segmented before the job started
+    resumed: usize,
+    progress: Arc<AtomicUsize>,
+    cancel: Arc<AtomicBool>,
+    result: mpsc::Receiver<LodOutput>,
+}
+
+impl LodJob {
+    /// Continue `builder` with `events` on a worker thread
+    ///
+    /// A `live` job only finalizes the segments of a running capture; otherwise the
+    /// job also segments the rest and builds the strategies and error points. With a
+    /// `cache` file, it loads them from there if the file is up to date, or saves them
+    /// there once built. The job drops `shared` before sending its output.
+    fn spawn<E: AsRef<[MouseMoveEvent]> + Send + Sync + ?Sized + 'static>(shared: Arc<E>, mut builder: SegmentBuilder, live: bool, cache: Option<PathBuf>) -> Self {
+        let resumed = builder.finalized_len();
+        let progress = Arc::new(AtomicUsize::new(resumed));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (tx, result) = mpsc::channel();
+        let total = (*shared).as_ref().len();
+        let (done, stop) = (Arc::clone(&progress), Arc::clone(&cancel));
+        thread::spawn(move || {
+            let started = Instant::now();
+            let events = (*shared).as_ref();
+            let cache = cache.filter(|_| !live).map(|path| (LodFileKey::new(events, builder.params(), SEGMENT_TREE_PLOT_SIZE.0, SEGMENT_TREE_PLOT_SIZE.1), path));
+            if let Some((key, path)) = &cache
+                && let Some(file) = load_lod_cache(path, key)
+            {
+                let builder = SegmentBuilder::from_segments(*builder.params(), &file.segments, file.finalized_len);
+                let error_points = calculate_error_points(&file.segments, events);
+                println!("Loaded {} LOD segments and {} error points for {} events from {} in {:.2?}", file.segments.len(), error_points.len(), events.len(), path.display(), started.elapsed());
+                let advanced = AdvancedLod::new(file.segments, events);
+                drop(shared);
+                tx.send(LodOutput { builder, result: Some((SegmentTreeLod::new(file.tree), advanced, error_points)) }).ok();
+                return;
+            }
+            let report = |n: usize| {
+                done.store(n, Ordering::Relaxed);
+                !stop.load(Ordering::Relaxed)
+            };
+            let finished = builder.extend_with_progress(events, report) && !stop.load(Ordering::Relaxed);
+            if !finished || live {
+                if !finished {
+                    println!("LOD build cancelled");
+                }
+                // A live buffer must be unshared again by the time the GUI gets the builder, so it can grow in place
+                drop(shared);
+                tx.send(LodOutput { builder, result: None }).ok();
+                return;
+            }
+            let segments = builder.segments(events);
+            // The builder is kept until the events change, without its sums
+            builder.release_fitter();
+            let tree = SegmentTreeLod::build(events, SEGMENT_TREE_PLOT_SIZE.0, SEGMENT_TREE_PLOT_SIZE.1, builder.params().degree);
+            let error_points = calculate_error_points(&segments, events);
+            let discrete = segments.iter().filter(|s| matches!(s, Segment::Discrete { .. })).count();
+            println!("Built {} LOD segments ({} discrete) and {} error points for {} events ({} new) in {:.2?}", segments.len(), discrete, error_points.len(), events.len(), events.len() - resumed, started.elapsed());
+            let (tree, segments) = match cache {
+                Some((key, path)) => {
+                    let file = LodFile { key, tree: tree.tree, segments, finalized_len: builder.finalized_len() };
+                    match file.save(&path) {
+                        Ok(()) => println!("Saved LOD cache {}", path.display()),
+                        Err(e) => eprintln!("LOD cache error: {:#}", e),
+                    }
+                    (SegmentTreeLod::new(file.tree), file.segments)
+                }
+                None => (tree, segments),
+            };
+            let advanced = AdvancedLod::new(segments, events);
+            drop(shared);
+            tx.send(LodOutput { builder, result: Some((tree, advanced, error_points)) }).ok();
+        });
+        Self { total, resumed, progress, cancel, result }
+    }
+
+    /// Share of the events segmented so far
+    fn fraction(&self) -> f32 {
+        self.progress.load(Ordering::Relaxed) as f32 / self.total.max(1) as f32
+    }
+
+    /// Stop the job and wait for the segments it finalized
+    fn cancel(self) -> Option<SegmentBuilder> {
+        self.cancel.store(true, Ordering::Relaxed);
+        self.result.recv().ok().map(|output| output.builder)
+    }
+}
+
+/// The LOD cache file at `path` if it was built for `key`; damaged files are reported and ignored
+fn load_lod_cache(path: &Path, key: &LodFileKey) -> Option<LodFile> {
+    LodFile::load(path, key).unwrap_or_else(|e| {
+        eprintln!("LOD cache error: {:#}", e);
+        None
+    })
+}
+
+/// Whether `events` start with all of `prefix`
+fn continues(prefix: &[MouseMoveEvent], events: &[MouseMoveEvent]) -> bool {
+    prefix.len() <= events.len() && prefix.iter().zip(events).all(|(a, b)| (a.dx, a.dy, a.ts_sec, a.ts_usec) == (b.dx, b.dy, b.ts_sec, b.ts_usec))
+}
+
+impl Drop for LodJob {
+    fn drop(&mut self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Capture control requested from the GUI buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CaptureAction {
+    Start,
+    Stop,
+    Restart,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -42,140 +259,75 @@ struct PlotBounds {
 }
 
 impl MouseAnalyzerGui {
-    pub fn new(events: Arc<Mutex<Vec<MouseMoveEvent>>>, stop_flag: Arc<AtomicBool>, target_device: Option<crate::TargetDevice>) -> Self {
+    pub fn new(events: EventStore, stop_flag: Arc<AtomicBool>, capture_options: CaptureOptions, segment_params: SegmentParams) -> Self {
         Self {
             events,
+            live_count: ProgressCounter::default(),
+            capture_thread: None,
             stop_flag,
             show_plot: true,
             show_stats: false,
             show_histogram: false,
             show_events_table: false,
             is_capturing: true, // Start capturing initially
-            captured_events: Vec::new(),
+            captured_events: Arc::default(),
+            stats: Stats::default(),
             last_f2_state: false,
-            target_device,
-
-            // Advanced LOD initialization
-            advanced_lod_segments: Vec::new(),
+            pending_action: None,
+            capture_options,
+            segment_params,
+            session: None,
+            loaded_file: None,
+            reduced_from: None,
+            max_shown_events: MAX_SHOWN_EVENTS,
+            load_error: None,
+            metadata: None,
+            export_status: None,
+
+            // LOD initialization
+            lod_choice: LodChoice::Advanced,
+            m4_lod: M4Lod,
+            segment_tree_lod: None,
+            advanced_lod: None,
             advanced_lod_error_points: Vec::new(),
             advanced_lod_error_points_backup: Vec::new(),
             advanced_lod_last_events_len: 0,
-            advanced_lod_last_bounds: None,
+            lod_view: None,
+            lod_job: None,
+            lod_cancelled: false,
+            segment_builder: None,
+            segmented: Segmented::Shown(Arc::default()),
+            live_events: Arc::default(),
+            live_segmented_at: None,
         }
     }
 
-    /// Check if plot bounds have changed significantly
-    fn bounds_changed_significantly(&self, new_bounds: &PlotBounds) -> bool {
-        match self.advanced_lod_last_bounds {
-            None => true, // First time, always recalculate
-            Some(old_bounds) => {
-                let x_range_old = (old_bounds.x_max - old_bounds.x_min).abs();
-                let y_range_old = (old_bounds.y_max - old_bounds.y_min).abs();
-                let x_range_new = (new_bounds.x_max - new_bounds.x_min).abs();
-                let y_range_new = (new_bounds.y_max - new_bounds.y_min).abs();
-
-                // Check if range changed by more than threshold (10%)
-                let x_change = ((x_range_new - x_range_old) / x_range_old.max(1e-6)).abs();
-                let y_change = ((y_range_new - y_range_old) / y_range_old.max(1e-6)).abs();
-
-                // Also check if center position moved significantly
-                let x_center_old = (old_bounds.x_min + old_bounds.x_max) / 2.0;
-                let x_center_new = (new_bounds.x_min + new_bounds.x_max) / 2.0;
-                let y_center_old = (old_bounds.y_min + old_bounds.y_max) / 2.0;
-                let y_center_new = (new_bounds.y_min + new_bounds.y_max) / 2.0;
-
-                let x_center_change = ((x_center_new - x_center_old) / x_range_old.max(1e-6)).abs();
-                let y_center_change = ((y_center_new - y_center_old) / y_range_old.max(1e-6)).abs();
-
-                // Trigger if any change exceeds 10% threshold
-                let threshold = 0.1;
-                x_change > threshold || y_change > threshold || x_center_change > threshold || y_center_change > threshold
-            }
-        }
+    /// Spill every capture run to a session file as well as memory
+    pub fn with_session(mut self, session: Option<SessionOutput>) -> Self {
+        self.session = session;
+        self
     }
 
-    /// Calculate error points based on regression residuals
-    /// Error is detected when: abs(y0-y1)/max(smallestPositive,abs(y1)) > (sqrt(1-r2)/k)
-    fn calculate_error_points(&self, events: &[MouseMoveEvent]) -> Vec<usize> {
-        let mut error_points = Vec::new();
-        const K: f64 = 3.0;
-        const SMALLEST_POSITIVE: f64 = 1e-8;
-
-        for segment in &self.advanced_lod_segments {
-            if let Segment::Good { start_idx, end_idx, fit } = segment {
-                let n = end_idx - start_idx;
-                if n < 4 {
-                    continue;
-                }
-
-                // Normalize indices to [0, 1] for polynomial evaluation
-                let indices: Vec<f64> = (0..n).map(|i| i as f64).collect();
-                let max_idx = (n - 1) as f64;
-                let idx_norm: Vec<f64> = indices.iter().map(|&i| if max_idx > 0.0 { i / max_idx } else { 0.0 }).collect();
-
-                // Check each event in the segment
-                for (local_idx, &normalized_idx) in idx_norm.iter().enumerate() {
-                    let global_idx = start_idx + local_idx;
-                    if global_idx >= events.len() {
-                        continue;
-                    }
-
-                    let event = &events[global_idx];
-
-                    // Get actual values
-                    let dx_actual = event.dx as f64;
-                    let dy_actual = event.dy as f64;
-                    let time_actual = event.time_secs();
-
-                    // Get predicted values from polynomials
-                    let dx_pred = fit.dx_poly.eval(normalized_idx);
-                    let dy_pred = fit.dy_poly.eval(normalized_idx);
-                    let time_pred = fit.time_poly.eval(normalized_idx);
-
-                    // Calculate error thresholds for each dimension
-                    let dx_threshold = (1.0 - fit.dx_r_squared).max(0.0).sqrt() * K;
-                    let dy_threshold = (1.0 - fit.dy_r_squared).max(0.0).sqrt() * K;
-                    let time_threshold = (1.0 - fit.time_r_squared).max(0.0).sqrt() * K;
-
-                    // Calculate relative errors
-                    let dx_error = (dx_actual - dx_pred).abs() / dx_pred.abs().max(SMALLEST_POSITIVE);
-                    let dy_error = (dy_actual - dy_pred).abs() / dy_pred.abs().max(SMALLEST_POSITIVE);
-                    let time_error = (time_actual - time_pred).abs() / time_pred.abs().max(SMALLEST_POSITIVE);
+    /// Show the capture started before the GUI
+    pub fn with_capture(mut self, capture: CaptureRun) -> Self {
+        (self.events, self.live_count, self.capture_thread) = (capture.events, capture.count, Some(capture.thread));
+        self
+    }
 
-                    // Mark as error if any dimension exceeds threshold
-                    if dx_error > dx_threshold || dy_error > dy_threshold || time_error > time_threshold {
-                        error_points.push(global_idx);
-                    }
-                }
-            }
+    /// The LOD strategy for `choice`, if it has been built for the shown events
+    fn lod_strategy(&mut self, choice: LodChoice) -> Option<&mut dyn LodStrategy> {
+        match choice {
+            LodChoice::M4 => Some(&mut self.m4_lod),
+            LodChoice::SegmentTree => self.segment_tree_lod.as_mut().map(|s| s as &mut dyn LodStrategy),
+            LodChoice::Advanced => self.advanced_lod.as_mut().map(|s| s as &mut dyn LodStrategy),
         }
-
-        error_points
     }
 
-    /// NEW ADVANCED LOD: Apply the advanced LOD algorithm with regression-based segmentation
-    /// Returns indices into the events slice for rendering
-    fn apply_advanced_lod_indices(&mut self, events: &[MouseMoveEvent], visible_width: f64, visible_height: f64, plot_bounds: Option<&PlotBounds>) -> Vec<usize> {
+    /// Apply the chosen LOD strategy, or M4 while it is not built for `events`
+    /// Returns indices into the events slice for rendering each of `PLOT_SERIES`
+    fn apply_lod_indices(&mut self, events: &[MouseMoveEvent], visible_width: f64, visible_height: f64, plot_bounds: Option<&PlotBounds>) -> [Vec<usize>; 2] {
         if events.is_empty() {
-            return Vec::new();
-        }
-
-        // Check if we need to rebuild segments (events changed)
-        if events.len() != self.advanced_lod_last_events_len {
-            println!("Building advanced LOD segments for {} events...", events.len());
-            // Build segments with good parameters for real mouse data
-            // - balance_weight: 0.091 (ln(len) is not and cant be normalized to 0.0 ~ 1.0)
-            self.advanced_lod_segments = build_segments(events, 10, 1.6, 0.98, 0.091);
-            self.advanced_lod_last_events_len = events.len();
-            println!("Created {} segments", self.advanced_lod_segments.len());
-            println!("Created {} discrete segments", self.advanced_lod_segments.iter().find(|&s| if let Segment::Discrete { idx: _ } = s {
-                true
-            }else{false}).into_iter().count());
-
-            // Calculate error points after building segments
-            let all_error_points = self.calculate_error_points(events);
-            println!("Detected {} error points", all_error_points.len());
-            self.advanced_lod_error_points_backup = all_error_points;
+            return Default::default();
         }
 
         // Get bounds or use full range
@@ -185,9 +337,8 @@ impl MouseAnalyzerGui {
             // Full range
             let x_min = events.first().map(|e| e.time_secs()).unwrap_or(0.0);
             let x_max = events.last().map(|e| e.time_secs()).unwrap_or(1.0);
-            let y_min = events.iter().map(|e| -(e.dy as f64)).fold(f64::INFINITY, f64::min);
-            let y_max = events.iter().map(|e| -(e.dy as f64)).fold(f64::NEG_INFINITY, f64::max);
-            (x_min, x_max, y_min, y_max)
+            let values = || events.iter().flat_map(|e| [e.dx as f64, -(e.dy as f64)]);
+            (x_min, x_max, values().fold(f64::INFINITY, f64::min), values().fold(f64::NEG_INFINITY, f64::max))
         };
 
         // Calculate visible range with zoom factor
@@ -207,92 +358,431 @@ impl MouseAnalyzerGui {
             }
         });
 
-        // Collect visible indices with advanced LOD
-        // - tolerance: 3.0 (allow up to 3 events per pixel before hiding)
-        // - zoom_factor: 1.2
-        let indices = collect_visible_indices(
-            &self.advanced_lod_segments,
-            events,
-            visible_width,
-            visible_height,
-            (x_min, x_max),
-            (y_min, y_max),
-            3.0, // tolerance
-            1.2, // zoom_factor
-        );
+        let view = PlotView { width: visible_width, height: visible_height, x_range: (x_min, x_max), y_range: (y_min, y_max) };
+        self.lod_view = Some(view);
+        let choice = self.effective_lod_choice(events.len());
+        // Each line gets its own indices, so dx detail survives where -dy is flat and vice versa
+        PLOT_SERIES.map(|series| self.lod_strategy(choice).map(|s| s.visible_indices(events, series, &view)).unwrap_or_default())
+    }
+
+    /// Points each strategy keeps for each of `PLOT_SERIES` in the last plot view, `None` for strategies not built for `events`
+    fn lod_comparison(&mut self, events: &[MouseMoveEvent]) -> Vec<(&'static str, Option<[usize; 2]>)> {
+        let view = self.lod_view;
+        let built = self.advanced_lod_last_events_len == events.len();
+        LodChoice::ALL
+            .into_iter()
+            .map(|choice| {
+                let ready = choice == LodChoice::M4 || built;
+                let count = view.filter(|_| ready).and_then(|view| self.lod_strategy(choice).map(|s| PLOT_SERIES.map(|series| s.visible_indices(events, series, &view).len())));
+                (choice.name(), count)
+            })
+            .collect()
+    }
 
-        indices
+    /// The chosen strategy if it is built for `n_events`, M4 otherwise
+    ///
+    /// The polynomial strategies are built for the events of a stopped capture or
+    /// loaded file, so they are unavailable while building and during a capture.
+    fn effective_lod_choice(&self, n_events: usize) -> LodChoice {
+        if n_events == self.advanced_lod_last_events_len { self.lod_choice } else { LodChoice::M4 }
+    }
+
+    /// Show `events` and start building their LOD segments in the background
+    fn set_events(&mut self, events: impl Into<Arc<[MouseMoveEvent]>>) {
+        self.show_events(events, None);
+    }
+
+    /// Show `events`, or a reduction of the events of session `reduced_from` with their `stats`
+    ///
+    /// The events copied from a running capture for the live plot are dropped.
+    fn show_events(&mut self, events: impl Into<Arc<[MouseMoveEvent]>>, reduced: Option<(PathBuf, Stats)>) {
+        self.captured_events = events.into();
+        (self.reduced_from, self.stats) = match reduced {
+            Some((path, stats)) => (Some(path), stats),
+            None => (None, compute_stats(&self.captured_events)),
+        };
+        self.start_lod_build();
+        self.live_events = Arc::default();
+    }
+
+    /// Events of a session file to show, with the stats of all its events if they had to be reduced
+    ///
+    /// Sessions with more than `max_shown_events` events are streamed from disk
+    /// twice, for the stats and for an `m4_reduce` reduction, so they never have
+    /// to fit in memory.
+    fn read_session(&self, path: &Path) -> Result<(Vec<MouseMoveEvent>, Option<Stats>, Option<SessionMetadata>)> {
+        let reader = open_session(path)?;
+        let total = reader.info().events as usize;
+        if total <= self.max_shown_events {
+            return Ok((reader.read_all()?, None, reader.metadata().cloned()));
+        }
+        let stats = compute_stats_with(|| reader.events());
+        let events = m4_reduce(reader.events(), total, self.max_shown_events);
+        println!("{} has {} events, showing {} of them (M4 reduction)", path.display(), total, events.len());
+        Ok((events, Some(stats), reader.metadata().cloned()))
     }
 
-    fn calculate_stats(&self, events: &[MouseMoveEvent]) -> Stats {
+    /// Discard the segments and build new ones for the shown events on a worker thread
+    fn start_lod_build(&mut self) {
+        self.segment_tree_lod = None;
+        self.advanced_lod = None;
+        self.advanced_lod_error_points.clear();
+        self.advanced_lod_error_points_backup.clear();
+        self.advanced_lod_last_events_len = 0;
+        self.lod_cancelled = false;
+        let events = Arc::clone(&self.captured_events);
+        let builder = self.take_segment_builder(&events);
         if events.is_empty() {
-            return Stats::default();
+            self.segment_builder = Some(builder);
+        } else {
+            self.lod_job = Some(LodJob::spawn(events, builder, false, self.lod_cache_path()));
         }
+    }
 
-        let count = events.len();
-        let time_start = events.iter().map(|e| e.time_secs()).fold(f64::INFINITY, |a, b| a.min(b));
-        let time_end = events.iter().map(|e| e.time_secs()).fold(f64::NEG_INFINITY, |a, b| a.max(b));
-        let duration = (time_end - time_start).max(0.0);
+    /// LOD cache file of the shown events: next to the file they were loaded from or the session of the capture
+    fn lod_cache_path(&self) -> Option<PathBuf> {
+        self.loaded_file.as_deref().or(self.session.as_ref().map(|s| s.path.as_path())).map(cache_path)
+    }
 
-        let total_dx: i64 = events.iter().map(|e| e.dx as i64).sum();
-        let total_dy: i64 = events.iter().map(|e| e.dy as i64).sum();
+    /// A segment builder for `events`, continuing the current one if they start with its events
+    ///
+    /// A running job is cancelled first, keeping what it finalized.
+    fn take_segment_builder(&mut self, events: &Arc<[MouseMoveEvent]>) -> SegmentBuilder {
+        let builder = match self.lod_job.take() {
+            Some(job) => job.cancel(),
+            None => self.segment_builder.take(),
+        };
+        let resume = match &self.segmented {
+            Segmented::Shown(prefix) => Arc::ptr_eq(prefix, events) || continues(prefix, events),
+            Segmented::Live(_) => false,
+        };
+        self.segmented = Segmented::Shown(Arc::clone(events));
+        builder.filter(|b| resume && *b.params() == self.segment_params).unwrap_or_else(|| SegmentBuilder::new(self.segment_params))
+    }
 
-        let mut total_distance = 0f64;
-        let mut magnitudes: Vec<f64> = Vec::with_capacity(count);
-        for e in events {
-            let mag = ((e.dx as f64).powi(2) + (e.dy as f64).powi(2)).sqrt();
-            magnitudes.push(mag);
-            total_distance += mag;
+    /// Segment the events of the running capture every `LIVE_SEGMENT_INTERVAL`
+    ///
+    /// Stopping the capture then only has to segment the events since the last run.
+    /// Each frame appends the events stored since the previous one to `live_events`,
+    /// unless a live job is reading it, so the work per frame follows the new events.
+    fn segment_live_capture(&mut self) {
+        if !self.is_capturing {
+            return;
+        }
+        if let Some(buffer) = Arc::get_mut(&mut self.live_events)
+            && buffer.len() < self.events.len()
+        {
+            buffer.extend(self.events.snapshot().iter_from(buffer.len()).copied());
+        }
+        if self.lod_job.is_some() || self.live_segmented_at.is_some_and(|at| at.elapsed() < LIVE_SEGMENT_INTERVAL) {
+            return;
         }
+        self.live_segmented_at = Some(Instant::now());
+        let segmented = match self.segmented {
+            Segmented::Live(n) => Some(n),
+            Segmented::Shown(_) => None,
+        };
+        if self.live_events.len() > segmented.unwrap_or(0) {
+            // The buffer only grows during a run, so it starts with the events of the previous live job
+            let builder = self.segment_builder.take().filter(|b| segmented.is_some() && *b.params() == self.segment_params).unwrap_or_else(|| SegmentBuilder::new(self.segment_params));
+            self.segmented = Segmented::Live(self.live_events.len());
+            self.lod_job = Some(LodJob::spawn(Arc::clone(&self.live_events), builder, true, None));
+        }
+    }
 
-        let avg_distance_per_event = total_distance / (count as f64);
-        let avg_speed = if duration > 0.0 { total_distance / duration } else { 0.0 };
-        let events_per_sec = if duration > 0.0 { count as f64 / duration } else { 0.0 };
+    /// Carry the segments of the live jobs over to `events`, the final events of the stopped capture
+    ///
+    /// They are a snapshot of the same store, so they start with the live events without comparing them.
+    fn end_live_segmenting(&mut self, events: &Arc<[MouseMoveEvent]>) {
+        if matches!(self.segmented, Segmented::Live(_)) {
+            if let Some(job) = self.lod_job.take() {
+                self.segment_builder = job.cancel();
+            }
+            self.segmented = Segmented::Shown(Arc::clone(events));
+        }
+    }
 
-        // Calculate histogram
-        let max_mag = magnitudes.iter().copied().fold(0.0f64, |a, b| a.max(b));
-        let bucket_count = 12usize;
-        let mut histogram = vec![0usize; bucket_count];
-        let bucket_size = if max_mag <= 0.0 { 1.0 } else { max_mag / (bucket_count as f64) };
+    /// Take the result of a finished LOD build
+    fn poll_lod_job(&mut self) {
+        let Some(job) = &self.lod_job else { return };
+        match job.result.try_recv() {
+            Ok(LodOutput { builder, result }) => {
+                self.segment_builder = Some(builder);
+                self.lod_job = None;
+                if let Some((tree, advanced, error_points)) = result {
+                    self.segment_tree_lod = Some(tree);
+                    self.advanced_lod = Some(advanced);
+                    self.advanced_lod_error_points_backup = error_points;
+                    self.advanced_lod_last_events_len = self.captured_events.len();
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => self.lod_job = None,
+            Err(mpsc::TryRecvError::Empty) => {}
+        }
+    }
 
-        for &m in &magnitudes {
-            let idx = if bucket_size == 0.0 {
-                0
-            } else {
-                let v = (m / bucket_size).floor() as isize;
-                let v = v.max(0).min((bucket_count - 1) as isize);
-                v as usize
-            };
-            histogram[idx] += 1;
+    /// Stop the LOD build and keep showing the M4 plot; building again resumes it
+    fn cancel_lod_build(&mut self) {
+        if let Some(job) = self.lod_job.take() {
+            self.segment_builder = job.cancel();
         }
+        self.lod_cancelled = true;
+    }
+
+    /// Show the events of a saved capture (CSV, pcap or session file) instead of a live one
+    ///
+    /// A running capture is stopped and its events discarded. The format is detected from
+    /// the extension unless given; pcap files use the target device of the capture options.
+    pub fn load_file(&mut self, path: &Path, format: Option<EventFormat>) -> Result<()> {
+        let (events, stats, metadata) = if resolve_format(path, format)? == EventFormat::Session {
+            self.read_session(path)?
+        } else {
+            (load_events(path, format, self.capture_options.target_device)?, None, None)
+        };
+        println!("Loaded {} events from {}", stats.as_ref().map_or(events.len(), |s| s.count), path.display());
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.is_capturing = false;
+        self.pending_action = None;
+        self.loaded_file = Some(path.to_path_buf());
+        self.show_events(events, stats.map(|stats| (path.to_path_buf(), stats)));
+        self.metadata = metadata;
+        self.export_status = None;
+        Ok(())
+    }
+
+    /// Load a file picked or dropped in the GUI, keeping the error for display
+    fn open_file(&mut self, path: &Path, format: Option<EventFormat>) {
+        self.load_error = match self.load_file(path, format) {
+            Ok(()) => None,
+            Err(e) => {
+                eprintln!("Open error: {:#}", e);
+                Some(format!("{:#}", e))
+            }
+        };
+    }
 
-        Stats {
-            count,
-            duration,
-            total_dx,
-            total_dy,
-            total_distance,
-            avg_distance_per_event,
-            avg_speed,
-            events_per_sec,
-            histogram,
-            bucket_size,
+    /// Analysis report of the shown events, or of all events of the session they were reduced from
+    fn report(&self) -> Result<Report> {
+        let report = match &self.reduced_from {
+            Some(path) => {
+                let reader = open_session(path)?;
+                Report::build(|| reader.events(), Some(self.stats.clone()))
+            }
+            None => Report::from_events(&self.captured_events),
+        };
+        Ok(report.with_source(self.loaded_file.as_deref().or(self.session.as_ref().map(|s| s.path.as_path()))).with_metadata(self.metadata.clone()))
+    }
+
+    /// Ask for a file name and write the analysis report of the shown events
+    ///
+    /// The format follows the chosen extension, JSON unless it is `.html`.
+    fn export_report(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("JSON report", &["json"]).add_filter("HTML report", &["html", "htm"]).set_file_name("report.json").save_file() else {
+            return;
+        };
+        let is_html = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"));
+        let format = if is_html { ReportFormat::Html } else { ReportFormat::Json };
+        self.export_status = Some(match self.report().and_then(|report| report.save(&path, format, &self.captured_events)) {
+            Ok(()) => {
+                println!("Wrote {} report to {}", format, path.display());
+                Ok(path)
+            }
+            Err(e) => {
+                eprintln!("Export error: {:#}", e);
+                Err(format!("{:#}", e))
+            }
+        });
+    }
+
+    /// Stop the running capture and take a snapshot of its events for plotting
+    ///
+    /// A disk-only capture is read back from its session once the capture thread has finished it.
+    fn stop_capture(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.loaded_file = None;
+        match self.session.clone().filter(|s| s.disk_only) {
+            Some(session) => {
+                if let Some(thread) = self.capture_thread.take() {
+                    thread.join().ok();
+                }
+                match self.read_session(&session.path) {
+                    Ok((events, stats, _)) => self.show_events(events, stats.map(|stats| (session.path, stats))),
+                    Err(e) => {
+                        eprintln!("Session error: {:#}", e);
+                        self.load_error = Some(format!("{:#}", e));
+                        self.set_events(Vec::new());
+                    }
+                }
+            }
+            None => {
+                let events: Arc<[MouseMoveEvent]> = self.events.snapshot().to_vec().into();
+                self.end_live_segmenting(&events);
+                self.set_events(events);
+            }
         }
+        self.is_capturing = false;
+        self.metadata = self.session.as_ref().map(|s| s.metadata.clone());
+        self.export_status = None;
+    }
+
+    /// Clear previous data and spawn a new capture thread
+    ///
+    /// A still running capture is stopped and its events discarded. Each run gets
+    /// its own stop flag and event storage so a quick restart can neither revive
+    /// the previous thread nor receive its last events.
+    fn start_capture(&mut self) {
+        self.stop_flag.store(true, Ordering::SeqCst);
+        self.stop_flag = Arc::new(AtomicBool::new(false));
+        self.set_events(Vec::new());
+        self.loaded_file = None;
+
+        self.is_capturing = true;
+
+        // Options have the F2 watcher disabled since the GUI handles F2 itself
+        let run = spawn_capture(Arc::clone(&self.stop_flag), self.capture_options.clone(), self.session.clone());
+        (self.events, self.live_count, self.capture_thread) = (run.events, run.count, Some(run.thread));
     }
 }
 
-#[derive(Default)]
-struct Stats {
-    count: usize,
-    duration: f64,
-    total_dx: i64,
-    total_dy: i64,
-    total_distance: f64,
-    avg_distance_per_event: f64,
-    avg_speed: f64,
-    events_per_sec: f64,
-    histogram: Vec<usize>,
-    bucket_size: f64,
+/// Calculate error points based on regression residuals
+/// Error is detected when: abs(y0-y1)/max(smallestPositive,abs(y1)) > (sqrt(1-r2)/k)
+fn calculate_error_points(segments: &[Segment], events: &[MouseMoveEvent]) -> Vec<usize> {
+    let mut error_points = Vec::new();
+    const K: f64 = 3.0;
+    const SMALLEST_POSITIVE: f64 = 1e-8;
+
+    for segment in segments {
+        if let Segment::Good { start_idx, end_idx, fit } = segment {
+            let n = end_idx - start_idx;
+            if n < 4 {
+                continue;
+            }
+
+            // Normalize indices to [0, 1] for polynomial evaluation
+            let indices: Vec<f64> = (0..n).map(|i| i as f64).collect();
+            let max_idx = (n - 1) as f64;
+            let idx_norm: Vec<f64> = indices.iter().map(|&i| if max_idx > 0.0 { i / max_idx } else { 0.0 }).collect();
+
+            // Check each event in the segment
+            for (local_idx, &normalized_idx) in idx_norm.iter().enumerate() {
+                let global_idx = start_idx + local_idx;
+                if global_idx >= events.len() {
+                    continue;
+                }
+
+                let event = &events[global_idx];
+
+                // Get actual values
+                let dx_actual = event.dx as f64;
+                let dy_actual = event.dy as f64;
+                let time_actual = event.time_secs();
+
+                // Get predicted values from polynomials
+                let dx_pred = fit.dx_poly.eval(normalized_idx);
+                let dy_pred = fit.dy_poly.eval(normalized_idx);
+                let time_pred = fit.time_poly.eval(normalized_idx);
+
+                // Calculate error thresholds for each dimension
+                let dx_threshold = (1.0 - fit.dx_r_squared).max(0.0).sqrt() * K;
+                let dy_threshold = (1.0 - fit.dy_r_squared).max(0.0).sqrt() * K;
+                let time_threshold = (1.0 - fit.time_r_squared).max(0.0).sqrt() * K;
+
+                // Calculate relative errors
+                let dx_error = (dx_actual - dx_pred).abs() / dx_pred.abs().max(SMALLEST_POSITIVE);
+                let dy_error = (dy_actual - dy_pred).abs() / dy_pred.abs().max(SMALLEST_POSITIVE);
+                let time_error = (time_actual - time_pred).abs() / time_pred.abs().max(SMALLEST_POSITIVE);
+
+                // Mark as error if any dimension exceeds threshold
+                if dx_error > dx_threshold || dy_error > dy_threshold || time_error > time_threshold {
+                    error_points.push(global_idx);
+                }
+            }
+        }
+    }
+
+    error_points
+}
+
+/// Plot the last `LIVE_PLOT_WINDOW` seconds of a running capture, decimated with M4 to the plot width
+///
+/// Only the events in the window are looked at, so a long capture costs no more per frame.
+fn show_live_plot(ui: &mut egui::Ui, events: &[MouseMoveEvent]) {
+    use egui_plot::{Line, Plot, PlotPoints};
+
+    let Some(last) = events.last() else { return };
+    let x_range = (last.time_secs() - LIVE_PLOT_WINDOW, last.time_secs());
+    let columns = ui.available_width().max(1.0) as usize;
+    Plot::new("live_plot").view_aspect(2.0).legend(egui_plot::Legend::default()).allow_drag(false).allow_zoom(false).allow_scroll(false).show(ui, |plot_ui| {
+        for (series, name, color) in [(Series::Dx, "dx", egui::Color32::from_rgb(255, 0, 0)), (Series::NegDy, "-dy", egui::Color32::from_rgb(0, 0, 255))] {
+            let points: PlotPoints = m4_indices(events, series, x_range, columns).into_iter().map(|idx| [events[idx].time_secs(), series.value(events, idx)]).collect();
+            plot_ui.line(Line::new(points).color(color).name(name));
+        }
+    });
+}
+
+/// Session file written by GUI captures
+#[derive(Debug, Clone)]
+pub struct SessionOutput {
+    pub path: PathBuf,
+    /// Written at the start of each run, with the capture settings refreshed
+    pub metadata: SessionMetadata,
+    /// Keep events only in the session, for captures too big for memory
+    pub disk_only: bool,
+}
+
+/// A capture running on a background thread
+pub struct CaptureRun {
+    /// Events of the run; stays empty if they only go to a disk-only session
+    pub events: EventStore,
+    pub count: ProgressCounter,
+    pub thread: JoinHandle<()>,
+}
+
+/// Run a capture on a background thread into memory and, if given, a session file
+///
+/// The session also receives the analysis of the run when the capture ends.
+pub fn spawn_capture(stop_flag: Arc<AtomicBool>, capture_options: CaptureOptions, session: Option<SessionOutput>) -> CaptureRun {
+    let (events, count) = (EventStore::new(), ProgressCounter::default());
+    let disk_only = session.as_ref().is_some_and(|s| s.disk_only);
+    let (store, counter) = ((!disk_only).then(|| events.clone()), count.clone());
+    let thread = thread::spawn(move || {
+        let session = session.map(|mut output| {
+            output.metadata.capture = CaptureSettings::from_options(&capture_options);
+            SessionWriter::create_with(&output.path, &output.metadata)
+        });
+        let session = match session.transpose() {
+            Ok(session) => session,
+            Err(e) => {
+                eprintln!("Capture error: {:#}", e);
+                return;
+            }
+        };
+        let session = session.map(Mutex::new);
+        if let Err(e) = with_timed_flush(session.as_ref(), || run_capture(((counter, store.clone()), session.as_ref()), stop_flag, &capture_options)) {
+            eprintln!("Capture error: {}", e);
+        }
+        if let Some(mut session) = session.map(|session| session.into_inner().unwrap_or_else(PoisonError::into_inner)) {
+            let analysis = match &store {
+                Some(store) => {
+                    let events = store.snapshot();
+                    (!events.is_empty()).then(|| compute_stats_with(|| events.iter().copied()))
+                }
+                // Disk-only: stream the events written so far back from the session
+                None => session.flush().and_then(|_| open_session(session.path())).map(|reader| compute_stats_with(|| reader.events())).inspect_err(|e| eprintln!("Session error: {:#}", e)).ok().filter(|stats| stats.count > 0),
+            };
+            if let Some(stats) = analysis
+                && let Err(e) = session.write_analysis(&stats)
+            {
+                eprintln!("Session error: {:#}", e);
+            }
+            let path = session.path().display().to_string();
+            match session.finish() {
+                Ok(n) => println!("Saved {} events to session {}", n, path),
+                Err(e) => eprintln!("Session error: {:#}", e),
+            }
+        }
+    });
+    CaptureRun { events, count, thread }
 }
 
 impl eframe::App for MouseAnalyzerGui {
@@ -310,67 +800,83 @@ impl eframe::App for MouseAnalyzerGui {
         // Handle F2 key press
         if f2_just_pressed {
             if self.is_capturing {
-                // Stop current capture and take snapshot
                 println!("F2 pressed: stopping capture and drawing plot...");
-                self.stop_flag.store(true, Ordering::SeqCst);
-                self.captured_events = self.events.lock().unwrap().clone();
-                self.is_capturing = false;
-
-                // Clear Advanced LOD cache since we have new data
-                self.advanced_lod_segments.clear();
-                self.advanced_lod_last_events_len = 0;
-                self.advanced_lod_last_bounds = None;
+                self.stop_capture();
             } else {
-                // Start a new capture
                 println!("F2 pressed: starting new capture...");
-                // Clear previous data
-                self.events.lock().unwrap().clear();
-                self.captured_events.clear();
-
-                // Clear Advanced LOD cache
-                self.advanced_lod_segments.clear();
-                self.advanced_lod_last_events_len = 0;
-                self.advanced_lod_last_bounds = None;
-
-                // Reset stop flag and restart capture
-                self.stop_flag.store(false, Ordering::SeqCst);
-                self.is_capturing = true;
-
-                // Spawn new capture thread
-                let events_capture = Arc::clone(&self.events);
-                let stop_capture = Arc::clone(&self.stop_flag);
-                let target_device = self.target_device;
-                thread::spawn(move || {
-                    // Disable F2 watcher in GUI mode since GUI handles F2 itself
-                    if let Err(e) = crate::run_capture(events_capture, stop_capture, target_device, true) {
-                        eprintln!("Capture error: {}", e);
-                    }
-                });
+                self.start_capture();
+            }
+        } else if let Some(action) = self.pending_action.take() {
+            match action {
+                CaptureAction::Start | CaptureAction::Restart => {
+                    println!("Starting new capture...");
+                    self.start_capture();
+                }
+                CaptureAction::Stop => {
+                    println!("Stopping capture and drawing plot...");
+                    self.stop_capture();
+                }
             }
+        } else if self.is_capturing && self.stop_flag.load(Ordering::SeqCst) {
+            // A stop condition (duration, event count, idle) ended the capture
+            println!("Capture stopped automatically: drawing plot...");
+            self.stop_capture();
+        }
+
+        // Open a file dropped onto the window (the first one if several)
+        let dropped = ctx.input(|i| i.raw.dropped_files.iter().find_map(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open_file(&path, None);
         }
 
         // Only request repaint if we're capturing (to show live event count)
         // When not capturing, we only repaint when needed (user interaction)
         if self.is_capturing {
-            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        // Pick up finished LOD segments, and keep the progress bar moving until then
+        self.poll_lod_job();
+        self.segment_live_capture();
+        if self.lod_job.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(50));
         }
 
         // Use appropriate event data source
         let display_events = if self.is_capturing {
-            // During capture, don't show plot (deferred plotting)
-            Vec::new()
+            // During capture, the live plot shows live_events instead
+            Arc::default()
         } else {
-            // After capture, show the captured snapshot
-            self.captured_events.clone()
+            // After capture, share the captured snapshot (no copy of the events)
+            Arc::clone(&self.captured_events)
         };
 
-        // Calculate stats (use live events for counting during capture)
-        let live_events = self.events.lock().unwrap().clone();
-        let count_for_display = if self.is_capturing { live_events.len() } else { display_events.len() };
-        let stats = self.calculate_stats(&display_events);
+        // The live count is an atomic read; the stats were computed when the events were set
+        let count_for_display = if self.is_capturing { self.live_count.position().0 } else { display_events.len() };
+        let stats = self.stats.clone();
 
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Open…").on_hover_text("Load a CSV, pcap or session file (or drop it onto the window)").clicked() {
+                        ui.close_menu();
+                        let picked = rfd::FileDialog::new()
+                            .add_filter("Captures", &["csv", "pcap", SESSION_EXTENSION])
+                            .add_filter("All files", &["*"])
+                            .pick_file();
+                        if let Some(path) = picked {
+                            self.open_file(&path, None);
+                        }
+                    }
+                    let reload = self.loaded_file.clone();
+                    if ui.add_enabled(reload.is_some(), egui::Button::new("Reload")).on_hover_text("Load the file again; if it has grown, only the new events are segmented").clicked() {
+                        ui.close_menu();
+                        if let Some(path) = reload {
+                            self.open_file(&path, None);
+                        }
+                    }
+                });
+                ui.separator();
                 ui.heading("🖱 Mouse Event Analyzer");
                 ui.separator();
 
@@ -381,8 +887,19 @@ impl eframe::App for MouseAnalyzerGui {
                     if stats.duration > 0.0 {
                         ui.label(format!("Duration: {:.2}s", stats.duration));
                     }
+                    if let Some(path) = &self.loaded_file {
+                        ui.separator();
+                        ui.label(format!("File: {}", path.display())).on_hover_text("Loaded from disk; Start begins a new live capture");
+                    }
+                    if self.reduced_from.is_some() {
+                        ui.separator();
+                        ui.label(format!("Showing {} of them", display_events.len())).on_hover_text("Too many events to hold in memory: the plot and table show the first, last, min and max dx/dy of each run of events, the statistics and reports cover all of them");
+                    }
                 }
             });
+            if let Some(error) = &self.load_error {
+                ui.colored_label(egui::Color32::RED, format!("Could not open file: {}", error));
+            }
         });
 
         egui::SidePanel::left("side_panel").min_width(200.0).show(ctx, |ui| {
@@ -399,32 +916,71 @@ impl eframe::App for MouseAnalyzerGui {
             if self.is_capturing {
                 ui.colored_label(egui::Color32::GREEN, "● Recording");
                 ui.label(format!("{} events captured", count_for_display));
-                ui.label("Press F2 to stop and plot");
+                ui.label("Press F2 or Stop to stop and plot");
             } else {
                 ui.colored_label(egui::Color32::RED, "● Stopped");
                 ui.label(format!("{} events total", stats.count));
-                ui.label("Press F2 to start new capture");
+                ui.label("Press F2 or Start to start new capture");
+            }
+
+            // Applied at the start of the next frame, like F2
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!self.is_capturing, egui::Button::new("▶ Start")).clicked() {
+                    self.pending_action = Some(CaptureAction::Start);
+                }
+                if ui.add_enabled(self.is_capturing, egui::Button::new("⏹ Stop")).clicked() {
+                    self.pending_action = Some(CaptureAction::Stop);
+                }
+                if ui.button("⟲ Restart").on_hover_text("Discard the current capture and start over").clicked() {
+                    self.pending_action = Some(CaptureAction::Restart);
+                }
+            });
+            if self.pending_action.is_some() {
+                ctx.request_repaint();
+            }
+
+            ui.separator();
+            let can_export = !self.is_capturing && !self.captured_events.is_empty();
+            if ui.add_enabled(can_export, egui::Button::new("💾 Export Report…")).on_hover_text("Save stats, histograms, strokes and anomalies as JSON or as an HTML page with charts").clicked() {
+                self.export_report();
+            }
+            match &self.export_status {
+                Some(Ok(path)) => {
+                    ui.label(format!("Saved {}", path.display()));
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(egui::Color32::RED, format!("Export failed: {}", error));
+                }
+                None => {}
             }
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
                 if self.is_capturing {
-                    // Show waiting message during capture
+                    // Show the newest events as they come in, or a waiting message
                     ui.vertical_centered(|ui| {
-                        ui.add_space(50.0);
+                        ui.add_space(if self.live_events.is_empty() { 50.0 } else { 10.0 });
                         ui.heading("Capturing mouse events...");
                         ui.label(format!("{} events captured", count_for_display));
                         ui.add_space(20.0);
                         ui.label("Move your mouse to record events");
                         ui.label("Press F2 to stop capture and display results");
                     });
+                    if self.show_plot && !self.live_events.is_empty() {
+                        ui.group(|ui| {
+                            ui.heading(format!("Live Plot (last {:.0} s)", LIVE_PLOT_WINDOW));
+                            ui.separator();
+                            show_live_plot(ui, &self.live_events);
+                        });
+                    }
                 } else if display_events.is_empty() {
                     // Show message when no capture has been done yet
                     ui.vertical_centered(|ui| {
                         ui.add_space(50.0);
                         ui.heading("Ready to capture");
                         ui.label("Press F2 to start capturing (wait for capture thread to initialize)");
+                        ui.label("or open a saved capture with File → Open… or by dropping it here");
                     });
                 } else {
                     // Show statistics and plots after capture is stopped
@@ -477,6 +1033,44 @@ impl eframe::App for MouseAnalyzerGui {
                             ui.heading("Movement Plot (dx and -dy vs time)");
                             ui.separator();
 
+                            let built = self.effective_lod_choice(display_events.len()) == self.lod_choice;
+                            ui.horizontal(|ui| {
+                                ui.label("LOD strategy:");
+                                egui::ComboBox::from_id_source("lod_strategy").selected_text(self.lod_choice.name()).show_ui(ui, |ui| {
+                                    for choice in LodChoice::ALL {
+                                        ui.selectable_value(&mut self.lod_choice, choice, choice.name());
+                                    }
+                                });
+                                if !built {
+                                    ui.label("(not built yet, showing M4)");
+                                }
+                            });
+
+                            if let Some(job) = &self.lod_job {
+                                let fraction = job.fraction();
+                                let mut cancel = false;
+                                ui.horizontal(|ui| {
+                                    let text = match job.resumed {
+                                        0 => format!("Building LOD segments… {:.0}%", fraction * 100.0),
+                                        resumed => format!("Building LOD segments for {} new events… {:.0}%", job.total - resumed, fraction * 100.0),
+                                    };
+                                    ui.add(egui::ProgressBar::new(fraction).desired_width(300.0).text(text));
+                                    cancel = ui.button("Cancel").on_hover_text("Keep the M4 plot and skip error point detection").clicked();
+                                });
+                                if cancel {
+                                    self.cancel_lod_build();
+                                }
+                            } else if self.lod_cancelled {
+                                let mut rebuild = false;
+                                ui.horizontal(|ui| {
+                                    ui.label("LOD build cancelled: showing M4 decimation");
+                                    rebuild = ui.button("Build LOD").clicked();
+                                });
+                                if rebuild {
+                                    self.start_lod_build();
+                                }
+                            }
+
                             use egui_plot::{Line, Plot, PlotPoints, Points};
 
                             // Get screen resolution for LOD calculation
@@ -494,17 +1088,17 @@ impl eframe::App for MouseAnalyzerGui {
                                     y_max: bounds.max()[1],
                                 };
 
-                                // Apply Advanced LOD
-                                let lod_indices = self.apply_advanced_lod_indices(&display_events, available_width as f64, available_height as f64, Some(&current_bounds));
+                                // Apply the chosen LOD strategy
+                                let [dx_indices, ndy_indices] = self.apply_lod_indices(&display_events, available_width as f64, available_height as f64, Some(&current_bounds));
 
                                 // Helper to safely map indices to plot points
                                 let map_to_points = |indices: &[usize], map_fn: fn(&MouseMoveEvent) -> [f64; 2]| indices.iter().filter_map(|&idx| if idx < display_events.len() { Some(map_fn(&display_events[idx])) } else { None }).collect::<PlotPoints>();
 
                                 // Build plot lines by mapping indices to events
-                                let dx_points = map_to_points(&lod_indices, |e| [e.time_secs(), e.dx as f64]);
+                                let dx_points = map_to_points(&dx_indices, |e| [e.time_secs(), e.dx as f64]);
                                 let dx_line = Line::new(dx_points).color(egui::Color32::from_rgb(255, 0, 0)).name("dx");
 
-                                let ndy_points = map_to_points(&lod_indices, |e| [e.time_secs(), -(e.dy as f64)]);
+                                let ndy_points = map_to_points(&ndy_indices, |e| [e.time_secs(), -(e.dy as f64)]);
                                 let ndy_line = Line::new(ndy_points).color(egui::Color32::from_rgb(0, 0, 255)).name("-dy");
 
                                 plot_ui.line(dx_line);
@@ -529,22 +1123,44 @@ impl eframe::App for MouseAnalyzerGui {
                                     plot_ui.points(ndy_error_markers);
                                 }
 
-                                (current_bounds, lod_indices)
+                                [dx_indices.len(), ndy_indices.len()]
                             });
-
-                            // Update cached values
-                            let (current_bounds, lod_indices) = plot_response.inner;
-                            self.advanced_lod_last_bounds = Some(current_bounds);
+                            let [dx_shown, ndy_shown] = plot_response.inner;
 
                             // Show LOD info if downsampling occurred
-                            if lod_indices.len() < display_events.len() {
-                                // Calculate reduction percentage
-                                let reduction = 100.0 * (1.0 - lod_indices.len() as f64 / display_events.len() as f64);
-                                ui.label(format!("Advanced LOD: Showing {} of {} points ({:.1}% reduction)", lod_indices.len(), display_events.len(), reduction));
+                            let shown = self.effective_lod_choice(display_events.len());
+                            if dx_shown.max(ndy_shown) < display_events.len() {
+                                let reduction = reduction_percent(dx_shown + ndy_shown, 2 * display_events.len());
+                                ui.label(format!("{}: Showing {} dx and {} -dy of {} points ({:.1}% reduction)", shown.name(), dx_shown, ndy_shown, display_events.len(), reduction));
                             } else {
                                 ui.label(format!("Showing all {} points (no LOD)", display_events.len()));
                             }
 
+                            // Points each built strategy keeps for the same view, computed only while open
+                            egui::CollapsingHeader::new("Compare LOD strategies").id_source("lod_compare").show(ui, |ui| {
+                                let counts = self.lod_comparison(&display_events);
+                                egui::Grid::new("lod_compare_grid").num_columns(3).striped(true).show(ui, |ui| {
+                                    ui.label("Strategy");
+                                    ui.label("dx / -dy points");
+                                    ui.label("Reduction");
+                                    ui.end_row();
+                                    for (name, count) in counts {
+                                        ui.label(name);
+                                        match count {
+                                            Some([dx, ndy]) => {
+                                                ui.label(format!("{} / {} of {}", dx, ndy, display_events.len()));
+                                                ui.label(format!("{:.1}%", reduction_percent(dx + ndy, 2 * display_events.len())));
+                                            }
+                                            None => {
+                                                ui.label("not built");
+                                                ui.label("");
+                                            }
+                                        }
+                                        ui.end_row();
+                                    }
+                                });
+                            });
+
                             // Show error points info
                             if !self.advanced_lod_error_points.is_empty() {
                                 ui.colored_label(
@@ -616,18 +1232,42 @@ impl eframe::App for MouseAnalyzerGui {
     }
 }
 
-pub fn run_gui(events: Arc<Mutex<Vec<MouseMoveEvent>>>, stop_flag: Arc<AtomicBool>, target_device: Option<crate::TargetDevice>) -> Result<(), eframe::Error> {
+/// File to show at startup instead of a live capture
+#[derive(Debug, Clone)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    pub format: Option<EventFormat>,
+}
+
+pub fn run_gui(capture: Option<CaptureRun>, stop_flag: Arc<AtomicBool>, capture_options: CaptureOptions, segment_params: SegmentParams, session: Option<SessionOutput>, open: Option<OpenFile>) -> Result<(), eframe::Error> {
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1200.0, 800.0]).with_title("Mouse Event Analyzer"),
         ..Default::default()
     };
 
-    eframe::run_native("Mouse Event Analyzer", options, Box::new(move |_cc| Box::new(MouseAnalyzerGui::new(events, stop_flag, target_device))))
+    eframe::run_native(
+        "Mouse Event Analyzer",
+        options,
+        Box::new(move |_cc| {
+            let mut gui = MouseAnalyzerGui::new(EventStore::new(), stop_flag, capture_options, segment_params).with_session(session);
+            if let Some(capture) = capture {
+                gui = gui.with_capture(capture);
+            }
+            if let Some(open) = open {
+                gui.open_file(&open.path, open.format);
+                // Show the empty stopped view rather than a capture that was never started
+                gui.is_capturing = false;
+            }
+            Box::new(gui)
+        }),
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use MouseTesterRust::fit::Degree;
+    use MouseTesterRust::lod_advanced::build_segments;
 
     fn create_test_events(n: usize) -> Vec<MouseMoveEvent> {
         let mut events = Vec::new();
@@ -645,29 +1285,17 @@ mod tests {
     #[test]
     fn test_calculate_error_points_empty_segments() {
         let events = create_test_events(10);
-        let gui = MouseAnalyzerGui::new(
-            Arc::new(Mutex::new(Vec::new())),
-            Arc::new(AtomicBool::new(false)),
-            None
-        );
-        
-        let error_points = gui.calculate_error_points(&events);
+        let error_points = calculate_error_points(&[], &events);
         assert_eq!(error_points.len(), 0, "Should have no error points with empty segments");
     }
 
     #[test]
     fn test_calculate_error_points_with_good_segments() {
         let events = create_test_events(50);
-        let mut gui = MouseAnalyzerGui::new(
-            Arc::new(Mutex::new(Vec::new())),
-            Arc::new(AtomicBool::new(false)),
-            None
-        );
-        
         // Build segments
-        gui.advanced_lod_segments = build_segments(&events, 10, 1.6, 0.8, 0.091);
+        let segments = build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic);
         
-        let error_points = gui.calculate_error_points(&events);
+        let error_points = calculate_error_points(&segments, &events);
         
         // With linear data, we should have very few or no error points
         // (since the data fits well to polynomial regression)
@@ -684,16 +1312,10 @@ mod tests {
             events[16].dy = -1000; // Major outlier
         }
         
-        let mut gui = MouseAnalyzerGui::new(
-            Arc::new(Mutex::new(Vec::new())),
-            Arc::new(AtomicBool::new(false)),
-            None
-        );
-        
         // Build segments
-        gui.advanced_lod_segments = build_segments(&events, 10, 1.6, 0.8, 0.091);
+        let segments = build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic);
         
-        let error_points = gui.calculate_error_points(&events);
+        let error_points = calculate_error_points(&segments, &events);
         
         // Should detect some error points due to outliers
         println!("Detected {} error points with outliers", error_points.len());
@@ -704,14 +1326,18 @@ mod tests {
     fn test_error_points_filtered_by_visible_range() {
         let events = create_test_events(100);
         let mut gui = MouseAnalyzerGui::new(
-            Arc::new(Mutex::new(Vec::new())),
+            EventStore::new(),
             Arc::new(AtomicBool::new(false)),
-            None
+            CaptureOptions::default(),
+            SegmentParams::default()
         );
         
         // Build segments and calculate error points
-        gui.advanced_lod_segments = build_segments(&events, 10, 1.6, 0.8, 0.091);
-        gui.advanced_lod_error_points = gui.calculate_error_points(&events);
+        let segments = build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic);
+        gui.advanced_lod_error_points = calculate_error_points(&segments, &events);
+        gui.advanced_lod = Some(AdvancedLod::new(segments, &events));
+        gui.advanced_lod_error_points_backup = gui.advanced_lod_error_points.clone();
+        gui.advanced_lod_last_events_len = events.len();
         
         let initial_error_count = gui.advanced_lod_error_points.len();
         
@@ -723,7 +1349,7 @@ mod tests {
             y_max: 500.0,
         };
         
-        gui.apply_advanced_lod_indices(&events, 800.0, 600.0, Some(&bounds));
+        gui.apply_lod_indices(&events, 800.0, 600.0, Some(&bounds));
         
         // Error points should be filtered to visible range
         for &idx in &gui.advanced_lod_error_points {
@@ -748,4 +1374,218 @@ mod tests {
             gui.advanced_lod_error_points.len()
         );
     }
+
+    fn wait_for_lod(gui: &mut MouseAnalyzerGui) {
+        let started = Instant::now();
+        while gui.lod_job.is_some() {
+            assert!(started.elapsed() < Duration::from_secs(60), "LOD build did not finish");
+            gui.poll_lod_job();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_lod_build_runs_in_background_and_can_be_cancelled() {
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), SegmentParams::default());
+        gui.set_events(create_test_events(200));
+        assert!(gui.lod_job.is_some());
+        // Until the segments arrive the plot gets M4
+        let preview = gui.apply_lod_indices(&Arc::clone(&gui.captured_events), 800.0, 600.0, None);
+        assert!(preview.iter().all(|indices| !indices.is_empty()));
+        assert_eq!(gui.effective_lod_choice(200), LodChoice::M4);
+        wait_for_lod(&mut gui);
+        assert!(!gui.advanced_lod.as_ref().unwrap().segments.is_empty());
+        assert_eq!(gui.effective_lod_choice(200), LodChoice::Advanced);
+        assert_eq!(gui.advanced_lod_last_events_len, 200);
+        assert!(!gui.lod_cancelled);
+
+        let events: Vec<MouseMoveEvent> = (0..200_000u32).map(|i| MouseMoveEvent::new((i % 7) as i16, 1, i / 8000, (i % 8000) * 125)).collect();
+        gui.set_events(events);
+        gui.cancel_lod_build();
+        assert!(gui.lod_job.is_none() && gui.lod_cancelled);
+        assert!(gui.advanced_lod.is_none() && gui.segment_tree_lod.is_none());
+        assert_eq!(gui.stats.count, 200_000);
+    }
+
+    #[test]
+    fn test_plot_reuses_cached_view_while_panning() {
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), SegmentParams::default());
+        gui.set_events(create_test_events(300));
+        wait_for_lod(&mut gui);
+        let events = Arc::clone(&gui.captured_events);
+        let view = |x_min: f64, x_max: f64| PlotBounds { x_min, x_max, y_min: -2000.0, y_max: 100.0 };
+
+        let cache = |gui: &MouseAnalyzerGui| gui.advanced_lod.as_ref().unwrap().caches[Series::Dx as usize].as_ref().unwrap().last_x_range;
+
+        let first = gui.apply_lod_indices(&events, 800.0, 400.0, Some(&view(100.0, 150.0)));
+        let region = cache(&gui);
+        assert_eq!(region, (75.0, 175.0));
+        // Panning within the cached region keeps it and its indices
+        assert_eq!(gui.apply_lod_indices(&events, 800.0, 400.0, Some(&view(110.0, 160.0))), first);
+        assert_eq!(cache(&gui), region);
+        // Leaving it recomputes
+        gui.apply_lod_indices(&events, 800.0, 400.0, Some(&view(200.0, 250.0)));
+        assert_eq!(cache(&gui), (175.0, 275.0));
+        // New segments drop the cache
+        gui.start_lod_build();
+        assert!(gui.advanced_lod.is_none());
+    }
+
+    #[test]
+    fn test_lod_strategy_can_be_switched_and_compared() {
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), SegmentParams::default());
+        let mut events: Vec<MouseMoveEvent> = (0..4_000u32).map(|i| MouseMoveEvent::new(((i / 40) % 9) as i16, 2, 0, i * 125)).collect();
+        events[2_345].dx = 60;
+        gui.set_events(events);
+        wait_for_lod(&mut gui);
+        let events = Arc::clone(&gui.captured_events);
+
+        for choice in LodChoice::ALL {
+            gui.lod_choice = choice;
+            assert_eq!(gui.lod_strategy(choice).unwrap().name(), choice.name());
+            let [dx, ndy] = gui.apply_lod_indices(&events, 200.0, 100.0, None);
+            assert!(!dx.is_empty() && dx.len() < events.len(), "{:?} kept {} dx points", choice, dx.len());
+            assert!(!ndy.is_empty() && ndy.len() < events.len(), "{:?} kept {} -dy points", choice, ndy.len());
+            // The dx spike survives although -dy is flat around it
+            if choice != LodChoice::SegmentTree {
+                assert!(dx.contains(&2_345), "{:?} dropped the dx spike", choice);
+            }
+        }
+        let m4 = gui.lod_comparison(&events)[0].1.unwrap();
+        gui.lod_choice = LodChoice::M4;
+        assert_eq!(gui.apply_lod_indices(&events, 200.0, 100.0, None).map(|indices| indices.len()), m4);
+        assert!(gui.lod_comparison(&events).iter().all(|(_, count)| count.is_some()));
+        // Only M4 is available for other events, such as a running capture
+        assert_eq!(gui.lod_comparison(&events[..100]).iter().filter(|(_, count)| count.is_some()).count(), 1);
+        assert!((reduction_percent(25, 100) - 75.0).abs() < 1e-9);
+    }
+
+    /// Bounds and kinds of segments, for comparing builds
+    fn segment_bounds(segments: &[Segment]) -> Vec<(usize, usize, bool)> {
+        segments
+            .iter()
+            .map(|s| match *s {
+                Segment::Good { start_idx, end_idx, .. } => (start_idx, end_idx, true),
+                Segment::Discrete { idx } => (idx, idx + 1, false),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_live_capture_and_reload_resume_segmenting() {
+        let events: Vec<MouseMoveEvent> = (0..3_000u32).map(|i| MouseMoveEvent::new((25.0 * (i as f64 / 300.0).sin()) as i16, (i % 5) as i16 - 2, 0, i * 125)).collect();
+        let params = SegmentParams { min_r_squared: 0.8, ..SegmentParams::default() };
+        let full = segment_bounds(&build_segments(&events, params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree));
+
+        // A running capture is segmented in the background, and stopping it continues from there
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), params);
+        events[..2_000].iter().for_each(|&e| gui.events.push(e));
+        gui.segment_live_capture();
+        wait_for_lod(&mut gui);
+        let finalized = gui.segment_builder.as_ref().unwrap().finalized_len();
+        assert!(finalized > 0 && gui.advanced_lod.is_none());
+        assert_eq!(gui.live_events.len(), 2_000);
+
+        // Each frame appends the new events to the same buffer; the next live job continues the previous one
+        let buffer = Arc::as_ptr(&gui.live_events);
+        events[2_000..2_500].iter().for_each(|&e| gui.events.push(e));
+        gui.segment_live_capture();
+        assert!(gui.lod_job.is_none(), "live jobs run once per interval");
+        assert_eq!((gui.live_events.len(), Arc::as_ptr(&gui.live_events)), (2_500, buffer));
+        gui.live_segmented_at = None;
+        gui.segment_live_capture();
+        assert_eq!(gui.lod_job.as_ref().unwrap().resumed, finalized);
+        wait_for_lod(&mut gui);
+        let finalized = gui.segment_builder.as_ref().unwrap().finalized_len();
+
+        events[2_500..].iter().for_each(|&e| gui.events.push(e));
+        gui.stop_capture();
+        assert!(gui.live_events.is_empty());
+        assert_eq!(gui.lod_job.as_ref().unwrap().resumed, finalized);
+        wait_for_lod(&mut gui);
+        assert_eq!(segment_bounds(&gui.advanced_lod.as_ref().unwrap().segments), full);
+
+        // Reloading a grown file does the same
+        let path = std::env::temp_dir().join(format!("mousetester_gui_reload_{}.csv", std::process::id()));
+        let save = |n: usize| MouseTesterRust::formats::save_events(&path, Some(EventFormat::Csv), events[..n].iter().copied(), None).unwrap();
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), params);
+        save(2_000);
+        gui.load_file(&path, None).unwrap();
+        wait_for_lod(&mut gui);
+        let finalized = gui.segment_builder.as_ref().unwrap().finalized_len();
+        save(3_000);
+        gui.load_file(&path, None).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(gui.lod_job.as_ref().unwrap().resumed, finalized);
+        wait_for_lod(&mut gui);
+        std::fs::remove_file(cache_path(&path)).unwrap();
+        assert_eq!(segment_bounds(&gui.advanced_lod.as_ref().unwrap().segments), full);
+
+        // A different file starts over
+        gui.set_events(events[1..].to_vec());
+        assert_eq!(gui.lod_job.as_ref().unwrap().resumed, 0);
+    }
+
+    #[test]
+    fn test_load_file_replaces_capture_and_builds_segments() {
+        let path = std::env::temp_dir().join(format!("mousetester_gui_open_{}.csv", std::process::id()));
+        let events = create_test_events(50);
+        MouseTesterRust::formats::save_events(&path, Some(EventFormat::Csv), events.iter().copied(), None).unwrap();
+
+        let stop_flag = Arc::new(AtomicBool::new(false));
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::clone(&stop_flag), CaptureOptions::default(), SegmentParams::default());
+        gui.load_file(&path, None).unwrap();
+        std::fs::remove_file(&path).ok();
+        wait_for_lod(&mut gui);
+        std::fs::remove_file(cache_path(&path)).expect("the segments should be cached next to the file");
+
+        assert!(!gui.is_capturing);
+        assert!(stop_flag.load(Ordering::SeqCst), "loading should stop the live capture");
+        let fields = |events: &[MouseMoveEvent]| events.iter().map(|e| (e.dx, e.dy, e.ts_sec, e.ts_usec)).collect::<Vec<_>>();
+        assert_eq!(fields(&gui.captured_events), fields(&events));
+        assert_eq!(gui.loaded_file.as_deref(), Some(path.as_path()));
+        assert!(gui.advanced_lod.is_some() && gui.segment_tree_lod.is_some());
+        assert_eq!(gui.advanced_lod_last_events_len, events.len());
+        let report = gui.report().unwrap();
+        assert_eq!(report.stats.count, events.len());
+        assert_eq!(report.source.as_deref(), Some(path.as_path()));
+
+        gui.open_file(&path, None);
+        assert!(gui.load_error.is_some(), "a missing file should be reported");
+        assert_eq!(gui.captured_events.len(), events.len(), "a failed open keeps the shown events");
+    }
+
+    #[test]
+    fn test_large_sessions_are_reduced_and_disk_only_captures_read_back() {
+        let path = std::env::temp_dir().join(format!("mousetester_gui_large_{}.{}", std::process::id(), SESSION_EXTENSION));
+        let events: Vec<MouseMoveEvent> = (0..20_000u32).map(|i| MouseMoveEvent::new(if i == 12_345 { 90 } else { (i % 7) as i16 - 3 }, 1, i / 8000, (i % 8000) * 125)).collect();
+        MouseTesterRust::formats::save_events(&path, Some(EventFormat::Session), events.iter().copied(), None).unwrap();
+
+        // More events than the GUI holds: stats and reports stream all of them, the plot gets a reduction
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), CaptureOptions::default(), SegmentParams::default());
+        gui.max_shown_events = 3_000;
+        gui.load_file(&path, None).unwrap();
+        wait_for_lod(&mut gui);
+        std::fs::remove_file(cache_path(&path)).unwrap();
+        assert!(gui.captured_events.len() <= 3_000 && gui.captured_events.iter().any(|e| e.dx == 90), "{} events", gui.captured_events.len());
+        assert_eq!(gui.reduced_from.as_deref(), Some(path.as_path()));
+        assert_eq!(gui.stats.count, 20_000);
+        let (report, full) = (gui.report().unwrap(), Report::from_events(&events));
+        assert_eq!((report.stats.count, report.strokes.len(), report.anomalies.len()), (20_000, full.strokes.len(), full.anomalies.len()));
+        gui.max_shown_events = MAX_SHOWN_EVENTS;
+        gui.load_file(&path, None).unwrap();
+        assert!(gui.reduced_from.is_none() && gui.captured_events.len() == 20_000);
+        wait_for_lod(&mut gui);
+        std::fs::remove_file(cache_path(&path)).unwrap();
+
+        // A disk-only capture is shown from its session once the capture thread is done with it
+        let options = CaptureOptions { usbpcap_cmd: PathBuf::from("/nonexistent/USBPcapCMD"), ..CaptureOptions::default() };
+        let session = SessionOutput { path: path.clone(), metadata: SessionMetadata::default(), disk_only: true };
+        let mut gui = MouseAnalyzerGui::new(EventStore::new(), Arc::new(AtomicBool::new(false)), options, SegmentParams::default()).with_session(Some(session));
+        gui.start_capture();
+        gui.stop_capture();
+        assert!(gui.capture_thread.is_none() && gui.load_error.is_none() && !gui.is_capturing);
+        assert!(gui.captured_events.is_empty() && gui.events.is_empty(), "the failed capture recorded nothing");
+        std::fs::remove_file(&path).unwrap();
+    }
 }
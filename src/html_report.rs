@@ -0,0 +1,436 @@
+//! Self-contained HTML analysis reports
+//!
+//! `render` turns a `Report` and its events into a single HTML page that works
+//! offline: the summary statistics and anomaly list are plain HTML, and the
+//! charts (dx/dy vs time, report interval histogram, magnitude histogram and
+//! XY path) are drawn as inline SVG by an embedded script from data embedded
+//! in the page. There are no external resources.
+//!
+//! The dx/dy series is reduced with the `lod` segment tree to roughly what a
+//! chart `CHART_WIDTH` pixels wide can show, which keeps the file small even
+//! for long 8 kHz captures. The XY path is decimated to `MAX_PATH_POINTS`.
+
+use crate::analysis::{AnomalyKind, counts_to_mm, interval_histogram};
+use crate::lod::collect_for_width;
+use crate::mouse_event::MouseMoveEvent;
+use crate::report::Report;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Width of the charts in pixels, used to size the LOD reduction
+pub const CHART_WIDTH: usize = 1000;
+
+/// Height of the dx/dy chart in pixels
+const CHART_HEIGHT: f64 = 320.0;
+
+/// Most XY path points embedded in the page
+pub const MAX_PATH_POINTS: usize = 4000;
+
+/// Most anomalies listed in the table; all of them are still marked on the chart
+const MAX_ANOMALY_ROWS: usize = 500;
+
+/// Buckets of the report interval histogram
+const INTERVAL_BUCKETS: usize = 40;
+
+/// Chart data embedded in the page as JSON
+#[derive(Debug, Serialize)]
+struct ChartData {
+    /// Seconds since the first event
+    t: Vec<f64>,
+    dx: Vec<f64>,
+    /// -dy, so that up is up as in the other plots
+    ndy: Vec<f64>,
+    path_x: Vec<i64>,
+    path_y: Vec<i64>,
+    interval_bucket_us: f64,
+    interval_counts: Vec<usize>,
+    magnitude_bucket: f64,
+    magnitude_counts: Vec<usize>,
+    anomaly_t: Vec<f64>,
+}
+
+/// dx and dy over time reduced with the LOD tree to about `width` pixels of detail
+///
+/// Returns (time_micros, dx, dy) tuples like `collect_for_view`.
+pub fn reduced_series(events: &[MouseMoveEvent], width: usize) -> Vec<(u64, f64, f64)> {
+    // Pixels per count on a chart that shows the full dx/dy range
+    let range = events.iter().map(|e| (e.dx as f64).abs().max((e.dy as f64).abs())).fold(1.0, f64::max) * 2.0;
+    collect_for_width(events, width, CHART_HEIGHT / range)
+}
+
+/// Cumulative position after each event, decimated to at most `max_points` (plus the end)
+fn path_points(events: &[MouseMoveEvent], max_points: usize) -> (Vec<i64>, Vec<i64>) {
+    let step = events.len().div_ceil(max_points.max(1)).max(1);
+    let (mut x, mut y) = (0i64, 0i64);
+    let (mut xs, mut ys) = (vec![0], vec![0]);
+    for (i, e) in events.iter().enumerate() {
+        x += e.dx as i64;
+        y -= e.dy as i64;
+        if (i + 1) % step == 0 || i + 1 == events.len() {
+            xs.push(x);
+            ys.push(y);
+        }
+    }
+    (xs, ys)
+}
+
+/// Escape text for HTML element content and attribute values
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn anomaly_label(kind: AnomalyKind) -> &'static str {
+    match kind {
+        AnomalyKind::TimeReversal => "Time reversal",
+        AnomalyKind::DuplicateTimestamp => "Duplicate timestamp",
+        AnomalyKind::ReportGap => "Report gap",
+        AnomalyKind::Spike => "Spike",
+    }
+}
+
+fn anomaly_value(kind: AnomalyKind, value: f64) -> String {
+    match kind {
+        AnomalyKind::TimeReversal | AnomalyKind::ReportGap => format!("{:.0} µs", value),
+        AnomalyKind::DuplicateTimestamp => String::new(),
+        AnomalyKind::Spike => format!("{:.1} counts", value),
+    }
+}
+
+/// Render the report of `events` as a self-contained HTML page
+pub fn render(report: &Report, events: &[MouseMoveEvent]) -> Result<String> {
+    let t0 = report.start_time;
+    let series = reduced_series(events, CHART_WIDTH);
+    let (path_x, path_y) = path_points(events, MAX_PATH_POINTS);
+    let (interval_counts, interval_bucket_us) = interval_histogram(events, INTERVAL_BUCKETS);
+    let data = ChartData {
+        t: series.iter().map(|&(t, _, _)| t as f64 / 1e6 - t0).collect(),
+        dx: series.iter().map(|&(_, dx, _)| dx).collect(),
+        ndy: series.iter().map(|&(_, _, dy)| -dy).collect(),
+        path_x,
+        path_y,
+        interval_bucket_us,
+        interval_counts,
+        magnitude_bucket: report.stats.bucket_size,
+        magnitude_counts: report.stats.histogram.clone(),
+        anomaly_t: report.anomalies.iter().map(|a| a.time - t0).collect(),
+    };
+    // "</" would end the script element early
+    let json = serde_json::to_string(&data).context("Failed to serialize chart data")?.replace("</", "<\\/");
+
+    let title = match &report.source {
+        Some(source) => format!("Mouse report: {}", source.display()),
+        None => "Mouse report".to_string(),
+    };
+    let stats = &report.stats;
+    let cpi = report.metadata.as_ref().and_then(|m| m.notes.cpi);
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>", escape(&title), STYLE)?;
+    writeln!(html, "<h1>{}</h1>\n<p class=\"muted\">Generated by {}</p>", escape(&title), escape(&report.generator))?;
+
+    // Session metadata
+    if let Some(metadata) = &report.metadata {
+        let mut rows = Vec::new();
+        if let Some(target) = metadata.device.target {
+            rows.push(("Device", target.to_string()));
+        }
+        if let Some(model) = &metadata.notes.mouse_model {
+            rows.push(("Mouse", model.clone()));
+        }
+        if let Some(cpi) = metadata.notes.cpi {
+            rows.push(("CPI", cpi.to_string()));
+        }
+        if let Some(surface) = &metadata.notes.surface {
+            rows.push(("Surface", surface.clone()));
+        }
+        if let Some(comment) = &metadata.notes.comment {
+            rows.push(("Note", comment.clone()));
+        }
+        if !rows.is_empty() {
+            writeln!(html, "<h2>Session</h2>\n<table class=\"kv\">")?;
+            for (k, v) in rows {
+                writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", k, escape(&v))?;
+            }
+            writeln!(html, "</table>")?;
+        }
+    }
+
+    // Summary statistics
+    writeln!(html, "<h2>Summary</h2>\n<table class=\"kv\">")?;
+    let mut rows = vec![
+        ("Events", stats.count.to_string()),
+        ("Duration", format!("{:.6} s", stats.duration)),
+        ("Sum dx / dy", format!("{} / {}", stats.total_dx, stats.total_dy)),
+        ("Total distance", format!("{:.3} counts", stats.total_distance)),
+        ("Avg distance/event", format!("{:.3} counts", stats.avg_distance_per_event)),
+        ("Events/sec", format!("{:.3}", stats.events_per_sec)),
+        ("Avg speed", format!("{:.3} counts/s", stats.avg_speed)),
+        ("Report interval", format!("min {} µs, max {} µs, mean {:.3} µs, std {:.3} µs", stats.interval.min_us, stats.interval.max_us, stats.interval.mean_us, stats.interval.std_dev_us)),
+        ("Strokes", report.strokes.len().to_string()),
+        ("Anomalies", report.anomalies.len().to_string()),
+    ];
+    if let Some(cpi) = cpi {
+        rows.insert(4, ("Total distance (mm)", format!("{:.1} mm at {} CPI", counts_to_mm(stats.total_distance, cpi), cpi)));
+    }
+    for (k, v) in rows {
+        writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", k, escape(&v))?;
+    }
+    writeln!(html, "</table>")?;
+
+    // Charts, filled in by the script
+    writeln!(
+        html,
+        "<h2>dx and -dy vs time</h2>\n<p class=\"muted\">{} of {} events shown (LOD reduced). Drag to zoom, double-click to reset; red ticks mark anomalies.</p>\n<div id=\"time\" class=\"chart\"></div>",
+        data.t.len(),
+        events.len()
+    )?;
+    writeln!(html, "<div class=\"row\"><div><h2>Report interval histogram</h2><div id=\"intervals\" class=\"chart half\"></div></div>")?;
+    writeln!(html, "<div><h2>Movement magnitude histogram</h2><div id=\"magnitudes\" class=\"chart half\"></div></div></div>")?;
+    writeln!(html, "<h2>XY path</h2>\n<div id=\"path\" class=\"chart square\"></div>")?;
+
+    // Anomalies
+    writeln!(html, "<h2>Anomalies</h2>")?;
+    if report.anomalies.is_empty() {
+        writeln!(html, "<p>None detected.</p>")?;
+    } else {
+        if report.anomalies.len() > MAX_ANOMALY_ROWS {
+            writeln!(html, "<p class=\"muted\">Showing the first {} of {}.</p>", MAX_ANOMALY_ROWS, report.anomalies.len())?;
+        }
+        writeln!(html, "<table class=\"list\">\n<tr><th>Time (s)</th><th>Event</th><th>Kind</th><th>Value</th></tr>")?;
+        for a in report.anomalies.iter().take(MAX_ANOMALY_ROWS) {
+            writeln!(html, "<tr><td>{:.6}</td><td>{}</td><td>{}</td><td>{}</td></tr>", a.time - t0, a.event, anomaly_label(a.kind), escape(&anomaly_value(a.kind, a.value)))?;
+        }
+        writeln!(html, "</table>")?;
+    }
+
+    writeln!(html, "<script type=\"application/json\" id=\"data\">{}</script>\n<script>{}</script>\n</body>\n</html>", json, SCRIPT)?;
+    Ok(html)
+}
+
+const STYLE: &str = r#"
+body { font-family: system-ui, sans-serif; margin: 2em auto; max-width: 1080px; color: #222; }
+h1 { font-size: 1.5em; } h2 { font-size: 1.15em; margin-top: 1.6em; }
+.muted { color: #777; font-size: 0.9em; }
+table { border-collapse: collapse; }
+.kv th { text-align: left; padding: 2px 16px 2px 0; font-weight: 600; }
+.list th, .list td { padding: 2px 12px; border-bottom: 1px solid #eee; text-align: left; }
+.chart { width: 100%; height: 360px; position: relative; }
+.half { height: 260px; } .square { height: 520px; }
+.row { display: flex; gap: 24px; } .row > div { flex: 1; min-width: 0; }
+.tip { position: absolute; pointer-events: none; background: rgba(255,255,255,0.9); border: 1px solid #ccc; padding: 2px 6px; font-size: 12px; display: none; }
+svg text { font-size: 11px; fill: #555; }
+"#;
+
+const SCRIPT: &str = r#"
+const data = JSON.parse(document.getElementById('data').textContent);
+const NS = 'http://www.w3.org/2000/svg';
+const M = { l: 56, r: 12, t: 10, b: 28 };
+
+function el(name, attrs, parent) {
+  const e = document.createElementNS(NS, name);
+  for (const k in attrs) e.setAttribute(k, attrs[k]);
+  if (parent) parent.appendChild(e);
+  return e;
+}
+
+function ticks(min, max, n) {
+  const span = max - min || 1, raw = span / n, mag = Math.pow(10, Math.floor(Math.log10(raw)));
+  const step = [1, 2, 5, 10].map(m => m * mag).find(s => s >= raw);
+  const out = [];
+  for (let v = Math.ceil(min / step) * step; v <= max + step * 1e-9; v += step) out.push(+v.toPrecision(12));
+  return out;
+}
+
+function extent(arrays) {
+  let lo = Infinity, hi = -Infinity;
+  for (const a of arrays) for (const v of a) { if (v < lo) lo = v; if (v > hi) hi = v; }
+  if (!isFinite(lo)) { lo = 0; hi = 1; }
+  if (lo === hi) { lo -= 1; hi += 1; }
+  return [lo, hi];
+}
+
+// Axes, grid and scales for a chart in `box` showing [x0, x1] x [y0, y1]
+function frame(box, x0, x1, y0, y1, xLabel) {
+  box.innerHTML = '';
+  const w = box.clientWidth, h = box.clientHeight;
+  const svg = el('svg', { width: w, height: h }, box);
+  const sx = v => M.l + (v - x0) / (x1 - x0) * (w - M.l - M.r);
+  const sy = v => h - M.b - (v - y0) / (y1 - y0) * (h - M.t - M.b);
+  for (const v of ticks(y0, y1, 6)) {
+    el('line', { x1: M.l, x2: w - M.r, y1: sy(v), y2: sy(v), stroke: '#eee' }, svg);
+    el('text', { x: M.l - 4, y: sy(v) + 4, 'text-anchor': 'end' }, svg).textContent = v;
+  }
+  for (const v of ticks(x0, x1, 8)) {
+    el('line', { x1: sx(v), x2: sx(v), y1: M.t, y2: h - M.b, stroke: '#f3f3f3' }, svg);
+    el('text', { x: sx(v), y: h - M.b + 14, 'text-anchor': 'middle' }, svg).textContent = v;
+  }
+  el('text', { x: w - M.r, y: h - 2, 'text-anchor': 'end' }, svg).textContent = xLabel;
+  el('rect', { x: M.l, y: M.t, width: w - M.l - M.r, height: h - M.t - M.b, fill: 'none', stroke: '#bbb' }, svg);
+  return { svg, sx, sy, w, h };
+}
+
+function polyline(svg, xs, ys, sx, sy, color, from, to) {
+  let pts = '';
+  for (let i = from; i < to; i++) pts += sx(xs[i]).toFixed(1) + ',' + sy(ys[i]).toFixed(1) + ' ';
+  el('polyline', { points: pts, fill: 'none', stroke: color, 'stroke-width': 1 }, svg);
+}
+
+function tip(box) {
+  const t = document.createElement('div');
+  t.className = 'tip';
+  box.appendChild(t);
+  return t;
+}
+
+// dx / -dy vs time with drag-to-zoom, double-click reset and hover readout
+function timeChart() {
+  const box = document.getElementById('time');
+  const full = extent([data.t]);
+  let view = full.slice();
+  function lowerBound(a, v) { let lo = 0, hi = a.length; while (lo < hi) { const m = (lo + hi) >> 1; if (a[m] < v) lo = m + 1; else hi = m; } return lo; }
+  function draw() {
+    const from = Math.max(0, lowerBound(data.t, view[0]) - 1), to = Math.min(data.t.length, lowerBound(data.t, view[1]) + 1);
+    const yr = extent([data.dx.slice(from, to), data.ndy.slice(from, to)]);
+    const pad = (yr[1] - yr[0]) * 0.05;
+    const f = frame(box, view[0], view[1], yr[0] - pad, yr[1] + pad, 'time (s)');
+    const clip = el('clipPath', { id: 'clip' }, el('defs', {}, f.svg));
+    el('rect', { x: M.l, y: M.t, width: f.w - M.l - M.r, height: f.h - M.t - M.b }, clip);
+    const g = el('g', { 'clip-path': 'url(#clip)' }, f.svg);
+    polyline(g, data.t, data.dx, f.sx, f.sy, '#d33', from, to);
+    polyline(g, data.t, data.ndy, f.sx, f.sy, '#36c', from, to);
+    for (const t of data.anomaly_t) if (t >= view[0] && t <= view[1]) el('line', { x1: f.sx(t), x2: f.sx(t), y1: f.h - M.b - 8, y2: f.h - M.b, stroke: '#e00', 'stroke-width': 2 }, g);
+    el('text', { x: M.l + 8, y: M.t + 14, fill: '#d33' }, f.svg).textContent = 'dx';
+    el('text', { x: M.l + 30, y: M.t + 14, fill: '#36c' }, f.svg).textContent = '-dy';
+    const sel = el('rect', { y: M.t, height: f.h - M.t - M.b, fill: 'rgba(80,120,255,0.15)', width: 0 }, f.svg);
+    const info = tip(box);
+    const toT = px => view[0] + (px - M.l) / (f.w - M.l - M.r) * (view[1] - view[0]);
+    let start = null;
+    f.svg.onmousedown = e => { start = e.offsetX; sel.setAttribute('x', start); sel.setAttribute('width', 0); };
+    f.svg.onmousemove = e => {
+      if (start !== null) { sel.setAttribute('x', Math.min(start, e.offsetX)); sel.setAttribute('width', Math.abs(e.offsetX - start)); }
+      const i = Math.min(data.t.length - 1, lowerBound(data.t, toT(e.offsetX)));
+      if (i < 0 || !data.t.length) return;
+      info.style.display = 'block';
+      info.style.left = (e.offsetX + 12) + 'px';
+      info.style.top = (e.offsetY + 12) + 'px';
+      info.textContent = 't=' + data.t[i].toFixed(6) + 's  dx=' + data.dx[i] + '  -dy=' + data.ndy[i];
+    };
+    f.svg.onmouseleave = () => { info.style.display = 'none'; start = null; sel.setAttribute('width', 0); };
+    f.svg.onmouseup = e => {
+      if (start !== null && Math.abs(e.offsetX - start) > 4) { const a = toT(Math.min(start, e.offsetX)), b = toT(Math.max(start, e.offsetX)); view = [a, b]; start = null; draw(); return; }
+      start = null;
+      sel.setAttribute('width', 0);
+    };
+    f.svg.ondblclick = () => { view = full.slice(); draw(); };
+  }
+  draw();
+  window.addEventListener('resize', draw);
+}
+
+function barChart(id, width, counts, xLabel, color) {
+  const box = document.getElementById(id);
+  function draw() {
+    const max = Math.max(1, ...counts);
+    const f = frame(box, 0, width * counts.length, 0, max * 1.05, xLabel);
+    const info = tip(box);
+    counts.forEach((c, i) => {
+      const x0 = f.sx(i * width), x1 = f.sx((i + 1) * width);
+      const r = el('rect', { x: x0 + 0.5, y: f.sy(c), width: Math.max(0.5, x1 - x0 - 1), height: f.sy(0) - f.sy(c), fill: color }, f.svg);
+      r.onmousemove = e => {
+        info.style.display = 'block';
+        info.style.left = (e.offsetX + 12) + 'px';
+        info.style.top = (e.offsetY + 12) + 'px';
+        info.textContent = '[' + (i * width).toFixed(1) + ', ' + ((i + 1) * width).toFixed(1) + '): ' + c;
+      };
+      r.onmouseleave = () => { info.style.display = 'none'; };
+    });
+  }
+  draw();
+  window.addEventListener('resize', draw);
+}
+
+// Cumulative position with equal scales on both axes
+function pathChart() {
+  const box = document.getElementById('path');
+  function draw() {
+    let [x0, x1] = extent([data.path_x]), [y0, y1] = extent([data.path_y]);
+    const w = box.clientWidth - M.l - M.r, h = box.clientHeight - M.t - M.b;
+    const scale = Math.max((x1 - x0) / w, (y1 - y0) / h);
+    const cx = (x0 + x1) / 2, cy = (y0 + y1) / 2;
+    x0 = cx - scale * w / 2; x1 = cx + scale * w / 2; y0 = cy - scale * h / 2; y1 = cy + scale * h / 2;
+    const f = frame(box, x0, x1, y0, y1, 'x (counts)');
+    polyline(f.svg, data.path_x, data.path_y, f.sx, f.sy, '#393', 0, data.path_x.length);
+    if (data.path_x.length) {
+      el('circle', { cx: f.sx(data.path_x[0]), cy: f.sy(data.path_y[0]), r: 4, fill: '#393' }, f.svg);
+      const n = data.path_x.length - 1;
+      el('circle', { cx: f.sx(data.path_x[n]), cy: f.sy(data.path_y[n]), r: 4, fill: '#e00' }, f.svg);
+    }
+  }
+  draw();
+  window.addEventListener('resize', draw);
+}
+
+timeChart();
+barChart('intervals', data.interval_bucket_us, data.interval_counts, 'interval (µs)', '#c73');
+barChart('magnitudes', data.magnitude_bucket, data.magnitude_counts, 'magnitude (counts)', '#777');
+pathChart();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noisy_events(n: u32) -> Vec<MouseMoveEvent> {
+        (0..n).map(|i| MouseMoveEvent::new((i.wrapping_mul(7919) % 23) as i16 - 11, (i.wrapping_mul(104_729) % 17) as i16 - 8, i / 8000, (i % 8000) * 125)).collect()
+    }
+
+    #[test]
+    fn test_render_is_self_contained() {
+        let events = noisy_events(3000);
+        let mut report = Report::from_events(&events).with_source(Some(std::path::Path::new("a<b>.csv")));
+        report.metadata = Some(Default::default());
+        report.metadata.as_mut().unwrap().notes.comment = Some("</script><script>alert(1)".to_string());
+        let html = render(&report, &events).unwrap();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("a&lt;b&gt;.csv"));
+        assert!(!html.contains("<script>alert"), "user text must be escaped");
+        for attr in ["src=", "href=", "@import", "url(http"] {
+            assert!(!html.contains(attr), "external resource via {}", attr);
+        }
+        // Exactly one closing tag per script element
+        assert_eq!(html.matches("</script>").count(), 2);
+    }
+
+    #[test]
+    fn test_series_is_reduced_for_large_captures() {
+        let events = noisy_events(30_000);
+        let series = reduced_series(&events, CHART_WIDTH);
+        assert!(series.len() < events.len() / 4, "{} points kept of {}", series.len(), events.len());
+        assert_eq!(series.first().unwrap().0, events[0].time_micros());
+        assert!(series.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        let small = noisy_events(100);
+        assert_eq!(reduced_series(&small, CHART_WIDTH).len(), 100);
+    }
+
+    #[test]
+    fn test_path_points_are_decimated_and_keep_the_end() {
+        let events: Vec<MouseMoveEvent> = (0..10_001u32).map(|i| MouseMoveEvent::new(1, -2, 0, i)).collect();
+        let (xs, ys) = path_points(&events, MAX_PATH_POINTS);
+        assert!(xs.len() <= MAX_PATH_POINTS + 2);
+        assert_eq!((*xs.last().unwrap(), *ys.last().unwrap()), (10_001, 20_002));
+    }
+}
@@ -0,0 +1,31 @@
+//! Global keyboard state helpers used to stop a capture with F2
+//!
+//! On Windows this polls `GetAsyncKeyState`, which works even when the
+//! application window is not focused. Other platforms have no OS-level F2
+//! detection and always report the key as released.
+
+#[cfg(windows)]
+mod imp {
+    // Win32 GetAsyncKeyState via FFI
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        pub fn GetAsyncKeyState(vKey: i32) -> i16;
+    }
+
+    pub const VK_F2: i32 = 0x71;
+
+    pub fn is_f2_pressed() -> bool {
+        // Cast to u16 so mask literal fits correctly
+        unsafe { ((GetAsyncKeyState(VK_F2) as u16) & 0x8000u16) != 0u16 }
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    // fallback: no OS-level F2 detection available here
+    pub fn is_f2_pressed() -> bool {
+        false
+    }
+}
+
+pub use imp::*;
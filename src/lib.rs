@@ -4,26 +4,56 @@
 //! captured via pcap. It includes:
 //!
 //! - `mouse_event`: Data structures and parsers for mouse movement events with pcap timestamps
+//! - `capture`: USBPcap stream parsing, device filtering and the capture driver
+//! - `analysis`: Summary statistics, histograms, report interval metrics, strokes and anomalies
+//! - `config`: Layered `mousetester.toml` settings (user, project, CLI overrides)
+//! - `event_store`: Chunked append-only event storage shared by capture and UI threads
+//! - `formats`: Loading and saving events as CSV or replaying pcap captures
+//! - `plot`: Multi-panel static report images as PNG or SVG
+//! - `report`: Analysis reports as JSON (with a published schema) or HTML
+//! - `session`: Spill-to-disk session files written during capture, with crash recovery
+//! - `html_report`: Self-contained HTML reports with inline SVG charts
+//! - `key_utils`: Global F2 key detection used to stop a capture
+//! - `fit`: Polynomial least-squares fits of configurable degree with R², RMSE and max-error metrics
 //! - `lod`: Level-of-detail hierarchical segmentation for efficient offline data visualization
 //! - `lod_advanced`: Advanced LOD with time consistency analysis and adaptive segmentation
+//! - `lod_file`: Cache files of the LOD segments and segment tree of a capture, rebuilt when stale
+//! - `lod_strategy`: Common interface over the LOD algorithms and M4 min/max decimation
 //!
 //! # Example
 //!
 //! ```rust,ignore
 //! use MouseTesterRust::mouse_event::MouseMoveEvent;
+//! use MouseTesterRust::fit::Degree;
 //! use MouseTesterRust::lod::{build_segment_tree, collect_for_view};
 //!
 //! // Create or capture events
 //! let events: Vec<MouseMoveEvent> = vec![/* ... */];
 //!
 //! // Build LOD tree for efficient visualization
-//! let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0);
+//! let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0, Degree::Cubic);
 //!
 //! // Collect points for a specific view
 //! let mut view_points = Vec::new();
 //! collect_for_view(&tree, &events, 1.0, 0.5, &mut view_points);
 //! ```
 
+// For the crate name `MouseTesterRust` only, which the lint does not let a narrower allow cover
+#![allow(non_snake_case)]
+
+pub mod analysis;
+pub mod capture;
+pub mod config;
+pub mod event_store;
+pub mod fit;
+pub mod formats;
+pub mod html_report;
+pub mod key_utils;
 pub mod lod;
 pub mod lod_advanced;
+pub mod lod_file;
+pub mod lod_strategy;
 pub mod mouse_event;
+pub mod plot;
+pub mod report;
+pub mod session;
@@ -1,7 +1,7 @@
 //! Level-of-Detail (LOD) module for offline hierarchical segmentation
 //!
 //! This module implements hierarchical segmentation of mouse movement data based on
-//! cubic polynomial fits. It's designed for static plotting workflows where the full
+//! polynomial fits of a chosen degree. It's designed for static plotting workflows where the full
 //! event stream is captured first, then processed offline to build a segment tree.
 //! The tree can then be queried at different tolerance levels for efficient rendering
 //! with automatic point reduction.
@@ -9,6 +9,7 @@
 //! # Usage
 //!
 //! ```rust,ignore
+//! use mouse_tester::fit::Degree;
 //! use mouse_tester::lod::{build_segment_tree, collect_for_view};
 //! use mouse_tester::mouse_event::MouseMoveEvent;
 //!
@@ -23,7 +24,8 @@
 //!     5,      // min_pts: minimum points per segment
 //!     1000,   // max_pts: maximum points before splitting
 //!     1.0,    // px_scale: pixel scale factor
-//!     1.0     // tol_px: build tolerance in pixels
+//!     1.0,    // tol_px: build tolerance in pixels
+//!     Degree::Cubic,
 //! );
 //!
 //! // Collect points for a specific view tolerance (higher = more reduction)
@@ -56,8 +58,9 @@
 //!   - 5.0+: Maximum reduction for overview (90-99% reduction)
 //! - **px_scale**: Set based on your display DPI and zoom level
 //!
-//! The module uses SVD decomposition for numerical stability in least-squares fitting
-//! and normalizes time coordinates to [-1, 1] to improve conditioning.
+//! Fits come from the shared `fit` module, which uses SVD decomposition for numerical
+//! stability in least-squares fitting; time coordinates are normalized to [-1, 1] to
+//! improve conditioning.
 //!
 //! # Real-World Performance
 //!
@@ -66,132 +69,30 @@
 //! - 1kHz sensor @ 5px tolerance: 46% reduction (5,853 → ~3,160 points)
 //! - Quality remains visually identical at appropriate tolerance levels
 
+use crate::fit::{Degree, Domain, FitMetrics, Poly, fit_poly, normalize};
 use crate::mouse_event::MouseMoveEvent;
-use nalgebra::{DMatrix, DVector};
-
-/// Cubic polynomial coefficients: f(t) = a0 + a1*t + a2*t^2 + a3*t^3
-#[derive(Debug, Clone, Copy)]
-pub struct Poly3 {
-    pub a0: f64,
-    pub a1: f64,
-    pub a2: f64,
-    pub a3: f64,
-}
-
-impl Poly3 {
-    /// Evaluate the polynomial at time t
-    pub fn eval(&self, t: f64) -> f64 {
-        self.a0 + self.a1 * t + self.a2 * t * t + self.a3 * t * t * t
-    }
-
-    /// Create a zero polynomial
-    pub fn zero() -> Self {
-        Self { a0: 0.0, a1: 0.0, a2: 0.0, a3: 0.0 }
-    }
-}
+use serde::{Deserialize, Serialize};
 
 /// A node in the hierarchical segment tree
 ///
-/// Each node represents a time range [start, end) and stores cubic polynomial
+/// Each node represents a time range [start, end) and stores polynomial
 /// approximations for both dx and dy movements, along with the RMSE error metric.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SegmentNode {
     /// Start index in the events array (inclusive)
     pub start: usize,
     /// End index in the events array (exclusive)
     pub end: usize,
-    /// Cubic polynomial coefficients for dx
-    pub coeff_x: Poly3,
-    /// Cubic polynomial coefficients for dy
-    pub coeff_y: Poly3,
+    /// Polynomial for dx over time normalized to [-1, 1]
+    pub coeff_x: Poly,
+    /// Polynomial for dy over time normalized to [-1, 1]
+    pub coeff_y: Poly,
     /// Root Mean Square Error in pixel space
     pub rmse_px: f64,
     /// Child nodes (empty for leaf nodes)
     pub children: Vec<SegmentNode>,
 }
 
-/// Normalize times to [-1, 1] range for better numerical conditioning
-///
-/// Returns (normalized_times, time_offset, time_scale) where:
-/// - normalized_times: times mapped to [-1, 1]
-/// - time_offset: offset to subtract from original times
-/// - time_scale: scale factor for normalization
-fn normalize_times(times: &[f64]) -> (Vec<f64>, f64, f64) {
-    if times.is_empty() {
-        return (Vec::new(), 0.0, 1.0);
-    }
-
-    let t_min = times.iter().copied().fold(f64::INFINITY, f64::min);
-    let t_max = times.iter().copied().fold(f64::NEG_INFINITY, f64::max);
-    let t_mid = (t_min + t_max) / 2.0;
-    let t_range = (t_max - t_min).max(1e-10); // Avoid division by zero
-    let t_scale = 2.0 / t_range;
-
-    let normalized: Vec<f64> = times.iter().map(|&t| (t - t_mid) * t_scale).collect();
-
-    (normalized, t_mid, t_scale)
-}
-
-/// Fit a cubic polynomial to data using least-squares with SVD decomposition
-///
-/// Returns the polynomial coefficients or None if fitting fails
-fn fit_cubic_poly(t_norm: &[f64], y: &[f64]) -> Option<Poly3> {
-    let n = t_norm.len();
-    if n < 4 {
-        return None; // Need at least 4 points for cubic fit
-    }
-
-    // Build design matrix: [1, t, t^2, t^3]
-    let mut a_data = vec![0.0; n * 4];
-    for i in 0..n {
-        let t = t_norm[i];
-        let t2 = t * t;
-        let t3 = t2 * t;
-        a_data[i * 4] = 1.0;
-        a_data[i * 4 + 1] = t;
-        a_data[i * 4 + 2] = t2;
-        a_data[i * 4 + 3] = t3;
-    }
-
-    let a = DMatrix::from_row_slice(n, 4, &a_data);
-    let b = DVector::from_row_slice(y);
-
-    // Solve using SVD for numerical stability with overdetermined systems
-    let svd = a.svd(true, true);
-    let coeffs = svd.solve(&b, 1e-10).ok()?;
-
-    Some(Poly3 {
-        a0: coeffs[0],
-        a1: coeffs[1],
-        a2: coeffs[2],
-        a3: coeffs[3],
-    })
-}
-
-/// Compute RMSE in pixel space for the fit
-fn compute_rmse_px(events: &[MouseMoveEvent], start: usize, end: usize, t_norm: &[f64], coeff_x: &Poly3, coeff_y: &Poly3, px_scale: f64) -> f64 {
-    let n = end - start;
-    if n == 0 {
-        return 0.0;
-    }
-
-    let mut sum_sq_error = 0.0;
-    for i in 0..n {
-        let event = &events[start + i];
-        let t = t_norm[i];
-
-        let dx_pred = coeff_x.eval(t);
-        let dy_pred = coeff_y.eval(t);
-
-        let dx_err = (event.dx as f64 - dx_pred) * px_scale;
-        let dy_err = (event.dy as f64 - dy_pred) * px_scale;
-
-        sum_sq_error += dx_err * dx_err + dy_err * dy_err;
-    }
-
-    (sum_sq_error / (n as f64)).sqrt()
-}
-
 /// Build a hierarchical segment tree for the given event range
 ///
 /// # Parameters
@@ -203,6 +104,7 @@ fn compute_rmse_px(events: &[MouseMoveEvent], start: usize, end: usize, t_norm:
 /// - `max_pts`: Maximum points before attempting to split
 /// - `px_scale`: Pixel scale factor for error measurement
 /// - `tol_px`: Error tolerance in pixels - segments with RMSE > tol_px are split
+/// - `degree`: Degree of the dx and dy polynomials
 ///
 /// # Returns
 ///
@@ -213,27 +115,28 @@ fn compute_rmse_px(events: &[MouseMoveEvent], start: usize, end: usize, t_norm:
 /// # Algorithm
 ///
 /// 1. Extract times and normalize to [-1, 1]
-/// 2. If n < 4, create a simple leaf node (no polynomial fit)
-/// 3. Fit cubic polynomials to dx and dy using SVD decomposition
+/// 2. If n is less than the number of coefficients, create a simple leaf node (no polynomial fit)
+/// 3. Fit polynomials to dx and dy using SVD decomposition
 /// 4. Compute RMSE in pixel space
 /// 5. If RMSE > tolerance and n > min_pts, recursively split at midpoint
 /// 6. Return node with fitted polynomials and children
-pub fn build_segment_tree(events: &[MouseMoveEvent], start: usize, end: usize, min_pts: usize, max_pts: usize, px_scale: f64, tol_px: f64) -> SegmentNode {
+#[allow(clippy::only_used_in_recursion, clippy::too_many_arguments)]
+pub fn build_segment_tree(events: &[MouseMoveEvent], start: usize, end: usize, min_pts: usize, max_pts: usize, px_scale: f64, tol_px: f64, degree: Degree) -> SegmentNode {
     let n = end - start;
 
     // Extract time values and convert to seconds
     let times: Vec<f64> = (start..end).map(|i| events[i].time_secs()).collect();
 
     // Normalize times to [-1, 1] for better conditioning
-    let (t_norm, _t_offset, _t_scale) = normalize_times(&times);
+    let (t_norm, _t_offset, _t_scale) = normalize(&times, Domain::Symmetric);
 
-    // If we have fewer than 4 points, create a simple leaf
-    if n < 4 {
+    // If we have fewer points than coefficients, create a simple leaf
+    if n < degree.terms() {
         return SegmentNode {
             start,
             end,
-            coeff_x: Poly3::zero(),
-            coeff_y: Poly3::zero(),
+            coeff_x: Poly::zero(degree),
+            coeff_y: Poly::zero(degree),
             rmse_px: 0.0,
             children: Vec::new(),
         };
@@ -243,12 +146,13 @@ pub fn build_segment_tree(events: &[MouseMoveEvent], start: usize, end: usize, m
     let dx_vals: Vec<f64> = (start..end).map(|i| events[i].dx as f64).collect();
     let dy_vals: Vec<f64> = (start..end).map(|i| events[i].dy as f64).collect();
 
-    // Fit cubic polynomials
-    let coeff_x = fit_cubic_poly(&t_norm, &dx_vals).unwrap_or_else(Poly3::zero);
-    let coeff_y = fit_cubic_poly(&t_norm, &dy_vals).unwrap_or_else(Poly3::zero);
+    // Fit polynomials
+    let coeff_x = fit_poly(&t_norm, &dx_vals, None, degree).unwrap_or_else(|| Poly::zero(degree));
+    let coeff_y = fit_poly(&t_norm, &dy_vals, None, degree).unwrap_or_else(|| Poly::zero(degree));
 
-    // Compute RMSE
-    let rmse_px = compute_rmse_px(events, start, end, &t_norm, &coeff_x, &coeff_y, px_scale);
+    // Combined RMSE of dx and dy in pixel space
+    let (rmse_x, rmse_y) = (FitMetrics::new(&t_norm, &dx_vals, &coeff_x, None).rmse, FitMetrics::new(&t_norm, &dy_vals, &coeff_y, None).rmse);
+    let rmse_px = (rmse_x * rmse_x + rmse_y * rmse_y).sqrt() * px_scale.abs();
 
     // Decide whether to split
     let should_split = rmse_px > tol_px && n > min_pts && n > 2 * min_pts;
@@ -258,8 +162,8 @@ pub fn build_segment_tree(events: &[MouseMoveEvent], start: usize, end: usize, m
         let mid = start + n / 2;
 
         // Recursively build children
-        let left = build_segment_tree(events, start, mid, min_pts, max_pts, px_scale, tol_px);
-        let right = build_segment_tree(events, mid, end, min_pts, max_pts, px_scale, tol_px);
+        let left = build_segment_tree(events, start, mid, min_pts, max_pts, px_scale, tol_px, degree);
+        let right = build_segment_tree(events, mid, end, min_pts, max_pts, px_scale, tol_px, degree);
 
         vec![left, right]
     } else {
@@ -290,13 +194,24 @@ pub fn build_segment_tree(events: &[MouseMoveEvent], start: usize, end: usize, m
 /// - dx: Horizontal movement (f64)
 /// - dy: Vertical movement (f64)
 pub fn collect_for_view(node: &SegmentNode, events: &[MouseMoveEvent], px_scale: f64, view_tol_px: f64, out: &mut Vec<(u64, f64, f64)>) {
+    let mut indices = Vec::new();
+    collect_indices_for_view(node, px_scale, view_tol_px, &mut indices);
+    out.extend(indices.into_iter().map(|i| {
+        let e = &events[i];
+        (e.time_micros(), e.dx as f64, e.dy as f64)
+    }));
+}
+
+/// `collect_for_view` returning indices into the events array instead of values
+#[allow(clippy::only_used_in_recursion)]
+pub fn collect_indices_for_view(node: &SegmentNode, px_scale: f64, view_tol_px: f64, out: &mut Vec<usize>) {
     let n = node.end - node.start;
 
     // If we have children and error exceeds tolerance, recurse
     if !node.children.is_empty() && node.rmse_px > view_tol_px {
         // Recursively collect from children
         for child in &node.children {
-            collect_for_view(child, events, px_scale, view_tol_px, out);
+            collect_indices_for_view(child, px_scale, view_tol_px, out);
         }
     } else {
         // Error is acceptable or leaf node: output reduced point set
@@ -304,30 +219,39 @@ pub fn collect_for_view(node: &SegmentNode, events: &[MouseMoveEvent], px_scale:
         // For larger segments with acceptable error, output key points only
         if n <= 10 {
             // Small segment: output all points
-            for i in node.start..node.end {
-                let e = &events[i];
-                out.push((e.time_micros(), e.dx as f64, e.dy as f64));
-            }
+            out.extend(node.start..node.end);
         } else {
             // Larger segment with acceptable error: output reduced set
             // Include first, last, and sample points based on segment size
             let sample_rate = (n / 10).max(2); // Sample every N points, at least every 2
 
-            for i in (node.start..node.end).step_by(sample_rate) {
-                let e = &events[i];
-                out.push((e.time_micros(), e.dx as f64, e.dy as f64));
-            }
+            out.extend((node.start..node.end).step_by(sample_rate));
 
             // Always include the last point if not already included
             let last_idx = node.end - 1;
-            if (last_idx - node.start) % sample_rate != 0 {
-                let e = &events[last_idx];
-                out.push((e.time_micros(), e.dx as f64, e.dy as f64));
+            if !(last_idx - node.start).is_multiple_of(sample_rate) {
+                out.push(last_idx);
             }
         }
     }
 }
 
+/// Reduce `events` to roughly what a chart `width_px` pixels wide can show
+///
+/// Builds a tree whose leaves are no narrower than about a pixel column and
+/// collects it at one pixel of tolerance. `px_scale` is the chart's pixels per
+/// count. Events that fit at two per column are returned unreduced.
+pub fn collect_for_width(events: &[MouseMoveEvent], width_px: usize, px_scale: f64) -> Vec<(u64, f64, f64)> {
+    if events.len() <= width_px * 2 {
+        return events.iter().map(|e| (e.time_micros(), e.dx as f64, e.dy as f64)).collect();
+    }
+    let min_pts = (events.len() / width_px.max(1)).max(5);
+    let tree = build_segment_tree(events, 0, events.len(), min_pts, 1000, px_scale, 1.0, Degree::Cubic);
+    let mut out = Vec::new();
+    collect_for_view(&tree, events, px_scale, 1.0, &mut out);
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,62 +269,11 @@ mod tests {
         events
     }
 
-    #[test]
-    fn test_poly3_eval() {
-        let poly = Poly3 { a0: 1.0, a1: 2.0, a2: 3.0, a3: 4.0 };
-        // f(0) = 1
-        assert_eq!(poly.eval(0.0), 1.0);
-        // f(1) = 1 + 2 + 3 + 4 = 10
-        assert_eq!(poly.eval(1.0), 10.0);
-        // f(2) = 1 + 4 + 12 + 32 = 49
-        assert_eq!(poly.eval(2.0), 49.0);
-    }
-
-    #[test]
-    fn test_normalize_times() {
-        let times = vec![0.0, 1.0, 2.0, 3.0, 4.0];
-        let (normalized, offset, scale) = normalize_times(&times);
-
-        // Should be centered at 2.0 with range 4.0
-        // offset should be 2.0, scale should be 2.0/4.0 = 0.5
-        assert_eq!(offset, 2.0);
-        assert_eq!(scale, 0.5);
-
-        // Check normalized values map to [-1, 1]
-        assert!((normalized[0] - (-1.0)).abs() < 1e-10);
-        assert!((normalized[4] - 1.0).abs() < 1e-10);
-        assert!((normalized[2] - 0.0).abs() < 1e-10);
-    }
-
-    #[test]
-    fn test_fit_cubic_poly() {
-        // Test with perfectly cubic data: y = t^3
-        let t_norm = vec![-1.0, -0.5, 0.0, 0.5, 1.0];
-        let y: Vec<f64> = t_norm.iter().map(|&t| t * t * t).collect();
-
-        let poly = fit_cubic_poly(&t_norm, &y).unwrap();
-
-        // Should get a0=0, a1=0, a2=0, a3=1 (approximately)
-        assert!((poly.a0).abs() < 1e-10);
-        assert!((poly.a1).abs() < 1e-10);
-        assert!((poly.a2).abs() < 1e-10);
-        assert!((poly.a3 - 1.0).abs() < 1e-10);
-    }
-
-    #[test]
-    fn test_fit_cubic_poly_insufficient_points() {
-        let t_norm = vec![0.0, 1.0, 2.0];
-        let y = vec![0.0, 1.0, 4.0];
-
-        // Should return None with fewer than 4 points
-        assert!(fit_cubic_poly(&t_norm, &y).is_none());
-    }
-
     #[test]
     fn test_build_segment_tree_small() {
         let events = make_test_events(10);
 
-        let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0);
+        let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0, Degree::Cubic);
 
         assert_eq!(tree.start, 0);
         assert_eq!(tree.end, 10);
@@ -412,7 +285,7 @@ mod tests {
     fn test_build_segment_tree_leaf() {
         let events = make_test_events(3);
 
-        let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0);
+        let tree = build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0, Degree::Cubic);
 
         // With only 3 points, should create a zero-coefficient leaf
         assert_eq!(tree.start, 0);
@@ -425,7 +298,7 @@ mod tests {
     fn test_collect_for_view() {
         let events = make_test_events(10);
 
-        let tree = build_segment_tree(&events, 0, events.len(), 3, 1000, 1.0, 100.0);
+        let tree = build_segment_tree(&events, 0, events.len(), 3, 1000, 1.0, 100.0, Degree::Cubic);
 
         let mut out = Vec::new();
         collect_for_view(&tree, &events, 1.0, 100.0, &mut out);
@@ -446,14 +319,14 @@ mod tests {
         let events = make_test_events(100);
 
         // Use a low tolerance to force splitting
-        let tree = build_segment_tree(&events, 0, events.len(), 5, 50, 1.0, 0.1);
+        let tree = build_segment_tree(&events, 0, events.len(), 5, 50, 1.0, 0.1, Degree::Cubic);
 
         let mut out = Vec::new();
         collect_for_view(&tree, &events, 1.0, 0.1, &mut out);
 
         // Should collect reduced set (with LOD, we get fewer points than original)
         // The exact number depends on tree structure, but should be < 100 and > 0
-        assert!(out.len() > 0, "Should collect some points");
+        assert!(!out.is_empty(), "Should collect some points");
         assert!(out.len() <= 100, "Should not exceed original count");
 
         // With our sampling strategy, expect roughly 10-50% of original points
@@ -464,7 +337,7 @@ mod tests {
     fn test_segment_node_structure() {
         let events = make_test_events(20);
 
-        let tree = build_segment_tree(&events, 0, events.len(), 5, 10, 1.0, 0.5);
+        let tree = build_segment_tree(&events, 0, events.len(), 5, 10, 1.0, 0.5, Degree::Cubic);
 
         // Check that children are properly created if needed
         if !tree.children.is_empty() {
@@ -475,4 +348,28 @@ mod tests {
             assert_eq!(child_end, tree.end);
         }
     }
+
+    #[test]
+    fn test_degree_sets_tree_depth() {
+        fn nodes(node: &SegmentNode) -> usize {
+            1 + node.children.iter().map(nodes).sum::<usize>()
+        }
+        // A parabola needs splitting into lines but fits one quadratic
+        let events: Vec<MouseMoveEvent> = (0..200u32).map(|i| MouseMoveEvent::new(((i as i32 - 100).pow(2) / 50) as i16, 0, 0, i * 125)).collect();
+        let tree = |degree| build_segment_tree(&events, 0, events.len(), 5, 1000, 1.0, 1.0, degree);
+        assert!(nodes(&tree(Degree::Linear)) > nodes(&tree(Degree::Quadratic)));
+        assert_eq!(tree(Degree::Quintic).coeff_x.coeffs().len(), 6);
+    }
+
+    #[test]
+    fn test_collect_for_width() {
+        // Smooth 8 kHz movement reduces to a few points per pixel column
+        let events: Vec<MouseMoveEvent> = (0..8_000u32).map(|i| MouseMoveEvent::new((20.0 * (i as f64 / 500.0).sin()) as i16, 3, 0, i * 125)).collect();
+        let out = collect_for_width(&events, 400, 1.0);
+        assert!(out.len() < events.len() / 2, "{} points kept", out.len());
+        assert_eq!(out[0].0, events[0].time_micros());
+        assert!(out.windows(2).all(|w| w[0].0 < w[1].0));
+
+        assert_eq!(collect_for_width(&events[..800], 400, 1.0).len(), 800);
+    }
 }
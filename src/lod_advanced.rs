@@ -1,29 +1,35 @@
 //! Advanced Level-of-Detail (LOD) algorithm with time consistency and regression-based segmentation
 //!
 //! This module implements an intelligent LOD algorithm that analyzes mouse movement events
-//! for time consistency and data quality, segments them based on cubic polynomial regression
+//! for time consistency and data quality, segments them based on polynomial regression
 //! quality (R-squared), and provides efficient view-dependent filtering with caching.
 //!
 //! # Dependencies
 //!
-//! This module uses the `nalgebra` crate for numerical linear algebra operations,
-//! specifically SVD (Singular Value Decomposition) for stable least-squares fitting
-//! of cubic polynomials to mouse movement data.
+//! Polynomials, SVD least-squares fits and R² come from the shared `fit` module;
+//! the degree of the fits is a `SegmentParams` setting (cubic by default).
+//! Segmentation fits windows with `PrefixFitter`, which solves them in O(1) from
+//! prefix sums using `nalgebra` and keeps SVD as a fallback.
 //!
 //! # Key Features
 //!
 //! - **Time Consistency Analysis**: Detects events with poor time linearity (report rate issues)
 //! - **Discrete Event Detection**: Identifies zero-movement events and outliers
 //! - **Adaptive Segmentation**: Creates segments with optimal R-squared and length balance
+//! - **Parallel Segmentation**: `build_segments_parallel` and `SegmentBuilder` split large
+//!   inputs at idle gaps into chunks segmented on the rayon thread pool, with the
+//!   sequential result
 //! - **Smart Caching**: Caches regression results and reuses them for zoom operations
 //! - **View-Dependent Filtering**: Hides redundant events based on rendering resolution
+//! - **Viewport Index**: `SegmentIndex` finds the segments in view by binary search, so
+//!   zoomed-in queries cost time proportional to what is visible
 //!
 //! # Algorithm Overview
 //!
 //! 1. **Segment Analysis Phase**:
 //!    - Start with initial segment size (e.g., 10 events)
 //!    - Try larger segments by multiplying size by growth factor (1.5 or 2)
-//!    - For each segment size, compute cubic polynomial fit for dx, dy, and time vs index
+//!    - For each segment size, compute a polynomial fit (cubic by default) for dx, dy, and time vs index
 //!    - Calculate R-squared to measure fit quality
 //!    - Balance between segment length and R-squared (prefer longer segments with good R²)
 //!    - Mark discrete events: zero dx/dy, or poor time/position consistency
@@ -34,166 +40,181 @@
 //!    - Store indices of discrete events separately
 //!
 //! 3. **View Collection Phase**:
-//!    - Given rendering resolution, x/y ranges, tolerance, and zoom factor
-//!    - Calculate which events map to same pixel on screen
+//!    - Given the plotted series, rendering resolution, x/y ranges, tolerance, and zoom factor
+//!    - Calculate which events map to same pixel on screen; each series (dx, -dy, ...)
+//!      gets its own pixels and index set, so detail in one is kept when another is flat
 //!    - Keep first and last event of each good segment (preserve continuity)
 //!    - Apply tolerance: hide events if more than tolerance map to same pixel
 //!    - Return list of event indices that should be rendered
+//!
+//! 4. **View Caching** (`LodCache`, `collect_visible_indices_cached`):
+//!    - Collect the indices for the view widened by the zoom factor, at the view's pixel density
+//!    - Reuse them while panning or zooming inside the widened region
+//!    - Recompute when the view leaves it or the pixel density changes by more than 10%
 
+use crate::analysis::magnitude;
+use crate::fit::{Degree, Domain, FitMetrics, MAX_TERMS, MIN_VARIANCE, Poly, fit_poly, normalize};
 use crate::mouse_event::MouseMoveEvent;
-use nalgebra::{DMatrix, DVector};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
 
 // Constants for numerical stability and tolerance
-const SVD_TOLERANCE: f64 = 1e-10; // Tolerance for SVD solving
 const MIN_RANGE_VALUE: f64 = 1e-10; // Minimum range to prevent division by zero
-const ZOOM_TOLERANCE_FACTOR: f64 = 0.9; // 10% tolerance for zoom factor comparison
-
-/// Cubic polynomial coefficients: f(t) = a0 + a1*t + a2*t^2 + a3*t^3
-#[derive(Debug, Clone, Copy)]
-pub struct Poly3 {
-    pub a0: f64,
-    pub a1: f64,
-    pub a2: f64,
-    pub a3: f64,
+const ZOOM_TOLERANCE_FACTOR: f64 = 0.9; // 10% tolerance for pixel density comparison
+
+/// Plotted value of the events, whose pixels decide which events a view keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Series {
+    Dx,
+    /// -dy, so upward movement plots upward
+    NegDy,
+    /// Movement per report, sqrt(dx^2 + dy^2)
+    Magnitude,
+    /// Microseconds since the previous event, 0 for the first
+    Interval,
 }
 
-impl Poly3 {
-    /// Evaluate the polynomial at time t
-    pub fn eval(&self, t: f64) -> f64 {
-        self.a0 + self.a1 * t + self.a2 * t * t + self.a3 * t * t * t
-    }
-
-    /// Create a zero polynomial
-    pub fn zero() -> Self {
-        Self { a0: 0.0, a1: 0.0, a2: 0.0, a3: 0.0 }
+impl Series {
+    pub const ALL: [Series; 4] = [Series::Dx, Series::NegDy, Series::Magnitude, Series::Interval];
+
+    /// Value of the series at `events[idx]`
+    pub fn value(self, events: &[MouseMoveEvent], idx: usize) -> f64 {
+        let e = &events[idx];
+        match self {
+            Series::Dx => e.dx as f64,
+            Series::NegDy => -(e.dy as f64),
+            Series::Magnitude => magnitude(e),
+            Series::Interval => idx.checked_sub(1).map_or(0.0, |prev| e.time_micros().saturating_sub(events[prev].time_micros()) as f64),
+        }
     }
 }
 
 /// Result of regression analysis for a segment
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SegmentFit {
     pub start_idx: usize,
     pub end_idx: usize,
-    pub dx_poly: Poly3,
-    pub dy_poly: Poly3,
-    pub time_poly: Poly3, // Time vs index fit
+    pub dx_poly: Poly,
+    pub dy_poly: Poly,
+    pub time_poly: Poly, // Time vs index fit
     pub dx_r_squared: f64,
     pub dy_r_squared: f64,
     pub time_r_squared: f64, // Time consistency metric
 }
 
 /// Represents a segment of events with classification
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Segment {
     /// Good segment with high-quality polynomial fit
-    Good { start_idx: usize, end_idx: usize, fit: SegmentFit },
+    Good { start_idx: usize, end_idx: usize, fit: Box<SegmentFit> },
     /// Discrete event that doesn't fit well
     Discrete { idx: usize },
 }
 
-/// Cached LOD analysis result
-#[derive(Debug, Clone)]
-pub struct LodCache {
-    pub segments: Vec<Segment>,
-    pub zoom_factor: f64,
-    pub last_x_range: (f64, f64),
-    pub last_y_range: (f64, f64),
+/// Parameters for `build_segments`
+///
+/// The defaults are tuned for real mouse data at 1-8 kHz report rates.
+/// `balance_weight` is small because ln(len) is not normalized to 0.0 ~ 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SegmentParams {
+    pub initial_size: usize,
+    pub growth_factor: f64,
+    pub min_r_squared: f64,
+    pub balance_weight: f64,
+    /// Degree of the dx, dy and time fits
+    pub degree: Degree,
 }
 
-impl LodCache {
-    /// Check if cached result can be reused for given view
-    pub fn can_reuse(&self, x_range: (f64, f64), y_range: (f64, f64), zoom_factor: f64) -> bool {
-        // Cache is valid if new view is within cached view (zoomed in)
-        let x_within = x_range.0 >= self.last_x_range.0 && x_range.1 <= self.last_x_range.1;
-        let y_within = y_range.0 >= self.last_y_range.0 && y_range.1 <= self.last_y_range.1;
-        let zoom_ok = zoom_factor >= self.zoom_factor * ZOOM_TOLERANCE_FACTOR;
-
-        x_within && y_within && zoom_ok
+impl Default for SegmentParams {
+    fn default() -> Self {
+        Self {
+            initial_size: 10,
+            growth_factor: 1.6,
+            min_r_squared: 0.98,
+            balance_weight: 0.091,
+            degree: Degree::Cubic,
+        }
     }
 }
 
-/// Normalize values to [0, 1] range for better numerical conditioning
-fn normalize_to_unit(values: &[f64]) -> (Vec<f64>, f64, f64) {
-    if values.is_empty() {
-        return (Vec::new(), 0.0, 1.0);
-    }
-
-    let min_val = values.iter().copied().fold(f64::INFINITY, f64::min);
-    let max_val = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
-    let range = (max_val - min_val).max(MIN_RANGE_VALUE);
-
-    let normalized: Vec<f64> = values.iter().map(|&v| (v - min_val) / range).collect();
-
-    (normalized, min_val, range)
-}
-
-/// Fit a cubic polynomial using least-squares with SVD
+/// Visible indices collected for a widened viewport
 ///
-/// Requires at least 4 data points for cubic fitting.
-/// Returns None if fewer than 4 points are provided or if SVD solving fails.
-fn fit_cubic(x_norm: &[f64], y: &[f64]) -> Option<Poly3> {
-    let n = x_norm.len();
-    if n < 4 {
-        return None;
-    }
-
-    // Build design matrix: [1, x, x^2, x^3]
-    let mut a_data = vec![0.0; n * 4];
-    for i in 0..n {
-        let x = x_norm[i];
-        let x2 = x * x;
-        let x3 = x2 * x;
-        a_data[i * 4] = 1.0;
-        a_data[i * 4 + 1] = x;
-        a_data[i * 4 + 2] = x2;
-        a_data[i * 4 + 3] = x3;
-    }
-
-    let a = DMatrix::from_row_slice(n, 4, &a_data);
-    let b = DVector::from_row_slice(y);
-
-    let svd = a.svd(true, true);
-    let coeffs = svd.solve(&b, SVD_TOLERANCE).ok()?;
+/// Built for the current view widened by `zoom_factor` on both axes, at the
+/// view's pixel density, so that panning or zooming a little can reuse it.
+/// It must be dropped when the segments or events change.
+#[derive(Debug, Clone)]
+pub struct LodCache {
+    /// Indices to render anywhere inside the cached region
+    pub indices: Vec<usize>,
+    /// Series the indices were collected for
+    pub series: Series,
+    pub zoom_factor: f64,
+    /// Cached region, the requested view widened by `zoom_factor`
+    pub last_x_range: (f64, f64),
+    pub last_y_range: (f64, f64),
+    /// Pixels per unit on each axis the indices were collected at
+    pub density: (f64, f64),
+}
 
-    Some(Poly3 {
-        a0: coeffs[0],
-        a1: coeffs[1],
-        a2: coeffs[2],
-        a3: coeffs[3],
-    })
+/// Pixels per unit on each axis of a view
+fn pixel_density(render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64)) -> (f64, f64) {
+    (render_width / (x_range.1 - x_range.0).max(MIN_RANGE_VALUE), render_height / (y_range.1 - y_range.0).max(MIN_RANGE_VALUE))
 }
 
-/// Calculate R-squared (coefficient of determination) for a fit
-fn calculate_r_squared(y_actual: &[f64], y_pred: &[f64]) -> f64 {
-    if y_actual.len() != y_pred.len() || y_actual.is_empty() {
-        return 0.0;
+impl LodCache {
+    /// Collect the indices for the view widened by `zoom_factor` (at least 1)
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(index: &SegmentIndex, segments: &[Segment], events: &[MouseMoveEvent], series: Series, render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64), tolerance: f64, zoom_factor: f64) -> Self {
+        let zoom_factor = zoom_factor.max(1.0);
+        let widen = |(lo, hi): (f64, f64)| {
+            let pad = (hi - lo) * (zoom_factor - 1.0) / 2.0;
+            (lo - pad, hi + pad)
+        };
+        let (last_x_range, last_y_range) = (widen(x_range), widen(y_range));
+        // Same pixel density over the wider region; it is already widened, so no extra margin
+        let indices = collect_visible_indices_indexed(index, segments, events, series, render_width * zoom_factor, render_height * zoom_factor, last_x_range, last_y_range, tolerance, 1.0);
+        Self { indices, series, zoom_factor, last_x_range, last_y_range, density: pixel_density(render_width, render_height, x_range, y_range) }
     }
 
-    let n = y_actual.len() as f64;
-    let y_mean = y_actual.iter().sum::<f64>() / n;
-
-    let ss_tot: f64 = y_actual.iter().map(|&y| (y - y_mean).powi(2)).sum();
-    let ss_res: f64 = y_actual.iter().zip(y_pred.iter()).map(|(&y_a, &y_p)| (y_a - y_p).powi(2)).sum();
+    /// Check if cached result can be reused for given view
+    ///
+    /// The series must match and the view must lie inside the cached region at
+    /// about the same pixel density: zooming in needs more detail, zooming out
+    /// fewer points.
+    pub fn can_reuse(&self, series: Series, render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64)) -> bool {
+        let x_within = x_range.0 >= self.last_x_range.0 && x_range.1 <= self.last_x_range.1;
+        let y_within = y_range.0 >= self.last_y_range.0 && y_range.1 <= self.last_y_range.1;
+        let density = pixel_density(render_width, render_height, x_range, y_range);
+        let similar = |new: f64, old: f64| {
+            let ratio = new / old.max(MIN_RANGE_VALUE);
+            (ZOOM_TOLERANCE_FACTOR..=1.0 / ZOOM_TOLERANCE_FACTOR).contains(&ratio)
+        };
 
-    if ss_tot < 1e-10 {
-        // If variance is near zero, perfect fit
-        return 1.0;
+        series == self.series && x_within && y_within && similar(density.0, self.density.0) && similar(density.1, self.density.1)
     }
-
-    1.0 - (ss_res / ss_tot)
 }
 
 /// Analyze a segment and compute regression fit with R-squared
-fn analyze_segment(events: &[MouseMoveEvent], start_idx: usize, end_idx: usize) -> Option<SegmentFit> {
+///
+/// Fits `degree` polynomials with SVD on a design matrix of the window;
+/// `PrefixFitter` gives the same result from prefix sums.
+pub fn analyze_segment(events: &[MouseMoveEvent], start_idx: usize, end_idx: usize, degree: Degree) -> Option<SegmentFit> {
     let n = end_idx - start_idx;
-    if n < 4 {
+    if n < degree.terms() {
         return None;
     }
 
     // Extract indices normalized to [0, 1]
     let indices: Vec<f64> = (0..n).map(|i| i as f64).collect();
-    let (idx_norm, _, _) = normalize_to_unit(&indices);
+    let (idx_norm, _, _) = normalize(&indices, Domain::Unit);
 
     // Extract time values
     let times: Vec<f64> = (start_idx..end_idx).map(|i| events[i].time_secs()).collect();
@@ -203,19 +224,14 @@ fn analyze_segment(events: &[MouseMoveEvent], start_idx: usize, end_idx: usize)
     let dy_vals: Vec<f64> = (start_idx..end_idx).map(|i| events[i].dy as f64).collect();
 
     // Fit polynomials
-    let dx_poly = fit_cubic(&idx_norm, &dx_vals)?;
-    let dy_poly = fit_cubic(&idx_norm, &dy_vals)?;
-    let time_poly = fit_cubic(&idx_norm, &times)?;
-
-    // Calculate predictions
-    let dx_pred: Vec<f64> = idx_norm.iter().map(|&x| dx_poly.eval(x)).collect();
-    let dy_pred: Vec<f64> = idx_norm.iter().map(|&x| dy_poly.eval(x)).collect();
-    let time_pred: Vec<f64> = idx_norm.iter().map(|&x| time_poly.eval(x)).collect();
+    let dx_poly = fit_poly(&idx_norm, &dx_vals, None, degree)?;
+    let dy_poly = fit_poly(&idx_norm, &dy_vals, None, degree)?;
+    let time_poly = fit_poly(&idx_norm, &times, None, degree)?;
 
     // Calculate R-squared for each
-    let dx_r_squared = calculate_r_squared(&dx_vals, &dx_pred);
-    let dy_r_squared = calculate_r_squared(&dy_vals, &dy_pred);
-    let time_r_squared = calculate_r_squared(&times, &time_pred);
+    let dx_r_squared = FitMetrics::new(&idx_norm, &dx_vals, &dx_poly, None).r_squared;
+    let dy_r_squared = FitMetrics::new(&idx_norm, &dy_vals, &dy_poly, None).r_squared;
+    let time_r_squared = FitMetrics::new(&idx_norm, &times, &time_poly, None).r_squared;
 
     Some(SegmentFit {
         start_idx,
@@ -229,7 +245,254 @@ fn analyze_segment(events: &[MouseMoveEvent], start_idx: usize, end_idx: usize)
     })
 }
 
+/// Slopes (coefficients after c0) of the highest `Degree`
+const MAX_SLOPES: usize = MAX_TERMS - 1;
+/// Binomial coefficients C(k, q) for k < `MAX_TERMS`
+const BINOMIAL: [[i128; MAX_TERMS]; MAX_TERMS] = [[1, 0, 0, 0, 0, 0], [1, 1, 0, 0, 0, 0], [1, 2, 1, 0, 0, 0], [1, 3, 3, 1, 0, 0], [1, 4, 6, 4, 1, 0], [1, 5, 10, 10, 5, 1]];
+/// Bernoulli numbers B0..B10 with B1 = +1/2, for Faulhaber's formula
+const BERNOULLI: [f64; 2 * MAX_TERMS - 1] = [1.0, 0.5, 1.0 / 6.0, 0.0, -1.0 / 30.0, 0.0, 1.0 / 42.0, 0.0, -1.0 / 30.0, 0.0, 5.0 / 66.0];
+
+/// Longest window `PrefixFitter` solves from its sums at `degree`, longer ones use SVD
+///
+/// Keeps n·Σiᵈy of the window well inside `i128`.
+fn max_prefix_window(degree: Degree) -> usize {
+    1 << (100 / (degree.value() + 2))
+}
+
+/// Σiᵏ for i = 0..=m, exact for k up to 5
+fn index_power_sum(m: i128, k: usize) -> i128 {
+    let p1 = m * (m + 1) / 2;
+    match k {
+        0 => m + 1,
+        1 => p1,
+        2 => m * (m + 1) * (2 * m + 1) / 6,
+        3 => p1 * p1,
+        4 => m * (m + 1) * (2 * m + 1) * (3 * m * m + 3 * m - 1) / 30,
+        _ => m * m * (m + 1) * (m + 1) * (2 * m * m + 2 * m - 1) / 12,
+    }
+}
+
+/// Σ(i/m)ᵖ for i = 0..=m by Faulhaber's formula
+fn unit_power_sum(m: f64, p: usize) -> f64 {
+    let mut binomial = 1.0; // C(p + 1, j)
+    let mut sum = 0.0;
+    for (j, b) in BERNOULLI.iter().enumerate().take(p + 1) {
+        sum += binomial * b * m.powi(1 - j as i32);
+        binomial *= (p + 1 - j) as f64 / (j + 1) as f64;
+    }
+    sum / (p + 1) as f64 + if p == 0 { 1.0 } else { 0.0 }
+}
+
+/// Least-squares polynomial fits of any window in O(1)
+///
+/// Keeps exact integer prefix sums of jᵏy (k up to the degree) and y² for dx,
+/// dy and the event time in microseconds, so the normal equations and R² of a
+/// window follow from a few differences instead of a design matrix and three
+/// SVDs. Results match `analyze_segment` up to rounding. Windows the sums
+/// cannot solve reliably (very long, overflowing, or a singular system) fall
+/// back to `analyze_segment`. Uses 48·(degree + 2) bytes per event from `start`
+/// on; `drop_before` frees the sums of events no window starts at any more.
+#[derive(Debug, Clone)]
+pub struct PrefixFitter {
+    degree: Degree,
+    /// First event windows can start at
+    start: usize,
+    /// Sums over the events before index j start at `(j - start) * stride()`:
+    /// for each of dx, dy and time, Σjᵏy for k = 0..=degree, then Σy²
+    sums: Vec<i128>,
+    /// Time of the first event in microseconds, subtracted from all times
+    base_micros: i128,
+}
+
+/// Powers of x = i/(n-1) over a window of n events, shared by its three series
+struct WindowBasis {
+    degree: usize,
+    n: i128,
+    /// Σiᵏ for k = 0..=degree, exact
+    index_sums: [i128; MAX_TERMS],
+    /// Mean of xᵏ for k = 0..=degree
+    x_means: [f64; MAX_TERMS],
+    /// (n-1)ᵏ for k = 0..=degree
+    index_scale: [f64; MAX_TERMS],
+    /// Centred Gram matrix of x..x^degree, padded with the identity
+    gram: nalgebra::Cholesky<f64, nalgebra::Const<MAX_SLOPES>>,
+}
+
+impl WindowBasis {
+    fn new(n: usize, degree: Degree) -> Option<Self> {
+        let d = degree.value();
+        let m = (n - 1) as i128;
+        let (mf, nf) = (m as f64, n as f64);
+        let x_sums: [f64; 2 * MAX_TERMS - 1] = std::array::from_fn(|p| if p <= 2 * d { unit_power_sum(mf, p) } else { 0.0 });
+        let gram = nalgebra::SMatrix::<f64, MAX_SLOPES, MAX_SLOPES>::from_fn(|k, l| match (k < d && l < d, k == l) {
+            (true, _) => x_sums[k + l + 2] - x_sums[k + 1] * x_sums[l + 1] / nf,
+            (false, diagonal) => diagonal as u8 as f64,
+        });
+        Some(Self {
+            degree: d,
+            n: m + 1,
+            index_sums: std::array::from_fn(|k| if k <= d { index_power_sum(m, k) } else { 0 }),
+            x_means: std::array::from_fn(|k| x_sums[k] / nf),
+            index_scale: std::array::from_fn(|k| mf.powi(k as i32)),
+            gram: gram.cholesky()?,
+        })
+    }
+
+    /// Fit of one series from its window sums [Σiᵏy for k = 0..=degree, Σy²], y shifted by `offset`
+    ///
+    /// `unit` converts y to the units the constant-data threshold `MIN_VARIANCE` applies to.
+    fn fit(&self, sums: &[i128; MAX_TERMS + 1], offset: i128, unit: f64, degree: Degree) -> Option<(Poly, f64)> {
+        let (n, d) = (self.n, self.degree);
+        // Shift y by the window's first value to keep the numbers small
+        let y_sum = sums[0].wrapping_sub(n.wrapping_mul(offset));
+        let y2_sum = sums[d + 1].wrapping_sub(2i128.wrapping_mul(offset).wrapping_mul(sums[0])).wrapping_add(n.wrapping_mul(offset).wrapping_mul(offset));
+        // n·Σxᵏ(y - ȳ) for k = 1..=degree, and n·SS_tot, in exact integers
+        let mut centred = nalgebra::SVector::<f64, MAX_SLOPES>::zeros();
+        for k in 1..=d {
+            let moment = sums[k].wrapping_sub(offset.wrapping_mul(self.index_sums[k]));
+            let scaled = n.checked_mul(moment)?.checked_sub(y_sum.checked_mul(self.index_sums[k])?)?;
+            centred[k - 1] = scaled as f64 / (n as f64 * self.index_scale[k]);
+        }
+        let ss_tot = n.checked_mul(y2_sum)?.checked_sub(y_sum.checked_mul(y_sum)?)? as f64 / n as f64;
+
+        let beta = self.gram.solve(&centred);
+        let mut coeffs = [0.0; MAX_TERMS];
+        coeffs[0] = y_sum as f64 / n as f64 + offset as f64;
+        for k in 1..=d {
+            coeffs[k] = beta[k - 1];
+            coeffs[0] -= beta[k - 1] * self.x_means[k];
+        }
+        // For least squares with an intercept, 1 - SS_res/SS_tot = ESS/SS_tot = β·Σxᵏ(y - ȳ)/SS_tot
+        let r_squared = if ss_tot * unit * unit < MIN_VARIANCE { 1.0 } else { beta.dot(&centred) / ss_tot };
+        (r_squared.is_finite() && coeffs.iter().all(|c| c.is_finite())).then(|| (Poly::new(degree, &coeffs[..=d]), r_squared))
+    }
+}
+
+impl PrefixFitter {
+    /// Fitter of `degree` polynomials over `events`
+    pub fn new(events: &[MouseMoveEvent], degree: Degree) -> Self {
+        let mut fitter = Self::starting_at(0, degree);
+        fitter.extend(events);
+        fitter
+    }
+
+    /// Fitter without sums whose windows start at `start` or later
+    pub fn starting_at(start: usize, degree: Degree) -> Self {
+        Self { degree, start, sums: Vec::new(), base_micros: 0 }
+    }
+
+    pub fn degree(&self) -> Degree {
+        self.degree
+    }
+
+    /// Sums kept per event
+    fn stride(&self) -> usize {
+        3 * (self.degree.terms() + 1)
+    }
+
+    /// First event windows can start at
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Number of events covered by the sums, counting those before `start`
+    pub fn len(&self) -> usize {
+        self.start + (self.sums.len() / self.stride()).saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add the sums of the events after those already covered
+    ///
+    /// `events` must start with the events of previous calls, and reach
+    /// `start` before sums are added.
+    pub fn extend(&mut self, events: &[MouseMoveEvent]) {
+        let (stride, terms) = (self.stride(), self.degree.terms());
+        if self.sums.is_empty() {
+            let Some(first) = events.first().filter(|_| events.len() > self.start) else { return };
+            self.base_micros = first.time_micros() as i128;
+            self.sums.resize(stride, 0);
+        }
+        self.sums.reserve(events.len().saturating_sub(self.len()) * stride);
+        for (j, e) in events.iter().enumerate().skip(self.len()) {
+            let (j, last) = (j as i128, self.sums.len() - stride);
+            for (series, y) in [e.dx as i128, e.dy as i128, e.time_micros() as i128 - self.base_micros].into_iter().enumerate() {
+                let mut term = y;
+                for q in 0..=terms {
+                    let value = if q < terms { term } else { y * y };
+                    term = term.wrapping_mul(j);
+                    let sum = self.sums[last + series * (terms + 1) + q].wrapping_add(value);
+                    self.sums.push(sum);
+                }
+            }
+        }
+    }
+
+    /// Free the sums of the events before `start`; windows starting there fall back to SVD
+    ///
+    /// The sums stay those of all events since the first, so windows after
+    /// `start` give the same fits as before.
+    pub fn drop_before(&mut self, start: usize) {
+        if start <= self.start {
+            return;
+        }
+        if start >= self.len() {
+            *self = Self::starting_at(start, self.degree);
+            return;
+        }
+        let stride = self.stride();
+        self.sums.drain(..(start - self.start) * stride);
+        self.sums.shrink_to(2 * self.sums.len());
+        self.start = start;
+    }
+
+    /// Sums of `series` over events start..end with the index counted from `start`
+    fn window_sums(&self, series: usize, start: usize, end: usize) -> [i128; MAX_TERMS + 1] {
+        let (stride, terms) = (self.stride(), self.degree.terms());
+        let at = |j: usize, q: usize| self.sums[(j - self.start) * stride + series * (terms + 1) + q];
+        let mut sums = [0i128; MAX_TERMS + 1];
+        // Σ(j - s)ᵏy by the binomial theorem, exact in wrapping arithmetic
+        let neg_start = (start as i128).wrapping_neg();
+        for k in 0..terms {
+            let mut power = 1i128;
+            for q in (0..=k).rev() {
+                let g = at(end, q).wrapping_sub(at(start, q));
+                sums[k] = sums[k].wrapping_add(BINOMIAL[k][q].wrapping_mul(power).wrapping_mul(g));
+                power = power.wrapping_mul(neg_start);
+            }
+        }
+        sums[terms] = at(end, terms).wrapping_sub(at(start, terms));
+        sums
+    }
+
+    /// Same as `analyze_segment` at the fitter's degree, in O(1) for windows the sums cover
+    pub fn fit(&self, events: &[MouseMoveEvent], start_idx: usize, end_idx: usize) -> Option<SegmentFit> {
+        let n = end_idx.checked_sub(start_idx)?;
+        if n < self.degree.terms() {
+            return None;
+        }
+        if n <= max_prefix_window(self.degree) && start_idx >= self.start && end_idx <= self.len() && let Some(fit) = self.fit_from_sums(events, start_idx, end_idx) {
+            return Some(fit);
+        }
+        analyze_segment(events, start_idx, end_idx, self.degree)
+    }
+
+    fn fit_from_sums(&self, events: &[MouseMoveEvent], start_idx: usize, end_idx: usize) -> Option<SegmentFit> {
+        let basis = WindowBasis::new(end_idx - start_idx, self.degree)?;
+        let first = &events[start_idx];
+        let (dx_poly, dx_r_squared) = basis.fit(&self.window_sums(0, start_idx, end_idx), first.dx as i128, 1.0, self.degree)?;
+        let (dy_poly, dy_r_squared) = basis.fit(&self.window_sums(1, start_idx, end_idx), first.dy as i128, 1.0, self.degree)?;
+        let (time_poly, time_r_squared) = basis.fit(&self.window_sums(2, start_idx, end_idx), first.time_micros() as i128 - self.base_micros, 1e-6, self.degree)?;
+        // Back from microseconds since the first event to seconds
+        let time_poly = time_poly.scaled(1e-6, events[0].time_secs());
+        Some(SegmentFit { start_idx, end_idx, dx_poly, dy_poly, time_poly, dx_r_squared, dy_r_squared, time_r_squared })
+    }
+}
+
 /// Check if an event is discrete (zero movement or poor fit)
+#[cfg_attr(not(test), allow(dead_code))]
 fn is_discrete_event(event: &MouseMoveEvent) -> bool {
     event.dx == 0 && event.dy == 0
 }
@@ -243,225 +506,715 @@ fn is_discrete_event(event: &MouseMoveEvent) -> bool {
 /// - `growth_factor`: Factor to multiply segment size when expanding (e.g., 1.5 or 2.0)
 /// - `min_r_squared`: Minimum acceptable R-squared (e.g., 0.8 or 0.9)
 /// - `balance_weight`: Weight for balancing length vs R-squared (0.0-1.0, higher favors length)
+/// - `degree`: Degree of the dx, dy and time fits; segments need at least `degree.terms()` events
 ///
 /// # Returns
 ///
 /// Vector of segments (Good or Discrete)
-pub fn build_segments(events: &[MouseMoveEvent], initial_size: usize, growth_factor: f64, min_r_squared: f64, balance_weight: f64) -> Vec<Segment> {
-    if events.is_empty() {
-        return Vec::new();
-    }
+pub fn build_segments(events: &[MouseMoveEvent], initial_size: usize, growth_factor: f64, min_r_squared: f64, balance_weight: f64, degree: Degree) -> Vec<Segment> {
+    build_segments_with_progress(events, initial_size, growth_factor, min_r_squared, balance_weight, degree, |_| true).unwrap_or_default()
+}
 
+/// `build_segments` that reports progress and can be cancelled
+///
+/// `progress` is called with the number of events segmented so far before each
+/// new segment; returning `false` stops the build and returns `None`.
+#[allow(clippy::too_many_arguments)]
+pub fn build_segments_with_progress(events: &[MouseMoveEvent], initial_size: usize, growth_factor: f64, min_r_squared: f64, balance_weight: f64, degree: Degree, mut progress: impl FnMut(usize) -> bool) -> Option<Vec<Segment>> {
+    let params = SegmentParams { initial_size, growth_factor, min_r_squared, balance_weight, degree };
+    let fitter = PrefixFitter::new(events, degree);
     let mut segments = Vec::new();
     let mut pos = 0;
 
     while pos < events.len() {
-        // Try progressively larger segments
-        let mut best_fit: Option<SegmentFit> = None;
-        let mut best_score = f64::NEG_INFINITY;
-        let mut best_r_squared = f64::NEG_INFINITY;
-        let mut current_size = initial_size;
-        let mut fit_tolerance = 0;
-        let max_fit_tolerance_r_squared_up = 10;
-        let max_fit_tolerance_r_squared_down = 3;
-
-        while pos + current_size <= events.len() {
-            let end = pos + current_size;
-
-            if let Some(fit) = analyze_segment(events, pos, end) {
-                // Calculate composite R-squared (average of dx, dy, time)
-                let avg_r_squared = (fit.dx_r_squared + fit.dy_r_squared + fit.time_r_squared) / 3.0;
-
-                // Only consider if all individual R-squared values are reasonable
-                if avg_r_squared >= min_r_squared && fit.time_r_squared >= min_r_squared * 0.7 {
-                    // Score balances R-squared and segment length
-                    // Higher balance_weight favors longer segments
-                    let length_score = (current_size as f64).ln();
-                    let score = balance_weight * length_score + (1.0 - balance_weight) * avg_r_squared;
-
-                    if score > best_score {
-                        best_score = score;
-                        best_fit = Some(fit);
-                        fit_tolerance = 0;
+        if !progress(pos) {
+            return None;
+        }
+        let (segment, _) = next_segment(events, &fitter, pos, &params);
+        pos = segment_bounds(&segment).1;
+        segments.push(segment);
+    }
+
+    if !events.is_empty() {
+        progress(events.len());
+    }
+    Some(segments)
+}
+
+/// The segment starting at `pos`, and whether more events after `events` could change it
+///
+/// The segment is final when growing it stopped on poor fits rather than on
+/// running out of events, since it only depends on the events it tried.
+fn next_segment(events: &[MouseMoveEvent], fitter: &PrefixFitter, pos: usize, params: &SegmentParams) -> (Segment, bool) {
+    let &SegmentParams { initial_size, growth_factor, min_r_squared, balance_weight, .. } = params;
+
+    // Try progressively larger segments
+    let mut best_fit: Option<SegmentFit> = None;
+    let mut best_score = f64::NEG_INFINITY;
+    let mut best_r_squared = f64::NEG_INFINITY;
+    let mut current_size = initial_size;
+    let mut fit_tolerance = 0;
+    let max_fit_tolerance_r_squared_up = 10;
+    let max_fit_tolerance_r_squared_down = 3;
+    let mut is_final = false;
+
+    while pos + current_size <= events.len() {
+        let end = pos + current_size;
+
+        if let Some(fit) = fitter.fit(events, pos, end) {
+            // Calculate composite R-squared (average of dx, dy, time)
+            let avg_r_squared = (fit.dx_r_squared + fit.dy_r_squared + fit.time_r_squared) / 3.0;
+
+            // Only consider if all individual R-squared values are reasonable
+            if avg_r_squared >= min_r_squared && fit.time_r_squared >= min_r_squared * 0.7 {
+                // Score balances R-squared and segment length
+                // Higher balance_weight favors longer segments
+                let length_score = (current_size as f64).ln();
+                let score = balance_weight * length_score + (1.0 - balance_weight) * avg_r_squared;
+
+                if score > best_score {
+                    best_score = score;
+                    best_fit = Some(fit);
+                    fit_tolerance = 0;
+                }
+            } else {
+                // Fit quality degraded
+                if avg_r_squared > best_r_squared {
+                    fit_tolerance += 1;
+                    if fit_tolerance > max_fit_tolerance_r_squared_up {
+                        is_final = true;
+                        break;
                     }
                 } else {
-                    // Fit quality degraded
-                    if avg_r_squared > best_r_squared {
-                        fit_tolerance += 1;
-                        if fit_tolerance > max_fit_tolerance_r_squared_up {
-                            break;
-                        }
-                    } else {
-                        fit_tolerance += 1;
-                        if fit_tolerance > max_fit_tolerance_r_squared_down {
-                            break;
-                        }
+                    fit_tolerance += 1;
+                    if fit_tolerance > max_fit_tolerance_r_squared_down {
+                        is_final = true;
+                        break;
                     }
                 }
-                // Try larger segment
-                current_size = ((current_size as f64) * growth_factor).ceil() as usize;
+            }
+            // Try larger segment
+            current_size = ((current_size as f64) * growth_factor).ceil() as usize;
 
-                if avg_r_squared > best_r_squared {
-                    best_r_squared = avg_r_squared;
+            if avg_r_squared > best_r_squared {
+                best_r_squared = avg_r_squared;
+            }
+        } else {
+            is_final = true;
+            break;
+        }
+    }
+
+    let segment = match best_fit {
+        Some(fit) => Segment::Good { start_idx: fit.start_idx, end_idx: fit.end_idx, fit: Box::new(fit) },
+        // Couldn't fit well, mark as discrete
+        None => Segment::Discrete { idx: pos },
+    };
+    (segment, is_final)
+}
+
+/// Events per chunk below which segmenting stays sequential
+const MIN_PARALLEL_CHUNK: usize = 1 << 14;
+/// Interval before an event that marks an idle gap, in microseconds
+const IDLE_GAP_MICROS: u64 = 10_000;
+/// How often a parallel `SegmentBuilder::extend_with_progress` reports progress
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+/// Visible events per pixel column above which a good segment is reduced to the M4 points of each column
+const MAX_COLUMN_EVENTS: usize = 8;
+
+/// Number of chunks that keeps every thread of the pool busy for `len` events, 1 if not worth splitting
+pub fn parallel_chunks(len: usize) -> usize {
+    if rayon::current_num_threads() < 2 {
+        return 1;
+    }
+    (rayon::current_num_threads() * 4).min(len / MIN_PARALLEL_CHUNK).max(1)
+}
+
+/// Start of each of `chunks` chunks of the events from `from` on, followed by `events.len()`
+///
+/// Each boundary is taken near an even split, at the longest idle gap or else
+/// the nearest zero-movement event. Segments rarely span either, so the
+/// sequential segmentation usually passes through the boundary.
+fn natural_boundaries(events: &[MouseMoveEvent], from: usize, chunks: usize) -> Vec<usize> {
+    let span = events.len() - from;
+    let chunks = chunks.clamp(1, span.max(1));
+    let radius = span / chunks / 4;
+    let gap = |i: usize| events[i].time_micros().saturating_sub(events[i - 1].time_micros());
+    let mut bounds = vec![from];
+    for k in 1..chunks {
+        let target = from + k * span / chunks;
+        let near = target - radius..target + radius + 1;
+        let idle = near.clone().max_by_key(|&i| gap(i)).filter(|&i| gap(i) >= IDLE_GAP_MICROS);
+        let still = || near.clone().filter(|&i| events[i].dx == 0 && events[i].dy == 0).min_by_key(|&i| i.abs_diff(target));
+        bounds.push(idle.or_else(still).unwrap_or(target));
+    }
+    bounds.push(events.len());
+    bounds.dedup();
+    bounds
+}
+
+/// Segments from `pos` on until one reaches `end`, with whether each is final
+///
+/// Adds the events segmented to `done`; returns `None` when `cancel` is set.
+fn segment_run(events: &[MouseMoveEvent], fitter: &PrefixFitter, params: &SegmentParams, mut pos: usize, end: usize, cancel: &AtomicBool, done: &AtomicUsize) -> Option<Vec<(Segment, bool)>> {
+    let mut run = Vec::new();
+    while pos < end {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        let (segment, is_final) = next_segment(events, fitter, pos, params);
+        let next = segment_bounds(&segment).1;
+        done.fetch_add(next.min(end) - pos, Ordering::Relaxed);
+        pos = next;
+        run.push((segment, is_final));
+    }
+    Some(run)
+}
+
+/// Segment the events from `from` on in `chunks` chunks on the rayon thread pool
+///
+/// Each chunk is segmented from its boundary as if a segment started there.
+/// Stitching keeps a chunk's segments from the first one that starts where the
+/// segments before it end, and segments sequentially up to there, so the
+/// result is the sequential one. Returns the segments of the leading chunks
+/// finished before `cancel` was set, and whether all finished.
+fn segment_parallel(events: &[MouseMoveEvent], fitter: &PrefixFitter, params: &SegmentParams, from: usize, chunks: usize, cancel: &AtomicBool, done: &AtomicUsize) -> (Vec<(Segment, bool)>, bool) {
+    let bounds = natural_boundaries(events, from, chunks);
+    let runs: Vec<_> = bounds.par_windows(2).map(|w| segment_run(events, fitter, params, w[0], w[1], cancel, done)).collect();
+
+    let mut segments: Vec<(Segment, bool)> = Vec::new();
+    let mut pos = from;
+    for (run, &run_end) in runs.iter().zip(&bounds[1..]) {
+        let Some(run) = run else { return (segments, false) };
+        loop {
+            match run.binary_search_by_key(&pos, |(segment, _)| segment_bounds(segment).0) {
+                Ok(i) => {
+                    segments.extend_from_slice(&run[i..]);
+                    pos = segments.last().map_or(pos, |(segment, _)| segment_bounds(segment).1);
+                    break;
                 }
-            } else {
+                Err(_) if pos >= run_end => break,
+                Err(_) => {
+                    let (segment, is_final) = next_segment(events, fitter, pos, params);
+                    pos = segment_bounds(&segment).1;
+                    segments.push((segment, is_final));
+                }
+            }
+        }
+    }
+    (segments, true)
+}
+
+/// `build_segments` on the rayon thread pool, split into `chunks` chunks
+///
+/// The chunks are split at idle gaps or zero-movement events and the result
+/// is identical to `build_segments`. Use `parallel_chunks` for the chunk count.
+#[allow(clippy::too_many_arguments)]
+pub fn build_segments_parallel(events: &[MouseMoveEvent], initial_size: usize, growth_factor: f64, min_r_squared: f64, balance_weight: f64, degree: Degree, chunks: usize) -> Vec<Segment> {
+    if events.is_empty() {
+        return Vec::new();
+    }
+    let params = SegmentParams { initial_size, growth_factor, min_r_squared, balance_weight, degree };
+    let fitter = PrefixFitter::new(events, degree);
+    let (segments, _) = segment_parallel(events, &fitter, &params, 0, chunks, &AtomicBool::new(false), &AtomicUsize::new(0));
+    segments.into_iter().map(|(segment, _)| segment).collect()
+}
+
+/// Incremental `build_segments` for a growing event sequence
+///
+/// Keeps the segments that more events can no longer change and only
+/// segments the events after them on each `extend`, so a live capture or a
+/// growing file costs time proportional to the new events. `segments` gives
+/// the same result as `build_segments` on all events.
+#[derive(Debug, Clone)]
+pub struct SegmentBuilder {
+    params: SegmentParams,
+    finalized: Vec<Segment>,
+    /// First event not covered by `finalized`
+    pos: usize,
+    fitter: PrefixFitter,
+}
+
+impl SegmentBuilder {
+    pub fn new(params: SegmentParams) -> Self {
+        Self { params, finalized: Vec::new(), pos: 0, fitter: PrefixFitter::new(&[], params.degree) }
+    }
+
+    /// Builder that resumes after the first `finalized_len` events of `segments`
+    ///
+    /// `segments` must be segments of events built with `params`, e.g. a
+    /// cached result of `segments`, and `finalized_len` the `finalized_len`
+    /// of the builder they came from.
+    pub fn from_segments(params: SegmentParams, segments: &[Segment], finalized_len: usize) -> Self {
+        let finalized: Vec<Segment> = segments.iter().take_while(|s| segment_bounds(s).1 <= finalized_len).cloned().collect();
+        let pos = finalized.last().map_or(0, |s| segment_bounds(s).1);
+        Self { finalized, pos, fitter: PrefixFitter::starting_at(pos, params.degree), params }
+    }
+
+    pub fn params(&self) -> &SegmentParams {
+        &self.params
+    }
+
+    /// Segments that more events cannot change
+    pub fn finalized(&self) -> &[Segment] {
+        &self.finalized
+    }
+
+    /// Number of events covered by the finalized segments
+    pub fn finalized_len(&self) -> usize {
+        self.pos
+    }
+
+    /// Free the prefix sums of the unfinalized events
+    ///
+    /// For builders that are kept but not extended soon; the next `extend`
+    /// sums the events after the finalized ones again.
+    pub fn release_fitter(&mut self) {
+        self.fitter = PrefixFitter::starting_at(self.pos, self.params.degree);
+    }
+
+    /// Finalize the segments of `events` that can no longer grow
+    ///
+    /// `events` must start with the events of previous calls. If it is
+    /// shorter than the finalized events, the builder starts over. Only the
+    /// prefix sums of the events after the finalized ones are kept.
+    pub fn extend(&mut self, events: &[MouseMoveEvent]) {
+        self.extend_with_progress(events, |_| true);
+    }
+
+    /// `extend` that reports progress and can be cancelled
+    ///
+    /// `progress` is called with the number of events finalized so far before
+    /// each new segment; returning `false` stops and returns `false`. The
+    /// segments finalized until then are kept.
+    pub fn extend_with_progress(&mut self, events: &[MouseMoveEvent], progress: impl FnMut(usize) -> bool) -> bool {
+        if events.len() < self.pos {
+            *self = Self::new(self.params);
+        }
+        self.fitter.extend(events);
+        let chunks = parallel_chunks(events.len() - self.pos);
+        let completed = if chunks > 1 { self.extend_parallel(events, chunks, progress) } else { self.extend_sequential(events, progress) };
+        // Finalized segments are never refitted
+        self.fitter.drop_before(self.pos);
+        completed
+    }
+
+    fn extend_sequential(&mut self, events: &[MouseMoveEvent], mut progress: impl FnMut(usize) -> bool) -> bool {
+        while self.pos < events.len() {
+            if !progress(self.pos) {
+                return false;
+            }
+            let (segment, is_final) = next_segment(events, &self.fitter, self.pos, &self.params);
+            if !is_final {
                 break;
             }
+            self.pos = segment_bounds(&segment).1;
+            self.finalized.push(segment);
         }
+        true
+    }
 
-        if let Some(fit) = best_fit {
-            let segment_len = fit.end_idx - fit.start_idx;
-            segments.push(Segment::Good {
-                start_idx: fit.start_idx,
-                end_idx: fit.end_idx,
-                fit,
+    /// `extend_with_progress` for many new events, segmented by `segment_parallel`
+    ///
+    /// `progress` is called on this thread with the number of events segmented so far.
+    fn extend_parallel(&mut self, events: &[MouseMoveEvent], chunks: usize, mut progress: impl FnMut(usize) -> bool) -> bool {
+        let (cancel, done) = (AtomicBool::new(false), AtomicUsize::new(self.pos));
+        let (segments, completed) = thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+            let (fitter, params, from, cancel, done) = (&self.fitter, &self.params, self.pos, &cancel, &done);
+            let worker = scope.spawn(move || {
+                let result = segment_parallel(events, fitter, params, from, chunks, cancel, done);
+                tx.send(()).ok();
+                result
             });
-            pos += segment_len;
+            while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(PROGRESS_INTERVAL) {
+                if !progress(done.load(Ordering::Relaxed)) {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+            worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        });
+        for (segment, is_final) in segments {
+            if !is_final {
+                break;
+            }
+            self.pos = segment_bounds(&segment).1;
+            self.finalized.push(segment);
+        }
+        completed && !cancel.load(Ordering::Relaxed)
+    }
+
+    /// All segments of `events`: the finalized ones and those of the events after them
+    ///
+    /// `events` must be the events of the last `extend` or more.
+    pub fn segments(&self, events: &[MouseMoveEvent]) -> Vec<Segment> {
+        let mut segments = self.finalized.clone();
+        let mut fitter = Cow::Borrowed(&self.fitter);
+        if fitter.len() < events.len() {
+            fitter.to_mut().extend(events);
+        }
+        let mut pos = self.pos;
+        while pos < events.len() {
+            let (segment, _) = next_segment(events, &fitter, pos, &self.params);
+            pos = segment_bounds(&segment).1;
+            segments.push(segment);
+        }
+        segments
+    }
+}
+
+/// Time index over segments for viewport queries
+///
+/// Keeps, per segment, the running maximum of event times up to it and the
+/// minimum of event times from it on. Both are sorted, so the segments that
+/// can hold events in a time range are found with two binary searches, even
+/// when timestamps occasionally go backwards.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentIndex {
+    /// Latest event time in `segments[..=i]`
+    max_end: Vec<f64>,
+    /// Earliest event time in `segments[i..]`
+    min_start: Vec<f64>,
+    /// Whether the event times inside segment `i` never decrease
+    monotone: Vec<bool>,
+}
+
+impl SegmentIndex {
+    /// Index `segments` built from `events`
+    pub fn new(segments: &[Segment], events: &[MouseMoveEvent]) -> Self {
+        let mut max_end = Vec::with_capacity(segments.len());
+        let mut min_start = Vec::with_capacity(segments.len());
+        let mut monotone = Vec::with_capacity(segments.len());
+        let mut latest = f64::NEG_INFINITY;
+        for segment in segments {
+            let (start, end) = segment_bounds(segment);
+            let times = events.get(start..end).unwrap_or_default().iter().map(|e| e.time_secs());
+            let (mut lo, mut hi, mut prev, mut sorted) = (f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY, true);
+            for t in times {
+                lo = lo.min(t);
+                hi = hi.max(t);
+                sorted &= t >= prev;
+                prev = t;
+            }
+            latest = latest.max(hi);
+            max_end.push(latest);
+            min_start.push(lo);
+            monotone.push(sorted);
+        }
+        for i in (0..min_start.len().saturating_sub(1)).rev() {
+            min_start[i] = min_start[i].min(min_start[i + 1]);
+        }
+        Self { max_end, min_start, monotone }
+    }
+
+    /// Number of indexed segments
+    pub fn len(&self) -> usize {
+        self.max_end.len()
+    }
+
+    /// Whether no segments are indexed
+    pub fn is_empty(&self) -> bool {
+        self.max_end.is_empty()
+    }
+
+    /// Segments that may hold events with times in `t_min..=t_max`
+    pub fn overlapping(&self, t_min: f64, t_max: f64) -> Range<usize> {
+        let first = self.max_end.partition_point(|&t| t < t_min);
+        let last = self.min_start.partition_point(|&t| t <= t_max).max(first);
+        first..last
+    }
+}
+
+/// Event index range `start..end` of a segment
+fn segment_bounds(segment: &Segment) -> (usize, usize) {
+    match *segment {
+        Segment::Good { start_idx, end_idx, .. } => (start_idx, end_idx),
+        Segment::Discrete { idx } => (idx, idx + 1),
+    }
+}
+
+/// Shared state of the visible index queries
+struct VisibleCollector<'a> {
+    events: &'a [MouseMoveEvent],
+    series: Series,
+    x_min: f64,
+    y_min: f64,
+    x_scale: f64,
+    y_scale: f64,
+    min_x_visible: f64,
+    max_x_visible: f64,
+    tolerance: f64,
+    seen_pixels: HashSet<(i32, i32)>,
+    visible_indices: Vec<usize>,
+    // Scratch space of `sort_column`
+    row_counts: Vec<usize>,
+    sorted: Vec<((i32, i32), usize)>,
+}
+
+/// Stable sort of the events of one pixel column by row
+///
+/// Rows usually span little more than the column has events, so a counting
+/// sort beats comparison sorting there.
+fn sort_column(column: &mut [((i32, i32), usize)], row_counts: &mut Vec<usize>, sorted: &mut Vec<((i32, i32), usize)>) {
+    if column.is_sorted_by_key(|p| p.0.1) {
+        return;
+    }
+    let (lo, hi) = column.iter().fold((i32::MAX, i32::MIN), |(lo, hi), p| (lo.min(p.0.1), hi.max(p.0.1)));
+    let span = (hi as i64 - lo as i64) as usize + 1;
+    if span > 4 * column.len() + 64 {
+        column.sort_by_key(|p| p.0.1);
+        return;
+    }
+    // Start of each row in the sorted column
+    row_counts.clear();
+    row_counts.resize(span + 1, 0);
+    for p in column.iter() {
+        row_counts[(p.0.1 - lo) as usize + 1] += 1;
+    }
+    for row in 1..=span {
+        row_counts[row] += row_counts[row - 1];
+    }
+    sorted.clear();
+    sorted.resize(column.len(), column[0]);
+    for &p in column.iter() {
+        let slot = &mut row_counts[(p.0.1 - lo) as usize];
+        sorted[*slot] = p;
+        *slot += 1;
+    }
+    column.copy_from_slice(sorted);
+}
+
+impl<'a> VisibleCollector<'a> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(events: &'a [MouseMoveEvent], series: Series, render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64), tolerance: f64, zoom_factor: f64) -> Self {
+        // Calculate pixel scales
+        let x_range_size = x_range.1 - x_range.0;
+        let y_range_size = y_range.1 - y_range.0;
+        Self {
+            events,
+            series,
+            x_min: x_range.0,
+            y_min: y_range.0,
+            x_scale: render_width / (x_range_size).max(1e-10),
+            y_scale: render_height / (y_range_size).max(1e-10),
+            min_x_visible: x_range.0 - (x_range_size * ((zoom_factor - 1.0) / 2.0)),
+            max_x_visible: x_range.1 + (x_range_size * ((zoom_factor - 1.0) / 2.0)),
+            tolerance,
+            seen_pixels: HashSet::new(),
+            visible_indices: Vec::new(),
+            row_counts: Vec::new(),
+            sorted: Vec::new(),
+        }
+    }
+
+    /// Convert an event to pixel coordinates of the series
+    fn to_pixel(&self, idx: usize) -> (i32, i32) {
+        let px = ((self.events[idx].time_secs() - self.x_min) * self.x_scale) as i32;
+        let py = ((self.series.value(self.events, idx) - self.y_min) * self.y_scale) as i32;
+        (px, py)
+    }
+
+    /// Check if event is within visible time range
+    fn is_visible(&self, event: &MouseMoveEvent) -> bool {
+        let time = event.time_secs();
+        time >= self.min_x_visible && time <= self.max_x_visible
+    }
+
+    fn add_discrete(&mut self, idx: usize) {
+        // Only include discrete events if they're visible
+        if idx < self.events.len() && self.is_visible(&self.events[idx]) {
+            self.visible_indices.push(idx);
+        }
+    }
+
+    /// Add a good segment given the indices of its visible events, in ascending order
+    ///
+    /// When a zoomed-out view puts more than `MAX_COLUMN_EVENTS` of them in each
+    /// pixel column they span, only the first, last, min and max of each column
+    /// are kept, found in one pass without grouping them by pixel.
+    fn add_good(&mut self, start_idx: usize, end_idx: usize, visible: impl DoubleEndedIterator<Item = usize> + ExactSizeIterator + Clone) {
+        // Skip entire segment if no events are visible
+        let (Some(first), Some(last)) = (visible.clone().next(), visible.clone().next_back()) else { return };
+        let columns = (self.to_pixel(last).0 as i64 - self.to_pixel(first).0 as i64).unsigned_abs() as usize + 1;
+        if visible.len() > columns * MAX_COLUMN_EVENTS {
+            self.add_column_extremes(start_idx, end_idx, visible);
+            return;
+        }
+        // Group the events by pixel; stable sorts keep each pixel's events in index order
+        let mut by_pixel: Vec<((i32, i32), usize)> = visible.map(|idx| (self.to_pixel(idx), idx)).collect();
+        if by_pixel.is_sorted_by_key(|p| p.0.0) {
+            // Time order means pixel columns are already contiguous, only sort within them
+            for column in by_pixel.chunk_by_mut(|a, b| a.0.0 == b.0.0) {
+                sort_column(column, &mut self.row_counts, &mut self.sorted);
+            }
         } else {
-            // Couldn't fit well, mark as discrete
-            segments.push(Segment::Discrete { idx: pos });
-            pos += 1;
+            by_pixel.sort_by_key(|p| p.0);
+        }
+
+        // Always include first and last to preserve continuity (if visible)
+        if self.is_visible(&self.events[start_idx]) {
+            self.visible_indices.push(start_idx);
+        }
+        if end_idx - start_idx > 1 && self.is_visible(&self.events[end_idx - 1]) {
+            self.visible_indices.push(end_idx - 1);
+        }
+
+        // Add events based on tolerance
+        for group in by_pixel.chunk_by(|a, b| a.0 == b.0) {
+            let pixel = group[0].0;
+            if !self.seen_pixels.insert(pixel) {
+                continue;
+            }
+
+            let count = group.len() as f64;
+            // Include all events at this pixel, or sample them if there are too many
+            let sample_rate = if count <= self.tolerance { 1 } else { (count / self.tolerance).ceil() as usize };
+            self.visible_indices.extend(group.iter().step_by(sample_rate).map(|&(_, idx)| idx).filter(|&idx| idx != start_idx && idx != end_idx - 1));
+        }
+    }
+
+    /// Add the first, last, min and max visible event of each pixel column of a dense good segment
+    fn add_column_extremes(&mut self, start_idx: usize, end_idx: usize, visible: impl Iterator<Item = usize>) {
+        // Always include first and last to preserve continuity (if visible)
+        for idx in [start_idx, end_idx - 1] {
+            if self.is_visible(&self.events[idx]) {
+                self.visible_indices.push(idx);
+            }
+        }
+        // Column, first, last, min and max event, and the min and max value
+        let mut column: Option<(i32, [usize; 4], f64, f64)> = None;
+        for idx in visible {
+            let px = ((self.events[idx].time_secs() - self.x_min) * self.x_scale) as i32;
+            let value = self.series.value(self.events, idx);
+            match &mut column {
+                Some((c, [_, last, min, max], lo, hi)) if *c == px => {
+                    *last = idx;
+                    if value < *lo {
+                        (*min, *lo) = (idx, value);
+                    }
+                    if value > *hi {
+                        (*max, *hi) = (idx, value);
+                    }
+                }
+                _ => {
+                    if let Some((_, kept, _, _)) = column.replace((px, [idx; 4], value, value)) {
+                        self.visible_indices.extend(kept);
+                    }
+                }
+            }
+        }
+        if let Some((_, kept, _, _)) = column {
+            self.visible_indices.extend(kept);
         }
     }
 
-    segments
+    fn finish(mut self) -> Vec<usize> {
+        // Sort indices to maintain time order
+        self.visible_indices.sort_unstable();
+        self.visible_indices.dedup();
+        self.visible_indices
+    }
 }
 
 /// Collect visible event indices for rendering based on view parameters
 ///
+/// Scans every segment; `collect_visible_indices_indexed` gives the same result
+/// while only touching the segments and events inside the view.
+///
 /// # Parameters
 ///
 /// - `segments`: Pre-computed segments from build_segments
 /// - `events`: The mouse movement events
+/// - `series`: Plotted value; every series needs its own query
 /// - `render_width`: Width of rendering area in pixels
 /// - `render_height`: Height of rendering area in pixels
 /// - `x_range`: (x_min, x_max) time range to render
-/// - `y_range`: (y_min, y_max) value range to render
+/// - `y_range`: (y_min, y_max) range of the series to render
 /// - `tolerance`: Maximum events per pixel before hiding (e.g., 3.0)
-/// - `zoom_factor`: Zoom factor for caching (>1.0, e.g., 1.5)
+/// - `zoom_factor`: Margin factor; events within the view widened by it are included (>=1.0, e.g., 1.2)
 ///
 /// # Returns
 ///
 /// Vector of event indices to render
+#[allow(clippy::too_many_arguments)]
 pub fn collect_visible_indices(
     segments: &[Segment],
     events: &[MouseMoveEvent],
+    series: Series,
     render_width: f64,
     render_height: f64,
     x_range: (f64, f64),
     y_range: (f64, f64),
     tolerance: f64,
-    zoom_factor: f64, // Reserved for future caching optimization
+    zoom_factor: f64, // Events within the view widened by this factor are included
 ) -> Vec<usize> {
     if events.is_empty() || segments.is_empty() {
         return Vec::new();
     }
 
-    let mut visible_indices = Vec::new();
-    let mut seen_pixels = HashSet::new();
-
-    // Calculate pixel scales
-    let x_range_size = x_range.1 - x_range.0;
-    let y_range_size = y_range.1 - y_range.0;
-    let x_scale = render_width / (x_range_size).max(1e-10);
-    let y_scale = render_height / (y_range_size).max(1e-10);
-    let min_x_visible = x_range.0 - (x_range_size * ((zoom_factor - 1.0) / 2.0));
-    let max_x_visible = x_range.1 + (x_range_size * ((zoom_factor - 1.0) / 2.0));
-
-    // Helper: convert event to pixel coordinates
-    let to_pixel = |event: &MouseMoveEvent| -> (i32, i32) {
-        let px = ((event.time_secs() - x_range.0) * x_scale) as i32;
-        let py = ((-(event.dy as f64) - y_range.0) * y_scale) as i32;
-        (px, py)
-    };
-
-    // Helper: check if event is within visible time range
-    let is_visible = |event: &MouseMoveEvent| -> bool {
-        let time = event.time_secs();
-        time >= min_x_visible && time <= max_x_visible
-    };
-
-    // Process each segment
+    let mut collector = VisibleCollector::new(events, series, render_width, render_height, x_range, y_range, tolerance, zoom_factor);
     for segment in segments {
-        match segment {
-            Segment::Discrete { idx } => {
-                // Only include discrete events if they're visible
-                if *idx < events.len() && is_visible(&events[*idx]) {
-                    visible_indices.push(*idx);
-                }
-            }
+        match *segment {
+            Segment::Discrete { idx } => collector.add_discrete(idx),
             Segment::Good { start_idx, end_idx, .. } => {
-                // For good segments, apply intelligent filtering
-                if *start_idx >= events.len() || *end_idx > events.len() {
+                if start_idx >= events.len() || end_idx > events.len() {
                     continue;
                 }
+                let visible: Vec<usize> = (start_idx..end_idx).filter(|&i| collector.is_visible(&events[i])).collect();
+                collector.add_good(start_idx, end_idx, visible.into_iter());
+            }
+        }
+    }
+    collector.finish()
+}
 
-                let segment_events = &events[*start_idx..*end_idx];
+/// `collect_visible_indices` in time proportional to what is visible
+///
+/// `index` must have been built from the same `segments` and `events`. Only
+/// the segments overlapping the view are visited, and inside them the visible
+/// events are found by binary search (a linear scan for the rare segments
+/// whose timestamps go backwards).
+#[allow(clippy::too_many_arguments)]
+pub fn collect_visible_indices_indexed(index: &SegmentIndex, segments: &[Segment], events: &[MouseMoveEvent], series: Series, render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64), tolerance: f64, zoom_factor: f64) -> Vec<usize> {
+    if events.is_empty() || segments.is_empty() || index.len() != segments.len() {
+        return Vec::new();
+    }
 
-                // Check if any event in this segment is visible
-                let has_visible = segment_events.iter().any(|e| is_visible(e));
-                if !has_visible {
-                    // Skip entire segment if no events are visible
+    let mut collector = VisibleCollector::new(events, series, render_width, render_height, x_range, y_range, tolerance, zoom_factor);
+    let (t_min, t_max) = (collector.min_x_visible, collector.max_x_visible);
+    for i in index.overlapping(t_min, t_max) {
+        match segments[i] {
+            Segment::Discrete { idx } => collector.add_discrete(idx),
+            Segment::Good { start_idx, end_idx, .. } => {
+                if start_idx >= events.len() || end_idx > events.len() {
                     continue;
                 }
-
-                // Always include first and last to preserve continuity (if visible)
-                if is_visible(&events[*start_idx]) {
-                    visible_indices.push(*start_idx);
-                }
-                if end_idx - start_idx > 1 && is_visible(&events[*end_idx - 1]) {
-                    visible_indices.push(*end_idx - 1);
-                }
-
-                // For interior points, apply tolerance-based filtering
-                let mut pixel_counts: std::collections::HashMap<(i32, i32), Vec<usize>> = std::collections::HashMap::new();
-
-                for (local_idx, event) in segment_events.iter().enumerate() {
-                    // Only process visible events
-                    if !is_visible(event) {
-                        continue;
-                    }
-                    let global_idx = start_idx + local_idx;
-                    let pixel = to_pixel(event);
-                    pixel_counts.entry(pixel).or_insert_with(Vec::new).push(global_idx);
-                }
-
-                // Add events based on tolerance
-                for (pixel, indices) in pixel_counts.iter() {
-                    if seen_pixels.contains(pixel) {
-                        continue;
-                    }
-
-                    let count = indices.len() as f64;
-                    if count <= tolerance {
-                        // Include all events at this pixel
-                        for &idx in indices {
-                            // Don't duplicate first/last
-                            if idx != *start_idx && idx != *end_idx - 1 {
-                                visible_indices.push(idx);
-                            }
-                        }
-                    } else {
-                        // Too many events, sample them
-                        let sample_rate = (count / tolerance).ceil() as usize;
-                        for (i, &idx) in indices.iter().enumerate() {
-                            if i % sample_rate == 0 && idx != *start_idx && idx != *end_idx - 1 {
-                                visible_indices.push(idx);
-                            }
-                        }
-                    }
-
-                    seen_pixels.insert(*pixel);
+                let segment_events = &events[start_idx..end_idx];
+                if index.monotone[i] {
+                    let first = start_idx + segment_events.partition_point(|e| e.time_secs() < t_min);
+                    let last = start_idx + segment_events.partition_point(|e| e.time_secs() <= t_max);
+                    collector.add_good(start_idx, end_idx, first..last.max(first));
+                } else {
+                    let visible: Vec<usize> = (start_idx..end_idx).filter(|&i| collector.is_visible(&events[i])).collect();
+                    collector.add_good(start_idx, end_idx, visible.into_iter());
                 }
             }
         }
     }
+    collector.finish()
+}
 
-    // Sort indices to maintain time order
-    visible_indices.sort_unstable();
-    visible_indices.dedup();
-
-    visible_indices
+/// `collect_visible_indices_indexed` through a `LodCache`
+///
+/// Reuses `cache` when it covers the view at a similar pixel density and
+/// otherwise replaces it with one for the view widened by `zoom_factor`.
+/// Returns the indices to render, which may extend beyond the view.
+#[allow(clippy::too_many_arguments)]
+pub fn collect_visible_indices_cached<'a>(cache: &'a mut Option<LodCache>, index: &SegmentIndex, segments: &[Segment], events: &[MouseMoveEvent], series: Series, render_width: f64, render_height: f64, x_range: (f64, f64), y_range: (f64, f64), tolerance: f64, zoom_factor: f64) -> &'a [usize] {
+    if !cache.as_ref().is_some_and(|c| c.can_reuse(series, render_width, render_height, x_range, y_range)) {
+        *cache = Some(LodCache::build(index, segments, events, series, render_width, render_height, x_range, y_range, tolerance, zoom_factor));
+    }
+    cache.as_ref().map(|c| c.indices.as_slice()).unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -493,41 +1246,6 @@ mod tests {
         events
     }
 
-    #[test]
-    fn test_poly3_eval() {
-        let poly = Poly3 { a0: 1.0, a1: 2.0, a2: 3.0, a3: 4.0 };
-        assert_eq!(poly.eval(0.0), 1.0);
-        assert_eq!(poly.eval(1.0), 10.0);
-    }
-
-    #[test]
-    fn test_normalize_to_unit() {
-        let values = vec![0.0, 5.0, 10.0];
-        let (normalized, min_val, range) = normalize_to_unit(&values);
-        assert_eq!(min_val, 0.0);
-        assert_eq!(range, 10.0);
-        assert_eq!(normalized, vec![0.0, 0.5, 1.0]);
-    }
-
-    #[test]
-    fn test_fit_cubic() {
-        let x = vec![0.0, 0.25, 0.5, 0.75, 1.0];
-        let y: Vec<f64> = x.iter().map(|&t| t * t * t).collect();
-        let poly = fit_cubic(&x, &y).unwrap();
-
-        // Should approximate cubic function
-        assert!((poly.a0).abs() < 0.1);
-        assert!((poly.a3 - 1.0).abs() < 0.1);
-    }
-
-    #[test]
-    fn test_calculate_r_squared() {
-        let y_actual = vec![1.0, 2.0, 3.0, 4.0, 5.0];
-        let y_pred = vec![1.1, 1.9, 3.0, 4.1, 4.9];
-        let r2 = calculate_r_squared(&y_actual, &y_pred);
-        assert!(r2 > 0.95); // Should be very close to 1
-    }
-
     #[test]
     fn test_is_discrete_event() {
         let zero_event = MouseMoveEvent::new(0, 0, 0, 0);
@@ -540,7 +1258,7 @@ mod tests {
     #[test]
     fn test_analyze_segment() {
         let events = make_test_events(10);
-        let fit = analyze_segment(&events, 0, 10);
+        let fit = analyze_segment(&events, 0, 10, Degree::Cubic);
 
         assert!(fit.is_some());
         let fit = fit.unwrap();
@@ -552,10 +1270,101 @@ mod tests {
         assert!(fit.time_r_squared > 0.9);
     }
 
+    /// `PrefixFitter` and the SVD fit agree on coefficients and R²
+    fn assert_fits_match(fast: &SegmentFit, svd: &SegmentFit, time_tol: f64) {
+        for x in [0.0, 0.3, 0.7, 1.0] {
+            for (a, b) in [(&fast.dx_poly, &svd.dx_poly), (&fast.dy_poly, &svd.dy_poly)] {
+                assert!((a.eval(x) - b.eval(x)).abs() < 1e-7 * (1.0 + b.eval(x).abs()), "{:?} vs {:?}", a, b);
+            }
+            assert!((fast.time_poly.eval(x) - svd.time_poly.eval(x)).abs() < time_tol, "{:?} vs {:?}", fast.time_poly, svd.time_poly);
+        }
+        assert!((fast.dx_r_squared - svd.dx_r_squared).abs() < 1e-9 && (fast.dy_r_squared - svd.dy_r_squared).abs() < 1e-9, "{:?} vs {:?}", fast, svd);
+    }
+
+    #[test]
+    fn test_prefix_fitter_matches_svd() {
+        let events = make_realistic_events(8_000);
+        let zeros = make_test_events_with_zeros(300);
+        let windows = |len: usize, degree: Degree| [0, 3, 97, 497, 1_990].into_iter().flat_map(move |start| [4, 5, 6, 10, 16, 26, 41, 66, 106, 170, 272, 435, 696, 1_114, 3_000].into_iter().map(move |n| (start, start + n))).filter(move |&(start, end)| end <= len && end - start >= degree.terms());
+        for degree in Degree::ALL {
+            for events in [&events, &zeros] {
+                let fitter = PrefixFitter::new(events, degree);
+                assert_eq!(fitter.len(), events.len());
+                for (start, end) in windows(events.len(), degree) {
+                    let (fast, svd) = (fitter.fit(events, start, end).unwrap(), analyze_segment(events, start, end, degree).unwrap());
+                    assert_eq!((fast.start_idx, fast.end_idx, fast.dx_poly.degree()), (start, end, degree));
+                    assert_fits_match(&fast, &svd, 1e-9);
+                    assert!((fast.time_r_squared - svd.time_r_squared).abs() < 1e-9, "{:?} vs {:?}", fast, svd);
+                }
+            }
+        }
+
+        // SVD on epoch seconds loses precision, the integer sums do not
+        let epoch: Vec<_> = events.iter().map(|e| MouseMoveEvent::new(e.dx, e.dy, e.ts_sec + 1_700_000_000, e.ts_usec)).collect();
+        let (fitter, epoch_fitter) = (PrefixFitter::new(&events, Degree::Cubic), PrefixFitter::new(&epoch, Degree::Cubic));
+        for (start, end) in windows(events.len(), Degree::Cubic) {
+            let (fit, epoch_fit) = (fitter.fit(&events, start, end).unwrap(), epoch_fitter.fit(&epoch, start, end).unwrap());
+            assert_fits_match(&epoch_fit, &analyze_segment(&epoch, start, end, Degree::Cubic).unwrap(), 1e-4);
+            assert!((epoch_fit.time_poly.eval(0.5) - 1_700_000_000.0 - fit.time_poly.eval(0.5)).abs() < 1e-6);
+            assert_eq!(epoch_fit.time_r_squared, fit.time_r_squared);
+        }
+    }
+
+    #[test]
+    fn test_prefix_fitter_exact_fit_and_fallback() {
+        // dx = i² - 3i = 361x² - 57x on 20 events, constant dy
+        let events: Vec<_> = (0..20u32).map(|i| MouseMoveEvent::new((i * i) as i16 - 3 * i as i16, 2, 0, i * 125)).collect();
+        for degree in [Degree::Quadratic, Degree::Cubic, Degree::Quintic] {
+            let fitter = PrefixFitter::new(&events, degree);
+            let fit = fitter.fit(&events, 0, 20).unwrap();
+            let expected = [0.0, -57.0, 361.0, 0.0, 0.0, 0.0];
+            assert!(fit.dx_poly.coeffs().iter().zip(expected).all(|(c, e)| (c - e).abs() < 1e-6), "{:?}", fit.dx_poly);
+            assert!((fit.dx_r_squared - 1.0).abs() < 1e-12 && fit.dy_r_squared == 1.0 && (fit.time_r_squared - 1.0).abs() < 1e-12);
+            assert!((fit.dy_poly.eval(0.5) - 2.0).abs() < 1e-12);
+            assert!(fitter.fit(&events, 5, 5 + degree.value()).is_none());
+        }
+        // A line misses the curve
+        let linear = PrefixFitter::new(&events, Degree::Linear).fit(&events, 0, 20).unwrap();
+        assert!(linear.dx_r_squared < 0.99 && linear.dx_poly.coeffs().len() == 2);
+
+        // Windows past the sums fall back to SVD
+        let short = PrefixFitter::new(&events[..10], Degree::Cubic);
+        assert_fits_match(&short.fit(&events, 4, 20).unwrap(), &analyze_segment(&events, 4, 20, Degree::Cubic).unwrap(), 1e-9);
+
+        // Extending matches building over all events
+        let mut grown = PrefixFitter::new(&[], Degree::Cubic);
+        grown.extend(&events[..7]);
+        grown.extend(&events);
+        assert_eq!(grown.sums, PrefixFitter::new(&events, Degree::Cubic).sums);
+
+        // Dropping a prefix keeps the fits after it and falls back to SVD before it
+        let full = PrefixFitter::new(&events, Degree::Cubic);
+        grown.drop_before(8);
+        assert_eq!((grown.start(), grown.len()), (8, events.len()));
+        assert_eq!(grown.sums.len(), (events.len() - 8 + 1) * grown.stride());
+        let (fit, expected) = (grown.fit(&events, 9, 20).unwrap(), full.fit(&events, 9, 20).unwrap());
+        assert_eq!((fit.dx_poly, fit.time_poly), (expected.dx_poly, expected.time_poly));
+        assert_fits_match(&grown.fit(&events, 2, 15).unwrap(), &analyze_segment(&events, 2, 15, Degree::Cubic).unwrap(), 1e-9);
+        let mut restarted = PrefixFitter::starting_at(8, Degree::Cubic);
+        restarted.extend(&events[..5]);
+        assert!(restarted.sums.is_empty());
+        restarted.extend(&events);
+        assert_fits_match(&restarted.fit(&events, 9, 20).unwrap(), &expected, 1e-9);
+    }
+
+    #[test]
+    fn test_degree_changes_segmentation() {
+        let events = make_realistic_events(3_000);
+        let count = |degree: Degree| build_segments(&events, 10, 1.6, 0.8, 0.091, degree).len();
+        // Higher degrees follow the sine longer, so fewer segments cover the events
+        assert!(count(Degree::Linear) > count(Degree::Quintic), "{} vs {}", count(Degree::Linear), count(Degree::Quintic));
+        assert!(build_segments(&events[..5], 4, 1.6, 0.8, 0.091, Degree::Quintic).iter().all(|s| matches!(s, Segment::Discrete { .. })));
+    }
+
     #[test]
     fn test_build_segments_simple() {
         let events = make_test_events(20);
-        let segments = build_segments(&events, 5, 2.0, 0.8, 0.5);
+        let segments = build_segments(&events, 5, 2.0, 0.8, 0.5, Degree::Cubic);
 
         assert!(!segments.is_empty());
         // Should have at least one good segment
@@ -563,14 +1372,146 @@ mod tests {
         assert!(has_good);
     }
 
+    #[test]
+    fn test_build_segments_covers_all_events_with_zeros() {
+        let events = make_test_events_with_zeros(50);
+        let segments = build_segments(&events, 5, 2.0, 0.8, 0.5, Degree::Cubic);
+
+        // Segments must tile the event range without gaps or overlaps
+        let mut next = 0;
+        for segment in &segments {
+            match segment {
+                Segment::Good { start_idx, end_idx, .. } => {
+                    assert_eq!(*start_idx, next);
+                    next = *end_idx;
+                }
+                Segment::Discrete { idx } => {
+                    assert_eq!(*idx, next);
+                    next = idx + 1;
+                }
+            }
+        }
+        assert_eq!(next, events.len());
+    }
+
+    #[test]
+    fn test_build_segments_progress_and_cancel() {
+        let events: Vec<MouseMoveEvent> = (0..200).map(|i| MouseMoveEvent::new(1 + (i % 3) as i16, 2, 0, i * 1000)).collect();
+        let mut seen = Vec::new();
+        let segments = build_segments_with_progress(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic, |done| {
+            seen.push(done);
+            true
+        })
+        .unwrap();
+        assert_eq!(segments.len(), build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic).len());
+        assert!(seen.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(seen.last(), Some(&events.len()));
+
+        assert!(build_segments_with_progress(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic, |done| done < 50).is_none());
+    }
+
+    fn bounds(segments: &[Segment]) -> Vec<(usize, usize, bool)> {
+        segments.iter().map(|s| (segment_bounds(s).0, segment_bounds(s).1, matches!(s, Segment::Good { .. }))).collect()
+    }
+
+    #[test]
+    fn test_segment_builder_matches_full_build() {
+        let events = make_realistic_events(3_000);
+        let params = SegmentParams { min_r_squared: 0.8, ..SegmentParams::default() };
+        let full = |n: usize| bounds(&build_segments(&events[..n], params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree));
+
+        let mut builder = SegmentBuilder::new(params);
+        let mut finalized = 0;
+        for n in [0, 5, 700, 701, 1_600, 3_000] {
+            builder.extend(&events[..n]);
+            assert_eq!(bounds(&builder.segments(&events[..n])), full(n), "after {} events", n);
+            // Finalized segments only ever grow, and are a prefix of the full build
+            assert!(builder.finalized_len() >= finalized && builder.finalized_len() <= n);
+            finalized = builder.finalized_len();
+            assert_eq!(bounds(builder.finalized()), full(n)[..builder.finalized().len()]);
+        }
+        assert!(finalized > 2_000, "only {} events finalized", finalized);
+
+        // Cancelling keeps what was finalized, and fewer events start over
+        let mut builder = SegmentBuilder::new(params);
+        assert!(!builder.extend_with_progress(&events, |done| done < 1_000));
+        assert!(builder.finalized_len() >= 1_000);
+        builder.extend(&events[..10]);
+        assert_eq!(bounds(&builder.segments(&events[..10])), full(10));
+    }
+
+    #[test]
+    fn test_segment_builder_keeps_only_unfinalized_sums() {
+        let events = make_realistic_events(3_000);
+        let params = SegmentParams { min_r_squared: 0.8, ..SegmentParams::default() };
+        let full = |n: usize| bounds(&build_segments(&events[..n], params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree));
+        let tail_rows = |builder: &SegmentBuilder, n: usize| builder.fitter.sums.len() / builder.fitter.stride() <= n - builder.finalized_len() + 1;
+
+        let mut builder = SegmentBuilder::new(params);
+        for n in (100..=3_000).step_by(100) {
+            builder.extend(&events[..n]);
+            assert_eq!(builder.fitter.start(), builder.finalized_len());
+            assert!(tail_rows(&builder, n), "{} sums rows after {} events, {} finalized", builder.fitter.sums.len() / builder.fitter.stride(), n, builder.finalized_len());
+        }
+        assert_eq!(bounds(&builder.segments(&events)), full(3_000));
+
+        // A released fitter is summed again from the finalized events on
+        let mut builder = SegmentBuilder::new(params);
+        builder.extend(&events[..1_500]);
+        builder.release_fitter();
+        assert!(builder.fitter.sums.is_empty());
+        assert_eq!(bounds(&builder.segments(&events[..1_500])), full(1_500));
+        builder.extend(&events);
+        assert!(tail_rows(&builder, events.len()));
+        assert_eq!(bounds(&builder.segments(&events)), full(3_000));
+
+        // Resuming from segments only sums the events after them
+        let mut resumed = SegmentBuilder::from_segments(params, &builder.segments(&events), builder.finalized_len());
+        resumed.extend(&events);
+        assert!(tail_rows(&resumed, events.len()));
+        assert_eq!(bounds(&resumed.segments(&events)), full(3_000));
+    }
+
+    #[test]
+    fn test_parallel_build_matches_sequential() {
+        // Idle gaps every 3000 events give the chunks natural boundaries
+        let events: Vec<_> = make_realistic_events(12_000).into_iter().enumerate().map(|(i, e)| MouseMoveEvent::new(e.dx, e.dy, e.ts_sec + (i / 3_000) as u32, e.ts_usec)).collect();
+        let params = SegmentParams { min_r_squared: 0.8, ..SegmentParams::default() };
+        let sequential = bounds(&build_segments(&events, params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree));
+        for chunks in [1, 2, 5, 33, 20_000] {
+            assert_eq!(bounds(&build_segments_parallel(&events, params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree, chunks)), sequential, "{} chunks", chunks);
+        }
+        let boundaries = natural_boundaries(&events, 0, 4);
+        assert_eq!(boundaries, [0, 3_000, 6_000, 9_000, 12_000]);
+        assert!(build_segments_parallel(&[], 10, 1.6, 0.8, 0.091, Degree::Cubic, 4).is_empty());
+    }
+
+    #[test]
+    fn test_segment_builder_extends_in_parallel() {
+        let events = make_realistic_events(2 * MIN_PARALLEL_CHUNK as u32 + 500);
+        let params = SegmentParams { min_r_squared: 0.8, ..SegmentParams::default() };
+        let full = bounds(&build_segments(&events, params.initial_size, params.growth_factor, params.min_r_squared, params.balance_weight, params.degree));
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        pool.install(|| {
+            assert!(parallel_chunks(events.len()) > 1);
+            // A cancelled parallel extend keeps a prefix that later extends continue
+            let mut builder = SegmentBuilder::new(params);
+            builder.extend_with_progress(&events, |_| false);
+            assert_eq!(bounds(builder.finalized()), full[..builder.finalized().len()]);
+            builder.extend(&events);
+            assert_eq!(bounds(&builder.segments(&events)), full);
+            assert!(builder.finalized_len() > events.len() - 1_000);
+        });
+    }
+
     #[test]
     fn test_collect_visible_indices() {
         let events = make_test_events(100);
-        let segments = build_segments(&events, 10, 1.5, 0.85, 0.5);
+        let segments = build_segments(&events, 10, 1.5, 0.85, 0.5, Degree::Cubic);
 
         let x_range = (0.0, 100.0);
         let y_range = (-500.0, 1000.0);
-        let indices = collect_visible_indices(&segments, &events, 800.0, 600.0, x_range, y_range, 5.0, 1.5);
+        let indices = collect_visible_indices(&segments, &events, Series::NegDy, 800.0, 600.0, x_range, y_range, 5.0, 1.5);
 
         assert!(!indices.is_empty());
         assert!(indices.len() <= events.len());
@@ -580,19 +1521,155 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_series_get_their_own_pixels() {
+        // dx steps with one spike while dy stays flat, all in one good segment
+        let mut events: Vec<MouseMoveEvent> = (0..4_000u32).map(|i| MouseMoveEvent::new(((i / 40) % 9) as i16, 1, 0, i * 125)).collect();
+        events[2_345].dx = 60;
+        let segments = vec![Segment::Good { start_idx: 0, end_idx: events.len(), fit: Box::new(analyze_segment(&events, 0, events.len(), Degree::Cubic).unwrap()) }];
+        let query = |series| collect_visible_indices(&segments, &events, series, 100.0, 100.0, (0.0, 0.5), (-70.0, 70.0), 3.0, 1.0);
+
+        let (dx, ndy) = (query(Series::Dx), query(Series::NegDy));
+        assert!(dx.contains(&2_345), "the dx spike must survive decimation");
+        assert!(!ndy.contains(&2_345), "flat -dy needs no more than a few events per column");
+        assert!(ndy.len() < dx.len());
+        // 40 events per column: only the first, last, min and max of each of the 100 columns are kept
+        assert!(dx.len() <= 4 * 101, "{} dx points", dx.len());
+
+        assert_eq!(Series::Magnitude.value(&events, 2_345), (60.0f64.powi(2) + 1.0).sqrt());
+        assert_eq!((Series::Interval.value(&events, 0), Series::Interval.value(&events, 1)), (0.0, 125.0));
+    }
+
+    #[test]
+    fn test_sort_column_is_stable() {
+        let (mut counts, mut sorted) = (Vec::new(), Vec::new());
+        // Narrow rows take the counting sort, a wide spread the comparison sort
+        for spread in [7, 100_000] {
+            let mut column: Vec<((i32, i32), usize)> = (0..200).map(|i| ((3, (i * 37 % 11) * spread - 20), i as usize)).collect();
+            let mut expected = column.clone();
+            expected.sort_by_key(|p| p.0.1);
+            sort_column(&mut column, &mut counts, &mut sorted);
+            assert_eq!(column, expected);
+        }
+    }
+
+    /// 8 kHz events with smooth movement, idle zeros and a few timestamps going backwards
+    fn make_realistic_events(n: u32) -> Vec<MouseMoveEvent> {
+        (0..n)
+            .map(|i| {
+                let us = i * 125 - if i % 997 == 500 { 300 } else { 0 };
+                let dx = if i % 50 < 5 { 0 } else { (30.0 * (i as f64 / 400.0).sin()) as i16 };
+                MouseMoveEvent::new(dx, (i % 9) as i16 - 4, us / 1_000_000, us % 1_000_000)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_segment_index_overlapping() {
+        let events = make_test_events(100);
+        let segments = build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic);
+        let index = SegmentIndex::new(&segments, &events);
+        assert_eq!(index.len(), segments.len());
+        assert_eq!(index.overlapping(0.0, 99.0), 0..segments.len());
+        assert!(index.overlapping(200.0, 300.0).is_empty());
+        assert!(index.overlapping(-10.0, -1.0).is_empty());
+
+        // Every segment with an event in the range is included
+        let range = index.overlapping(40.5, 41.5);
+        for (i, segment) in segments.iter().enumerate() {
+            let (start, end) = segment_bounds(segment);
+            if (start..end).any(|j| (40.5..=41.5).contains(&events[j].time_secs())) {
+                assert!(range.contains(&i), "segment {} missing from {:?}", i, range);
+            }
+        }
+        assert!(range.len() < segments.len());
+    }
+
+    #[test]
+    fn test_indexed_query_matches_full_scan() {
+        let events = make_realistic_events(20_000);
+        // Fixed-size segments with every 7th event discrete, so some good segments contain a reversal
+        let mut segments = Vec::new();
+        let mut pos = 0;
+        while pos < events.len() {
+            if pos % 7 == 0 {
+                segments.push(Segment::Discrete { idx: pos });
+                pos += 1;
+                continue;
+            }
+            let end = (pos + 97).min(events.len());
+            match analyze_segment(&events, pos, end, Degree::Cubic) {
+                Some(fit) => segments.push(Segment::Good { start_idx: pos, end_idx: end, fit: Box::new(fit) }),
+                None => segments.extend((pos..end).map(|idx| Segment::Discrete { idx })),
+            }
+            pos = end;
+        }
+        let index = SegmentIndex::new(&segments, &events);
+        assert!(index.monotone.iter().any(|m| !m), "test data should have a segment with a time reversal");
+        let end = events.last().unwrap().time_secs();
+        for series in [Series::Dx, Series::NegDy] {
+            for (x0, x1) in [(0.0, end), (1.0, 3.0), (2.4, 2.41), (2.0625, 2.0626), (end + 1.0, end + 2.0)] {
+                let full = collect_visible_indices(&segments, &events, series, 1200.0, 600.0, (x0, x1), (-40.0, 40.0), 3.0, 1.2);
+                let indexed = collect_visible_indices_indexed(&index, &segments, &events, series, 1200.0, 600.0, (x0, x1), (-40.0, 40.0), 3.0, 1.2);
+                assert_eq!(indexed, full, "{:?} view {}..{}", series, x0, x1);
+            }
+        }
+        assert!(collect_visible_indices_indexed(&SegmentIndex::default(), &segments, &events, Series::NegDy, 1200.0, 600.0, (0.0, end), (-40.0, 40.0), 3.0, 1.2).is_empty());
+    }
+
     #[test]
     fn test_lod_cache_can_reuse() {
         let cache = LodCache {
-            segments: Vec::new(),
-            zoom_factor: 1.0,
+            indices: Vec::new(),
+            series: Series::Dx,
+            zoom_factor: 2.0,
             last_x_range: (0.0, 100.0),
             last_y_range: (0.0, 100.0),
+            density: (1000.0 / 50.0, 500.0 / 50.0),
         };
 
-        // Should reuse when zoomed in
-        assert!(cache.can_reuse((10.0, 50.0), (10.0, 50.0), 1.5));
+        // Panning inside the cached region at the same density
+        assert!(cache.can_reuse(Series::Dx, 1000.0, 500.0, (25.0, 75.0), (25.0, 75.0)));
+        assert!(cache.can_reuse(Series::Dx, 1000.0, 500.0, (50.0, 100.0), (0.0, 50.0)));
+        // Zooming a little either way
+        assert!(cache.can_reuse(Series::Dx, 1000.0, 500.0, (26.0, 73.0), (25.0, 75.0)));
+        assert!(cache.can_reuse(Series::Dx, 1000.0, 500.0, (24.0, 77.0), (25.0, 75.0)));
+
+        // Panning out of the region
+        assert!(!cache.can_reuse(Series::Dx, 1000.0, 500.0, (60.0, 110.0), (25.0, 75.0)));
+        assert!(!cache.can_reuse(Series::Dx, 1000.0, 500.0, (25.0, 75.0), (-10.0, 40.0)));
+        // Zooming in needs more detail, zooming out leaves the region
+        assert!(!cache.can_reuse(Series::Dx, 1000.0, 500.0, (40.0, 60.0), (25.0, 75.0)));
+        assert!(!cache.can_reuse(Series::Dx, 1000.0, 500.0, (0.0, 200.0), (0.0, 200.0)));
+        // A resized plot changes the density too
+        assert!(!cache.can_reuse(Series::Dx, 1600.0, 500.0, (25.0, 75.0), (25.0, 75.0)));
+        // Other series have other pixels
+        assert!(!cache.can_reuse(Series::NegDy, 1000.0, 500.0, (25.0, 75.0), (25.0, 75.0)));
+    }
 
-        // Should not reuse when zoomed out
-        assert!(!cache.can_reuse((0.0, 200.0), (0.0, 200.0), 1.0));
+    #[test]
+    fn test_cached_query_reuses_and_refreshes() {
+        let events = make_realistic_events(4_000);
+        let segments = build_segments(&events, 10, 1.6, 0.8, 0.091, Degree::Cubic);
+        let index = SegmentIndex::new(&segments, &events);
+        let mut cache = None;
+
+        let first = collect_visible_indices_cached(&mut cache, &index, &segments, &events, Series::NegDy, 1000.0, 500.0, (0.1, 0.2), (-40.0, 40.0), 3.0, 2.0).to_vec();
+        assert!(!first.is_empty());
+        let built = cache.as_ref().unwrap();
+        assert!((built.last_x_range.0 - 0.05).abs() < 1e-9 && (built.last_x_range.1 - 0.25).abs() < 1e-9);
+        assert_eq!(first, LodCache::build(&index, &segments, &events, Series::NegDy, 1000.0, 500.0, (0.1, 0.2), (-40.0, 40.0), 3.0, 2.0).indices);
+        // The cached set covers the widened region, beyond the view
+        assert!(first.iter().any(|&i| events[i].time_secs() < 0.1) && first.iter().any(|&i| events[i].time_secs() > 0.2));
+
+        // A small pan reuses the cached set
+        let panned = collect_visible_indices_cached(&mut cache, &index, &segments, &events, Series::NegDy, 1000.0, 500.0, (0.13, 0.23), (-40.0, 40.0), 3.0, 2.0).to_vec();
+        assert_eq!(panned, first);
+        assert!((cache.as_ref().unwrap().last_x_range.0 - 0.05).abs() < 1e-9);
+
+        // Zooming in recomputes at the new density
+        let zoomed = collect_visible_indices_cached(&mut cache, &index, &segments, &events, Series::NegDy, 1000.0, 500.0, (0.14, 0.16), (-40.0, 40.0), 3.0, 2.0).to_vec();
+        assert!((cache.as_ref().unwrap().last_x_range.0 - 0.13).abs() < 1e-9);
+        assert!(zoomed.iter().all(|&i| (0.13..=0.17).contains(&events[i].time_secs())));
     }
 }
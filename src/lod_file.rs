@@ -0,0 +1,231 @@
+//! LOD cache files: the segments and segment tree of a capture, kept next to it
+//!
+//! Building the LOD segments of a large capture takes seconds, so the GUI saves
+//! them to `<capture file>.mtlod` and loads them instead of segmenting again
+//! when the capture is opened the next time. A cache file is keyed by the
+//! number and CRC-32 of the events and the parameters the segments and the tree
+//! were built with; if any of them differ, the file is stale and is rebuilt.
+//!
+//! # File layout (little endian)
+//!
+//! ```text
+//! magic "MTLD" | version u16 | reserved u16 | payload length u64 | CRC-32 of payload u32 | payload
+//! ```
+//!
+//! The payload is `LodFile` encoded with bincode, starting with its key.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use MouseTesterRust::lod_file::{LodFile, LodFileKey, cache_path};
+//!
+//! let key = LodFileKey::new(&events, &params, 1000, 500.0);
+//! let path = cache_path("run.mtsession");
+//! let file = match LodFile::load(&path, &key)? {
+//!     Some(file) => file,
+//!     None => {
+//!         let file = LodFile { key, tree, segments, finalized_len };
+//!         file.save(&path)?;
+//!         file
+//!     }
+//! };
+//! ```
+
+use crate::lod::SegmentNode;
+use crate::lod_advanced::{Segment, SegmentParams};
+use crate::mouse_event::MouseMoveEvent;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+/// Magic bytes at the start of every LOD cache file
+pub const LOD_FILE_MAGIC: [u8; 4] = *b"MTLD";
+/// Current LOD cache format version
+pub const LOD_FILE_VERSION: u16 = 1;
+/// File extension appended to the capture file name
+pub const LOD_FILE_EXTENSION: &str = "mtlod";
+
+const HEADER_LEN: usize = 20;
+
+/// What the LOD data of a cache file was built from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LodFileKey {
+    pub event_count: u64,
+    /// CRC-32 of the dx, dy and timestamps of all events
+    pub events_crc: u32,
+    pub params: SegmentParams,
+    /// Plot size the segment tree was built for
+    pub tree_width: usize,
+    pub tree_height: f64,
+}
+
+impl LodFileKey {
+    pub fn new(events: &[MouseMoveEvent], params: &SegmentParams, tree_width: usize, tree_height: f64) -> Self {
+        let mut hasher = crc32fast::Hasher::new();
+        for e in events {
+            hasher.update(&e.dx.to_le_bytes());
+            hasher.update(&e.dy.to_le_bytes());
+            hasher.update(&e.ts_sec.to_le_bytes());
+            hasher.update(&e.ts_usec.to_le_bytes());
+        }
+        Self { event_count: events.len() as u64, events_crc: hasher.finalize(), params: *params, tree_width, tree_height }
+    }
+}
+
+/// LOD data of a capture as stored in a cache file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LodFile {
+    pub key: LodFileKey,
+    /// `lod` segment tree over all events
+    pub tree: SegmentNode,
+    /// `lod_advanced` segments of all events
+    pub segments: Vec<Segment>,
+    /// `SegmentBuilder::finalized_len` of the builder the segments came from
+    pub finalized_len: usize,
+}
+
+impl LodFile {
+    /// Write the cache file, replacing an existing one only once it is complete
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let payload = bincode::serialize(self).context("Failed to encode LOD cache")?;
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let write = || -> Result<()> {
+            let mut writer = BufWriter::new(File::create(&temp)?);
+            writer.write_all(&LOD_FILE_MAGIC)?;
+            writer.write_all(&LOD_FILE_VERSION.to_le_bytes())?;
+            writer.write_all(&[0; 2])?;
+            writer.write_all(&(payload.len() as u64).to_le_bytes())?;
+            writer.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+            writer.write_all(&payload)?;
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+            fs::rename(&temp, path)?;
+            Ok(())
+        };
+        write().inspect_err(|_| _ = fs::remove_file(&temp)).with_context(|| format!("Failed to write LOD cache {}", path.display()))
+    }
+
+    /// Read the cache file if it exists and was built for `key`
+    ///
+    /// Returns `None` if there is no file or it is stale: built from other
+    /// events or parameters, or by another format version. Damaged files are
+    /// errors.
+    pub fn load(path: impl AsRef<Path>, key: &LodFileKey) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read LOD cache {}", path.display())),
+        };
+        if data.len() < HEADER_LEN || data[..4] != LOD_FILE_MAGIC {
+            bail!("{} is not a LOD cache file", path.display());
+        }
+        if u16::from_le_bytes([data[4], data[5]]) != LOD_FILE_VERSION {
+            return Ok(None);
+        }
+        let len = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        let crc = u32::from_le_bytes(data[16..20].try_into().unwrap());
+        let payload = &data[HEADER_LEN..];
+        if payload.len() as u64 != len || crc32fast::hash(payload) != crc {
+            bail!("LOD cache {} is damaged", path.display());
+        }
+        // The key is encoded first, so a stale file is recognized without decoding the rest
+        let stored: LodFileKey = bincode::deserialize(payload).with_context(|| format!("Failed to decode LOD cache {}", path.display()))?;
+        if stored != *key {
+            return Ok(None);
+        }
+        let file: LodFile = bincode::deserialize(payload).with_context(|| format!("Failed to decode LOD cache {}", path.display()))?;
+        if file.tree.end as u64 != key.event_count || file.finalized_len as u64 > key.event_count {
+            bail!("LOD cache {} does not cover its events", path.display());
+        }
+        Ok(Some(file))
+    }
+}
+
+/// Cache file of the capture at `source`: its file name with `.mtlod` appended
+pub fn cache_path(source: impl AsRef<Path>) -> PathBuf {
+    let mut name = OsString::from(source.as_ref().as_os_str());
+    name.push(".");
+    name.push(LOD_FILE_EXTENSION);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fit::Degree;
+    use crate::lod::build_segment_tree;
+    use crate::lod_advanced::SegmentBuilder;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mousetester_lod_{}_{}.{}", name, std::process::id(), LOD_FILE_EXTENSION))
+    }
+
+    fn events(n: u32) -> Vec<MouseMoveEvent> {
+        (0..n).map(|i| MouseMoveEvent::new(((i / 40) % 7) as i16 * 3 - 9 + (i % 3) as i16, -(((i / 25) % 5) as i16), i / 8000, (i % 8000) * 125)).collect()
+    }
+
+    fn lod_file(events: &[MouseMoveEvent], params: SegmentParams) -> LodFile {
+        let mut builder = SegmentBuilder::new(params);
+        builder.extend(events);
+        LodFile { key: LodFileKey::new(events, &params, 1000, 500.0), tree: build_segment_tree(events, 0, events.len(), 5, 1000, 1.0, 1.0, params.degree), segments: builder.segments(events), finalized_len: builder.finalized_len() }
+    }
+
+    #[test]
+    fn test_round_trip_and_resume() {
+        let (path, events, params) = (temp_path("round_trip"), events(5000), SegmentParams::default());
+        let file = lod_file(&events, params);
+        file.save(&path).unwrap();
+        let loaded = LodFile::load(&path, &file.key).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.key, loaded.finalized_len, loaded.segments.len()), (file.key, file.finalized_len, file.segments.len()));
+        assert_eq!(format!("{:?}", loaded.segments), format!("{:?}", file.segments));
+        assert_eq!(format!("{:?}", loaded.tree), format!("{:?}", file.tree));
+
+        // A builder restored from the file continues like the one it came from
+        let more = self::events(6000);
+        let (mut restored, mut original) = (SegmentBuilder::from_segments(params, &loaded.segments, loaded.finalized_len), SegmentBuilder::new(params));
+        assert_eq!(restored.finalized_len(), file.finalized_len);
+        restored.extend(&more);
+        original.extend(&more);
+        assert_eq!(format!("{:?}", restored.segments(&more)), format!("{:?}", original.segments(&more)));
+    }
+
+    #[test]
+    fn test_stale_and_damaged_files() {
+        let (path, events, params) = (temp_path("stale"), events(3000), SegmentParams::default());
+        assert!(LodFile::load(&path, &LodFileKey::new(&events, &params, 1000, 500.0)).unwrap().is_none(), "missing file");
+        let file = lod_file(&events, params);
+        file.save(&path).unwrap();
+
+        let mut changed = events.clone();
+        changed[1234].dx += 1;
+        let quintic = SegmentParams { degree: Degree::Quintic, ..params };
+        for key in [LodFileKey::new(&changed, &params, 1000, 500.0), LodFileKey::new(&events[..2999], &params, 1000, 500.0), LodFileKey::new(&events, &quintic, 1000, 500.0), LodFileKey::new(&events, &params, 800, 500.0)] {
+            assert!(LodFile::load(&path, &key).unwrap().is_none(), "{:?}", key);
+        }
+
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        fs::write(&path, &data).unwrap();
+        assert!(LodFile::load(&path, &file.key).is_err());
+        fs::write(&path, b"not a cache file at all").unwrap();
+        assert!(LodFile::load(&path, &file.key).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_cache_path() {
+        assert_eq!(cache_path("captures/run.mtsession"), PathBuf::from("captures/run.mtsession.mtlod"));
+        assert_eq!(cache_path("run.csv"), PathBuf::from("run.csv.mtlod"));
+    }
+}
@@ -0,0 +1,292 @@
+//! Interchangeable level-of-detail strategies for interactive plots
+//!
+//! `LodStrategy` turns the events, a plotted `Series` and the current plot view
+//! into the indices of the events to draw for that series. Three strategies
+//! are provided:
+//!
+//! - `M4Lod`: min/max envelope decimation. For every pixel column it keeps the
+//!   first and last event and the events with the smallest and largest value,
+//!   so single-report spikes are never dropped. Needs no preparation.
+//! - `SegmentTreeLod`: the `lod` segment tree with `collect_indices_for_view`.
+//!   Its nodes are split on the error of dx and dy together, so it returns the
+//!   same indices for every series.
+//! - `AdvancedLod`: the `lod_advanced` segments with the time index and view
+//!   cache.
+//!
+//! The polynomial strategies draw smoother lines with fewer points but may hide
+//! spikes inside a well-fitting segment; M4 always shows the extremes.
+//!
+//! `m4_reduce` applies the same idea by event index to a stream, for captures
+//! too large to hold before a strategy can be applied.
+
+use crate::fit::Degree;
+use crate::lod::{SegmentNode, build_segment_tree, collect_indices_for_view};
+use crate::lod_advanced::{LodCache, Segment, SegmentIndex, Series, collect_visible_indices_cached};
+use crate::mouse_event::MouseMoveEvent;
+
+/// Plot area and the data ranges it shows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlotView {
+    pub width: f64,
+    pub height: f64,
+    /// Time range in seconds
+    pub x_range: (f64, f64),
+    pub y_range: (f64, f64),
+}
+
+/// Reduces events to what a plot view can show
+pub trait LodStrategy {
+    /// Short name for the UI
+    fn name(&self) -> &'static str;
+
+    /// Indices of the events to draw for the line of `series` in `view`, ascending
+    ///
+    /// The result may include events just outside the view so lines reach its edges.
+    fn visible_indices(&mut self, events: &[MouseMoveEvent], series: Series, view: &PlotView) -> Vec<usize>;
+}
+
+/// Index range of the events with times in `x_range`, plus one on either side
+fn visible_range(events: &[MouseMoveEvent], x_range: (f64, f64)) -> (usize, usize) {
+    let start = events.partition_point(|e| e.time_secs() < x_range.0);
+    let end = events.partition_point(|e| e.time_secs() <= x_range.1).max(start);
+    (start.saturating_sub(1), (end + 1).min(events.len()))
+}
+
+/// M4 decimation: first, last, min and max of `series` in each of `columns` pixel columns of `x_range`
+///
+/// Returns ascending indices, at most four per column plus one event on either
+/// side of the range.
+pub fn m4_indices(events: &[MouseMoveEvent], series: Series, x_range: (f64, f64), columns: usize) -> Vec<usize> {
+    let (start, end) = visible_range(events, x_range);
+    let per_column = columns.max(1) as f64 / (x_range.1 - x_range.0).max(1e-12);
+    let column_of = |e: &MouseMoveEvent| ((e.time_secs() - x_range.0) * per_column).floor().clamp(-1.0, columns as f64) as i64;
+
+    let mut out = Vec::new();
+    let mut i = start;
+    while i < end {
+        let column = column_of(&events[i]);
+        let first = i;
+        let ((mut min, mut min_value), (mut max, mut max_value)) = ((i, series.value(events, i)), (i, series.value(events, i)));
+        i += 1;
+        while i < end && column_of(&events[i]) == column {
+            let value = series.value(events, i);
+            if value < min_value {
+                (min, min_value) = (i, value);
+            }
+            if value > max_value {
+                (max, max_value) = (i, value);
+            }
+            i += 1;
+        }
+        let mut keep = [first, i - 1, min, max];
+        keep.sort_unstable();
+        out.extend(keep);
+    }
+    // Timestamps that go backwards can revisit a column
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+/// M4 decimation of an event stream too large to hold, by event index
+///
+/// Splits the `count` events into runs of equal length and keeps, per run, the
+/// first and last event and those with the smallest and largest dx and dy, so
+/// at most `max_points` events remain and no spike of either line is dropped.
+/// Streams with at most `max_points` events are kept whole.
+pub fn m4_reduce(events: impl Iterator<Item = MouseMoveEvent>, count: usize, max_points: usize) -> Vec<MouseMoveEvent> {
+    if count <= max_points {
+        return events.collect();
+    }
+    let run_len = count.div_ceil((max_points / 6).max(1));
+    // First, last, min dx, max dx, min dy, max dy of the current run, with their indices
+    let mut keep = [(0, MouseMoveEvent::new(0, 0, 0, 0)); 6];
+    let (mut out, mut seen) = (Vec::with_capacity(max_points), false);
+    let flush = |keep: &mut [(usize, MouseMoveEvent); 6], out: &mut Vec<MouseMoveEvent>| {
+        keep.sort_unstable_by_key(|&(i, _)| i);
+        out.push(keep[0].1);
+        out.extend(keep.windows(2).filter(|w| w[0].0 != w[1].0).map(|w| w[1].1));
+    };
+    for (i, e) in events.enumerate() {
+        if i % run_len == 0 {
+            if i > 0 {
+                flush(&mut keep, &mut out);
+            }
+            keep = [(i, e); 6];
+        }
+        keep[1] = (i, e);
+        seen = true;
+        if e.dx < keep[2].1.dx {
+            keep[2] = (i, e);
+        }
+        if e.dx > keep[3].1.dx {
+            keep[3] = (i, e);
+        }
+        if e.dy < keep[4].1.dy {
+            keep[4] = (i, e);
+        }
+        if e.dy > keep[5].1.dy {
+            keep[5] = (i, e);
+        }
+    }
+    if seen {
+        flush(&mut keep, &mut out);
+    }
+    out
+}
+
+/// Min/max envelope decimation, see `m4_indices`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct M4Lod;
+
+impl LodStrategy for M4Lod {
+    fn name(&self) -> &'static str {
+        "M4 min/max"
+    }
+
+    fn visible_indices(&mut self, events: &[MouseMoveEvent], series: Series, view: &PlotView) -> Vec<usize> {
+        m4_indices(events, series, view.x_range, view.width.max(1.0) as usize)
+    }
+}
+
+/// The `lod` segment tree
+#[derive(Debug, Clone)]
+pub struct SegmentTreeLod {
+    pub tree: SegmentNode,
+    /// Tolerance in pixels when collecting a view
+    pub view_tol_px: f64,
+}
+
+impl SegmentTreeLod {
+    pub fn new(tree: SegmentNode) -> Self {
+        Self { tree, view_tol_px: 1.0 }
+    }
+
+    /// Build the tree of `degree` fits for `events` on a plot about `width` by `height` pixels showing all of them
+    pub fn build(events: &[MouseMoveEvent], width: usize, height: f64, degree: Degree) -> Self {
+        let range = events.iter().map(|e| (e.dx as f64).abs().max((e.dy as f64).abs())).fold(1.0, f64::max) * 2.0;
+        let min_pts = (events.len() / width.max(1)).max(5);
+        Self::new(build_segment_tree(events, 0, events.len(), min_pts, 1000, height / range, 1.0, degree))
+    }
+}
+
+impl LodStrategy for SegmentTreeLod {
+    fn name(&self) -> &'static str {
+        "Segment tree"
+    }
+
+    fn visible_indices(&mut self, events: &[MouseMoveEvent], _series: Series, view: &PlotView) -> Vec<usize> {
+        if self.tree.end != events.len() {
+            return Vec::new();
+        }
+        let px_scale = view.height / (view.y_range.1 - view.y_range.0).max(1e-12);
+        let mut out = Vec::new();
+        collect_indices_for_view(&self.tree, px_scale, self.view_tol_px, &mut out);
+        let (start, end) = visible_range(events, view.x_range);
+        out.retain(|&i| (start..end).contains(&i));
+        out
+    }
+}
+
+/// The `lod_advanced` segments, queried through the time index and view cache
+#[derive(Debug, Clone)]
+pub struct AdvancedLod {
+    pub segments: Vec<Segment>,
+    pub index: SegmentIndex,
+    /// View cache of each series, indexed by `series as usize`
+    pub caches: [Option<LodCache>; Series::ALL.len()],
+    /// Events per pixel before they are thinned out
+    pub tolerance: f64,
+    /// Size of the cached region relative to the view
+    pub zoom_factor: f64,
+}
+
+impl AdvancedLod {
+    /// Index `segments` built from `events`
+    pub fn new(segments: Vec<Segment>, events: &[MouseMoveEvent]) -> Self {
+        let index = SegmentIndex::new(&segments, events);
+        Self { segments, index, caches: Default::default(), tolerance: 3.0, zoom_factor: 2.0 }
+    }
+}
+
+impl LodStrategy for AdvancedLod {
+    fn name(&self) -> &'static str {
+        "Adaptive segments"
+    }
+
+    fn visible_indices(&mut self, events: &[MouseMoveEvent], series: Series, view: &PlotView) -> Vec<usize> {
+        collect_visible_indices_cached(&mut self.caches[series as usize], &self.index, &self.segments, events, series, view.width, view.height, view.x_range, view.y_range, self.tolerance, self.zoom_factor).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lod_advanced::build_segments;
+
+    /// Smooth 8 kHz movement with one single-report spike
+    fn spiky_events(n: u32, spike: u32) -> Vec<MouseMoveEvent> {
+        (0..n).map(|i| MouseMoveEvent::new((20.0 * (i as f64 / 300.0).sin()) as i16 + if i == spike { 120 } else { 0 }, -3, 0, i * 125)).collect()
+    }
+
+    fn full_view(events: &[MouseMoveEvent], width: f64) -> PlotView {
+        PlotView { width, height: 400.0, x_range: (0.0, events.last().unwrap().time_secs()), y_range: (-140.0, 140.0) }
+    }
+
+    #[test]
+    fn test_m4_keeps_extremes_and_bounds_the_point_count() {
+        let events = spiky_events(8_000, 4_321);
+        let indices = m4_indices(&events, Series::Dx, (0.0, 1.0), 200);
+        assert!(indices.len() <= 4 * 200 + 2, "{} indices", indices.len());
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        assert!(indices.contains(&4_321));
+        assert_eq!((indices[0], *indices.last().unwrap()), (0, 7_999));
+
+        // A zoomed view keeps one event beyond each edge and nothing further out
+        let zoomed = m4_indices(&events, Series::Dx, (0.5, 0.501), 100);
+        assert_eq!(zoomed, (3_999..=4_009).collect::<Vec<_>>());
+        assert!(m4_indices(&[], Series::Dx, (0.0, 1.0), 100).is_empty());
+        // -dy is constant, so its columns need only their first and last events
+        let flat = m4_indices(&events, Series::NegDy, (0.0, 1.0), 200);
+        assert!(flat.len() <= 2 * 200 + 2 && !flat.contains(&4_321));
+    }
+
+    #[test]
+    fn test_m4_reduce_bounds_the_stream_and_keeps_spikes() {
+        let mut events = spiky_events(100_000, 4_321);
+        events[77_777].dy = -90;
+        let reduced = m4_reduce(events.iter().copied(), events.len(), 6_000);
+        assert!(reduced.len() <= 6_000 && reduced.len() > 2_000, "{} events", reduced.len());
+        assert!(reduced.windows(2).all(|w| w[0].time_micros() < w[1].time_micros()));
+        assert!(reduced.iter().any(|e| e.dx > 100) && reduced.iter().any(|e| e.dy == -90));
+        assert_eq!((reduced[0].time_micros(), reduced.last().unwrap().time_micros()), (0, 99_999 * 125));
+
+        assert_eq!(m4_reduce(events[..500].iter().copied(), 500, 6_000).len(), 500);
+        // A stream shorter than announced ends with its last run
+        let short = m4_reduce(events[..10].iter().copied(), 100_000, 60);
+        assert!((2..=6).contains(&short.len()) && short.last().unwrap().time_micros() == 9 * 125, "{} events", short.len());
+        assert!(m4_reduce(std::iter::empty(), 100, 60).is_empty());
+    }
+
+    #[test]
+    fn test_strategies_reduce_large_captures() {
+        let events = spiky_events(16_000, 9_000);
+        let view = full_view(&events, 500.0);
+        let segments = build_segments(&events, 10, 1.6, 0.98, 0.091, Degree::Cubic);
+        let mut strategies: Vec<Box<dyn LodStrategy>> = vec![Box::new(M4Lod), Box::new(SegmentTreeLod::build(&events, 500, 400.0, Degree::Cubic)), Box::new(AdvancedLod::new(segments, &events))];
+        for strategy in &mut strategies {
+            let indices = strategy.visible_indices(&events, Series::Dx, &view);
+            assert!(!indices.is_empty() && indices.len() < events.len() / 2, "{} kept {} of {}", strategy.name(), indices.len(), events.len());
+            assert!(indices.windows(2).all(|w| w[0] < w[1]), "{} is not ascending", strategy.name());
+        }
+        // Only M4 guarantees the spike
+        assert!(M4Lod.visible_indices(&events, Series::Dx, &view).contains(&9_000));
+    }
+
+    #[test]
+    fn test_segment_tree_ignores_other_events() {
+        let events = spiky_events(2_000, 10);
+        let mut tree = SegmentTreeLod::build(&events, 500, 400.0, Degree::Cubic);
+        assert!(tree.visible_indices(&events[..1_000], Series::Dx, &full_view(&events, 500.0)).is_empty());
+    }
+}
@@ -1,504 +1,631 @@
-use anyhow::{Result, anyhow};
-use byteorder::{LittleEndian, ReadBytesExt};
-use plotters::prelude::*;
+use anyhow::{Context, Result, anyhow, bail};
+use clap::Parser;
 use std::{
-    env,
-    fs::OpenOptions,
-    io::{BufReader, Cursor, Read, Write},
-    process::{Command, Stdio},
-    str::FromStr,
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
     sync::{
-        Arc, Mutex,
+        Arc, Mutex, PoisonError,
         atomic::{AtomicBool, Ordering as AtomicOrdering},
     },
     thread,
     time::Duration,
 };
 
+mod cli;
+mod control;
 mod gui;
-pub mod lod;
-pub mod lod_advanced;
-pub mod mouse_event;
-
-use mouse_event::{MouseMoveEvent, PcapRecordHeader};
-
-#[derive(Debug)]
-#[allow(dead_code)]
-struct UsbPcapHeader {
-    header_len: u16,
-    irp_id: u64,
-    status: u32,
-    function: u16,
-    info: u8,
-    bus_id: u16,
-    device_address: u16,
-    endpoint: u8,
-    direction_in: bool,
-    transfer_type: u8,
-    data_length: u32,
-}
 
-impl UsbPcapHeader {
-    fn parse(data: &[u8]) -> Option<(Self, usize)> {
-        if data.len() < 27 {
-            return None;
-        }
-        let mut cur = Cursor::new(data);
-
-        let header_len = cur.read_u16::<LittleEndian>().ok()?;
-        let irp_id = cur.read_u64::<LittleEndian>().ok()?;
-        let status = cur.read_u32::<LittleEndian>().ok()?;
-        let function = cur.read_u16::<LittleEndian>().ok()?;
-        let info = cur.read_u8().ok()?;
-        let bus_id = cur.read_u16::<LittleEndian>().ok()?;
-        let device_address = cur.read_u16::<LittleEndian>().ok()?;
-        let raw_endpoint = cur.read_u8().ok()?;
-        let transfer_type = cur.read_u8().ok()?;
-        let data_length = cur.read_u32::<LittleEndian>().ok()?;
-
-        let direction_in = (raw_endpoint & 0x80) != 0;
-        let endpoint_number = raw_endpoint & 0x7F;
-        Some((
-            UsbPcapHeader {
-                header_len,
-                irp_id,
-                status,
-                function,
-                info,
-                bus_id,
-                device_address,
-                endpoint: endpoint_number,
-                direction_in,
-                transfer_type,
-                data_length,
-            },
-            27,
-        ))
+use MouseTesterRust::analysis::{Stats, compute_stats, compute_stats_with, counts_to_mm};
+use MouseTesterRust::capture::{CaptureOptions, EventSink, StopConditions, TargetDevice, UsbPcapHeader, run_capture};
+use MouseTesterRust::config::{self, Config, TemplateVars};
+use MouseTesterRust::event_store::EventStore;
+use MouseTesterRust::formats::{self, EventFormat};
+use MouseTesterRust::mouse_event::MouseMoveEvent;
+use MouseTesterRust::plot::{self, ImageFormat, PlotOptions};
+use MouseTesterRust::report::{Report, ReportFormat};
+use MouseTesterRust::session::{self, CaptureSettings, DeviceIdentity, SessionMark, SessionMetadata, SessionNotes, SessionWriter};
+use cli::{AnalyzeArgs, CaptureArgs, Cli, CompareArgs, ConvertArgs, DevicesArgs, GuiArgs, NotesArgs, OutputArgs, RecoverArgs, SourceArgs, StopArgs};
+use control::{ConsoleControl, Mark, ProgressCounter};
+
+/// Apply command line capture overrides on top of the loaded config
+fn apply_source_args(config: &mut Config, device: Option<TargetDevice>, source: &SourceArgs) {
+    if device.is_some() {
+        config.capture.device = device;
+    }
+    if let Some(cmd) = &source.usbpcap_cmd {
+        config.capture.usbpcap_cmd = cmd.clone();
+    }
+    if let Some(interface) = &source.interface {
+        config.capture.interface = interface.clone();
     }
+    if source.cpi.is_some() {
+        config.mouse.cpi = source.cpi;
+    }
+}
 
-    fn is_in_direction(&self) -> bool {
-        self.info == 1u8
+/// Apply command line stop conditions on top of the loaded config
+fn apply_stop_args(config: &mut Config, stop: &StopArgs) {
+    if stop.max_duration.is_some() {
+        config.stop.max_duration_secs = stop.max_duration;
+    }
+    if stop.max_events.is_some() {
+        config.stop.max_events = stop.max_events;
+    }
+    if stop.idle_timeout.is_some() {
+        config.stop.idle_timeout_secs = stop.idle_timeout;
+    }
+    if stop.arm_on_motion {
+        config.stop.arm_on_motion = true;
     }
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct TargetDevice {
-    bus_id: u16,
-    device_address: u16,
-    endpoint: u8,
+/// Apply command line test setup notes on top of the loaded config
+fn apply_notes_args(config: &mut Config, notes: &NotesArgs) {
+    if notes.mouse_model.is_some() {
+        config.mouse.model = notes.mouse_model.clone();
+    }
+    if notes.surface.is_some() {
+        config.mouse.surface = notes.surface.clone();
+    }
 }
 
-fn parse_target_device(arg: &str) -> Result<TargetDevice> {
-    let parts: Vec<&str> = arg.split('.').collect();
-    if parts.len() != 3 {
-        return Err(anyhow!("Invalid device format: {}, expected bus.device.endpoint", arg));
+/// Metadata for a session file of a capture starting now
+fn session_metadata(config: &Config, options: &CaptureOptions, comment: Option<String>) -> SessionMetadata {
+    SessionMetadata {
+        device: DeviceIdentity { target: options.target_device },
+        capture: CaptureSettings::from_options(options),
+        notes: SessionNotes { mouse_model: config.mouse.model.clone(), cpi: config.mouse.cpi, surface: config.mouse.surface.clone(), comment },
     }
-    Ok(TargetDevice {
-        bus_id: u16::from_str(parts[0])?,
-        device_address: u16::from_str(parts[1])?,
-        endpoint: u8::from_str(parts[2])?,
-    })
 }
 
-#[cfg(windows)]
-pub mod key_utils {
-    // Win32 GetAsyncKeyState via FFI
-    #[link(name = "user32")]
-    unsafe extern "system" {
-        pub fn GetAsyncKeyState(vKey: i32) -> i16;
+/// Output settings after applying config defaults to `OutputArgs`
+#[derive(Debug, Clone)]
+struct OutputSettings {
+    events_path: Option<PathBuf>,
+    events_format: Option<EventFormat>,
+    plot_path: Option<PathBuf>,
+    plot: PlotOptions,
+    reports: Vec<(ReportFormat, PathBuf)>,
+    open_plot: bool,
+    cpi: Option<u32>,
+    verbosity: i8,
+}
+
+impl OutputSettings {
+    fn from_args(args: &OutputArgs, config: &Config, verbosity: i8) -> Result<Self> {
+        let mut config = config.clone();
+        if let Some(dir) = &args.output_dir {
+            config.output.directory = dir.clone();
+        }
+        let vars = TemplateVars::now(config.capture.device, config.mouse.cpi);
+        let default_ext = args.format.unwrap_or(EventFormat::Csv).extension();
+
+        let events_path = if args.no_output { None } else { Some(args.output.clone().unwrap_or_else(|| config.events_path(&vars, default_ext))) };
+        if let Some(path) = &events_path {
+            let format = formats::resolve_format(path, args.format)?;
+            if !format.is_writable() {
+                bail!("Cannot write events as {}; use --format csv", format);
+            }
+        }
+        let plot_path = if args.no_plot { None } else { Some(args.plot.clone().unwrap_or_else(|| config.plot_path(&vars, "png"))) };
+        if let Some(path) = &plot_path
+            && ImageFormat::from_path(path).is_none()
+        {
+            bail!("Plot output {} must be a .png or .svg file", path.display());
+        }
+
+        let reports = args
+            .report
+            .iter()
+            .map(|&format| {
+                let path = match &args.report_output {
+                    Some(path) if args.report.len() > 1 => path.with_extension(format.extension()),
+                    Some(path) => path.clone(),
+                    None => config.report_path(&vars, format.extension()),
+                };
+                (format, path)
+            })
+            .collect();
+
+        Ok(Self {
+            events_path,
+            events_format: args.format,
+            plot_path,
+            reports,
+            plot: PlotOptions {
+                size: (args.plot_width.unwrap_or(config.plot.width), args.plot_height.unwrap_or(config.plot.height)),
+                dpi: args.plot_dpi.unwrap_or(config.plot.dpi),
+                panels: if args.panels.is_empty() { config.plot.panels.clone() } else { args.panels.clone() },
+                cpi: config.mouse.cpi,
+                full_resolution: args.full_resolution || config.plot.full_resolution,
+            },
+            open_plot: !args.no_open,
+            cpi: config.mouse.cpi,
+            verbosity,
+        })
     }
+}
 
-    pub const VK_F2: i32 = 0x71;
+fn print_analysis(stats: &Stats, cpi: Option<u32>) {
+    println!("--- MouseMoveEvents Analysis ---");
+    println!("Events: {}", stats.count);
+    println!("Duration (s): {:.6}", stats.duration);
+    println!("Sum dx: {}, Sum dy: {}", stats.total_dx, stats.total_dy);
+    println!("Total distance (sum of step lengths): {:.3}", stats.total_distance);
+    println!("Avg per event: dx={:.3}, dy={:.3}", stats.total_dx as f64 / stats.count as f64, stats.total_dy as f64 / stats.count as f64);
+    if stats.duration > 0.0 {
+        println!("Events/sec: {:.3}", stats.events_per_sec);
+        println!("Average speed (units/sec): {:.3}", stats.avg_speed);
+    }
+    if let Some(cpi) = cpi {
+        println!("Total distance at {} CPI: {:.1} mm", cpi, counts_to_mm(stats.total_distance, cpi));
+        if stats.duration > 0.0 {
+            println!("Average speed at {} CPI: {:.1} mm/s", cpi, counts_to_mm(stats.avg_speed, cpi));
+        }
+    }
 
-    pub fn is_f2_pressed() -> bool {
-        // Cast to u16 so mask literal fits correctly
-        unsafe { ((GetAsyncKeyState(VK_F2) as u16) & 0x8000u16) != 0u16 }
+    println!("\nMovement magnitude histogram (each bucket range shown):");
+    let max_bucket = *stats.histogram.iter().max().unwrap_or(&0);
+    for (i, &c) in stats.histogram.iter().enumerate() {
+        let range_start = stats.bucket_size * (i as f64);
+        let range_end = stats.bucket_size * ((i + 1) as f64);
+        let bar_len = if max_bucket > 0 { ((c as f64 / max_bucket as f64) * 40.0).round() as usize } else { 0 };
+        let bar = "#".repeat(bar_len);
+        println!("  [{:6.3} - {:6.3}) : {:5} {}", range_start, range_end, c, bar);
     }
 }
 
-#[cfg(not(windows))]
-pub mod key_utils {
-    // fallback: no OS-level F2 detection available here
-    pub fn is_f2_pressed() -> bool {
-        false
+/// Open a file with the platform default application
+fn open_with_default_app(path: &Path) {
+    #[cfg(target_os = "windows")]
+    {
+        // Use start via cmd (start requires a title arg; provide empty title)
+        let _ = Command::new("cmd").arg("/C").arg("start").arg("").arg(path).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = Command::new("xdg-open").arg(path).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
     }
 }
 
-/// Create a PNG plot of dx(t) and -dy(t) using plotters.
+/// Analyze, save and plot events, and write the requested reports
 ///
-/// `path` - output PNG path
-/// `times` - X values (seconds)
-/// `dx` - dx values
-/// `ndy` - -dy values
-fn plot_to_png(path: &str, times: &[f64], dx: &[f64], ndy: &[f64]) -> Result<()> {
-    let width = 3840u32;
-    let height = 2160u32;
-    let root = BitMapBackend::new(path, (width, height)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    // X range
-    let t_min = times.first().copied().unwrap_or(0.0);
-    let t_max = times.last().copied().unwrap_or(1.0);
-    let t_span = (t_max - t_min).abs().max(1e-6);
-    let x_range = (t_min - 0.02 * t_span)..(t_max + 0.02 * t_span);
-
-    // Y range across both series
-    let v_min = dx.iter().chain(ndy.iter()).copied().fold(f64::INFINITY, f64::min);
-    let v_max = dx.iter().chain(ndy.iter()).copied().fold(f64::NEG_INFINITY, f64::max);
-    let v_span = (v_max - v_min).abs().max(1e-6);
-    let y_range = (v_min - 0.1 * v_span)..(v_max + 0.1 * v_span);
-
-    let mut chart = ChartBuilder::on(&root)
-        .caption("dx and -dy vs time", ("sans-serif", 24).into_font())
-        .margin(10)
-        .x_label_area_size(40)
-        .y_label_area_size(60)
-        .build_cartesian_2d(x_range, y_range)?;
-
-    chart.configure_mesh().x_desc("time (s)").y_desc("value").draw()?;
-
-    chart.draw_series(LineSeries::new(times.iter().copied().zip(dx.iter().copied()), &RED))?.label("dx");
-
-    chart.draw_series(LineSeries::new(times.iter().copied().zip(ndy.iter().copied()), &BLUE))?.label("-dy");
-
-    // draw points optionally
-    chart.draw_series(times.iter().copied().zip(dx.iter().copied()).map(|(t, v)| Circle::new((t, v), 2, RED.filled())))?;
-    chart.draw_series(times.iter().copied().zip(ndy.iter().copied()).map(|(t, v)| Circle::new((t, v), 2, BLUE.filled())))?;
-
-    chart.configure_series_labels().background_style(&WHITE.mix(0.8)).border_style(&BLACK).draw()?;
-
-    root.present()?;
-    Ok(())
+/// `events` is called once per pass, so the events can be streamed from a
+/// session file instead of held in memory. Only the plot and HTML reports collect them.
+/// `source` and `metadata` describe the capture in reports.
+fn analyze_and_write_csv_and_plot<F, I>(events: F, out: &OutputSettings, source: Option<&Path>, metadata: Option<&SessionMetadata>) -> Result<Option<Stats>>
+where
+    F: Fn() -> I,
+    I: Iterator<Item = MouseMoveEvent>,
+{
+    if events().next().is_none() {
+        println!("No MouseMoveEvents recorded.");
+        return Ok(None);
+    }
+
+    let stats = compute_stats_with(&events);
+    if out.verbosity >= 0 {
+        print_analysis(&stats, out.cpi);
+    }
+    if out.verbosity >= 1 {
+        println!(
+            "Report interval (us): min={} max={} mean={:.3} std={:.3}",
+            stats.interval.min_us, stats.interval.max_us, stats.interval.mean_us, stats.interval.std_dev_us
+        );
+    }
+
+    // Write events file with summary
+    if let Some(path) = &out.events_path {
+        create_parent_dir(path)?;
+        formats::save_events(path, out.events_format, events(), Some(&stats))?;
+        if out.verbosity >= 0 {
+            println!("\nWrote detailed events + summary to {}", path.display());
+        }
+    }
+
+    // The plot and the reports share the stroke and anomaly analysis
+    let report = (out.plot_path.is_some() || !out.reports.is_empty()).then(|| Report::build(&events, Some(stats.clone())).with_source(source).with_metadata(metadata.cloned()));
+    // Only the plot and chart formats need the events in memory
+    let needs_events = out.plot_path.is_some() || out.reports.iter().any(|(f, _)| f.needs_events());
+    let all_events: Vec<MouseMoveEvent> = if needs_events { events().collect() } else { Vec::new() };
+
+    // Draw the report image and open it in the system default viewer
+    if let (Some(path), Some(report)) = (&out.plot_path, &report) {
+        create_parent_dir(path)?;
+        plot::save_plot(path, &all_events, report, &out.plot).with_context(|| format!("Failed to write plot {}", path.display()))?;
+        if out.verbosity >= 1 {
+            println!("Wrote {}x{} plot at {} DPI to {}", out.plot.size.0, out.plot.size.1, out.plot.dpi, path.display());
+        }
+
+        if out.open_plot {
+            open_with_default_app(path);
+        }
+    }
+
+    if let Some(report) = &report {
+        for (format, path) in &out.reports {
+            create_parent_dir(path)?;
+            report.save(path, *format, &all_events)?;
+            if out.verbosity >= 0 {
+                println!("Wrote {} report ({} strokes, {} anomalies) to {}", format, report.strokes.len(), report.anomalies.len(), path.display());
+            }
+        }
+    }
+
+    Ok(Some(stats))
 }
 
-fn analyze_and_write_csv_and_plot(events: &[MouseMoveEvent]) -> Result<()> {
-    if events.is_empty() {
-        println!("No MouseMoveEvents recorded.");
-        return Ok(());
+/// Create the directory an output file goes into, e.g. a configured output directory
+fn create_parent_dir(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir).with_context(|| format!("Failed to create directory {}", dir.display())),
+        _ => Ok(()),
     }
+}
 
-    let count = events.len();
-    let time_start = events.iter().map(|e| e.time_secs()).fold(f64::INFINITY, |a, b| a.min(b));
-    let time_end = events.iter().map(|e| e.time_secs()).fold(f64::NEG_INFINITY, |a, b| a.max(b));
-    let duration = (time_end - time_start).max(0.0);
+fn load_input(path: &Path, format: Option<EventFormat>, device: Option<TargetDevice>) -> Result<Vec<MouseMoveEvent>> {
+    if !path.exists() {
+        bail!("Input file not found: {}", path.display());
+    }
+    formats::load_events(path, format, device)
+}
 
-    let total_dx: i64 = events.iter().map(|e| e.dx as i64).sum();
-    let total_dy: i64 = events.iter().map(|e| e.dy as i64).sum();
+fn cmd_capture(args: CaptureArgs, mut config: Config, verbosity: i8) -> Result<()> {
+    apply_source_args(&mut config, args.device, &args.source);
+    apply_stop_args(&mut config, &args.stop);
+    apply_notes_args(&mut config, &args.notes);
+    let out = OutputSettings::from_args(&args.output, &config, verbosity)?;
+    if let Some(path) = &args.session {
+        create_parent_dir(path)?;
+    }
+    // With --disk-only the session file is the only copy of the events
+    let events_store = (!args.disk_only).then(EventStore::new);
+    let progress = ProgressCounter::default();
+    let stop_flag = Arc::new(AtomicBool::new(false));
 
-    let mut total_distance = 0f64;
-    let mut magnitudes: Vec<f64> = Vec::with_capacity(events.len());
-    for e in events {
-        let mag = ((e.dx as f64).powi(2) + (e.dy as f64).powi(2)).sqrt();
-        magnitudes.push(mag);
-        total_distance += mag;
+    // Ctrl-C and stdin commands work on every OS, F2 only on Windows
+    let control = ConsoleControl::new(progress.clone(), Arc::clone(&stop_flag));
+    control.install_ctrlc()?;
+    control.spawn_stdin_reader();
+    if verbosity >= 0 {
+        println!("Type 'stop', 'mark [label]' or 'restart' and press Enter, or press Ctrl-C to stop");
     }
 
-    let avg_distance_per_event = total_distance / (count as f64);
-    let avg_speed = if duration > 0.0 { total_distance / duration } else { 0.0 };
+    // Capture on main thread with the F2 watcher enabled for keyboard control
+    let mut options = config.capture_options();
+    options.raw_output = args.raw.output();
+    if let Some(raw) = &options.raw_output {
+        create_parent_dir(&raw.path)?;
+    }
+    let mut session;
+    let mut metadata;
+    loop {
+        if let Some(store) = &events_store {
+            store.clear();
+        }
+        progress.reset();
+        metadata = session_metadata(&config, &options, args.notes.note.clone());
+        session = args.session.as_deref().map(|path| SessionWriter::create_with(path, &metadata)).transpose()?.map(Mutex::new);
+        stop_flag.store(false, AtomicOrdering::SeqCst);
+        session::with_timed_flush(session.as_ref(), || run_capture((progress.clone(), (events_store.clone(), session.as_ref())), Arc::clone(&stop_flag), &options))?;
+        if !control.take_restart() {
+            break;
+        }
+        println!("Restarting capture...");
+    }
+    let mut session = session.map(|session| session.into_inner().unwrap_or_else(PoisonError::into_inner));
+    if let Some(session) = &mut session {
+        session.flush()?;
+    }
 
-    println!("--- MouseMoveEvents Analysis ---");
-    println!("Events: {}", count);
-    println!("Duration (s): {:.6}", duration.max(0.0));
-    println!("Sum dx: {}, Sum dy: {}", total_dx, total_dy);
-    println!("Total distance (sum of step lengths): {:.3}", total_distance);
-    println!("Avg per event: dx={:.3}, dy={:.3}", total_dx as f64 / count as f64, total_dy as f64 / count as f64);
-    if duration > 0.0 {
-        println!("Events/sec: {:.3}", count as f64 / duration);
-        println!("Average speed (units/sec): {:.3}", total_distance / duration);
-    }
-
-    // Histogram
-    let max_mag = magnitudes.iter().copied().fold(0.0f64, |a, b| a.max(b));
-    let bucket_count = 12usize;
-    let mut buckets = vec![0usize; bucket_count];
-    let bucket_size = if max_mag <= 0.0 { 1.0 } else { max_mag / (bucket_count as f64) };
-
-    for &m in &magnitudes {
-        let idx = if bucket_size == 0.0 {
-            0
-        } else {
-            let v = (m / bucket_size).floor() as isize;
-            let v = v.max(0).min((bucket_count - 1) as isize);
-            v as usize
-        };
-        buckets[idx] += 1;
+    // write events & print analysis, create PNG plot and open it
+    let stats = match &events_store {
+        Some(store) => {
+            let events = store.snapshot();
+            analyze_and_write_csv_and_plot(|| events.iter().copied(), &out, args.session.as_deref(), Some(&metadata))?
+        }
+        None => {
+            let reader = formats::open_session(args.session.as_deref().expect("--disk-only requires --session"))?;
+            analyze_and_write_csv_and_plot(|| reader.events(), &out, args.session.as_deref(), Some(&metadata))?
+        }
+    };
+    // Marks only mean something next to the events they point into
+    let marks = if stats.is_some() { control.marks() } else { Vec::new() };
+
+    if let Some(mut session) = session {
+        if let Some(stats) = &stats {
+            session.write_analysis(stats)?;
+        }
+        if !marks.is_empty() {
+            session.write_marks(&session_marks(&marks))?;
+        }
+        let path = session.path().to_path_buf();
+        let written = session.finish()?;
+        if verbosity >= 0 {
+            println!("Saved {} events to session {}", written, path.display());
+        }
     }
 
-    println!("\nMovement magnitude histogram (each bucket range shown):");
-    let max_bucket = *buckets.iter().max().unwrap_or(&0);
-    for (i, &c) in buckets.iter().enumerate() {
-        let range_start = bucket_size * (i as f64);
-        let range_end = bucket_size * ((i + 1) as f64);
-        let bar_len = if max_bucket > 0 { ((c as f64 / max_bucket as f64) * 40.0).round() as usize } else { 0 };
-        let bar = std::iter::repeat('#').take(bar_len).collect::<String>();
-        println!("  [{:6.3} - {:6.3}) : {:5} {}", range_start, range_end, c, bar);
+    if !marks.is_empty() {
+        print_marks(&marks);
+        if let Some(path) = &out.events_path {
+            save_marks(path, out.events_format, &marks)?;
+        }
     }
+    Ok(())
+}
 
-    // Write CSV file summary + events
-    let mut f = OpenOptions::new().write(true).truncate(true).create(true).open("output.csv")?;
-    writeln!(f, "dx,dy,time")?;
-    for e in events {
-        writeln!(f, "{},{},{:.6}", e.dx, e.dy, e.time_secs())?;
+fn print_marks(marks: &[Mark]) {
+    println!("\nMarks:");
+    for mark in marks {
+        println!("  {:>10.6}s  event {:>8}  {}", mark.time_secs, mark.event_index, mark.label);
     }
-    writeln!(f, "\n# Summary")?;
-    writeln!(f, "# Count,{},TimeSpan(s),{:.6}", count, duration)?;
-    writeln!(f, "# TotalDistance,{:.6}", total_distance)?;
-    writeln!(f, "# AvgDistancePerEvent,{:.6}", avg_distance_per_event)?;
-    writeln!(f, "# AvgSpeed(units/s),{:.6}", avg_speed)?;
+}
 
-    println!("\nWrote detailed events + summary to output.csv");
+fn session_marks(marks: &[Mark]) -> Vec<SessionMark> {
+    marks.iter().map(|mark| SessionMark { event_index: mark.event_index as u64, time_secs: mark.time_secs, label: mark.label.clone() }).collect()
+}
 
-    // Prepare PNG plot and open it in the system default viewer
-    let times_plot: Vec<f64> = events.iter().map(|e| e.time_secs()).collect();
-    let dx_plot: Vec<f64> = events.iter().map(|e| e.dx as f64).collect();
-    let ndy_plot: Vec<f64> = events.iter().map(|e| -(e.dy as f64)).collect();
-    let png_path = "mouse_plot.png";
-    plot_to_png(png_path, &times_plot, &dx_plot, &ndy_plot)?;
+/// Add marks to a saved events file: a marks block for sessions, comment lines for CSV
+fn save_marks(path: &Path, format: Option<EventFormat>, marks: &[Mark]) -> Result<()> {
+    match formats::resolve_format(path, format)? {
+        EventFormat::Session => {
+            let mut writer = SessionWriter::append(path)?;
+            writer.write_marks(&session_marks(marks))?;
+            writer.finish().map(|_| ())
+        }
+        EventFormat::Csv => append_marks(path, marks),
+        EventFormat::Pcap => Ok(()),
+    }
+}
 
-    // try to open the PNG with platform default
-    #[cfg(target_os = "windows")]
-    {
-        // Use start via cmd (start requires a title arg; provide empty title)
-        let _ = Command::new("cmd").args(&["/C", "start", "", png_path]).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+/// Append marks as `# Mark,<event index>,<time>,<label>` comment lines, which CSV readers skip
+fn append_marks(path: &Path, marks: &[Mark]) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new().append(true).open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    for mark in marks {
+        writeln!(file, "# Mark,{},{:.6},{}", mark.event_index, mark.time_secs, mark.label.replace(['\n', ','], " "))?;
     }
-    #[cfg(not(target_os = "windows"))]
-    {
-        let _ = Command::new("xdg-open").arg(png_path).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    Ok(())
+}
+
+fn cmd_analyze(args: AnalyzeArgs, mut config: Config, verbosity: i8) -> Result<()> {
+    if args.device.is_some() {
+        config.capture.device = args.device;
+    }
+    let out = OutputSettings::from_args(&args.output, &config, verbosity)?;
+    if formats::resolve_format(&args.input, args.input_format)? == EventFormat::Session && args.input.exists() {
+        // Stream sessions from disk, they may not fit in memory
+        let reader = formats::open_session(&args.input)?;
+        if verbosity >= 1 {
+            println!("Streaming {} events from {} (session format v{})", reader.info().events, args.input.display(), reader.info().version);
+            if let Some(metadata) = reader.metadata() {
+                print!("{}", toml::to_string(metadata)?);
+            }
+        }
+        return analyze_and_write_csv_and_plot(|| reader.events(), &out, Some(&args.input), reader.metadata()).map(|_| ());
+    }
+    let events = load_input(&args.input, args.input_format, config.capture.device)?;
+    if verbosity >= 1 {
+        println!("Loaded {} events from {}", events.len(), args.input.display());
     }
+    analyze_and_write_csv_and_plot(|| events.iter().copied(), &out, Some(&args.input), None).map(|_| ())
+}
 
+fn cmd_recover(args: RecoverArgs, verbosity: i8) -> Result<()> {
+    let info = session::recover(&args.input)?;
+    if info.is_truncated() {
+        println!("Removed {} trailing bytes; {} recovers {} events in {} chunks", info.file_len - info.valid_len, args.input.display(), info.events, info.chunks);
+    } else if verbosity >= 0 {
+        println!("{} is intact: {} events in {} chunks", args.input.display(), info.events, info.chunks);
+    }
     Ok(())
 }
 
-pub fn run_capture(events_arc: Arc<Mutex<Vec<MouseMoveEvent>>>, stop_flag: Arc<AtomicBool>, target_device: Option<TargetDevice>, disable_f2_watcher: bool) -> Result<()> {
-    println!("Filtering for target device: {:?}", target_device);
-    println!("Starting USBPcapCMD for device {}", r"\\.\USBPcap1");
+/// Counts untargeted reports per device for `devices`
+#[derive(Default)]
+struct DeviceTally {
+    reports: BTreeMap<(u16, u16, u8), usize>,
+}
 
-    // Start usbpcap child
-    let mut child = Command::new(r"C:\Program Files\USBPcap\USBPcapCMD.exe")
-        .args(&["-d", r"\\.\USBPcap1", "-o", "-", "-A", "-s", "65535", "-b", "262144"])
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(|e| anyhow!("Failed to start USBPcapCMD: {}", e))?;
+impl EventSink for DeviceTally {
+    fn push_event(&mut self, _event: MouseMoveEvent) {}
 
-    // Extract stdout before moving child into Arc
-    let stdout = child.stdout.take().ok_or_else(|| anyhow!("Failed to capture stdout"))?;
+    fn push_untargeted(&mut self, usb_hdr: &UsbPcapHeader, _event: MouseMoveEvent, _payload: &[u8]) {
+        *self.reports.entry((usb_hdr.bus_id, usb_hdr.device_address, usb_hdr.endpoint)).or_default() += 1;
+    }
+}
 
-    // Put child in Arc<Mutex<>> for shared access
-    let child_arc = Arc::new(Mutex::new(child));
+fn cmd_devices(args: DevicesArgs, mut config: Config, verbosity: i8) -> Result<()> {
+    apply_source_args(&mut config, None, &args.source);
+    // Listen to every device on the interface for the requested time, whatever the config says
+    let mut options = config.capture_options();
+    options.target_device = None;
+    options.stop = StopConditions::default();
 
-    // Keyboard watcher thread (Windows: GetAsyncKeyState). On F2 it will try to stop the capture.
-    // Only start this thread if not disabled (e.g., when GUI is handling F2 separately)
-    if !disable_f2_watcher {
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    {
         let stop_flag = Arc::clone(&stop_flag);
-        let child_for_watcher = Arc::clone(&child_arc);
+        let seconds = args.seconds;
         thread::spawn(move || {
-            loop {
-                if stop_flag.load(AtomicOrdering::SeqCst) {
-                    break;
-                }
-                #[cfg(windows)]
-                {
-                    if key_utils::is_f2_pressed() {
-                        println!("F2 pressed: requesting stop...");
-                        stop_flag.store(true, AtomicOrdering::SeqCst);
-                        // Kill the child directly using the shared handle
-                        if let Ok(mut child) = child_for_watcher.lock() {
-                            let _ = child.kill();
-                        }
-                        break;
-                    }
-                }
-                thread::sleep(Duration::from_millis(80));
-            }
+            thread::sleep(Duration::from_secs_f64(seconds));
+            stop_flag.store(true, AtomicOrdering::SeqCst);
         });
     }
 
-    // reader loop, collect events
-    let mut reader = BufReader::new(stdout);
-    let mut buffer = Vec::<u8>::with_capacity(262144);
-    let mut temp = vec![0u8; 65535];
-    let mut first_target_ts: Option<(u32, u32)> = None; // Store (ts_sec, ts_usec) as base
-    let mut skipped_global = false;
+    if verbosity >= 0 {
+        println!("Listening for {:.1}s, move the mouse you want to test...", args.seconds);
+    }
+    let mut tally = DeviceTally::default();
+    run_capture(&mut tally, stop_flag, &options)?;
 
-    println!("Reading USB data from pipe... (press F2 to stop capture and analyze)");
+    if tally.reports.is_empty() {
+        println!("No mouse reports seen. Move the mouse during capture or check USBPcap.");
+        return Ok(());
+    }
+    println!("{:<16} {:>10} {:>12}", "device", "reports", "reports/s");
+    for ((bus, dev, ep), count) in &tally.reports {
+        println!("{:<16} {:>10} {:>12.1}", format!("{}.{}.{}", bus, dev, ep), count, *count as f64 / args.seconds);
+    }
+    Ok(())
+}
 
-    loop {
-        if stop_flag.load(AtomicOrdering::SeqCst) {
-            println!("Stop requested, breaking read loop...");
-            break;
-        }
+fn cmd_convert(args: ConvertArgs, config: Config, verbosity: i8) -> Result<()> {
+    let format = formats::resolve_format(&args.output, args.format)?;
+    if !format.is_writable() {
+        bail!("Cannot write {} files; choose another output format", format);
+    }
+    let events = load_input(&args.input, args.input_format, args.device.or(config.capture.device))?;
+    formats::save_events(&args.output, Some(format), events.iter().copied(), Some(&compute_stats(&events)))?;
+    if verbosity >= 0 {
+        println!("Converted {} events from {} to {}", events.len(), args.input.display(), args.output.display());
+    }
+    Ok(())
+}
 
-        let n = match reader.read(&mut temp) {
-            Ok(n) => n,
-            Err(e) => {
-                eprintln!("Error reading from pipe: {}", e);
-                break;
-            }
-        };
-        if n == 0 {
-            println!("USBPcapCMD closed pipe, exiting read loop...");
-            break;
-        }
-        buffer.extend_from_slice(&temp[..n]);
-        let mut offset: usize = 0;
+/// Extracts one number from `Stats` for the comparison table
+type StatMetric = fn(&Stats) -> f64;
 
-        if !skipped_global {
-            if buffer.len() < 24 {
-                // wait until we have global header
-            } else {
-                // drop the global pcap header
-                buffer.drain(0..24);
-                skipped_global = true;
-            }
-        }
+fn cmd_compare(args: CompareArgs, config: Config, _verbosity: i8) -> Result<()> {
+    let device = args.device.or(config.capture.device);
+    let mut rows = Vec::with_capacity(args.inputs.len());
+    for path in &args.inputs {
+        let events = load_input(path, None, device)?;
+        rows.push((path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(), compute_stats(&events)));
+    }
 
-        while offset + 16 <= buffer.len() {
-            let Some((rec_hdr, rec_size)) = PcapRecordHeader::parse(&buffer[offset..]) else {
-                break;
-            };
-            let total_needed = offset + rec_size + rec_hdr.incl_len as usize;
-            if buffer.len() < total_needed {
-                break;
-            }
-            let record_data = &buffer[offset + rec_size..offset + rec_size + rec_hdr.incl_len as usize];
-            if let Some((usb_hdr, usb_size)) = UsbPcapHeader::parse(record_data) {
-                let payload = &record_data[usb_size..];
-                if usb_hdr.is_in_direction() {
-                    if usb_hdr.data_length == 8 && payload.len() >= 8 {
-                        if let Some(td) = target_device {
-                            if td.bus_id == usb_hdr.bus_id && td.device_address == usb_hdr.device_address && td.endpoint == usb_hdr.endpoint {
-                                // Calculate relative timestamp
-                                let (rel_sec, rel_usec) = if let Some((base_sec, base_usec)) = first_target_ts {
-                                    // Compute relative time
-                                    let mut sec_diff = rec_hdr.ts_sec as i64 - base_sec as i64;
-                                    let mut usec_diff = rec_hdr.ts_usec as i64 - base_usec as i64;
-
-                                    if usec_diff < 0 {
-                                        sec_diff -= 1;
-                                        usec_diff += 1_000_000;
-                                    }
-
-                                    (sec_diff as u32, usec_diff as u32)
-                                } else {
-                                    first_target_ts = Some((rec_hdr.ts_sec, rec_hdr.ts_usec));
-                                    (0, 0)
-                                };
-
-                                if let Some(event) = mouse_event::parser::parse_with_report_id(payload, &rec_hdr) {
-                                    let mut events = events_arc.lock().unwrap();
-                                    // Create event with relative timestamp
-                                    events.push(MouseMoveEvent::new(event.dx, event.dy, rel_sec, rel_usec));
-                                }
-                            }
-                        } else {
-                            // no target specified, just print sample debug
-                            if let Some(event) = mouse_event::parser::parse_with_report_id(payload, &rec_hdr) {
-                                println!("?Mouse Move: dx={:<4} dy={:<4} raw={:02X?}", event.dx, event.dy, payload);
-                            }
-                        }
-                    } else if usb_hdr.data_length == 7 && payload.len() >= 7 {
-                        if let Some(td) = target_device {
-                            if td.bus_id == usb_hdr.bus_id && td.device_address == usb_hdr.device_address && td.endpoint == usb_hdr.endpoint {
-                                // Calculate relative timestamp
-                                let (rel_sec, rel_usec) = if let Some((base_sec, base_usec)) = first_target_ts {
-                                    // Compute relative time
-                                    let mut sec_diff = rec_hdr.ts_sec as i64 - base_sec as i64;
-                                    let mut usec_diff = rec_hdr.ts_usec as i64 - base_usec as i64;
-
-                                    if usec_diff < 0 {
-                                        sec_diff -= 1;
-                                        usec_diff += 1_000_000;
-                                    }
-
-                                    (sec_diff as u32, usec_diff as u32)
-                                } else {
-                                    first_target_ts = Some((rec_hdr.ts_sec, rec_hdr.ts_usec));
-                                    (0, 0)
-                                };
-
-                                if let Some(event) = mouse_event::parser::parse_without_report_id(payload, &rec_hdr) {
-                                    let mut events = events_arc.lock().unwrap();
-                                    // Create event with relative timestamp
-                                    events.push(MouseMoveEvent::new(event.dx, event.dy, rel_sec, rel_usec));
-                                }
-                            }
-                        } else {
-                            // no target specified, just print sample debug
-                            if let Some(event) = mouse_event::parser::parse_without_report_id(payload, &rec_hdr) {
-                                println!("?Mouse Move: dx={:<4} dy={:<4} raw={:02X?}", event.dx, event.dy, payload);
-                            }
-                        }
-                    }
-                }
+    let metrics: [(&str, StatMetric); 7] = [
+        ("events", |s| s.count as f64),
+        ("duration (s)", |s| s.duration),
+        ("events/s", |s| s.events_per_sec),
+        ("interval mean (us)", |s| s.interval.mean_us),
+        ("interval std (us)", |s| s.interval.std_dev_us),
+        ("avg distance/event", |s| s.avg_distance_per_event),
+        ("avg speed (units/s)", |s| s.avg_speed),
+    ];
+
+    print!("{:<22}", "metric");
+    for (name, _) in &rows {
+        print!(" {:>24}", name);
+    }
+    println!();
+    let baseline = &rows[0].1;
+    for (label, metric) in metrics {
+        print!("{:<22}", label);
+        let base = metric(baseline);
+        for (i, (_, stats)) in rows.iter().enumerate() {
+            let v = metric(stats);
+            if i == 0 || base == 0.0 {
+                print!(" {:>24.3}", v);
+            } else {
+                print!(" {:>24}", format!("{:.3} ({:+.1}%)", v, (v - base) / base * 100.0));
             }
-            offset = total_needed;
         }
+        println!();
+    }
+    Ok(())
+}
 
-        if offset > 0 {
-            buffer.drain(0..offset);
-        }
+fn cmd_gui(args: GuiArgs, mut config: Config) -> Result<()> {
+    apply_source_args(&mut config, args.device, &args.source);
+    apply_stop_args(&mut config, &args.stop);
+    apply_notes_args(&mut config, &args.notes);
+    // Shared stop flag
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    // Disable F2 watcher in GUI mode since GUI handles F2 itself
+    let mut capture_options = config.capture_options();
+    capture_options.disable_f2_watcher = true;
+    capture_options.raw_output = args.raw.output();
+    if let Some(raw) = &capture_options.raw_output {
+        create_parent_dir(&raw.path)?;
     }
 
-    // ensure child stopped
-    match child_arc.lock() {
-        Ok(mut child) => {
-            child.kill().ok();
-            child.wait().ok();
+    let session = match args.session {
+        Some(path) => {
+            create_parent_dir(&path)?;
+            Some(gui::SessionOutput { path, metadata: session_metadata(&config, &capture_options, args.notes.note.clone()), disk_only: args.disk_only })
         }
-        Err(_) => {}
+        None => None,
     };
 
-    Ok(())
+    // GUI mode: run capture in background thread, GUI on main thread; a file to open replaces the first capture
+    let open = args.open.map(|path| gui::OpenFile { path, format: args.input_format });
+    let capture = open.is_none().then(|| gui::spawn_capture(Arc::clone(&stop_flag), capture_options.clone(), session.clone()));
+
+    // Run GUI on main thread (required by eframe)
+    gui::run_gui(capture, stop_flag, capture_options, config.lod, session, open).map_err(|e| anyhow!("GUI failed: {}", e))
 }
 
-fn main() -> Result<()> {
-    // Shared event storage and stop flag
-    let events_arc: Arc<Mutex<Vec<MouseMoveEvent>>> = Arc::new(Mutex::new(Vec::new()));
-    let stop_flag = Arc::new(AtomicBool::new(false));
+/// Print the effective config as TOML, preceded by the files it came from
+fn cmd_config(config: &Config, files: &[PathBuf]) -> Result<()> {
+    if let Some(user) = config::user_config_path() {
+        println!("# User config: {}", user.display());
+    }
+    if files.is_empty() {
+        println!("# No config files found, showing built-in defaults");
+    }
+    for path in files {
+        println!("# Loaded: {}", path.display());
+    }
+    println!();
+    print!("{}", config.to_toml()?);
+    Ok(())
+}
 
-    // parse args for optional target device and gui flag
-    let args: Vec<String> = env::args().collect();
-    let mut target_device: Option<TargetDevice> = None;
-    let mut use_gui = false;
-    let mut i = 0usize;
-    while i < args.len() {
-        if args[i] == "-d" && i + 1 < args.len() {
-            target_device = Some(parse_target_device(&args[i + 1])?);
-            i += 1;
-        } else if args[i] == "--gui" || args[i] == "-g" {
-            use_gui = true;
+fn run(cli: Cli) -> Result<()> {
+    let verbosity = cli.verbosity();
+    let (config, files) = Config::load(cli.config.as_deref())?;
+    if verbosity >= 1 {
+        for path in &files {
+            println!("Using config {}", path.display());
         }
-        i += 1;
     }
 
-    if use_gui {
-        // GUI mode: run capture in background thread, GUI on main thread
-        let events_capture = Arc::clone(&events_arc);
-        let stop_capture = Arc::clone(&stop_flag);
-        thread::spawn(move || {
-            // Disable F2 watcher in GUI mode since GUI handles F2 itself
-            if let Err(e) = run_capture(events_capture, stop_capture, target_device, true) {
-                eprintln!("Capture error: {}", e);
-            }
-        });
+    match cli.command {
+        cli::Command::Capture(args) => cmd_capture(args, config, verbosity),
+        cli::Command::Analyze(args) => cmd_analyze(args, config, verbosity),
+        cli::Command::Devices(args) => cmd_devices(args, config, verbosity),
+        cli::Command::Convert(args) => cmd_convert(args, config, verbosity),
+        cli::Command::Compare(args) => cmd_compare(args, config, verbosity),
+        cli::Command::Gui(args) => cmd_gui(args, config),
+        cli::Command::Recover(args) => cmd_recover(args, verbosity),
+        cli::Command::Config => cmd_config(&config, &files),
+    }
+}
 
-        // Run GUI on main thread (required by eframe)
-        let stop_gui = Arc::clone(&stop_flag);
-        if let Err(e) = gui::run_gui(events_arc, stop_gui, target_device) {
-            eprintln!("GUI error: {}", e);
-            return Err(anyhow!("GUI failed: {}", e));
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Print the error chain on one line instead of a debug dump
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
         }
-    } else {
-        // CLI mode: run capture on main thread
-        // Enable F2 watcher in CLI mode for keyboard control
-        run_capture(Arc::clone(&events_arc), Arc::clone(&stop_flag), target_device, false)?;
-
-        // extract events for analysis and plotting
-        let events = events_arc.lock().unwrap().clone();
-
-        // write CSV & print analysis, create PNG plot and open it
-        analyze_and_write_csv_and_plot(&events)?;
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_marks_keeps_session_and_csv_outputs_readable() {
+        let events: Vec<MouseMoveEvent> = (0..300u32).map(|i| MouseMoveEvent::new((i % 7) as i16, 1, 0, i * 125)).collect();
+        let marks = vec![Mark { event_index: 100, time_secs: 0.0125, label: "flick, fast".to_string() }];
+        for ext in ["mtsession", "csv"] {
+            let path = std::env::temp_dir().join(format!("mousetester_marks_{}.{}", std::process::id(), ext));
+            formats::save_events(&path, None, events.iter().copied(), None).unwrap();
+            save_marks(&path, None, &marks).unwrap();
+            assert_eq!(formats::load_events(&path, None, None).unwrap().len(), events.len(), "{}", ext);
+            if ext == "mtsession" {
+                let reader = session::SessionReader::open(&path).unwrap();
+                assert!(!reader.info().is_truncated(), "marks must not leave a torn tail");
+                assert_eq!(reader.marks(), &session_marks(&marks)[..]);
+            } else {
+                assert!(std::fs::read_to_string(&path).unwrap().ends_with("# Mark,100,0.012500,flick  fast\n"));
+            }
+            std::fs::remove_file(&path).ok();
+        }
+    }
 }
@@ -0,0 +1,520 @@
+//! Static report images
+//!
+//! `save_plot` draws a capture as a multi-panel PNG or SVG image: dx and -dy
+//! vs time, report interval vs time, the interval and magnitude histograms,
+//! the XY path and a text panel with the statistics. Which panels appear is
+//! configurable; the time panel spans the top row and the others share the
+//! rows below. Anomalies from the `Report` are marked in red.
+//!
+//! Sizes are in pixels. Text and line widths are given at 96 DPI and scaled
+//! by `dpi / 96`, so a larger DPI gives a print-friendly image of the same size.
+//!
+//! Captures with more than two events per pixel column are not drawn point by
+//! point: the dx/dy lines go through `lod::collect_for_width`, sized to the
+//! panel's pixel width, and a shaded min/max envelope per pixel column keeps
+//! spikes visible. Report intervals are drawn as the envelope alone.
+//! `PlotOptions::full_resolution` draws every event instead.
+
+use crate::analysis::{AnomalyKind, STROKE_IDLE_GAP_US, counts_to_mm, interval_histogram};
+use crate::lod::collect_for_width;
+use crate::mouse_event::MouseMoveEvent;
+use crate::report::Report;
+use anyhow::{Result, anyhow, bail};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::Range, path::Path, str::FromStr};
+
+/// Buckets of the report interval histogram
+const INTERVAL_BUCKETS: usize = 40;
+
+/// Font family used for all text
+const FONT: &str = "sans-serif";
+
+/// One chart of the report image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Panel {
+    /// dx and -dy vs time
+    Time,
+    /// Time since the previous report vs time
+    Interval,
+    IntervalHistogram,
+    MagnitudeHistogram,
+    /// Cumulative position
+    Path,
+    /// Statistics as text
+    Stats,
+}
+
+impl Panel {
+    /// All panels, in layout order
+    pub const ALL: [Panel; 6] = [Panel::Time, Panel::Interval, Panel::IntervalHistogram, Panel::MagnitudeHistogram, Panel::Path, Panel::Stats];
+
+    /// Canonical name used on the command line and in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Panel::Time => "time",
+            Panel::Interval => "interval",
+            Panel::IntervalHistogram => "interval_histogram",
+            Panel::MagnitudeHistogram => "magnitude_histogram",
+            Panel::Path => "path",
+            Panel::Stats => "stats",
+        }
+    }
+}
+
+impl FromStr for Panel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Panel::ALL
+            .iter()
+            .copied()
+            .find(|p| p.name().eq_ignore_ascii_case(s) || p.name().replace('_', "-").eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("Unknown panel '{}', expected one of: {}", s, Panel::ALL.map(|p| p.name()).join(", ")))
+    }
+}
+
+impl fmt::Display for Panel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Image file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageFormat::Png),
+            "svg" => Some(ImageFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Layout and size of the report image
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlotOptions {
+    /// Image size in pixels
+    pub size: (u32, u32),
+    /// Scales text and lines relative to 96 DPI
+    pub dpi: u32,
+    pub panels: Vec<Panel>,
+    /// Mouse resolution for distances in millimetres on the stats panel
+    pub cpi: Option<u32>,
+    /// Draw every event instead of the LOD reduction and envelope
+    pub full_resolution: bool,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        Self { size: (3840, 2160), dpi: 96, panels: Panel::ALL.to_vec(), cpi: None, full_resolution: false }
+    }
+}
+
+impl PlotOptions {
+    fn scale(&self) -> f64 {
+        self.dpi as f64 / 96.0
+    }
+
+    fn px(&self, size: f64) -> u32 {
+        (size * self.scale()).round().max(1.0) as u32
+    }
+
+    /// Whether `n` events are reduced on a plot `width` pixels wide
+    fn reduces(&self, n: usize, width: u32) -> bool {
+        !self.full_resolution && n > 2 * width as usize
+    }
+}
+
+/// Draw the report image of `events` to `path`, PNG or SVG by extension
+pub fn save_plot(path: &Path, events: &[MouseMoveEvent], report: &Report, options: &PlotOptions) -> Result<()> {
+    if options.panels.is_empty() {
+        bail!("No plot panels selected");
+    }
+    match ImageFormat::from_path(path) {
+        Some(ImageFormat::Png) => draw(BitMapBackend::new(path, options.size).into_drawing_area(), events, report, options),
+        Some(ImageFormat::Svg) => draw(SVGBackend::new(path, options.size).into_drawing_area(), events, report, options),
+        None => bail!("Plot output {} must be a .png or .svg file", path.display()),
+    }
+}
+
+/// Split `root` into panel areas: the time panel spans the top, the rest share a grid
+fn layout<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, panels: &[Panel]) -> Vec<(Panel, DrawingArea<DB, Shift>)> {
+    let (top, rest): (Vec<Panel>, Vec<Panel>) = panels.iter().partition(|&&p| p == Panel::Time);
+    let mut areas = Vec::new();
+    let (w, h) = root.dim_in_pixel();
+    let lower = if top.is_empty() {
+        root.clone()
+    } else if rest.is_empty() {
+        areas.push((Panel::Time, root.clone()));
+        return areas;
+    } else {
+        let (upper, lower) = root.split_vertically((h as f64 * 0.42) as u32);
+        areas.push((Panel::Time, upper));
+        lower
+    };
+    let cols = rest.len().clamp(1, if w >= h { 3 } else { 2 });
+    let rows = rest.len().div_ceil(cols);
+    areas.extend(rest.into_iter().zip(lower.split_evenly((rows, cols))));
+    areas
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, events: &[MouseMoveEvent], report: &Report, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    for (panel, area) in layout(&root, &options.panels) {
+        let area = area.margin(options.px(8.0), options.px(8.0), options.px(8.0), options.px(8.0));
+        match panel {
+            Panel::Time => draw_time(&area, events, report, options)?,
+            Panel::Interval => draw_intervals(&area, events, report, options)?,
+            Panel::IntervalHistogram => {
+                let (counts, width) = interval_histogram(events, INTERVAL_BUCKETS);
+                draw_histogram(&area, "Report interval histogram", "interval (µs)", &counts, width, RGBColor(204, 119, 51), options)?
+            }
+            Panel::MagnitudeHistogram => draw_histogram(&area, "Movement magnitude histogram", "magnitude (counts)", &report.stats.histogram, report.stats.bucket_size, RGBColor(119, 119, 119), options)?,
+            Panel::Path => draw_path(&area, events, report, options)?,
+            Panel::Stats => draw_stats(&area, report, options)?,
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+/// Range of `values` with `pad` of the span added on both sides
+fn padded_range(values: impl Iterator<Item = f64>, pad: f64) -> Range<f64> {
+    let (lo, hi) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if !lo.is_finite() {
+        return 0.0..1.0;
+    }
+    let span = (hi - lo).abs().max(1e-6);
+    (lo - pad * span)..(hi + pad * span)
+}
+
+/// Min and max of the `(x, y)` points in each of `columns` equal slices of `x_range`
+fn envelope(points: impl Iterator<Item = (f64, f64)>, x_range: &Range<f64>, columns: usize) -> Vec<Option<(f64, f64)>> {
+    let mut out = vec![None; columns];
+    let per_column = columns as f64 / (x_range.end - x_range.start);
+    for (x, y) in points {
+        let column = ((x - x_range.start) * per_column).floor();
+        if column >= 0.0 && (column as usize) < columns {
+            let slot = &mut out[column as usize];
+            *slot = Some(slot.map_or((y, y), |(lo, hi): (f64, f64)| (lo.min(y), hi.max(y))));
+        }
+    }
+    out
+}
+
+/// Shade each non-empty envelope column from its min to its max, at least a line thick
+fn draw_envelope<DB: DrawingBackend>(chart: &mut ChartContext<'_, DB, Cartesian2d<plotters::coord::types::RangedCoordf64, plotters::coord::types::RangedCoordf64>>, columns: &[Option<(f64, f64)>], x_range: &Range<f64>, style: ShapeStyle, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let width = (x_range.end - x_range.start) / columns.len() as f64;
+    let y_range = chart.y_range();
+    let half_line = (y_range.end - y_range.start) / chart.plotting_area().dim_in_pixel().1.max(1) as f64 * options.px(1.0) as f64 / 2.0;
+    chart.draw_series(columns.iter().enumerate().filter_map(|(i, c)| c.map(|(lo, hi)| (x_range.start + width * i as f64, lo, hi))).map(|(x, lo, hi)| {
+        let pad = (half_line - (hi - lo) / 2.0).max(0.0);
+        Rectangle::new([(x, lo - pad), (x + width, hi + pad)], style)
+    }))?;
+    Ok(())
+}
+
+fn draw_time<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, events: &[MouseMoveEvent], report: &Report, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let t0 = report.start_time;
+    let times: Vec<f64> = events.iter().map(|e| e.time_secs() - t0).collect();
+    let x_range = padded_range(times.iter().copied(), 0.02);
+    let y_range = padded_range(events.iter().flat_map(|e| [e.dx as f64, -(e.dy as f64)]), 0.1);
+
+    let mut chart = ChartBuilder::on(area)
+        .caption("dx and -dy vs time", (FONT, options.px(24.0)))
+        .margin(options.px(10.0))
+        .x_label_area_size(options.px(40.0))
+        .y_label_area_size(options.px(72.0))
+        .build_cartesian_2d(x_range.clone(), y_range.clone())?;
+    chart.configure_mesh().x_desc("time (s)").y_desc("counts").label_style((FONT, options.px(14.0))).axis_desc_style((FONT, options.px(16.0))).draw()?;
+
+    draw_anomaly_lines(&mut chart, report, y_range.clone(), options)?;
+    let line = options.px(1.0);
+    let (width, height) = chart.plotting_area().dim_in_pixel();
+    if options.reduces(events.len(), width) {
+        // The envelope shows every extreme, the reduced lines show the shape
+        let columns = width as usize;
+        draw_envelope(&mut chart, &envelope(times.iter().copied().zip(events.iter().map(|e| e.dx as f64)), &x_range, columns), &x_range, RED.mix(0.25).filled(), options)?;
+        draw_envelope(&mut chart, &envelope(times.iter().copied().zip(events.iter().map(|e| -(e.dy as f64))), &x_range, columns), &x_range, BLUE.mix(0.25).filled(), options)?;
+        let series = collect_for_width(events, columns, height as f64 / (y_range.end - y_range.start));
+        let t = |us: u64| us as f64 / 1e6 - t0;
+        chart.draw_series(LineSeries::new(series.iter().map(|&(us, dx, _)| (t(us), dx)), RED.stroke_width(line)))?.label("dx").legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+        chart.draw_series(LineSeries::new(series.iter().map(|&(us, _, dy)| (t(us), -dy)), BLUE.stroke_width(line)))?.label("-dy").legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    } else {
+        chart.draw_series(LineSeries::new(times.iter().copied().zip(events.iter().map(|e| e.dx as f64)), RED.stroke_width(line)))?.label("dx").legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+        chart.draw_series(LineSeries::new(times.iter().copied().zip(events.iter().map(|e| -(e.dy as f64))), BLUE.stroke_width(line)))?.label("-dy").legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+        let dot = options.px(2.0);
+        chart.draw_series(times.iter().zip(events).map(|(&t, e)| Circle::new((t, e.dx as f64), dot, RED.filled())))?;
+        chart.draw_series(times.iter().zip(events).map(|(&t, e)| Circle::new((t, -(e.dy as f64)), dot, BLUE.filled())))?;
+    }
+
+    // Spikes are events, so they also get a ring at their value
+    let ring = options.px(7.0);
+    chart.draw_series(report.anomalies.iter().filter(|a| a.kind == AnomalyKind::Spike).filter_map(|a| events.get(a.event)).flat_map(|e| {
+        let t = e.time_secs() - t0;
+        [Circle::new((t, e.dx as f64), ring, RED.stroke_width(options.px(2.0))), Circle::new((t, -(e.dy as f64)), ring, RED.stroke_width(options.px(2.0)))]
+    }))?;
+
+    chart.configure_series_labels().label_font((FONT, options.px(16.0))).background_style(WHITE.mix(0.8)).border_style(BLACK).draw()?;
+    Ok(())
+}
+
+/// Vertical translucent red lines at the anomaly times
+fn draw_anomaly_lines<DB: DrawingBackend>(chart: &mut ChartContext<'_, DB, Cartesian2d<plotters::coord::types::RangedCoordf64, plotters::coord::types::RangedCoordf64>>, report: &Report, y_range: std::ops::Range<f64>, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let t0 = report.start_time;
+    let style = RED.mix(0.35).stroke_width(options.px(1.0));
+    chart.draw_series(report.anomalies.iter().map(|a| PathElement::new(vec![(a.time - t0, y_range.start), (a.time - t0, y_range.end)], style)))?;
+    Ok(())
+}
+
+fn draw_intervals<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, events: &[MouseMoveEvent], report: &Report, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let t0 = report.start_time;
+    // Idle gaps between strokes are not report intervals
+    let points: Vec<(f64, f64)> = events
+        .windows(2)
+        .map(|w| (w[1].time_secs() - t0, w[1].time_micros().saturating_sub(w[0].time_micros())))
+        .filter(|&(_, d)| d < STROKE_IDLE_GAP_US)
+        .map(|(t, d)| (t, d as f64))
+        .collect();
+    let x_range = padded_range(events.iter().map(|e| e.time_secs() - t0), 0.02);
+    let y_max = points.iter().map(|&(_, d)| d).fold(1.0, f64::max) * 1.1;
+
+    let mut chart = ChartBuilder::on(area)
+        .caption("Report interval vs time", (FONT, options.px(20.0)))
+        .margin(options.px(8.0))
+        .x_label_area_size(options.px(36.0))
+        .y_label_area_size(options.px(72.0))
+        .build_cartesian_2d(x_range.clone(), 0.0..y_max)?;
+    chart.configure_mesh().x_desc("time (s)").y_desc("interval (µs)").label_style((FONT, options.px(13.0))).axis_desc_style((FONT, options.px(14.0))).draw()?;
+    draw_anomaly_lines(&mut chart, report, 0.0..y_max, options)?;
+    let width = chart.plotting_area().dim_in_pixel().0;
+    if options.reduces(points.len(), width) {
+        draw_envelope(&mut chart, &envelope(points.iter().copied(), &x_range, width as usize), &x_range, RGBColor(51, 51, 51).filled(), options)?;
+    } else {
+        let dot = options.px(1.5);
+        chart.draw_series(points.iter().map(|&p| Circle::new(p, dot, RGBColor(51, 51, 51).filled())))?;
+    }
+
+    // Gaps are intervals, so they get a red dot at their value
+    let ring = options.px(5.0);
+    chart.draw_series(report.anomalies.iter().filter(|a| a.kind == AnomalyKind::ReportGap).map(|a| Circle::new((a.time - t0, a.value), ring, RED.filled())))?;
+    Ok(())
+}
+
+fn draw_histogram<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, caption: &str, x_desc: &str, counts: &[usize], bucket_width: f64, color: RGBColor, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let x_max = (bucket_width * counts.len() as f64).max(1e-6);
+    let y_max = counts.iter().copied().max().unwrap_or(0).max(1) as f64 * 1.05;
+    let mut chart = ChartBuilder::on(area)
+        .caption(caption, (FONT, options.px(20.0)))
+        .margin(options.px(8.0))
+        .x_label_area_size(options.px(36.0))
+        .y_label_area_size(options.px(72.0))
+        .build_cartesian_2d(0.0..x_max, 0.0..y_max)?;
+    chart.configure_mesh().x_desc(x_desc).y_desc("events").y_label_formatter(&|v| format!("{:.0}", v)).label_style((FONT, options.px(13.0))).axis_desc_style((FONT, options.px(14.0))).draw()?;
+    chart.draw_series(counts.iter().enumerate().map(|(i, &c)| {
+        let x0 = bucket_width * i as f64;
+        Rectangle::new([(x0 + bucket_width * 0.05, 0.0), (x0 + bucket_width * 0.95, c as f64)], color.filled())
+    }))?;
+    Ok(())
+}
+
+fn draw_path<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, events: &[MouseMoveEvent], report: &Report, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let mut points = Vec::with_capacity(events.len() + 1);
+    let (mut x, mut y) = (0.0, 0.0);
+    points.push((x, y));
+    for e in events {
+        x += e.dx as f64;
+        y -= e.dy as f64;
+        points.push((x, y));
+    }
+
+    // Equal scales on both axes so shapes are not distorted
+    let (w, h) = area.dim_in_pixel();
+    let xr = padded_range(points.iter().map(|p| p.0), 0.05);
+    let yr = padded_range(points.iter().map(|p| p.1), 0.05);
+    let per_px = ((xr.end - xr.start) / w.max(1) as f64).max((yr.end - yr.start) / h.max(1) as f64);
+    let (cx, cy) = ((xr.start + xr.end) / 2.0, (yr.start + yr.end) / 2.0);
+    let (hw, hh) = (per_px * w as f64 / 2.0, per_px * h as f64 / 2.0);
+
+    let mut chart = ChartBuilder::on(area)
+        .caption("XY path", (FONT, options.px(20.0)))
+        .margin(options.px(8.0))
+        .x_label_area_size(options.px(36.0))
+        .y_label_area_size(options.px(72.0))
+        .build_cartesian_2d((cx - hw)..(cx + hw), (cy - hh)..(cy + hh))?;
+    chart.configure_mesh().x_desc("x (counts)").y_desc("y (counts)").x_label_formatter(&|v| format!("{:.0}", v)).y_label_formatter(&|v| format!("{:.0}", v)).label_style((FONT, options.px(13.0))).axis_desc_style((FONT, options.px(14.0))).draw()?;
+    chart.draw_series(LineSeries::new(points.iter().copied(), RGBColor(51, 153, 51).stroke_width(options.px(1.0))))?;
+    let dot = options.px(5.0);
+    chart.draw_series([Circle::new(points[0], dot, RGBColor(51, 153, 51).filled()), Circle::new(*points.last().unwrap(), dot, BLACK.filled())])?;
+    // Position after each anomalous event
+    chart.draw_series(report.anomalies.iter().filter_map(|a| points.get(a.event + 1)).map(|&p| Circle::new(p, options.px(4.0), RED.filled())))?;
+    Ok(())
+}
+
+/// Lines of the stats panel
+fn stats_lines(report: &Report, cpi: Option<u32>) -> Vec<String> {
+    let s = &report.stats;
+    let mut lines = vec![
+        format!("Events: {}", s.count),
+        format!("Duration: {:.6} s", s.duration),
+        format!("Sum dx / dy: {} / {}", s.total_dx, s.total_dy),
+        format!("Total distance: {:.3} counts", s.total_distance),
+    ];
+    if let Some(cpi) = cpi {
+        lines.push(format!("  = {:.1} mm at {} CPI", counts_to_mm(s.total_distance, cpi), cpi));
+    }
+    lines.extend([
+        format!("Avg distance/event: {:.3}", s.avg_distance_per_event),
+        format!("Events/sec: {:.3}", s.events_per_sec),
+        format!("Avg speed: {:.3} counts/s", s.avg_speed),
+        format!("Interval: min {} / max {} µs", s.interval.min_us, s.interval.max_us),
+        format!("Interval: mean {:.3} / std {:.3} µs", s.interval.mean_us, s.interval.std_dev_us),
+        format!("Strokes: {}", report.strokes.len()),
+    ]);
+    let count = |kind| report.anomalies.iter().filter(|a| a.kind == kind).count();
+    lines.push(format!("Anomalies: {}", report.anomalies.len()));
+    for (kind, label) in [(AnomalyKind::ReportGap, "report gaps"), (AnomalyKind::Spike, "spikes"), (AnomalyKind::DuplicateTimestamp, "duplicate timestamps"), (AnomalyKind::TimeReversal, "time reversals")] {
+        let n = count(kind);
+        if n > 0 {
+            lines.push(format!("  {} {}", n, label));
+        }
+    }
+    if let Some(metadata) = &report.metadata {
+        if let Some(model) = &metadata.notes.mouse_model {
+            lines.push(format!("Mouse: {}", model));
+        }
+        if let Some(surface) = &metadata.notes.surface {
+            lines.push(format!("Surface: {}", surface));
+        }
+    }
+    lines
+}
+
+fn draw_stats<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, report: &Report, options: &PlotOptions) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let cpi = options.cpi.or(report.metadata.as_ref().and_then(|m| m.notes.cpi));
+    let lines = stats_lines(report, cpi);
+    let (_, h) = area.dim_in_pixel();
+    // Shrink the text when the panel is too short for all lines
+    let line_height = (options.px(22.0) as f64).min(h as f64 / (lines.len() + 2) as f64);
+    let font = (FONT, line_height * 0.8).into_font();
+    area.draw(&Text::new("Statistics", (options.px(12.0) as i32, (line_height * 0.5) as i32), font.clone().style(FontStyle::Bold)))?;
+    for (i, line) in lines.iter().enumerate() {
+        area.draw(&Text::new(line.as_str(), (options.px(12.0) as i32, (line_height * (i as f64 + 2.0)) as i32), font.clone()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_events() -> Vec<MouseMoveEvent> {
+        (0..400u32).map(|i| MouseMoveEvent::new(((i % 13) as i16 - 6) * if i == 200 { 20 } else { 1 }, (i % 7) as i16 - 3, 0, i * 1_000 + if i > 300 { 5_000 } else { 0 })).collect()
+    }
+
+    #[test]
+    fn test_parse_panels() {
+        assert_eq!("interval-histogram".parse::<Panel>().unwrap(), Panel::IntervalHistogram);
+        assert_eq!("XY".parse::<Panel>().ok(), None);
+        for p in Panel::ALL {
+            assert_eq!(p.name().parse::<Panel>().unwrap(), p);
+        }
+    }
+
+    #[test]
+    fn test_layout_puts_time_on_top() {
+        let mut buf = String::new();
+        let root = SVGBackend::with_string(&mut buf, (1200, 800)).into_drawing_area();
+        let areas = layout(&root, &Panel::ALL);
+        assert_eq!(areas.len(), 6);
+        assert_eq!(areas[0].0, Panel::Time);
+        assert_eq!(areas[0].1.dim_in_pixel().0, 1200);
+        assert!(areas[1..].iter().all(|(_, a)| a.dim_in_pixel().0 == 400));
+        assert_eq!(layout(&root, &[Panel::Path]).len(), 1);
+    }
+
+    #[test]
+    fn test_save_svg_and_png() {
+        let events = sample_events();
+        let report = Report::from_events(&events);
+        assert!(report.anomalies.iter().any(|a| a.kind == AnomalyKind::Spike));
+        let dir = std::env::temp_dir();
+        let svg = dir.join(format!("mousetester_plot_{}.svg", std::process::id()));
+        let png = dir.join(format!("mousetester_plot_{}.png", std::process::id()));
+        let options = PlotOptions { size: (900, 600), dpi: 120, cpi: Some(800), ..Default::default() };
+        save_plot(&svg, &events, &report, &options).unwrap();
+        save_plot(&png, &events, &report, &PlotOptions { panels: vec![Panel::Time, Panel::Stats], ..options.clone() }).unwrap();
+
+        let text = std::fs::read_to_string(&svg).unwrap();
+        assert!(text.starts_with("<svg"));
+        for caption in ["dx and -dy vs time", "Report interval vs time", "XY path", "Statistics", "mm at 800 CPI"] {
+            assert!(text.contains(caption), "missing {}", caption);
+        }
+        assert_eq!(&std::fs::read(&png).unwrap()[1..4], b"PNG");
+        assert!(save_plot(&dir.join("plot.jpg"), &events, &report, &options).is_err());
+        std::fs::remove_file(svg).ok();
+        std::fs::remove_file(png).ok();
+    }
+
+    #[test]
+    fn test_envelope_keeps_extremes() {
+        let points = (0..1000).map(|i| (i as f64, if i == 517 { 90.0 } else { (i % 5) as f64 }));
+        let columns = envelope(points, &(0.0..1000.0), 10);
+        assert_eq!(columns.len(), 10);
+        assert_eq!(columns[5], Some((0.0, 90.0)));
+        assert_eq!(columns[4], Some((0.0, 4.0)));
+        assert_eq!(envelope([(2.0, 1.0)].into_iter(), &(0.0..10.0), 5), vec![None, Some((1.0, 1.0)), None, None, None]);
+    }
+
+    #[test]
+    fn test_large_captures_are_reduced_unless_full_resolution() {
+        let events: Vec<MouseMoveEvent> = (0..6_000u32).map(|i| MouseMoveEvent::new(((i * 7) % 11) as i16 - 5 + if i == 3_000 { 60 } else { 0 }, 2, 0, i * 125)).collect();
+        let report = Report::from_events(&events);
+        let render = |options: &PlotOptions| {
+            let mut buf = String::new();
+            draw(SVGBackend::with_string(&mut buf, options.size).into_drawing_area(), &events, &report, options).unwrap();
+            buf
+        };
+        let options = PlotOptions { size: (800, 500), panels: vec![Panel::Time, Panel::Interval], ..Default::default() };
+        let reduced = render(&options);
+        let full = render(&PlotOptions { full_resolution: true, ..options.clone() });
+        // Only the spike ring is a circle once reduced, every event is one at full resolution
+        assert!(reduced.matches("<circle").count() < 10);
+        assert!(full.matches("<circle").count() > 2 * events.len());
+        assert!(reduced.len() * 3 < full.len(), "reduced {} bytes vs full {}", reduced.len(), full.len());
+    }
+}
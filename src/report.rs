@@ -0,0 +1,247 @@
+//! Machine-readable analysis reports
+//!
+//! A `Report` bundles everything the analysis knows about a capture: the
+//! session metadata, the summary statistics, the magnitude histogram as
+//! explicit buckets, per-stroke records and the detected anomalies. It is
+//! written as JSON following the schema published in
+//! `docs/report.schema.json` (also available as `REPORT_SCHEMA`), or as a
+//! self-contained HTML page with charts (see `html_report`).
+//!
+//! All times are in seconds on the capture clock, like the CSV time column.
+
+use crate::analysis::{Anomaly, Stats, Stroke, compute_stats_with, find_anomalies_with, find_strokes};
+use crate::html_report;
+use crate::mouse_event::MouseMoveEvent;
+use crate::session::SessionMetadata;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Version of the report layout; bumped on incompatible changes
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// JSON Schema describing the report
+pub const REPORT_SCHEMA: &str = include_str!("../docs/report.schema.json");
+
+/// Supported report formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Json,
+    Html,
+}
+
+impl ReportFormat {
+    /// All formats, in the order shown to users
+    pub const ALL: [ReportFormat; 2] = [ReportFormat::Json, ReportFormat::Html];
+
+    /// Canonical lowercase name
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportFormat::Json => "json",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// File extension for default file names
+    pub fn extension(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Whether the format draws charts from the events themselves
+    pub fn needs_events(&self) -> bool {
+        matches!(self, ReportFormat::Html)
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ReportFormat::ALL
+            .iter()
+            .copied()
+            .find(|f| f.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| anyhow!("Unknown report format '{}', expected one of: {}", s, ReportFormat::ALL.map(|f| f.name()).join(", ")))
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// One bucket of the movement magnitude histogram, covering `[start, end)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+/// Full analysis of one capture
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub schema_version: u32,
+    /// Program name and version that wrote the report
+    pub generator: String,
+    /// Wall clock time the report was written, seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generated_unix_secs: Option<u64>,
+    /// File the events were loaded from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PathBuf>,
+    /// Device, capture settings and notes of a session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<SessionMetadata>,
+    /// Time of the first event
+    pub start_time: f64,
+    pub stats: Stats,
+    pub histogram: Vec<HistogramBucket>,
+    pub strokes: Vec<Stroke>,
+    pub anomalies: Vec<Anomaly>,
+}
+
+impl Report {
+    /// Analyze a re-iterable event source, e.g. a session streamed from disk
+    ///
+    /// `stats` can be passed when already computed for the same events.
+    pub fn build<F, I>(mut events: F, stats: Option<Stats>) -> Self
+    where
+        F: FnMut() -> I,
+        I: Iterator<Item = MouseMoveEvent>,
+    {
+        let stats = stats.unwrap_or_else(|| compute_stats_with(&mut events));
+        let histogram = stats
+            .histogram
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| HistogramBucket { start: stats.bucket_size * i as f64, end: stats.bucket_size * (i + 1) as f64, count })
+            .collect();
+        Self {
+            schema_version: REPORT_SCHEMA_VERSION,
+            generator: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            generated_unix_secs: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+            source: None,
+            metadata: None,
+            start_time: events().next().map(|e| e.time_secs()).unwrap_or(0.0),
+            strokes: find_strokes(events()),
+            anomalies: find_anomalies_with(&mut events),
+            histogram,
+            stats,
+        }
+    }
+
+    /// Analyze events held in memory
+    pub fn from_events(events: &[MouseMoveEvent]) -> Self {
+        Self::build(|| events.iter().copied(), None)
+    }
+
+    /// Record the file the events came from
+    pub fn with_source(mut self, source: Option<&Path>) -> Self {
+        self.source = source.map(Path::to_path_buf);
+        self
+    }
+
+    /// Attach session metadata
+    pub fn with_metadata(mut self, metadata: Option<SessionMetadata>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Serialize as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize report")
+    }
+
+    /// Write the report to `path` in `format`
+    ///
+    /// `events` are the analyzed events; they are only read by formats that
+    /// `needs_events`, so other formats can be given an empty slice.
+    pub fn save(&self, path: &Path, format: ReportFormat, events: &[MouseMoveEvent]) -> Result<()> {
+        let contents = match format {
+            ReportFormat::Json => self.to_json()?,
+            ReportFormat::Html => html_report::render(self, events)?,
+        };
+        let mut writer = BufWriter::new(File::create(path).with_context(|| format!("Failed to create {}", path.display()))?);
+        writer.write_all(contents.as_bytes()).and_then(|_| writer.flush()).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn sample_events() -> Vec<MouseMoveEvent> {
+        // Two strokes at 1 kHz with a spike in the first
+        (0..40u32).map(|i| MouseMoveEvent::new(if i == 10 { 80 } else { 3 }, -2, 5, if i < 20 { i * 1_000 } else { 600_000 + i * 1_000 })).collect()
+    }
+
+    #[test]
+    fn test_report_contents() {
+        let report = Report::from_events(&sample_events()).with_source(Some(Path::new("run.csv")));
+        assert_eq!(report.stats.count, 40);
+        assert_eq!(report.histogram.len(), report.stats.histogram.len());
+        assert_eq!(report.histogram.iter().map(|b| b.count).sum::<usize>(), 40);
+        assert_eq!(report.strokes.len(), 2);
+        assert_eq!(report.anomalies.len(), 1);
+        assert_eq!(report.anomalies[0].event, 10);
+        assert!((report.start_time - 5.0).abs() < 1e-9);
+
+        let parsed: Report = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    /// Check that `value` has every property the schema requires, recursively
+    fn check_required(schema: &Value, value: &Value, root: &Value, path: &str) {
+        let schema = match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => root.pointer(reference.trim_start_matches('#')).unwrap_or_else(|| panic!("unresolved {}", reference)),
+            None => schema,
+        };
+        if let (Some(required), Some(object)) = (schema.get("required").and_then(Value::as_array), value.as_object()) {
+            for key in required {
+                let key = key.as_str().unwrap();
+                assert!(object.contains_key(key), "{} is missing required '{}'", path, key);
+            }
+        }
+        if let (Some(properties), Some(object)) = (schema.get("properties").and_then(Value::as_object), value.as_object()) {
+            if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                for key in object.keys() {
+                    assert!(properties.contains_key(key), "{} has '{}' which the schema does not describe", path, key);
+                }
+            }
+            for (key, sub) in properties {
+                if let Some(v) = object.get(key) {
+                    check_required(sub, v, root, &format!("{}.{}", path, key));
+                }
+            }
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (i, v) in array.iter().enumerate() {
+                check_required(items, v, root, &format!("{}[{}]", path, i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_report_matches_published_schema() {
+        let schema: Value = serde_json::from_str(REPORT_SCHEMA).unwrap();
+        assert_eq!(schema["properties"]["schema_version"]["const"], REPORT_SCHEMA_VERSION);
+
+        let mut report = Report::from_events(&sample_events()).with_source(Some(Path::new("run.mtsession")));
+        let mut metadata = SessionMetadata::default();
+        metadata.device.target = Some("1.2.1".parse().unwrap());
+        metadata.notes.cpi = Some(800);
+        report.metadata = Some(metadata);
+        let value = serde_json::to_value(&report).unwrap();
+        check_required(&schema, &value, &schema, "report");
+    }
+}
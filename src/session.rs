@@ -0,0 +1,858 @@
+//! Native session files: events, raw reports and capture metadata
+//!
+//! A session file receives events while the capture is running, so a crash or
+//! a long soak test loses at most the last unflushed block, and captures larger
+//! than memory can still be analyzed by streaming them back. Unlike CSV it
+//! keeps exact `ts_sec`/`ts_usec` timestamps, the raw HID report of every
+//! event, and describes the capture: device identity, settings, user notes,
+//! marks and analysis results.
+//!
+//! # File layout (little endian)
+//!
+//! ```text
+//! header:  magic "MTSN" | version u16 | reserved u16
+//! block:   tag [u8; 4] | payload length u32 | CRC-32 of payload u32 | payload
+//! ```
+//!
+//! Block types in version 2:
+//!
+//! - `EVNT`: events, each `dx i16 | dy i16 | ts_sec u32 | ts_usec u32 | report length u8 | report`
+//! - `META`: `SessionMetadata` as UTF-8 TOML; a later block replaces an earlier one
+//! - `ANLY`: analysis `Stats` as UTF-8 TOML; a later block replaces an earlier one
+//! - `MARK`: `SessionMark`s set during the capture as UTF-8 TOML; a later block replaces an earlier one
+//!
+//! Readers skip blocks with unknown tags. Version 1 files hold only events, in
+//! `CHNK` chunks (`"CHNK" | event count u32 | CRC-32 u32 | count x 12-byte events`
+//! without reports), and can still be read.
+//!
+//! Blocks are only ever appended. A block that is cut short or fails its
+//! checksum marks the end of the valid data: readers stop there and `recover`
+//! truncates the file to that point so writing can resume.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use MouseTesterRust::session::{SessionMetadata, SessionReader, SessionWriter};
+//!
+//! let mut writer = SessionWriter::create_with("run.mtsession", &metadata)?;
+//! writer.push_report(event, &report_bytes)?;
+//! writer.write_analysis(&stats)?;
+//! writer.finish()?;
+//!
+//! let reader = SessionReader::open("run.mtsession")?;
+//! let stats = compute_stats_with(|| reader.events());
+//! ```
+
+use crate::analysis::Stats;
+use crate::capture::{CaptureOptions, EventSink, StopConditions, TargetDevice};
+use crate::mouse_event::MouseMoveEvent;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Magic bytes at the start of every session file
+pub const SESSION_MAGIC: [u8; 4] = *b"MTSN";
+/// Current session format version
+pub const SESSION_VERSION: u16 = 2;
+/// File extension used for session files
+pub const SESSION_EXTENSION: &str = "mtsession";
+
+const HEADER_LEN: u64 = 8;
+const BLOCK_HEADER_LEN: usize = 12;
+const EVENTS_TAG: [u8; 4] = *b"EVNT";
+const METADATA_TAG: [u8; 4] = *b"META";
+const ANALYSIS_TAG: [u8; 4] = *b"ANLY";
+const MARKS_TAG: [u8; 4] = *b"MARK";
+/// Event chunk tag of version 1 files
+const V1_CHUNK_TAG: [u8; 4] = *b"CHNK";
+const EVENT_LEN: usize = 12;
+/// Reports longer than this are truncated (the length is stored in one byte)
+pub const MAX_REPORT_LEN: usize = 255;
+/// Upper bound on block payloads accepted by readers, to reject garbage lengths
+const MAX_BLOCK_LEN: u32 = 64 << 20;
+
+/// When the writer turns buffered events into a flushed block
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlushPolicy {
+    /// Flush once this many events are buffered
+    pub max_events: usize,
+    /// Flush when the oldest buffered event is this old
+    ///
+    /// Checked on each push, and on a timer by `with_timed_flush` so idle
+    /// periods do not keep events in memory.
+    pub max_interval: Duration,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        Self { max_events: 8192, max_interval: Duration::from_secs(1) }
+    }
+}
+
+/// The mouse a session was recorded from
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceIdentity {
+    /// USBPcap bus, device address and endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetDevice>,
+}
+
+/// How a session was captured
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSettings {
+    /// Version of the program that wrote the session
+    pub program_version: String,
+    /// Wall clock start of the capture, seconds since the Unix epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_unix_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<String>,
+    /// Raw pcap file saved alongside the session
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_pcap: Option<PathBuf>,
+    pub stop: StopConditions,
+}
+
+impl CaptureSettings {
+    /// Settings of a capture starting now with `options`
+    pub fn from_options(options: &CaptureOptions) -> Self {
+        Self {
+            program_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_unix_secs: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+            interface: Some(options.interface.clone()),
+            raw_pcap: options.raw_output.as_ref().map(|raw| raw.path.clone()),
+            stop: options.stop,
+        }
+    }
+}
+
+/// Notes about the test setup entered by the user
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionNotes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mouse_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpi: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Everything a session records besides the events
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionMetadata {
+    pub device: DeviceIdentity,
+    pub capture: CaptureSettings,
+    pub notes: SessionNotes,
+}
+
+/// A point of the capture labelled by the user, e.g. with the `mark` console command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMark {
+    /// Number of events recorded when the mark was set
+    pub event_index: u64,
+    /// Time of the last recorded event in seconds
+    pub time_secs: f64,
+    pub label: String,
+}
+
+/// Payload of a `MARK` block; TOML needs a table at the top
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct MarkList {
+    marks: Vec<SessionMark>,
+}
+
+fn encode_event(e: &MouseMoveEvent, out: &mut Vec<u8>) {
+    out.extend_from_slice(&e.dx.to_le_bytes());
+    out.extend_from_slice(&e.dy.to_le_bytes());
+    out.extend_from_slice(&e.ts_sec.to_le_bytes());
+    out.extend_from_slice(&e.ts_usec.to_le_bytes());
+}
+
+fn decode_event(b: &[u8]) -> MouseMoveEvent {
+    MouseMoveEvent::new(
+        i16::from_le_bytes([b[0], b[1]]),
+        i16::from_le_bytes([b[2], b[3]]),
+        u32::from_le_bytes([b[4], b[5], b[6], b[7]]),
+        u32::from_le_bytes([b[8], b[9], b[10], b[11]]),
+    )
+}
+
+/// Decode the event record at `pos` of an event block: the event, its report and the next position
+fn decode_record(tag: [u8; 4], payload: &[u8], pos: usize) -> Option<(MouseMoveEvent, &[u8], usize)> {
+    let event = decode_event(payload.get(pos..pos + EVENT_LEN)?);
+    if tag == V1_CHUNK_TAG {
+        return Some((event, &[], pos + EVENT_LEN));
+    }
+    let report_len = *payload.get(pos + EVENT_LEN)? as usize;
+    let start = pos + EVENT_LEN + 1;
+    Some((event, payload.get(start..start + report_len)?, start + report_len))
+}
+
+/// Number of events in an event block, or `None` if its records are malformed
+fn count_records(tag: [u8; 4], payload: &[u8]) -> Option<u64> {
+    let (mut pos, mut count) = (0, 0);
+    while pos < payload.len() {
+        pos = decode_record(tag, payload, pos)?.2;
+        count += 1;
+    }
+    Some(count)
+}
+
+fn is_event_block(tag: [u8; 4]) -> bool {
+    tag == EVENTS_TAG || tag == V1_CHUNK_TAG
+}
+
+/// Appends events and metadata to a session file in flushed, checksummed blocks
+///
+/// Dropping the writer flushes what is buffered; call `finish` to see errors.
+#[derive(Debug)]
+pub struct SessionWriter {
+    file: File,
+    path: PathBuf,
+    policy: FlushPolicy,
+    /// Encoded records of buffered events
+    pending: Vec<u8>,
+    pending_events: usize,
+    pending_since: Option<Instant>,
+    block: Vec<u8>,
+    events_written: u64,
+    error: Option<String>,
+}
+
+impl SessionWriter {
+    /// Create (or overwrite) a session file
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::create(path).with_context(|| format!("Failed to create session {}", path.display()))?;
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(&SESSION_MAGIC);
+        header.extend_from_slice(&SESSION_VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        file.write_all(&header)?;
+        file.sync_data()?;
+        Ok(Self::from_file(file, path, 0))
+    }
+
+    /// Create a session file that starts with `metadata`
+    pub fn create_with(path: impl AsRef<Path>, metadata: &SessionMetadata) -> Result<Self> {
+        let mut writer = Self::create(path)?;
+        writer.write_metadata(metadata)?;
+        Ok(writer)
+    }
+
+    /// Continue writing an existing session, dropping a torn trailing block first
+    pub fn append(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let info = recover(path)?;
+        if info.version != SESSION_VERSION {
+            bail!("Cannot append to {}: session format version {} (expected {})", path.display(), info.version, SESSION_VERSION);
+        }
+        let mut file = OpenOptions::new().append(true).open(path).with_context(|| format!("Failed to open session {}", path.display()))?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(Self::from_file(file, path, info.events))
+    }
+
+    fn from_file(file: File, path: &Path, events_written: u64) -> Self {
+        Self {
+            file,
+            path: path.to_path_buf(),
+            policy: FlushPolicy::default(),
+            pending: Vec::new(),
+            pending_events: 0,
+            pending_since: None,
+            block: Vec::new(),
+            events_written,
+            error: None,
+        }
+    }
+
+    /// Use a different flush policy
+    pub fn with_policy(mut self, policy: FlushPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Path of the session file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Buffer one event without a raw report
+    pub fn push(&mut self, event: MouseMoveEvent) -> Result<()> {
+        self.push_report(event, &[])
+    }
+
+    /// Buffer one event and the HID report it was decoded from, flushing when the policy says so
+    pub fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) -> Result<()> {
+        let report = &report[..report.len().min(MAX_REPORT_LEN)];
+        let now = Instant::now();
+        self.pending_since.get_or_insert(now);
+        encode_event(&event, &mut self.pending);
+        self.pending.push(report.len() as u8);
+        self.pending.extend_from_slice(report);
+        self.pending_events += 1;
+        if self.pending_events >= self.policy.max_events {
+            self.flush()?;
+        }
+        self.flush_if_due(now)
+    }
+
+    /// Flush if the oldest buffered event is `max_interval` old at `now`
+    pub fn flush_if_due(&mut self, now: Instant) -> Result<()> {
+        if self.pending_since.is_some_and(|since| now.duration_since(since) >= self.policy.max_interval) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, tag: [u8; 4], payload: &[u8]) -> Result<()> {
+        self.block.clear();
+        self.block.extend_from_slice(&tag);
+        self.block.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.block.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        self.block.extend_from_slice(payload);
+        self.file.write_all(&self.block).with_context(|| format!("Failed to write session {}", self.path.display()))?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Write buffered events as one block and sync it to disk
+    pub fn flush(&mut self) -> Result<()> {
+        if self.pending_events == 0 {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let result = self.write_block(EVENTS_TAG, &pending);
+        self.pending = pending;
+        result?;
+        self.events_written += self.pending_events as u64;
+        self.pending.clear();
+        self.pending_events = 0;
+        self.pending_since = None;
+        Ok(())
+    }
+
+    /// Append metadata, replacing what earlier blocks said
+    pub fn write_metadata(&mut self, metadata: &SessionMetadata) -> Result<()> {
+        self.flush()?;
+        let text = toml::to_string(metadata).context("Failed to serialize session metadata")?;
+        self.write_block(METADATA_TAG, text.as_bytes())
+    }
+
+    /// Append analysis results, replacing earlier ones
+    pub fn write_analysis(&mut self, stats: &Stats) -> Result<()> {
+        self.flush()?;
+        let text = toml::to_string(stats).context("Failed to serialize analysis results")?;
+        self.write_block(ANALYSIS_TAG, text.as_bytes())
+    }
+
+    /// Append marks, replacing earlier ones
+    pub fn write_marks(&mut self, marks: &[SessionMark]) -> Result<()> {
+        self.flush()?;
+        let text = toml::to_string(&MarkList { marks: marks.to_vec() }).context("Failed to serialize marks")?;
+        self.write_block(MARKS_TAG, text.as_bytes())
+    }
+
+    /// Events safely on disk (excluding buffered ones)
+    pub fn events_written(&self) -> u64 {
+        self.events_written
+    }
+
+    /// Flush remaining events and report any error hit while used as an `EventSink`
+    pub fn finish(mut self) -> Result<u64> {
+        if let Some(e) = self.error.take() {
+            bail!("{}", e);
+        }
+        self.flush()?;
+        Ok(self.events_written)
+    }
+
+    fn record_error(&mut self, result: Result<()>) {
+        if let Err(e) = result
+            && self.error.is_none()
+        {
+            eprintln!("Session write failed, later events may be lost: {:#}", e);
+            self.error = Some(format!("{:#}", e));
+        }
+    }
+}
+
+impl Drop for SessionWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl EventSink for SessionWriter {
+    fn push_event(&mut self, event: MouseMoveEvent) {
+        let result = self.push(event);
+        self.record_error(result);
+    }
+
+    fn push_report(&mut self, event: MouseMoveEvent, report: &[u8]) {
+        let result = SessionWriter::push_report(self, event, report);
+        self.record_error(result);
+    }
+}
+
+/// Run `capture` while a timer flushes `writer` once its events are due
+///
+/// A capture only pushes events while the mouse moves, so without the timer
+/// the events of a burst would stay buffered until the next movement. Pass
+/// `&writer` as (part of) the capture's sink.
+pub fn with_timed_flush<T>(writer: Option<&Mutex<SessionWriter>>, capture: impl FnOnce() -> T) -> T {
+    let Some(writer) = writer else { return capture() };
+    let tick = writer.lock().map_or(Duration::from_secs(1), |w| w.policy.max_interval) / 10;
+    thread::scope(|scope| {
+        let (done, finished) = mpsc::channel::<()>();
+        scope.spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(tick) {
+                if let Ok(mut writer) = writer.lock() {
+                    let result = writer.flush_if_due(Instant::now());
+                    writer.record_error(result);
+                }
+            }
+        });
+        let result = capture();
+        drop(done);
+        result
+    })
+}
+
+/// Summary of a session file found by `SessionReader::open`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub version: u16,
+    /// Events in valid blocks
+    pub events: u64,
+    /// Number of valid event blocks
+    pub chunks: u64,
+    /// Bytes up to the end of the last valid block
+    pub valid_len: u64,
+    pub file_len: u64,
+}
+
+impl SessionInfo {
+    /// Whether trailing bytes (a torn or corrupt block) follow the valid data
+    pub fn is_truncated(&self) -> bool {
+        self.valid_len < self.file_len
+    }
+}
+
+/// Read the next block into `payload`; `None` at the end of valid data
+fn read_block<R: Read>(reader: &mut R, version: u16, payload: &mut Vec<u8>) -> Result<Option<[u8; 4]>> {
+    let mut header = [0u8; BLOCK_HEADER_LEN];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let tag: [u8; 4] = header[0..4].try_into().unwrap();
+    let field = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    let crc = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let len = if version == 1 {
+        // Version 1 chunks store an event count instead of a length
+        if tag != V1_CHUNK_TAG || field == 0 || field > MAX_BLOCK_LEN / EVENT_LEN as u32 {
+            return Ok(None);
+        }
+        field as usize * EVENT_LEN
+    } else {
+        if field > MAX_BLOCK_LEN || !tag.iter().all(|b| b.is_ascii_uppercase()) {
+            return Ok(None);
+        }
+        field as usize
+    };
+    payload.resize(len, 0);
+    match reader.read_exact(payload) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    Ok((crc32fast::hash(payload) == crc).then_some(tag))
+}
+
+fn open_checked(path: &Path) -> Result<(BufReader<File>, u16)> {
+    let file = File::open(path).with_context(|| format!("Failed to open session {}", path.display()))?;
+    let mut reader = BufReader::with_capacity(1 << 16, file);
+    let mut header = [0u8; HEADER_LEN as usize];
+    reader.read_exact(&mut header).with_context(|| format!("{} is too short to be a session file", path.display()))?;
+    if header[0..4] != SESSION_MAGIC {
+        bail!("{} is not a session file", path.display());
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    if version == 0 || version > SESSION_VERSION {
+        bail!("{} uses session format version {}, newer than supported ({})", path.display(), version, SESSION_VERSION);
+    }
+    Ok((reader, version))
+}
+
+fn parse_toml_block<T: serde::de::DeserializeOwned>(payload: &[u8], what: &str, path: &Path) -> Result<T> {
+    let text = std::str::from_utf8(payload).with_context(|| format!("{} in {} is not UTF-8", what, path.display()))?;
+    toml::from_str(text).with_context(|| format!("Invalid {} in {}", what, path.display()))
+}
+
+/// Validated, streamable view of a session file
+#[derive(Debug, Clone)]
+pub struct SessionReader {
+    path: PathBuf,
+    info: SessionInfo,
+    metadata: Option<SessionMetadata>,
+    analysis: Option<Stats>,
+    marks: Vec<SessionMark>,
+}
+
+impl SessionReader {
+    /// Open a session and validate all blocks (stopping at a torn tail)
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let (mut reader, version) = open_checked(path)?;
+        let file_len = reader.get_ref().metadata()?.len();
+        let mut info = SessionInfo { version, events: 0, chunks: 0, valid_len: HEADER_LEN, file_len };
+        let (mut metadata, mut analysis, mut marks) = (None, None, Vec::new());
+        let mut payload = Vec::new();
+        while let Some(tag) = read_block(&mut reader, version, &mut payload)? {
+            match tag {
+                tag if is_event_block(tag) => {
+                    let Some(count) = count_records(tag, &payload) else { break };
+                    info.events += count;
+                    info.chunks += 1;
+                }
+                METADATA_TAG => metadata = Some(parse_toml_block(&payload, "session metadata", path)?),
+                ANALYSIS_TAG => analysis = Some(parse_toml_block(&payload, "analysis results", path)?),
+                MARKS_TAG => marks = parse_toml_block::<MarkList>(&payload, "marks", path)?.marks,
+                _ => {} // written by a newer version, not needed to read the events
+            }
+            info.valid_len += (BLOCK_HEADER_LEN + payload.len()) as u64;
+        }
+        Ok(Self { path: path.to_path_buf(), info, metadata, analysis, marks })
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        self.info
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The latest metadata block, if any (version 1 files have none)
+    pub fn metadata(&self) -> Option<&SessionMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// The latest analysis results, if any
+    pub fn analysis(&self) -> Option<&Stats> {
+        self.analysis.as_ref()
+    }
+
+    /// Marks of the latest marks block, empty if there is none
+    pub fn marks(&self) -> &[SessionMark] {
+        &self.marks
+    }
+
+    /// Stream all valid events from disk, one block in memory at a time
+    ///
+    /// I/O errors while streaming end the iteration early; `open` already
+    /// checked that every block up to `info().valid_len` is readable.
+    pub fn events(&self) -> SessionEvents {
+        let opened = open_checked(&self.path).ok();
+        let version = opened.as_ref().map(|(_, v)| *v).unwrap_or(SESSION_VERSION);
+        SessionEvents { reader: opened.map(|(r, _)| r), version, remaining: self.info.valid_len - HEADER_LEN, tag: EVENTS_TAG, payload: Vec::new(), pos: 0 }
+    }
+
+    /// Call `f` with every event and its raw report (empty if not recorded), in order
+    pub fn for_each_record<F: FnMut(MouseMoveEvent, &[u8])>(&self, mut f: F) -> Result<()> {
+        let (mut reader, version) = open_checked(&self.path)?;
+        let mut payload = Vec::new();
+        let mut remaining = self.info.valid_len - HEADER_LEN;
+        while remaining > 0 {
+            let Some(tag) = read_block(&mut reader, version, &mut payload)? else {
+                bail!("{} changed while reading", self.path.display());
+            };
+            remaining = remaining.saturating_sub((BLOCK_HEADER_LEN + payload.len()) as u64);
+            if !is_event_block(tag) {
+                continue;
+            }
+            let mut pos = 0;
+            while let Some((event, report, next)) = decode_record(tag, &payload, pos) {
+                f(event, report);
+                pos = next;
+            }
+        }
+        Ok(())
+    }
+
+    /// Load every valid event into memory
+    pub fn read_all(&self) -> Result<Vec<MouseMoveEvent>> {
+        let mut out = Vec::with_capacity(self.info.events as usize);
+        self.for_each_record(|event, _| out.push(event))?;
+        Ok(out)
+    }
+}
+
+/// Iterator over the events of a session file, see `SessionReader::events`
+pub struct SessionEvents {
+    reader: Option<BufReader<File>>,
+    version: u16,
+    /// Valid bytes not read yet
+    remaining: u64,
+    tag: [u8; 4],
+    payload: Vec<u8>,
+    pos: usize,
+}
+
+impl Iterator for SessionEvents {
+    type Item = MouseMoveEvent;
+
+    fn next(&mut self) -> Option<MouseMoveEvent> {
+        loop {
+            if let Some((event, _, next)) = decode_record(self.tag, &self.payload, self.pos) {
+                self.pos = next;
+                return Some(event);
+            }
+            if self.remaining == 0 {
+                return None;
+            }
+            let reader = self.reader.as_mut()?;
+            match read_block(reader, self.version, &mut self.payload) {
+                Ok(Some(tag)) => {
+                    self.remaining = self.remaining.saturating_sub((BLOCK_HEADER_LEN + self.payload.len()) as u64);
+                    self.pos = 0;
+                    self.tag = tag;
+                    if !is_event_block(tag) {
+                        self.payload.clear();
+                    }
+                }
+                _ => {
+                    self.reader = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Truncate a session to its last valid block, e.g. after a crash
+pub fn recover(path: impl AsRef<Path>) -> Result<SessionInfo> {
+    let path = path.as_ref();
+    let info = SessionReader::open(path)?.info();
+    if info.is_truncated() {
+        let file = OpenOptions::new().write(true).open(path).with_context(|| format!("Failed to open session {}", path.display()))?;
+        file.set_len(info.valid_len)?;
+        file.sync_all()?;
+    }
+    Ok(info)
+}
+
+/// Load all events of a session file
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<MouseMoveEvent>> {
+    SessionReader::open(path)?.read_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{compute_stats, compute_stats_with};
+    use std::fs;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mousetester_session_{}_{}.{}", name, std::process::id(), SESSION_EXTENSION))
+    }
+
+    fn events(n: u32) -> Vec<MouseMoveEvent> {
+        (0..n).map(|i| MouseMoveEvent::new((i % 50) as i16 - 25, -((i % 9) as i16), i / 8000, (i % 8000) * 125)).collect()
+    }
+
+    fn same(a: &MouseMoveEvent, b: &MouseMoveEvent) -> bool {
+        (a.dx, a.dy, a.ts_sec, a.ts_usec) == (b.dx, b.dy, b.ts_sec, b.ts_usec)
+    }
+
+    fn metadata() -> SessionMetadata {
+        SessionMetadata {
+            device: DeviceIdentity { target: Some("1.2.1".parse().unwrap()) },
+            capture: CaptureSettings { program_version: "0.1.0".to_string(), started_unix_secs: Some(1_700_000_000), interface: Some(r"\\.\USBPcap1".to_string()), raw_pcap: None, stop: StopConditions { max_events: Some(10), ..Default::default() } },
+            notes: SessionNotes { mouse_model: Some("G Pro".to_string()), cpi: Some(1600), surface: Some("cloth pad".to_string()), comment: None },
+        }
+    }
+
+    #[test]
+    fn test_round_trip_events_reports_and_metadata() {
+        let path = temp_path("round_trip");
+        let input = events(10_000);
+        let metadata = metadata();
+
+        let mut writer = SessionWriter::create_with(&path, &metadata).unwrap().with_policy(FlushPolicy { max_events: 3000, max_interval: Duration::from_secs(3600) });
+        for (i, e) in input.iter().enumerate() {
+            writer.push_report(*e, &[1, 0, i as u8, (i >> 8) as u8, 0, 0, 0, 0][..if i % 2 == 0 { 8 } else { 7 }]).unwrap();
+        }
+        assert_eq!(writer.events_written(), 9000, "only full blocks are flushed before finish");
+        let stats = compute_stats(&input);
+        writer.write_analysis(&stats).unwrap();
+        assert_eq!(writer.finish().unwrap(), 10_000);
+
+        let reader = SessionReader::open(&path).unwrap();
+        assert_eq!(reader.info().version, SESSION_VERSION);
+        assert_eq!(reader.info().events, 10_000);
+        assert_eq!(reader.info().chunks, 4);
+        assert!(!reader.info().is_truncated());
+        assert_eq!(reader.metadata(), Some(&metadata));
+        assert_eq!(reader.analysis(), Some(&stats));
+
+        let loaded = reader.read_all().unwrap();
+        assert!(loaded.iter().zip(&input).all(|(a, b)| same(a, b)));
+        assert_eq!(reader.events().count(), 10_000);
+        let mut i = 0;
+        reader
+            .for_each_record(|_, report| {
+                assert_eq!(report.len(), if i % 2 == 0 { 8 } else { 7 });
+                assert_eq!(report[2], i as u8);
+                i += 1;
+            })
+            .unwrap();
+
+        // Streaming stats from disk equal in-memory stats
+        let streamed = compute_stats_with(|| reader.events());
+        assert_eq!(streamed.histogram, stats.histogram);
+        assert_eq!(streamed.total_dx, stats.total_dx);
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_marks_round_trip_and_append() {
+        let path = temp_path("marks");
+        let marks = vec![SessionMark { event_index: 0, time_secs: 0.0, label: "start".to_string() }, SessionMark { event_index: 120, time_secs: 0.015, label: "fast flick, \"quoted\"\nline".to_string() }];
+        let mut writer = SessionWriter::create(&path).unwrap();
+        for e in events(200) {
+            writer.push(e).unwrap();
+        }
+        writer.write_marks(&marks[..1]).unwrap();
+        writer.write_marks(&marks).unwrap();
+        writer.finish().unwrap();
+        let reader = SessionReader::open(&path).unwrap();
+        assert_eq!(reader.marks(), &marks[..], "the later block replaces the earlier one");
+        assert_eq!(reader.info().events, 200);
+
+        // Marks appended to a finished session keep it intact
+        let mut writer = SessionWriter::append(&path).unwrap();
+        writer.write_marks(&marks[1..]).unwrap();
+        writer.finish().unwrap();
+        let reader = SessionReader::open(&path).unwrap();
+        assert!(!reader.info().is_truncated());
+        assert_eq!((reader.marks(), reader.info().events), (&marks[1..], 200));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_timed_flush_saves_events_when_none_follow() {
+        let path = temp_path("timed_flush");
+        let writer = SessionWriter::create(&path).unwrap().with_policy(FlushPolicy { max_events: 1000, max_interval: Duration::from_millis(200) });
+        let writer = Mutex::new(writer);
+        with_timed_flush(Some(&writer), || {
+            let mut sink = &writer;
+            for e in events(20) {
+                sink.push_event(e);
+            }
+            assert_eq!(writer.lock().unwrap().events_written(), 0, "the burst is buffered");
+            // No more events arrive, but the timer flushes the burst
+            let started = Instant::now();
+            while writer.lock().unwrap().events_written() == 0 && started.elapsed() < Duration::from_secs(10) {
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert_eq!(SessionReader::open(&path).unwrap().info().events, 20);
+        });
+        assert_eq!(writer.into_inner().unwrap().finish().unwrap(), 20);
+        assert_eq!(with_timed_flush(None, || 7), 7);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_recover_torn_tail_and_append() {
+        let path = temp_path("recover");
+        let mut writer = SessionWriter::create(&path).unwrap().with_policy(FlushPolicy { max_events: 100, max_interval: Duration::from_secs(3600) });
+        for e in events(250) {
+            writer.push(e).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // Simulate a crash in the middle of writing the last block
+        let len = fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 7).unwrap();
+        drop(file);
+
+        let info = SessionReader::open(&path).unwrap().info();
+        assert!(info.is_truncated());
+        assert_eq!(info.events, 200);
+
+        // Corrupt a byte in the second block: only the first block survives
+        let mut bytes = fs::read(&path).unwrap();
+        let second_block_payload = HEADER_LEN as usize + 2 * BLOCK_HEADER_LEN + 100 * (EVENT_LEN + 1) + 5;
+        bytes[second_block_payload] ^= 0xFF;
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(SessionReader::open(&path).unwrap().info().events, 100);
+
+        let mut writer = SessionWriter::append(&path).unwrap();
+        assert_eq!(writer.events_written(), 100);
+        writer.push(MouseMoveEvent::new(1, 1, 9, 9)).unwrap();
+        assert_eq!(writer.finish().unwrap(), 101);
+
+        let info = SessionReader::open(&path).unwrap().info();
+        assert_eq!((info.events, info.chunks), (101, 2));
+        assert!(!info.is_truncated());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_reads_version_1_files() {
+        let path = temp_path("v1");
+        let input = events(30);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&SESSION_MAGIC);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        for chunk in input.chunks(20) {
+            let mut payload = Vec::new();
+            chunk.iter().for_each(|e| encode_event(e, &mut payload));
+            bytes.extend_from_slice(&V1_CHUNK_TAG);
+            bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+            bytes.extend_from_slice(&payload);
+        }
+        fs::write(&path, &bytes).unwrap();
+
+        let reader = SessionReader::open(&path).unwrap();
+        assert_eq!((reader.info().version, reader.info().events, reader.info().chunks), (1, 30, 2));
+        assert!(reader.metadata().is_none());
+        assert!(reader.events().zip(&input).all(|(a, b)| same(&a, b)));
+        assert!(SessionWriter::append(&path).is_err(), "v1 files are read-only");
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        let path = temp_path("not_a_session");
+        fs::write(&path, b"dx,dy,time\n1,2,0.0\n").unwrap();
+        assert!(SessionReader::open(&path).is_err());
+        fs::remove_file(&path).ok();
+    }
+}
@@ -3,43 +3,15 @@
 //! This test loads CSV files from the examples/test directory and validates
 //! the advanced LOD algorithm with real mouse movement data.
 
-use MouseTesterRust::lod_advanced::{build_segments, collect_visible_indices};
+use MouseTesterRust::fit::Degree;
+use MouseTesterRust::formats::{self, EventFormat};
+use MouseTesterRust::lod_advanced::{Segment, SegmentParams, Series, build_segments, build_segments_parallel, collect_visible_indices};
 use MouseTesterRust::mouse_event::MouseMoveEvent;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::path::Path;
 
-/// Load events from a CSV file
+/// Load events from a CSV file with exact microsecond timestamps
 fn load_csv_events(path: &Path) -> Result<Vec<MouseMoveEvent>, Box<dyn std::error::Error>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut events = Vec::new();
-
-    for (line_num, line) in reader.lines().enumerate() {
-        let line = line?;
-        
-        // Skip header
-        if line_num == 0 && line.starts_with("dx,dy,time") {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() != 3 {
-            continue;
-        }
-
-        let dx: i16 = parts[0].trim().parse()?;
-        let dy: i16 = parts[1].trim().parse()?;
-        let time: f64 = parts[2].trim().parse()?;
-
-        // Convert time to ts_sec and ts_usec
-        let ts_sec = time.floor() as u32;
-        let ts_usec = ((time - time.floor()) * 1_000_000.0) as u32;
-
-        events.push(MouseMoveEvent::new(dx, dy, ts_sec, ts_usec));
-    }
-
-    Ok(events)
+    Ok(formats::load_events(path, Some(EventFormat::Csv), None)?)
 }
 
 #[test]
@@ -54,7 +26,7 @@ fn test_lod_with_1k_sensor() {
     println!("Loaded {} events from 1kHz sensor data", events.len());
 
     // Build segments
-    let segments = build_segments(&events, 10, 1.5, 0.85, 0.5);
+    let segments = build_segments(&events, 10, 1.5, 0.85, 0.5, Degree::Cubic);
     println!("Created {} segments", segments.len());
 
     // Collect visible indices for full view
@@ -66,6 +38,7 @@ fn test_lod_with_1k_sensor() {
     let visible = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0, // Full HD width
         1080.0, // Full HD height
         (x_min, x_max),
@@ -98,7 +71,7 @@ fn test_lod_with_8k_sensor() {
     println!("Loaded {} events from 8kHz sensor data", events.len());
 
     // Build segments with more relaxed parameters for noisy real-world data
-    let segments = build_segments(&events, 20, 2.0, 0.7, 0.7);
+    let segments = build_segments(&events, 20, 2.0, 0.7, 0.7, Degree::Cubic);
     println!("Created {} segments", segments.len());
 
     // Collect visible indices for full view
@@ -110,6 +83,7 @@ fn test_lod_with_8k_sensor() {
     let visible = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (x_min, x_max),
@@ -144,7 +118,7 @@ fn test_lod_zoom_behavior() {
     println!("Loaded {} events from 4kHz gaming mouse data", events.len());
 
     // Build segments
-    let segments = build_segments(&events, 15, 2.0, 0.7, 0.6);
+    let segments = build_segments(&events, 15, 2.0, 0.7, 0.6, Degree::Cubic);
 
     let x_min = events.first().map(|e| e.time_secs()).unwrap_or(0.0);
     let x_max = events.last().map(|e| e.time_secs()).unwrap_or(1.0);
@@ -155,6 +129,7 @@ fn test_lod_zoom_behavior() {
     let visible_full = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (x_min, x_max),
@@ -185,7 +160,7 @@ fn test_lod_preserves_segment_boundaries() {
     println!("Loaded {} events from low power mouse data", events.len());
 
     // Build segments
-    let segments = build_segments(&events, 8, 2.0, 0.8, 0.5);
+    let segments = build_segments(&events, 8, 2.0, 0.8, 0.5, Degree::Cubic);
     
     let x_min = events.first().map(|e| e.time_secs()).unwrap_or(0.0);
     let x_max = events.last().map(|e| e.time_secs()).unwrap_or(1.0);
@@ -195,6 +170,7 @@ fn test_lod_preserves_segment_boundaries() {
     let visible = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (x_min, x_max),
@@ -233,7 +209,7 @@ fn test_lod_segment_quality_metrics() {
 
     // Build segments with very relaxed quality requirements for real-world noisy data
     // Real mouse data can have significant noise and jitter
-    let segments = build_segments(&events, 20, 2.5, 0.5, 0.7);
+    let segments = build_segments(&events, 20, 2.5, 0.5, 0.7, Degree::Cubic);
 
     use MouseTesterRust::lod_advanced::Segment;
     
@@ -273,7 +249,7 @@ fn test_lod_visibility_filtering() {
     println!("Loaded {} events from 1kHz sensor data", events.len());
 
     // Build segments
-    let segments = build_segments(&events, 15, 2.0, 0.7, 0.25);
+    let segments = build_segments(&events, 15, 2.0, 0.7, 0.25, Degree::Cubic);
 
     // Get full time range
     let full_x_min = events.first().map(|e| e.time_secs()).unwrap_or(0.0);
@@ -285,6 +261,7 @@ fn test_lod_visibility_filtering() {
     let out_of_range_before = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (full_x_min - 100.0, full_x_min - 50.0), // Range before any data
@@ -304,6 +281,7 @@ fn test_lod_visibility_filtering() {
     let out_of_range_after = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (full_x_max + 50.0, full_x_max + 100.0), // Range after any data
@@ -325,6 +303,7 @@ fn test_lod_visibility_filtering() {
     let partial_range = collect_visible_indices(
         &segments,
         &events,
+        Series::NegDy,
         1920.0,
         1080.0,
         (mid_x - time_range * 0.1, mid_x + time_range * 0.1), // Small window in middle
@@ -335,7 +314,7 @@ fn test_lod_visibility_filtering() {
 
     println!("Partial range view: {} events", partial_range.len());
     assert!(
-        partial_range.len() > 0,
+        !partial_range.is_empty(),
         "Should return some events when view intersects data"
     );
     assert!(
@@ -343,18 +322,38 @@ fn test_lod_visibility_filtering() {
         "Should return fewer events than total when zoomed to partial range"
     );
 
-    // Verify all returned event indices are actually within the requested time range
+    // Verify all returned event indices are within the requested time range,
+    // widened on both sides by the zoom factor margin (1.5 => 25% per side)
     let tolerance = 1e-6; // Small tolerance for floating point comparison
+    let view_min = mid_x - time_range * 0.1;
+    let view_max = mid_x + time_range * 0.1;
+    let margin = (view_max - view_min) * ((1.5 - 1.0) / 2.0);
     for &idx in &partial_range {
         let event = &events[idx];
         let event_time = event.time_secs();
         assert!(
-            event_time >= (mid_x - time_range * 0.1 - tolerance) && 
-            event_time <= (mid_x + time_range * 0.1 + tolerance),
+            event_time >= (view_min - margin - tolerance) && 
+            event_time <= (view_max + margin + tolerance),
             "Event at index {} with time {} should be within view range [{}, {}]",
-            idx, event_time, mid_x - time_range * 0.1, mid_x + time_range * 0.1
+            idx, event_time, view_min - margin, view_max + margin
         );
     }
 
     println!("Visibility filtering working correctly!");
 }
+
+#[test]
+fn test_parallel_segmenting_matches_sequential() {
+    let p = SegmentParams::default();
+    let describe = |segments: &[Segment]| -> Vec<String> { segments.iter().map(|s| format!("{:?}", s)).collect() };
+    for entry in std::fs::read_dir("examples/test").expect("examples/test not found") {
+        let path = entry.unwrap().path();
+        let events = load_csv_events(&path).expect("Failed to load CSV");
+        let sequential = describe(&build_segments(&events, p.initial_size, p.growth_factor, p.min_r_squared, p.balance_weight, p.degree));
+        for chunks in [2, 7, 64] {
+            let parallel = describe(&build_segments_parallel(&events, p.initial_size, p.growth_factor, p.min_r_squared, p.balance_weight, p.degree, chunks));
+            assert!(parallel == sequential, "{:?} differs in {} chunks", path, chunks);
+        }
+        println!("{:?}: {} segments, identical in parallel", path, sequential.len());
+    }
+}